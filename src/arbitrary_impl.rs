@@ -0,0 +1,41 @@
+use crate::NomVec;
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+// elements are pushed one at a time as the fuzzer input is consumed, so
+// longer inputs naturally drive the buffer through several growths.
+impl<'a, T: Arbitrary<'a>> Arbitrary<'a> for NomVec<T> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        u.arbitrary_iter()?.collect()
+    }
+
+    fn arbitrary_take_rest(u: Unstructured<'a>) -> Result<Self> {
+        u.arbitrary_take_rest_iter()?.collect()
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (0, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arbitrary_matches_vec() {
+        // behaves exactly like std's Vec impl on the same input
+        let data = [1u8; 64];
+        let cv =
+            NomVec::<u32>::arbitrary(&mut Unstructured::new(&data)).unwrap();
+        let std_vec =
+            Vec::<u32>::arbitrary(&mut Unstructured::new(&data)).unwrap();
+        assert!(cv.len() > 1);
+        assert_eq!(cv, std_vec);
+
+        let cv = NomVec::<u8>::arbitrary_take_rest(Unstructured::new(&data))
+            .unwrap();
+        let std_vec =
+            Vec::<u8>::arbitrary_take_rest(Unstructured::new(&data)).unwrap();
+        assert_eq!(cv, std_vec);
+    }
+}
@@ -0,0 +1,290 @@
+// An arena for values of one type: `alloc` moves a value into the arena and
+// hands back a `&mut T` that lives as long as the arena does, so a tree of
+// nodes can point at each other with plain references. Values go into
+// chunks allocated from `A`, each twice the size of the last; a chunk is
+// never reallocated, so earlier references stay valid. Everything is
+// dropped together, on `reset` or when the arena goes away.
+//
+// The chunks are kept type-erased, behind a struct that isn't generic over
+// `T`, so that `TypedArena` needs no `Drop` impl of its own. That lets the
+// drop checker see that dropping the arena only runs `T`'s destructor,
+// which is what allows values that borrow from the arena itself.
+use crate::{Allocator, Global, NomVec};
+use std::alloc::{self, Layout};
+use std::cell::RefCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr::{self, NonNull};
+use std::slice;
+
+// the first chunk's size in bytes, and the largest a chunk grows to
+const FIRST_CHUNK: usize = 4096;
+const MAX_CHUNK: usize = 2 << 20;
+
+struct Chunk {
+    start: NonNull<u8>,
+    cap: usize,
+    len: usize,
+}
+
+struct Chunks<A: Allocator + Clone> {
+    list: RefCell<NomVec<Chunk, A>>,
+    alloc: A,
+    elem: Layout,
+    drop_elems: unsafe fn(*mut u8, usize),
+}
+
+unsafe fn drop_elems<T>(start: *mut u8, len: usize) {
+    ptr::drop_in_place(ptr::slice_from_raw_parts_mut(start as *mut T, len));
+}
+
+impl<A: Allocator + Clone> Chunks<A> {
+    fn chunk_layout(&self, cap: usize) -> Layout {
+        let size = self
+            .elem
+            .size()
+            .checked_mul(cap)
+            .expect("capacity overflow");
+        Layout::from_size_align(size, self.elem.align())
+            .expect("capacity overflow")
+    }
+
+    // A pointer to room for `n` more elements at the end of the last chunk,
+    // starting a new chunk if it hasn't got that much.
+    fn reserve(&self, n: usize) -> *mut u8 {
+        let mut list = self.list.borrow_mut();
+        if let Some(last) = list.last() {
+            if last.cap - last.len >= n {
+                return unsafe {
+                    last.start.as_ptr().add(last.len * self.elem.size())
+                };
+            }
+        }
+        let cap = if self.elem.size() == 0 {
+            usize::MAX
+        } else {
+            let next = match list.last() {
+                Some(last) => last.cap.saturating_mul(2),
+                None => FIRST_CHUNK / self.elem.size(),
+            };
+            next.min(MAX_CHUNK / self.elem.size()).max(n).max(1)
+        };
+        let layout = self.chunk_layout(cap);
+        let start = if layout.size() == 0 {
+            // dangling, but aligned for `T`
+            unsafe { NonNull::new_unchecked(self.elem.align() as *mut u8) }
+        } else {
+            match self.alloc.allocate(layout) {
+                Ok(start) => start.cast(),
+                Err(_) => alloc::handle_alloc_error(layout),
+            }
+        };
+        list.push(Chunk { start, cap, len: 0 });
+        start.as_ptr()
+    }
+
+    // Counts `n` elements written at `reserve`'s pointer as part of the last
+    // chunk.
+    unsafe fn commit(&self, n: usize) {
+        self.list.borrow_mut().last_mut().unwrap().len += n;
+    }
+
+    unsafe fn free(&self, chunk: &Chunk) {
+        (self.drop_elems)(chunk.start.as_ptr(), chunk.len);
+        let layout = self.chunk_layout(chunk.cap);
+        if layout.size() != 0 {
+            self.alloc.deallocate(chunk.start, layout);
+        }
+    }
+}
+
+impl<A: Allocator + Clone> Drop for Chunks<A> {
+    fn drop(&mut self) {
+        for chunk in self.list.borrow().iter() {
+            unsafe { self.free(chunk) };
+        }
+    }
+}
+
+pub struct TypedArena<T, A: Allocator + Clone = Global> {
+    chunks: Chunks<A>,
+    // owns the `T`s in the chunks
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: Send, A: Allocator + Clone + Send> Send for TypedArena<T, A> {}
+
+impl<T> TypedArena<T> {
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<T, A: Allocator + Clone> TypedArena<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        TypedArena {
+            chunks: Chunks {
+                list: RefCell::new(NomVec::new_in(alloc.clone())),
+                alloc,
+                elem: Layout::new::<T>(),
+                drop_elems: drop_elems::<T>,
+            },
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.chunks.alloc
+    }
+
+    // the number of values in the arena
+    pub fn len(&self) -> usize {
+        self.chunks.list.borrow().iter().map(|c| c.len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Every call hands out a fresh slot, so the `&mut`s never alias.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc(&self, value: T) -> &mut T {
+        unsafe {
+            let slot = self.chunks.reserve(1) as *mut T;
+            slot.write(value);
+            self.chunks.commit(1);
+            &mut *slot
+        }
+    }
+
+    // Moves every item of `iter` into the arena, next to each other.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_extend<I: IntoIterator<Item = T>>(&self, iter: I) -> &mut [T] {
+        // collected first, since the iterator might allocate from the arena
+        // too
+        let mut items = NomVec::from_iter_in(iter, self.chunks.alloc.clone());
+        let len = items.len();
+        unsafe {
+            let start = self.chunks.reserve(len) as *mut T;
+            ptr::copy_nonoverlapping(items.as_ptr(), start, len);
+            items.set_len(0);
+            self.chunks.commit(len);
+            slice::from_raw_parts_mut(start, len)
+        }
+    }
+
+    // Drops every value, keeping the newest (and biggest) chunk for reuse.
+    pub fn reset(&mut self) {
+        // taken out first, so a panicking destructor leaks the rest rather
+        // than leaving them to be dropped twice
+        let empty = NomVec::new_in(self.chunks.alloc.clone());
+        let mut list = mem::replace(self.chunks.list.get_mut(), empty);
+        let kept = list.pop();
+        for chunk in list.iter() {
+            unsafe { self.chunks.free(chunk) };
+        }
+        list.clear();
+        if let Some(mut chunk) = kept {
+            unsafe {
+                (self.chunks.drop_elems)(chunk.start.as_ptr(), chunk.len)
+            };
+            chunk.len = 0;
+            list.push(chunk);
+        }
+        *self.chunks.list.get_mut() = list;
+    }
+
+    // every value, oldest first
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.chunks.list.get_mut().iter().flat_map(|chunk| unsafe {
+            slice::from_raw_parts_mut(chunk.start.as_ptr() as *mut T, chunk.len)
+        })
+    }
+}
+
+impl<T> Default for TypedArena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, A: Allocator + Clone> fmt::Debug for TypedArena<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypedArena")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    enum Expr<'a> {
+        Num(i64),
+        Add(&'a Expr<'a>, &'a Expr<'a>),
+        Sum(&'a [&'a Expr<'a>]),
+    }
+
+    fn eval(e: &Expr<'_>) -> i64 {
+        match e {
+            Expr::Num(n) => *n,
+            Expr::Add(a, b) => eval(a) + eval(b),
+            Expr::Sum(terms) => terms.iter().map(|t| eval(t)).sum(),
+        }
+    }
+
+    #[test]
+    fn arena_nodes_borrow_each_other() {
+        let arena = TypedArena::new();
+        let refs = TypedArena::new();
+        let mut total: &Expr<'_> = arena.alloc(Expr::Num(0));
+        for i in 1..=1000 {
+            let n = arena.alloc(Expr::Num(i));
+            total = arena.alloc(Expr::Add(total, n));
+        }
+        assert_eq!(eval(total), 500500);
+        let terms =
+            refs.alloc_extend((0..3).map(|i| &*arena.alloc(Expr::Num(i))));
+        assert_eq!(eval(arena.alloc(Expr::Sum(terms))), 3);
+        assert_eq!(arena.len(), 2005);
+
+        // values never move once allocated
+        let first = arena.alloc(Expr::Num(7)) as *const Expr<'_>;
+        for i in 0..10_000 {
+            arena.alloc(Expr::Num(i));
+        }
+        assert_eq!(eval(unsafe { &*first }), 7);
+    }
+
+    struct Counted<'a>(&'a Cell<usize>);
+
+    impl Drop for Counted<'_> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn arena_reset_runs_drops() {
+        let drops = Cell::new(0);
+        let mut arena = TypedArena::new();
+        for _ in 0..1000 {
+            arena.alloc(Counted(&drops));
+        }
+        assert_eq!(arena.iter_mut().count(), 1000);
+        arena.reset();
+        assert_eq!(drops.get(), 1000);
+        assert!(arena.is_empty());
+        arena.alloc_extend((0..10).map(|_| Counted(&drops)));
+        drop(arena);
+        assert_eq!(drops.get(), 1010);
+
+        let units = TypedArena::new();
+        let zsts = units.alloc_extend(std::iter::repeat(()).take(5));
+        assert_eq!(zsts.len(), 5);
+        assert_eq!(format!("{:?}", units), "TypedArena { len: 5 }");
+    }
+}
@@ -0,0 +1,567 @@
+// A vector with a fixed maximum of `CAP` elements, stored inline: it never
+// touches an allocator. Operations that would overflow hand the element back
+// as `Err(elem)` instead of growing, and the panicking versions mirror
+// `NomVec`'s for when running out of room is a bug.
+use crate::{slice_range, AllocationError, RawValIter};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, FusedIterator};
+use std::marker::PhantomData;
+use std::mem::{ManuallyDrop, MaybeUninit};
+use std::ops::{Deref, DerefMut, Range, RangeBounds};
+use std::ptr::{self, NonNull};
+
+pub struct ArrayNomVec<T, const CAP: usize> {
+    buf: MaybeUninit<[T; CAP]>,
+    len: usize,
+}
+
+impl<T, const CAP: usize> ArrayNomVec<T, CAP> {
+    pub const fn new() -> Self {
+        ArrayNomVec {
+            buf: MaybeUninit::uninit(),
+            len: 0,
+        }
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub const fn capacity(&self) -> usize {
+        CAP
+    }
+
+    pub const fn is_full(&self) -> bool {
+        self.len == CAP
+    }
+
+    pub const fn remaining_capacity(&self) -> usize {
+        CAP - self.len
+    }
+
+    pub fn as_ptr(&self) -> *const T {
+        self.buf.as_ptr() as *const T
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.buf.as_mut_ptr() as *mut T
+    }
+
+    /// # Safety
+    ///
+    /// `new_len` must be at most `CAP`, and the elements up to it must be
+    /// initialized.
+    pub unsafe fn set_len(&mut self, new_len: usize) {
+        debug_assert!(new_len <= CAP);
+        self.len = new_len;
+    }
+
+    pub fn try_push(&mut self, elem: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(elem);
+        }
+        unsafe {
+            ptr::write(self.as_mut_ptr().add(self.len), elem);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn push(&mut self, elem: T) {
+        if self.try_push(elem).is_err() {
+            panic!("ArrayNomVec is full");
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            None
+        } else {
+            self.len -= 1;
+            unsafe { Some(ptr::read(self.as_ptr().add(self.len))) }
+        }
+    }
+
+    pub fn try_insert(&mut self, index: usize, elem: T) -> Result<(), T> {
+        assert!(index <= self.len, "index out of bounds");
+        if self.is_full() {
+            return Err(elem);
+        }
+        unsafe {
+            let p = self.as_mut_ptr().add(index);
+            ptr::copy(p, p.add(1), self.len - index);
+            ptr::write(p, elem);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn insert(&mut self, index: usize, elem: T) {
+        if self.try_insert(index, elem).is_err() {
+            panic!("ArrayNomVec is full");
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "index out of bounds");
+        self.len -= 1;
+        unsafe {
+            let p = self.as_mut_ptr().add(index);
+            let result = ptr::read(p);
+            ptr::copy(p.add(1), p, self.len - index);
+            result
+        }
+    }
+
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "index out of bounds");
+        let last = self.len - 1;
+        self.swap(index, last);
+        self.pop().unwrap()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        let old_len = self.len;
+        // shorten first so a panicking destructor can't cause a double drop
+        self.len = len;
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                self.as_mut_ptr().add(len),
+                old_len - len,
+            ));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut del = 0;
+        for i in 0..self.len {
+            if !f(&self[i]) {
+                del += 1;
+            } else if del > 0 {
+                self.swap(i - del, i);
+            }
+        }
+        self.truncate(self.len - del);
+    }
+
+    // Appends all of `other`, or nothing at all if it doesn't fit.
+    pub fn try_extend_from_slice(
+        &mut self,
+        other: &[T],
+    ) -> Result<(), AllocationError>
+    where
+        T: Clone,
+    {
+        if other.len() > self.remaining_capacity() {
+            return Err(AllocationError::CapacityOverflow);
+        }
+        for elem in other {
+            unsafe {
+                ptr::write(self.as_mut_ptr().add(self.len), elem.clone());
+            }
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    pub fn extend_from_slice(&mut self, other: &[T])
+    where
+        T: Clone,
+    {
+        if self.try_extend_from_slice(other).is_err() {
+            panic!("ArrayNomVec is full");
+        }
+    }
+
+    // Hands back the elements as a plain array if the vector is full.
+    pub fn into_inner(self) -> Result<[T; CAP], Self> {
+        if !self.is_full() {
+            return Err(self);
+        }
+        let this = ManuallyDrop::new(self);
+        Ok(unsafe { this.buf.as_ptr().read() })
+    }
+
+    pub fn drain<R>(&mut self, range: R) -> Drain<'_, T, CAP>
+    where
+        R: RangeBounds<usize>,
+    {
+        let len = self.len;
+        let Range { start, end } = slice_range(range, len);
+        unsafe {
+            // as with NomVec::drain, a forgotten Drain just leaks the
+            // drained range and the tail
+            self.len = start;
+            let range_slice = ::std::slice::from_raw_parts(
+                self.as_ptr().add(start),
+                end - start,
+            );
+            Drain {
+                tail_start: end,
+                tail_len: len - end,
+                iter: RawValIter::new(range_slice),
+                vec: NonNull::from(self),
+                _marker: PhantomData,
+            }
+        }
+    }
+}
+
+impl<T, const CAP: usize> Drop for ArrayNomVec<T, CAP> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(&mut self[..]) }
+    }
+}
+
+impl<T, const CAP: usize> Default for ArrayNomVec<T, CAP> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const CAP: usize> Deref for ArrayNomVec<T, CAP> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        unsafe { ::std::slice::from_raw_parts(self.as_ptr(), self.len) }
+    }
+}
+
+impl<T, const CAP: usize> DerefMut for ArrayNomVec<T, CAP> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { ::std::slice::from_raw_parts_mut(self.as_mut_ptr(), self.len) }
+    }
+}
+
+impl<T: Clone, const CAP: usize> Clone for ArrayNomVec<T, CAP> {
+    fn clone(&self) -> Self {
+        let mut vec = ArrayNomVec::new();
+        vec.extend_from_slice(self);
+        vec
+    }
+}
+
+impl<T: fmt::Debug, const CAP: usize> fmt::Debug for ArrayNomVec<T, CAP> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T, U, const CAP: usize, const M: usize> PartialEq<ArrayNomVec<U, M>>
+    for ArrayNomVec<T, CAP>
+where
+    T: PartialEq<U>,
+{
+    fn eq(&self, other: &ArrayNomVec<U, M>) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T: PartialEq<U>, U, const CAP: usize> PartialEq<[U]>
+    for ArrayNomVec<T, CAP>
+{
+    fn eq(&self, other: &[U]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T: PartialEq<U>, U, const CAP: usize, const M: usize> PartialEq<[U; M]>
+    for ArrayNomVec<T, CAP>
+{
+    fn eq(&self, other: &[U; M]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T: Eq, const CAP: usize> Eq for ArrayNomVec<T, CAP> {}
+
+impl<T: PartialOrd, const CAP: usize> PartialOrd for ArrayNomVec<T, CAP> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        PartialOrd::partial_cmp(&**self, &**other)
+    }
+}
+
+impl<T: Ord, const CAP: usize> Ord for ArrayNomVec<T, CAP> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        Ord::cmp(&**self, &**other)
+    }
+}
+
+impl<T: Hash, const CAP: usize> Hash for ArrayNomVec<T, CAP> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Hash::hash(&**self, state)
+    }
+}
+
+impl<T, const CAP: usize> From<[T; CAP]> for ArrayNomVec<T, CAP> {
+    fn from(array: [T; CAP]) -> Self {
+        ArrayNomVec {
+            buf: MaybeUninit::new(array),
+            len: CAP,
+        }
+    }
+}
+
+// Fails if the slice is longer than `CAP`.
+impl<T: Clone, const CAP: usize> TryFrom<&[T]> for ArrayNomVec<T, CAP> {
+    type Error = AllocationError;
+
+    fn try_from(slice: &[T]) -> Result<Self, AllocationError> {
+        let mut vec = ArrayNomVec::new();
+        vec.try_extend_from_slice(slice)?;
+        Ok(vec)
+    }
+}
+
+// Panics if the iterator yields more than the remaining capacity.
+impl<T, const CAP: usize> Extend<T> for ArrayNomVec<T, CAP> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T, const CAP: usize> FromIterator<T> for ArrayNomVec<T, CAP> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vec = ArrayNomVec::new();
+        vec.extend(iter);
+        vec
+    }
+}
+
+impl<T, const CAP: usize> IntoIterator for ArrayNomVec<T, CAP> {
+    type Item = T;
+    type IntoIter = IntoIter<T, CAP>;
+
+    fn into_iter(self) -> IntoIter<T, CAP> {
+        let this = ManuallyDrop::new(self);
+        IntoIter {
+            buf: unsafe { ptr::read(&this.buf) },
+            start: 0,
+            end: this.len,
+        }
+    }
+}
+
+// Indices rather than a RawValIter, since the buffer moves with the
+// iterator.
+pub struct IntoIter<T, const CAP: usize> {
+    buf: MaybeUninit<[T; CAP]>,
+    start: usize,
+    end: usize,
+}
+
+impl<T, const CAP: usize> IntoIter<T, CAP> {
+    pub fn as_slice(&self) -> &[T] {
+        unsafe {
+            ::std::slice::from_raw_parts(
+                (self.buf.as_ptr() as *const T).add(self.start),
+                self.end - self.start,
+            )
+        }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe {
+            ::std::slice::from_raw_parts_mut(
+                (self.buf.as_mut_ptr() as *mut T).add(self.start),
+                self.end - self.start,
+            )
+        }
+    }
+}
+
+impl<T, const CAP: usize> Iterator for IntoIter<T, CAP> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+        self.start += 1;
+        unsafe {
+            Some(ptr::read(
+                (self.buf.as_ptr() as *const T).add(self.start - 1),
+            ))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.start;
+        (len, Some(len))
+    }
+}
+
+impl<T, const CAP: usize> DoubleEndedIterator for IntoIter<T, CAP> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+        self.end -= 1;
+        unsafe {
+            Some(ptr::read((self.buf.as_ptr() as *const T).add(self.end)))
+        }
+    }
+}
+
+impl<T, const CAP: usize> ExactSizeIterator for IntoIter<T, CAP> {}
+
+impl<T, const CAP: usize> FusedIterator for IntoIter<T, CAP> {}
+
+impl<T, const CAP: usize> Drop for IntoIter<T, CAP> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.as_mut_slice()) }
+    }
+}
+
+pub struct Drain<'a, T: 'a, const CAP: usize> {
+    vec: NonNull<ArrayNomVec<T, CAP>>,
+    _marker: PhantomData<&'a mut ArrayNomVec<T, CAP>>,
+    tail_start: usize,
+    tail_len: usize,
+    iter: RawValIter<T>,
+}
+
+impl<'a, T, const CAP: usize> Drain<'a, T, CAP> {
+    pub fn as_slice(&self) -> &[T] {
+        self.iter.as_slice()
+    }
+}
+
+impl<'a, T, const CAP: usize> Iterator for Drain<'a, T, CAP> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.iter.next()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T, const CAP: usize> DoubleEndedIterator for Drain<'a, T, CAP> {
+    fn next_back(&mut self) -> Option<T> {
+        self.iter.next_back()
+    }
+}
+
+impl<'a, T, const CAP: usize> ExactSizeIterator for Drain<'a, T, CAP> {}
+
+impl<'a, T, const CAP: usize> FusedIterator for Drain<'a, T, CAP> {}
+
+unsafe impl<'a, T: Send, const CAP: usize> Send for Drain<'a, T, CAP> {}
+unsafe impl<'a, T: Sync, const CAP: usize> Sync for Drain<'a, T, CAP> {}
+
+impl<'a, T, const CAP: usize> Drop for Drain<'a, T, CAP> {
+    fn drop(&mut self) {
+        // moves the tail back even if dropping a drained element panics
+        struct DropGuard<'r, 'a, T, const CAP: usize>(
+            &'r mut Drain<'a, T, CAP>,
+        );
+
+        impl<'r, 'a, T, const CAP: usize> Drop for DropGuard<'r, 'a, T, CAP> {
+            fn drop(&mut self) {
+                let drain = &mut *self.0;
+                if drain.tail_len > 0 {
+                    unsafe {
+                        let vec = drain.vec.as_mut();
+                        let start = vec.len;
+                        let ptr = vec.as_mut_ptr();
+                        if drain.tail_start != start {
+                            ptr::copy(
+                                ptr.add(drain.tail_start),
+                                ptr.add(start),
+                                drain.tail_len,
+                            );
+                        }
+                        vec.len = start + drain.tail_len;
+                    }
+                }
+            }
+        }
+
+        let guard = DropGuard(self);
+        for _ in &mut guard.0.iter {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    #[test]
+    fn array_fixed_capacity() {
+        let mut av: ArrayNomVec<u32, 3> = ArrayNomVec::new();
+        assert_eq!(mem::size_of_val(&av), mem::size_of::<([u32; 3], usize)>());
+        assert_eq!(av.try_push(1), Ok(()));
+        av.push(3);
+        av.insert(1, 2);
+        assert!(av.is_full());
+        assert_eq!(av.try_push(4), Err(4));
+        assert_eq!(av.try_insert(0, 0), Err(0));
+        assert_eq!(
+            av.try_extend_from_slice(&[5]),
+            Err(AllocationError::CapacityOverflow)
+        );
+        assert_eq!(av, [1, 2, 3]);
+
+        let array = av.clone().into_inner().unwrap();
+        assert_eq!(array, [1, 2, 3]);
+        assert_eq!(av.remove(0), 1);
+        assert!(av.clone().into_inner().is_err());
+        av.retain(|&x| x != 2);
+        assert_eq!(av, [3]);
+        assert_eq!(av.remaining_capacity(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "ArrayNomVec is full")]
+    fn array_push_panics_when_full() {
+        let _: ArrayNomVec<u8, 2> = (0..3).collect();
+    }
+
+    #[test]
+    fn array_iterators() {
+        let mut av = ArrayNomVec::from([1, 2, 3, 4, 5]);
+        let drained: Vec<_> = av.drain(1..3).collect();
+        assert_eq!(drained, [2, 3]);
+        assert_eq!(av, [1, 4, 5]);
+
+        let mut iter = av.into_iter();
+        assert_eq!(iter.next_back(), Some(5));
+        assert_eq!(iter.as_slice(), [1, 4]);
+        assert_eq!(iter.collect::<Vec<_>>(), [1, 4]);
+
+        let strings: ArrayNomVec<String, 4> =
+            ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+        let mut iter = strings.into_iter();
+        assert_eq!(iter.next().as_deref(), Some("a"));
+        // the rest are dropped with the iterator
+        drop(iter);
+
+        let mut strings: ArrayNomVec<String, 4> =
+            ArrayNomVec::try_from(&["x".to_string(), "y".to_string()][..])
+                .unwrap();
+        fn send_and_sync<T: Send + Sync>(_: &T) {}
+        send_and_sync(&strings.drain(..0));
+        drop(strings.drain(..1));
+        assert_eq!(strings, ["y".to_string()]);
+    }
+}
@@ -0,0 +1,446 @@
+// A vector of bits packed into `usize` blocks. Bits past `len` in the last
+// block are always kept zero, so counting, comparing and hashing can work a
+// block at a time without masking.
+use crate::{Allocator, Global, NomVec};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, FusedIterator};
+use std::ops::{
+    BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not,
+};
+
+const BITS: usize = usize::BITS as usize;
+
+fn blocks_for(bits: usize) -> usize {
+    bits.div_ceil(BITS)
+}
+
+pub struct BitVec<A: Allocator = Global> {
+    blocks: NomVec<usize, A>,
+    len: usize,
+}
+
+impl BitVec {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(bits: usize) -> Self {
+        Self::with_capacity_in(bits, Global)
+    }
+
+    pub fn from_elem(len: usize, bit: bool) -> Self {
+        Self::from_elem_in(len, bit, Global)
+    }
+}
+
+impl<A: Allocator> BitVec<A> {
+    pub const fn new_in(alloc: A) -> Self {
+        BitVec {
+            blocks: NomVec::new_in(alloc),
+            len: 0,
+        }
+    }
+
+    pub fn with_capacity_in(bits: usize, alloc: A) -> Self {
+        BitVec {
+            blocks: NomVec::with_capacity_in(blocks_for(bits), alloc),
+            len: 0,
+        }
+    }
+
+    pub fn from_elem_in(len: usize, bit: bool, alloc: A) -> Self {
+        let mut bv = Self::new_in(alloc);
+        bv.resize(len, bit);
+        bv
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // in bits
+    pub fn capacity(&self) -> usize {
+        self.blocks.capacity().saturating_mul(BITS)
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.blocks.allocator()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = blocks_for(self.len.saturating_add(additional));
+        self.blocks.reserve(needed - self.blocks.len())
+    }
+
+    // the packed bits, least significant bit first
+    pub fn as_blocks(&self) -> &[usize] {
+        &self.blocks
+    }
+
+    // zeroes the bits past `len` in the last block
+    fn clear_unused(&mut self) {
+        let used = self.len % BITS;
+        if used != 0 {
+            if let Some(last) = self.blocks.last_mut() {
+                *last &= (1 << used) - 1;
+            }
+        }
+    }
+
+    pub fn push(&mut self, bit: bool) {
+        if self.len % BITS == 0 {
+            self.blocks.push(0);
+        }
+        self.len += 1;
+        self.set(self.len - 1, bit);
+    }
+
+    pub fn pop(&mut self) -> Option<bool> {
+        let bit = self.get(self.len.checked_sub(1)?)?;
+        self.truncate(self.len - 1);
+        Some(bit)
+    }
+
+    pub fn get(&self, index: usize) -> Option<bool> {
+        if index < self.len {
+            Some((self.blocks[index / BITS] >> (index % BITS)) & 1 == 1)
+        } else {
+            None
+        }
+    }
+
+    // Panics if `index` is out of bounds.
+    pub fn set(&mut self, index: usize, bit: bool) {
+        assert!(index < self.len, "index out of bounds");
+        let block = &mut self.blocks[index / BITS];
+        let mask = 1 << (index % BITS);
+        if bit {
+            *block |= mask;
+        } else {
+            *block &= !mask;
+        }
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self.len = len;
+            self.blocks.truncate(blocks_for(len));
+            self.clear_unused();
+        }
+    }
+
+    pub fn resize(&mut self, len: usize, bit: bool) {
+        if len <= self.len {
+            return self.truncate(len);
+        }
+        let old_len = self.len;
+        if bit && old_len % BITS != 0 {
+            *self.blocks.last_mut().unwrap() |= !0 << (old_len % BITS);
+        }
+        let fill = if bit { !0 } else { 0 };
+        self.blocks.resize(blocks_for(len), fill);
+        self.len = len;
+        self.clear_unused();
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.len = 0;
+    }
+
+    pub fn set_all(&mut self, bit: bool) {
+        let fill = if bit { !0 } else { 0 };
+        for block in self.blocks.iter_mut() {
+            *block = fill;
+        }
+        self.clear_unused();
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.blocks.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    pub fn count_zeros(&self) -> usize {
+        self.len - self.count_ones()
+    }
+
+    // The number of set bits before `index`. Panics if `index > len`.
+    pub fn rank(&self, index: usize) -> usize {
+        assert!(index <= self.len, "index out of bounds");
+        let full = index / BITS;
+        let mut ones: usize = self.blocks[..full]
+            .iter()
+            .map(|b| b.count_ones() as usize)
+            .sum();
+        if index % BITS != 0 {
+            let partial = self.blocks[full] & ((1 << (index % BITS)) - 1);
+            ones += partial.count_ones() as usize;
+        }
+        ones
+    }
+
+    pub fn any(&self) -> bool {
+        self.blocks.iter().any(|&b| b != 0)
+    }
+
+    pub fn all(&self) -> bool {
+        self.count_ones() == self.len
+    }
+
+    pub fn iter(&self) -> Iter<'_, A> {
+        Iter {
+            bv: self,
+            front: 0,
+            back: self.len,
+        }
+    }
+
+    // the indices of the set bits, in ascending order
+    pub fn iter_ones(&self) -> IterOnes<'_> {
+        IterOnes {
+            blocks: &self.blocks,
+            current: self.blocks.first().copied().unwrap_or(0),
+            base: 0,
+        }
+    }
+
+    // Applies `op` block by block. Panics if the lengths differ.
+    fn zip_blocks<B: Allocator>(
+        &mut self,
+        other: &BitVec<B>,
+        op: impl Fn(usize, usize) -> usize,
+    ) {
+        assert_eq!(self.len, other.len, "BitVec lengths differ");
+        for (a, &b) in self.blocks.iter_mut().zip(other.blocks.iter()) {
+            *a = op(*a, b);
+        }
+    }
+}
+
+impl Default for BitVec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Allocator + Clone> Clone for BitVec<A> {
+    fn clone(&self) -> Self {
+        BitVec {
+            blocks: self.blocks.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<A: Allocator> fmt::Debug for BitVec<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BitVec[")?;
+        for bit in self.iter() {
+            f.write_str(if bit { "1" } else { "0" })?;
+        }
+        f.write_str("]")
+    }
+}
+
+impl<A: Allocator, B: Allocator> PartialEq<BitVec<B>> for BitVec<A> {
+    fn eq(&self, other: &BitVec<B>) -> bool {
+        self.len == other.len && self.blocks[..] == other.blocks[..]
+    }
+}
+
+impl<A: Allocator> Eq for BitVec<A> {}
+
+impl<A: Allocator> Hash for BitVec<A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        self.blocks[..].hash(state);
+    }
+}
+
+impl<A: Allocator> Extend<bool> for BitVec<A> {
+    fn extend<I: IntoIterator<Item = bool>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for bit in iter {
+            self.push(bit);
+        }
+    }
+}
+
+impl FromIterator<bool> for BitVec {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> Self {
+        let mut bv = BitVec::new();
+        bv.extend(iter);
+        bv
+    }
+}
+
+macro_rules! impl_bit_op {
+    ($op:ident, $method:ident, $assign:ident, $assign_method:ident, $f:expr) => {
+        impl<A: Allocator, B: Allocator> $assign<&BitVec<B>> for BitVec<A> {
+            // Panics if the lengths differ.
+            fn $assign_method(&mut self, other: &BitVec<B>) {
+                self.zip_blocks(other, $f)
+            }
+        }
+
+        impl<A: Allocator + Clone, B: Allocator> $op<&BitVec<B>>
+            for &BitVec<A>
+        {
+            type Output = BitVec<A>;
+            fn $method(self, other: &BitVec<B>) -> BitVec<A> {
+                let mut out = self.clone();
+                out.zip_blocks(other, $f);
+                out
+            }
+        }
+    };
+}
+
+impl_bit_op!(BitAnd, bitand, BitAndAssign, bitand_assign, |a, b| a & b);
+impl_bit_op!(BitOr, bitor, BitOrAssign, bitor_assign, |a, b| a | b);
+impl_bit_op!(BitXor, bitxor, BitXorAssign, bitxor_assign, |a, b| a ^ b);
+
+impl<A: Allocator> Not for BitVec<A> {
+    type Output = BitVec<A>;
+    fn not(mut self) -> BitVec<A> {
+        for block in self.blocks.iter_mut() {
+            *block = !*block;
+        }
+        self.clear_unused();
+        self
+    }
+}
+
+impl<'a, A: Allocator> IntoIterator for &'a BitVec<A> {
+    type Item = bool;
+    type IntoIter = Iter<'a, A>;
+    fn into_iter(self) -> Iter<'a, A> {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, A: Allocator> {
+    bv: &'a BitVec<A>,
+    front: usize,
+    back: usize,
+}
+
+impl<'a, A: Allocator> Iterator for Iter<'a, A> {
+    type Item = bool;
+    fn next(&mut self) -> Option<bool> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        self.bv.get(self.front - 1)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl<'a, A: Allocator> DoubleEndedIterator for Iter<'a, A> {
+    fn next_back(&mut self) -> Option<bool> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        self.bv.get(self.back)
+    }
+}
+
+impl<'a, A: Allocator> ExactSizeIterator for Iter<'a, A> {}
+impl<'a, A: Allocator> FusedIterator for Iter<'a, A> {}
+
+pub struct IterOnes<'a> {
+    blocks: &'a [usize],
+    // the unvisited bits of `blocks[0]`
+    current: usize,
+    // the index of `blocks[0]`'s first bit
+    base: usize,
+}
+
+impl<'a> Iterator for IterOnes<'a> {
+    type Item = usize;
+    fn next(&mut self) -> Option<usize> {
+        while self.current == 0 {
+            self.blocks = self.blocks.get(1..).filter(|b| !b.is_empty())?;
+            self.current = self.blocks[0];
+            self.base += BITS;
+        }
+        let bit = self.current.trailing_zeros() as usize;
+        // clear the lowest set bit
+        self.current &= self.current - 1;
+        Some(self.base + bit)
+    }
+}
+
+impl<'a> FusedIterator for IterOnes<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bitvec_push_get_set() {
+        let mut bv = BitVec::new();
+        for i in 0..200 {
+            bv.push(i % 3 == 0);
+        }
+        assert_eq!(bv.len(), 200);
+        assert_eq!(bv.get(99), Some(true));
+        assert_eq!(bv.get(100), Some(false));
+        assert_eq!(bv.get(200), None);
+        bv.set(100, true);
+        assert_eq!(bv.count_ones(), 68);
+        assert_eq!(bv.rank(0), 0);
+        assert_eq!(bv.rank(7), 3);
+        assert_eq!(bv.rank(200), 68);
+
+        assert_eq!(bv.pop(), Some(false));
+        assert_eq!(bv.pop(), Some(true));
+        bv.truncate(65);
+        assert_eq!(bv.as_blocks().len(), 2);
+        assert_eq!(bv.count_ones(), 22);
+        bv.resize(130, true);
+        assert_eq!(bv.count_ones(), 22 + 65);
+        assert!(bv.iter().rev().take(65).all(|bit| bit));
+
+        let small: BitVec = [true, false, true, true].iter().copied().collect();
+        assert_eq!(format!("{:?}", small), "BitVec[1011]");
+        assert!(BitVec::from_elem(70, true).all());
+        assert!(!BitVec::from_elem(70, false).any());
+    }
+
+    #[test]
+    fn bitvec_ops_and_ones() {
+        let a: BitVec = (0..100).map(|i| i % 2 == 0).collect();
+        let b: BitVec = (0..100).map(|i| i % 3 == 0).collect();
+        assert!((&a & &b).iter_ones().eq((0..100).step_by(6)));
+        assert_eq!((&a | &b).count_ones(), 50 + 34 - 17);
+        assert_eq!((&a ^ &b).count_ones(), 50 + 34 - 2 * 17);
+
+        let not_a = !a.clone();
+        assert_eq!(not_a.count_ones(), 50);
+        assert_eq!(not_a.len(), 100);
+        assert!(not_a.iter_ones().eq((1..100).step_by(2)));
+        let mut c = a.clone();
+        c |= &not_a;
+        assert!(c.all());
+        c ^= &a;
+        assert_eq!(c, not_a);
+        c &= &a;
+        assert!(!c.any());
+
+        assert_eq!(BitVec::new().iter_ones().next(), None);
+        let sparse: BitVec = (0..300).map(|i| i == 299).collect();
+        assert!(sparse.iter_ones().eq([299]));
+    }
+}
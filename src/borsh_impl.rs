@@ -0,0 +1,102 @@
+// Same wire format as borsh's own Vec impl: a little-endian u32 length
+// followed by the elements.
+use crate::{cautious_capacity, Allocator, NomVec};
+use borsh::io::{Error, ErrorKind, Read, Result, Write};
+use borsh::{BorshDeserialize, BorshSerialize};
+use std::convert::TryFrom;
+use std::mem;
+
+impl<T: BorshSerialize, A: Allocator> BorshSerialize for NomVec<T, A> {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
+        check_zst::<T>()?;
+        let len =
+            u32::try_from(self.len()).map_err(|_| ErrorKind::InvalidData)?;
+        len.serialize(writer)?;
+        for elem in self.iter() {
+            elem.serialize(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: BorshDeserialize> BorshDeserialize for NomVec<T> {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> Result<Self> {
+        Self::deserialize_borsh_with_max_len(reader, u32::MAX as usize)
+    }
+}
+
+impl<T: BorshDeserialize> NomVec<T> {
+    // Like `BorshDeserialize::deserialize_reader`, but rejects any length
+    // prefix above `max_len` before reading (or allocating for) a single
+    // element, for parsing untrusted input.
+    pub fn deserialize_borsh_with_max_len<R: Read>(
+        reader: &mut R,
+        max_len: usize,
+    ) -> Result<Self> {
+        check_zst::<T>()?;
+        let len = u32::deserialize_reader(reader)? as usize;
+        if len > max_len {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("length {} exceeds the maximum of {}", len, max_len),
+            ));
+        }
+        let mut vec = NomVec::with_capacity(cautious_capacity::<T>(Some(len)));
+        for _ in 0..len {
+            vec.push(T::deserialize_reader(reader)?);
+        }
+        Ok(vec)
+    }
+}
+
+// borsh refuses collections of ZSTs: a tiny length prefix could otherwise
+// make us spin through billions of "elements".
+fn check_zst<T>() -> Result<()> {
+    if mem::size_of::<T>() == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "collections of zero-sized types are not allowed",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn borsh_matches_vec_layout() {
+        let cv = NomVec::from([1u16, 2, 3]);
+        let bytes = borsh::to_vec(&cv).unwrap();
+        assert_eq!(bytes, borsh::to_vec(&vec![1u16, 2, 3]).unwrap());
+        assert_eq!(&bytes[..4], &3u32.to_le_bytes());
+
+        let back: NomVec<u16> = borsh::from_slice(&bytes).unwrap();
+        assert_eq!(back, cv);
+    }
+
+    #[test]
+    fn borsh_max_len() {
+        let bytes = borsh::to_vec(&NomVec::from([1u8, 2, 3])).unwrap();
+        let ok =
+            NomVec::<u8>::deserialize_borsh_with_max_len(&mut &bytes[..], 3)
+                .unwrap();
+        assert_eq!(ok, [1, 2, 3]);
+
+        let err =
+            NomVec::<u8>::deserialize_borsh_with_max_len(&mut &bytes[..], 2)
+                .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        // a huge length prefix with no data behind it fails without
+        // allocating for the claimed length
+        let lying = u32::MAX.to_le_bytes();
+        assert!(borsh::from_slice::<NomVec<u64>>(&lying).is_err());
+    }
+
+    #[test]
+    fn borsh_rejects_zst() {
+        assert!(borsh::to_vec(&NomVec::from([(), ()])).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+// Collects the construction knobs (capacity, growth policy, allocator) in
+// one place instead of a constructor for every combination:
+//
+//     let cv = NomVec::builder().capacity(1024).growth(Doubling).allocator(&bump).build();
+use crate::{
+    AllocationError, Allocator, Doubling, Global, GrowthPolicy, NomVec,
+};
+use std::marker::PhantomData;
+
+#[derive(Clone, Debug)]
+pub struct NomVecBuilder<T, A: Allocator = Global, G: GrowthPolicy = Doubling> {
+    capacity: usize,
+    alloc: A,
+    policy: G,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> NomVec<T> {
+    pub fn builder() -> NomVecBuilder<T> {
+        NomVecBuilder {
+            capacity: 0,
+            alloc: Global,
+            policy: Doubling,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, A: Allocator, G: GrowthPolicy> NomVecBuilder<T, A, G> {
+    // the exact capacity to allocate upfront
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn growth<H: GrowthPolicy>(self, policy: H) -> NomVecBuilder<T, A, H> {
+        NomVecBuilder {
+            capacity: self.capacity,
+            alloc: self.alloc,
+            policy,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn allocator<B: Allocator>(self, alloc: B) -> NomVecBuilder<T, B, G> {
+        NomVecBuilder {
+            capacity: self.capacity,
+            alloc,
+            policy: self.policy,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn build(self) -> NomVec<T, A, G> {
+        let mut vec = NomVec::with_policy_in(self.alloc, self.policy);
+        vec.reserve_exact(self.capacity);
+        vec
+    }
+
+    pub fn try_build(self) -> Result<NomVec<T, A, G>, AllocationError> {
+        let mut vec = NomVec::with_policy_in(self.alloc, self.policy);
+        vec.try_reserve_exact(self.capacity)?;
+        Ok(vec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Exponential;
+
+    #[test]
+    fn builder_defaults() {
+        let mut cv = NomVec::<u8>::builder().build();
+        assert_eq!(cv.capacity(), 0);
+        cv.push(1);
+        assert_eq!(cv, [1]);
+    }
+
+    #[test]
+    fn builder_sets_every_knob() {
+        let alloc = Global;
+        let mut cv = NomVec::builder()
+            .capacity(4)
+            .growth(Exponential::new(3, 2))
+            .allocator(&alloc)
+            .build();
+        assert_eq!(cv.capacity(), 4);
+        cv.extend_from_slice(&[1u64, 2, 3, 4, 5]);
+        assert_eq!(cv.capacity(), 6);
+        assert_eq!(*cv.policy(), Exponential::new(3, 2));
+
+        let err = NomVec::<u64>::builder()
+            .capacity(usize::MAX)
+            .try_build()
+            .unwrap_err();
+        assert_eq!(err, AllocationError::CapacityOverflow);
+    }
+}
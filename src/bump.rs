@@ -0,0 +1,294 @@
+// A bump allocator: allocating just rounds a pointer up to the layout's
+// alignment and moves it past the block, and freeing does nothing, so
+// short-lived NomVecs (a request's scratch buffers, say) cost almost nothing
+// to create and nothing to drop. The memory comes back all at once, from
+// `reset` or when the Bump is dropped.
+//
+// Blocks are carved from chunks taken from `A`, each twice the size of the
+// last up to MAX_CHUNK, and linked through a header at the start of each.
+// The newest block is special-cased: freeing it moves the pointer back, and
+// growing it extends it in place if the chunk has room, which is exactly
+// what a NomVec growing by itself at the end of the arena asks for.
+//
+// Allocate through `&Bump`, as in `NomVec::new_in(&bump)`, so the borrow
+// checker makes sure nothing allocated is still alive at `reset`.
+use crate::{AllocError, Allocator, Global};
+use std::alloc::Layout;
+use std::cell::Cell;
+use std::fmt;
+use std::mem;
+use std::ptr::{self, NonNull};
+
+// the first chunk's size in bytes, and the largest doubling grows to
+const FIRST_CHUNK: usize = 4096;
+const MAX_CHUNK: usize = 2 << 20;
+
+struct Header {
+    prev: Option<NonNull<Header>>,
+    layout: Layout,
+}
+
+pub struct Bump<A: Allocator = Global> {
+    // the newest chunk, which the free space below is in
+    chunk: Cell<Option<NonNull<Header>>>,
+    ptr: Cell<*mut u8>,
+    end: Cell<*mut u8>,
+    alloc: A,
+}
+
+// The chunks are owned, and the Cells make it !Sync.
+unsafe impl<A: Allocator + Send> Send for Bump<A> {}
+
+impl Bump {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<A: Allocator> Bump<A> {
+    pub const fn new_in(alloc: A) -> Self {
+        Bump {
+            chunk: Cell::new(None),
+            ptr: Cell::new(ptr::null_mut()),
+            end: Cell::new(ptr::null_mut()),
+            alloc,
+        }
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    // The bytes taken from `A` for chunks, used or not.
+    pub fn allocated_bytes(&self) -> usize {
+        let mut total = 0;
+        let mut chunk = self.chunk.get();
+        while let Some(header) = chunk {
+            let header = unsafe { header.as_ref() };
+            total += header.layout.size();
+            chunk = header.prev;
+        }
+        total
+    }
+
+    // The bytes still free in the newest chunk.
+    pub fn remaining(&self) -> usize {
+        self.end.get() as usize - self.ptr.get() as usize
+    }
+
+    // Frees every block at once, keeping the newest (and biggest) chunk to
+    // allocate from again.
+    pub fn reset(&mut self) {
+        let header = match self.chunk.get() {
+            Some(header) => header,
+            None => return,
+        };
+        unsafe {
+            let prev = (*header.as_ptr()).prev.take();
+            self.free_chunks(prev);
+            self.ptr.set(header.as_ptr().add(1) as *mut u8);
+        }
+    }
+
+    unsafe fn free_chunks(&self, mut chunk: Option<NonNull<Header>>) {
+        while let Some(header) = chunk {
+            let Header { prev, layout } = header.as_ptr().read();
+            self.alloc.deallocate(header.cast(), layout);
+            chunk = prev;
+        }
+    }
+
+    // Takes `layout` from the free space, if there's room.
+    fn bump(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let ptr = self.ptr.get();
+        let pad = ptr.align_offset(layout.align());
+        let needed = pad.checked_add(layout.size())?;
+        if needed > self.remaining() {
+            return None;
+        }
+        unsafe {
+            self.ptr.set(ptr.add(needed));
+            Some(NonNull::new_unchecked(ptr.add(pad)))
+        }
+    }
+
+    // Starts a chunk with room for at least `layout`.
+    fn new_chunk(&self, layout: Layout) -> Result<(), AllocError> {
+        let prev_size = match self.chunk.get() {
+            Some(header) => unsafe { header.as_ref().layout.size() },
+            None => 0,
+        };
+        // enough to align the block wherever the header ends
+        let needed = mem::size_of::<Header>()
+            .checked_add(layout.size())
+            .and_then(|n| n.checked_add(layout.align()))
+            .ok_or(AllocError)?;
+        let size = prev_size
+            .saturating_mul(2)
+            .clamp(FIRST_CHUNK, MAX_CHUNK)
+            .max(needed);
+        let chunk_layout =
+            Layout::from_size_align(size, mem::align_of::<Header>())
+                .map_err(|_| AllocError)?;
+        let header = self.alloc.allocate(chunk_layout)?.cast::<Header>();
+        unsafe {
+            header.as_ptr().write(Header {
+                prev: self.chunk.get(),
+                layout: chunk_layout,
+            });
+            self.ptr.set(header.as_ptr().add(1) as *mut u8);
+            self.end.set((header.as_ptr() as *mut u8).add(size));
+        }
+        self.chunk.set(Some(header));
+        Ok(())
+    }
+
+    // whether `ptr..ptr + size` is the newest block
+    fn is_last(&self, ptr: NonNull<u8>, size: usize) -> bool {
+        ptr.as_ptr().wrapping_add(size) == self.ptr.get()
+    }
+}
+
+impl Default for Bump {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Allocator> Drop for Bump<A> {
+    fn drop(&mut self) {
+        unsafe { self.free_chunks(self.chunk.get()) }
+    }
+}
+
+impl<A: Allocator> fmt::Debug for Bump<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bump")
+            .field("allocated_bytes", &self.allocated_bytes())
+            .field("remaining", &self.remaining())
+            .finish()
+    }
+}
+
+fn block(ptr: NonNull<u8>, size: usize) -> NonNull<[u8]> {
+    NonNull::slice_from_raw_parts(ptr, size)
+}
+
+unsafe impl<A: Allocator> Allocator for Bump<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            // dangling, but aligned
+            let ptr =
+                unsafe { NonNull::new_unchecked(layout.align() as *mut u8) };
+            return Ok(block(ptr, 0));
+        }
+        let ptr = match self.bump(layout) {
+            Some(ptr) => ptr,
+            None => {
+                self.new_chunk(layout)?;
+                self.bump(layout).ok_or(AllocError)?
+            }
+        };
+        Ok(block(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        // only the newest block can be given back
+        if layout.size() != 0 && self.is_last(ptr, layout.size()) {
+            self.ptr.set(ptr.as_ptr());
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let extra = new_layout.size() - old_layout.size();
+        if old_layout.size() != 0
+            && self.is_last(ptr, old_layout.size())
+            && ptr.as_ptr().align_offset(new_layout.align()) == 0
+            && extra <= self.remaining()
+        {
+            self.ptr.set(self.ptr.get().add(extra));
+            return Ok(block(ptr, new_layout.size()));
+        }
+        let new = self.allocate(new_layout)?;
+        ptr::copy_nonoverlapping(
+            ptr.as_ptr(),
+            new.as_ptr() as *mut u8,
+            old_layout.size(),
+        );
+        Ok(new)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if ptr.as_ptr().align_offset(new_layout.align()) != 0 {
+            let new = self.allocate(new_layout)?;
+            ptr::copy_nonoverlapping(
+                ptr.as_ptr(),
+                new.as_ptr() as *mut u8,
+                new_layout.size(),
+            );
+            return Ok(new);
+        }
+        if old_layout.size() != 0 && self.is_last(ptr, old_layout.size()) {
+            self.ptr.set(ptr.as_ptr().add(new_layout.size()));
+        }
+        Ok(block(ptr, new_layout.size()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NomVec;
+
+    #[test]
+    fn bump_grows_newest_block_in_place() {
+        let bump = Bump::new();
+        let mut cv = NomVec::with_capacity_in(4, &bump);
+        cv.extend(0u32..4);
+        let ptr = cv.as_ptr();
+        cv.push(4);
+        assert_eq!(cv.as_ptr(), ptr);
+        assert_eq!(bump.allocated_bytes(), FIRST_CHUNK);
+
+        // once something else is allocated after it, growing copies
+        let other: NomVec<u8, _> = NomVec::with_capacity_in(1, &bump);
+        cv.extend(5..9);
+        assert_ne!(cv.as_ptr(), ptr);
+        assert!(cv.iter().copied().eq(0..9));
+        drop(other);
+        let aligned = NomVec::<u64, _>::with_capacity_in(1, &bump);
+        assert_eq!(aligned.as_ptr() as usize % 8, 0);
+    }
+
+    #[test]
+    fn bump_reset_keeps_newest_chunk() {
+        let mut bump = Bump::new();
+        {
+            let mut big = NomVec::new_in(&bump);
+            big.extend(0u64..100_000);
+            assert_eq!(big.iter().sum::<u64>(), 4_999_950_000);
+            let small: NomVec<u8, _> = NomVec::from_iter_in(0..10, &bump);
+            assert_eq!(small.len(), 10);
+        }
+        let before = bump.allocated_bytes();
+        assert!(before > 800_000);
+        bump.reset();
+        assert!(bump.allocated_bytes() > 0 && bump.allocated_bytes() < before);
+        let reused = bump.remaining();
+        let cv: NomVec<u8, _> = NomVec::from_iter_in(0..10, &bump);
+        assert_eq!(bump.remaining(), reused - 10);
+        drop(cv);
+        assert_eq!(bump.remaining(), reused);
+        assert!(format!("{:?}", bump).starts_with("Bump { allocated_bytes: "));
+    }
+}
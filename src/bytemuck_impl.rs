@@ -0,0 +1,121 @@
+use crate::{Allocator, Doubling, NomVec, RawVec};
+use bytemuck::{Pod, PodCastError};
+use std::mem::{self, ManuallyDrop};
+use std::ptr;
+
+// Reinterprets the elements of a vector of one `Pod` type as another. The
+// buffer is reused as-is when the alignments match and the capacity divides
+// evenly into `U`s (the allocator must see the same layout on free);
+// otherwise the bytes are copied into a fresh allocation from the same
+// allocator. Fails, handing the vector back, when the byte length isn't a
+// whole number of `U`s or exactly one of the types is zero-sized.
+pub fn try_cast_nomvec<T: Pod, U: Pod, A: Allocator>(
+    vec: NomVec<T, A>,
+) -> Result<NomVec<U, A>, (PodCastError, NomVec<T, A>)> {
+    let (t_size, u_size) = (mem::size_of::<T>(), mem::size_of::<U>());
+    if t_size == 0 || u_size == 0 {
+        if t_size != u_size {
+            return Err((PodCastError::SizeMismatch, vec));
+        }
+        let vec = ManuallyDrop::new(vec);
+        let alloc = unsafe { ptr::read(&vec.buf.alloc) };
+        let mut out = NomVec::new_in(alloc);
+        out.len = vec.len;
+        return Ok(out);
+    }
+
+    let byte_len = vec.len * t_size;
+    if byte_len % u_size != 0 {
+        return Err((PodCastError::OutputSliceWouldHaveSlop, vec));
+    }
+    let byte_cap = vec.cap() * t_size;
+
+    let vec = ManuallyDrop::new(vec);
+    let alloc = unsafe { ptr::read(&vec.buf.alloc) };
+    if mem::align_of::<T>() == mem::align_of::<U>() && byte_cap % u_size == 0 {
+        return Ok(NomVec {
+            buf: unsafe {
+                RawVec::from_raw_parts_in(
+                    vec.ptr() as *mut U,
+                    byte_cap / u_size,
+                    alloc,
+                    Doubling,
+                )
+            },
+            len: byte_len / u_size,
+        });
+    }
+
+    let mut out = NomVec::<U, A>::with_capacity_in(byte_len / u_size, alloc);
+    unsafe {
+        ptr::copy_nonoverlapping(
+            vec.ptr() as *const u8,
+            out.ptr() as *mut u8,
+            byte_len,
+        );
+        out.len = byte_len / u_size;
+        // the allocator now lives in `out`, so free the old buffer through it
+        if let Some((ptr, layout)) = vec.buf.current_memory() {
+            out.buf.alloc.deallocate(ptr, layout);
+        }
+    }
+    Ok(out)
+}
+
+// Like `try_cast_nomvec`, but panics if the cast isn't possible.
+pub fn cast_nomvec<T: Pod, U: Pod, A: Allocator>(
+    vec: NomVec<T, A>,
+) -> NomVec<U, A> {
+    match try_cast_nomvec(vec) {
+        Ok(out) => out,
+        Err((err, _)) => panic!("cast_nomvec failed: {:?}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytemuck_cast_reuses_buffer() {
+        let mut cv: NomVec<u32> = NomVec::with_capacity(4);
+        cv.extend_from_slice(&[1, 2, 3, 4]);
+        let ptr = cv.as_ptr() as usize;
+
+        let cast: NomVec<f32> = cast_nomvec(cv);
+        assert_eq!(cast.as_ptr() as usize, ptr);
+        assert_eq!(cast.len(), 4);
+        assert_eq!(cast.capacity(), 4);
+        assert_eq!(cast[2].to_bits(), 3);
+
+        let back: NomVec<u32> = cast_nomvec(cast);
+        assert_eq!(back, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn bytemuck_cast_bytes_to_words() {
+        let bytes: NomVec<u8> = 1u32
+            .to_ne_bytes()
+            .iter()
+            .chain(&7u32.to_ne_bytes())
+            .copied()
+            .collect();
+        let words: NomVec<u32> = cast_nomvec(bytes);
+        assert_eq!(words, [1, 7]);
+
+        let bytes: NomVec<u8> = cast_nomvec(words);
+        assert_eq!(bytes.len(), 8);
+        assert_eq!(&bytes[4..], &7u32.to_ne_bytes());
+    }
+
+    #[test]
+    fn bytemuck_cast_rejects_slop() {
+        let bytes = NomVec::from([0u8; 5]);
+        let (err, bytes) = try_cast_nomvec::<u8, u32, _>(bytes).unwrap_err();
+        assert_eq!(err, PodCastError::OutputSliceWouldHaveSlop);
+        assert_eq!(bytes.len(), 5);
+
+        let (err, _) = try_cast_nomvec::<u8, (), _>(bytes).unwrap_err();
+        assert_eq!(err, PodCastError::SizeMismatch);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{Allocator, NomBytes, NomVec};
+use bytes::buf::UninitSlice;
+use bytes::{Buf, BufMut};
+
+// Reading consumes from the front, shifting the remaining bytes down. That
+// keeps the buffer contiguous, so it works best when chunks are consumed
+// in large pieces (as codecs usually do).
+impl<A: Allocator> Buf for NomVec<u8, A> {
+    fn remaining(&self) -> usize {
+        self.len()
+    }
+
+    fn chunk(&self) -> &[u8] {
+        self
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        assert!(
+            cnt <= self.len(),
+            "cannot advance past `remaining`: {} <= {}",
+            cnt,
+            self.len()
+        );
+        self.drain(..cnt);
+    }
+}
+
+// NomBytes just moves its window, so advancing is O(1).
+impl<A: Allocator + Clone> Buf for NomBytes<A> {
+    fn remaining(&self) -> usize {
+        self.len()
+    }
+
+    fn chunk(&self) -> &[u8] {
+        self
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        NomBytes::advance(self, cnt)
+    }
+}
+
+unsafe impl<A: Allocator> BufMut for NomVec<u8, A> {
+    fn remaining_mut(&self) -> usize {
+        // a vector can never have more than isize::MAX bytes
+        isize::MAX as usize - self.len()
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        let len = self.len();
+        let remaining = self.capacity() - len;
+        assert!(
+            cnt <= remaining,
+            "cannot advance past `remaining_mut`: {} <= {}",
+            cnt,
+            remaining
+        );
+        self.set_len(len + cnt);
+    }
+
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        if self.capacity() == self.len() {
+            self.reserve(64);
+        }
+        UninitSlice::uninit(self.spare_capacity_mut())
+    }
+
+    fn put<T: Buf>(&mut self, mut src: T)
+    where
+        Self: Sized,
+    {
+        // in case the src isn't contiguous, reserve upfront
+        self.reserve(src.remaining());
+        while src.has_remaining() {
+            let chunk = src.chunk();
+            let len = chunk.len();
+            self.extend_from_slice_copy(chunk);
+            src.advance(len);
+        }
+    }
+
+    fn put_slice(&mut self, src: &[u8]) {
+        self.extend_from_slice_copy(src);
+    }
+
+    fn put_bytes(&mut self, val: u8, cnt: usize) {
+        let new_len = self.len().checked_add(cnt).unwrap();
+        self.resize(new_len, val);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_buf_nombytes() {
+        let mut frame = NomBytes::from(&[0, 5, b'h', b'i'][..]);
+        assert_eq!(frame.get_u16(), 5);
+        assert_eq!(frame.remaining(), 2);
+        assert_eq!(frame.get_u8(), b'h');
+        assert_eq!(frame, b"i");
+    }
+
+    #[test]
+    fn bytes_buf_mut() {
+        let mut cv: NomVec<u8> = NomVec::new();
+        cv.put_u8(1);
+        cv.put_u16(0x0203);
+        cv.put_slice(b"ab");
+        cv.put_bytes(0, 2);
+        cv.put(&b"cd"[..]);
+        assert_eq!(cv, [1, 2, 3, b'a', b'b', 0, 0, b'c', b'd']);
+
+        let chunk = cv.chunk_mut();
+        assert!(chunk.len() > 0);
+        chunk.write_byte(0, 9);
+        unsafe { cv.advance_mut(1) };
+        assert_eq!(cv.last(), Some(&9));
+    }
+
+    #[test]
+    fn bytes_buf() {
+        let mut cv = NomVec::from([0u8, 1, 0, 2, 7, 8]);
+        assert_eq!(cv.get_u8(), 0);
+        assert_eq!(cv.get_u8(), 1);
+        assert_eq!(cv.get_u16(), 2);
+        assert_eq!(cv.remaining(), 2);
+        assert_eq!(cv.chunk(), &[7, 8]);
+        cv.advance(2);
+        assert!(!cv.has_remaining());
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot advance past `remaining`")]
+    fn bytes_cant_advance() {
+        let mut cv = NomVec::from([1u8]);
+        cv.advance(2);
+    }
+}
@@ -0,0 +1,606 @@
+// A vector whose length and capacity are stored as a narrower integer `I`
+// (`u32` by default), so the handle is 16 bytes on 64-bit targets instead
+// of 24; handy when a graph or index structure holds millions of them. `I`
+// also types the length queries and index arguments, and growth past
+// `I::MAX` elements is rejected with `CapacityOverflow`. Converting to and
+// from `NomVec` reuses the buffer.
+use crate::{handle_error, AllocationError, Allocator, Global, NomVec};
+use std::alloc::Layout;
+use std::cmp;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::ptr::{self, NonNull};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+pub trait IndexWidth: Copy + Ord + Hash + fmt::Debug + sealed::Sealed {
+    const ZERO: Self;
+    // never more than `usize::MAX`, so `to_usize` is lossless
+    const MAX: Self;
+
+    fn to_usize(self) -> usize;
+    fn from_usize(n: usize) -> Option<Self>;
+}
+
+macro_rules! impl_index_width {
+    ($($t:ty),*) => {$(
+        impl sealed::Sealed for $t {}
+
+        impl IndexWidth for $t {
+            const ZERO: Self = 0;
+            const MAX: Self = if <$t>::MAX as u128 > usize::MAX as u128 {
+                usize::MAX as $t
+            } else {
+                <$t>::MAX
+            };
+
+            fn to_usize(self) -> usize {
+                self as usize
+            }
+
+            fn from_usize(n: usize) -> Option<Self> {
+                <$t>::try_from(n).ok().filter(|&n| n <= Self::MAX)
+            }
+        }
+    )*};
+}
+
+impl_index_width!(u8, u16, u32, u64, usize);
+
+// the caller has already checked that `n` is at most the capacity
+fn narrow<I: IndexWidth>(n: usize) -> I {
+    match I::from_usize(n) {
+        Some(n) => n,
+        None => unreachable!(),
+    }
+}
+
+pub struct CompactNomVec<T, A: Allocator = Global, I: IndexWidth = u32> {
+    ptr: NonNull<T>,
+    len: I,
+    cap: I,
+    alloc: A,
+}
+
+unsafe impl<T: Send, A: Allocator + Send, I: IndexWidth> Send
+    for CompactNomVec<T, A, I>
+{
+}
+unsafe impl<T: Sync, A: Allocator + Sync, I: IndexWidth> Sync
+    for CompactNomVec<T, A, I>
+{
+}
+
+impl<T, I: IndexWidth> CompactNomVec<T, Global, I> {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: I) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<T, A: Allocator, I: IndexWidth> CompactNomVec<T, A, I> {
+    pub const fn new_in(alloc: A) -> Self {
+        // as with RawVec, ZSTs are "full" from the start and never allocate
+        let cap = if mem::size_of::<T>() == 0 {
+            I::MAX
+        } else {
+            I::ZERO
+        };
+        CompactNomVec {
+            ptr: NonNull::dangling(),
+            len: I::ZERO,
+            cap,
+            alloc,
+        }
+    }
+
+    pub fn with_capacity_in(capacity: I, alloc: A) -> Self {
+        let mut vec = Self::new_in(alloc);
+        vec.reserve_exact(capacity);
+        vec
+    }
+
+    pub fn try_with_capacity_in(
+        capacity: I,
+        alloc: A,
+    ) -> Result<Self, AllocationError> {
+        let mut vec = Self::new_in(alloc);
+        vec.try_reserve_exact(capacity)?;
+        Ok(vec)
+    }
+
+    pub fn len(&self) -> I {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == I::ZERO
+    }
+
+    pub fn capacity(&self) -> I {
+        self.cap
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    fn len_usize(&self) -> usize {
+        self.len.to_usize()
+    }
+
+    fn cap_usize(&self) -> usize {
+        self.cap.to_usize()
+    }
+
+    fn set_len(&mut self, len: usize) {
+        self.len = narrow(len);
+    }
+
+    fn current_memory(&self) -> Option<(NonNull<u8>, Layout)> {
+        if mem::size_of::<T>() == 0 || self.cap == I::ZERO {
+            None
+        } else {
+            let layout = Layout::array::<T>(self.cap_usize()).unwrap();
+            Some((self.ptr.cast(), layout))
+        }
+    }
+
+    fn needs_to_grow(&self, additional: usize) -> bool {
+        additional > self.cap_usize() - self.len_usize()
+    }
+
+    // the smallest capacity that fits `additional` more elements, if `I`
+    // can represent it
+    fn required(&self, additional: usize) -> Result<usize, AllocationError> {
+        self.len_usize()
+            .checked_add(additional)
+            .filter(|&required| I::from_usize(required).is_some())
+            .ok_or(AllocationError::CapacityOverflow)
+    }
+
+    fn finish_grow(&mut self, new_cap: usize) -> Result<(), AllocationError> {
+        let new_layout = Layout::array::<T>(new_cap)
+            .map_err(|_| AllocationError::CapacityOverflow)?;
+        let new_ptr = match self.current_memory() {
+            Some((ptr, old_layout)) => unsafe {
+                self.alloc.grow(ptr, old_layout, new_layout)
+            },
+            None => self.alloc.allocate(new_layout),
+        };
+        self.ptr = new_ptr
+            .map_err(|_| AllocationError::AllocFailed { layout: new_layout })?
+            .cast();
+        self.cap = narrow(new_cap);
+        Ok(())
+    }
+
+    fn try_reserve_usize(
+        &mut self,
+        additional: usize,
+    ) -> Result<(), AllocationError> {
+        if !self.needs_to_grow(additional) {
+            return Ok(());
+        }
+        let required = self.required(additional)?;
+        let doubled =
+            cmp::min(self.cap_usize().saturating_mul(2), I::MAX.to_usize());
+        self.finish_grow(cmp::max(doubled, required))
+    }
+
+    pub fn try_reserve(
+        &mut self,
+        additional: I,
+    ) -> Result<(), AllocationError> {
+        self.try_reserve_usize(additional.to_usize())
+    }
+
+    pub fn try_reserve_exact(
+        &mut self,
+        additional: I,
+    ) -> Result<(), AllocationError> {
+        let additional = additional.to_usize();
+        if !self.needs_to_grow(additional) {
+            return Ok(());
+        }
+        let required = self.required(additional)?;
+        self.finish_grow(required)
+    }
+
+    pub fn reserve(&mut self, additional: I) {
+        if let Err(err) = self.try_reserve(additional) {
+            handle_error(err);
+        }
+    }
+
+    pub fn reserve_exact(&mut self, additional: I) {
+        if let Err(err) = self.try_reserve_exact(additional) {
+            handle_error(err);
+        }
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        let (ptr, layout) = match self.current_memory() {
+            Some(mem) if self.cap > self.len => mem,
+            _ => return,
+        };
+        unsafe {
+            if self.len == I::ZERO {
+                self.alloc.deallocate(ptr, layout);
+                self.ptr = NonNull::dangling();
+            } else {
+                let new_layout = Layout::array::<T>(self.len_usize()).unwrap();
+                match self.alloc.shrink(ptr, layout, new_layout) {
+                    Ok(new_ptr) => self.ptr = new_ptr.cast(),
+                    Err(_) => handle_error(AllocationError::AllocFailed {
+                        layout: new_layout,
+                    }),
+                }
+            }
+        }
+        self.cap = self.len;
+    }
+
+    pub fn try_push(&mut self, elem: T) -> Result<(), AllocationError> {
+        self.try_reserve_usize(1)?;
+        let len = self.len_usize();
+        unsafe {
+            ptr::write(self.ptr.as_ptr().add(len), elem);
+        }
+        self.set_len(len + 1);
+        Ok(())
+    }
+
+    pub fn push(&mut self, elem: T) {
+        if let Err(err) = self.try_push(elem) {
+            handle_error(err);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let len = self.len_usize();
+        if len == 0 {
+            None
+        } else {
+            self.set_len(len - 1);
+            unsafe { Some(ptr::read(self.ptr.as_ptr().add(len - 1))) }
+        }
+    }
+
+    pub fn insert(&mut self, index: I, elem: T) {
+        let (index, len) = (index.to_usize(), self.len_usize());
+        assert!(index <= len, "index out of bounds");
+        if let Err(err) = self.try_reserve_usize(1) {
+            handle_error(err);
+        }
+        unsafe {
+            let p = self.ptr.as_ptr().add(index);
+            ptr::copy(p, p.add(1), len - index);
+            ptr::write(p, elem);
+        }
+        self.set_len(len + 1);
+    }
+
+    pub fn remove(&mut self, index: I) -> T {
+        let (index, len) = (index.to_usize(), self.len_usize());
+        assert!(index < len, "index out of bounds");
+        self.set_len(len - 1);
+        unsafe {
+            let p = self.ptr.as_ptr().add(index);
+            let result = ptr::read(p);
+            ptr::copy(p.add(1), p, len - index - 1);
+            result
+        }
+    }
+
+    pub fn swap_remove(&mut self, index: I) -> T {
+        let index = index.to_usize();
+        assert!(index < self.len_usize(), "index out of bounds");
+        let last = self.len_usize() - 1;
+        self.swap(index, last);
+        self.pop().unwrap()
+    }
+
+    pub fn truncate(&mut self, len: I) {
+        let (len, old_len) = (len.to_usize(), self.len_usize());
+        if len >= old_len {
+            return;
+        }
+        self.set_len(len);
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                self.ptr.as_ptr().add(len),
+                old_len - len,
+            ));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(I::ZERO)
+    }
+
+    pub fn extend_from_slice(&mut self, other: &[T])
+    where
+        T: Clone,
+    {
+        if let Err(err) = self.try_reserve_usize(other.len()) {
+            handle_error(err);
+        }
+        for elem in other {
+            let len = self.len_usize();
+            unsafe {
+                ptr::write(self.ptr.as_ptr().add(len), elem.clone());
+            }
+            self.set_len(len + 1);
+        }
+    }
+
+    // hands the buffer over to a `NomVec` as-is
+    pub fn into_nomvec(self) -> NomVec<T, A> {
+        let this = mem::ManuallyDrop::new(self);
+        unsafe {
+            NomVec::from_raw_parts_in(
+                this.ptr.as_ptr(),
+                this.len_usize(),
+                this.cap_usize(),
+                ptr::read(&this.alloc),
+            )
+        }
+    }
+}
+
+impl<T, A: Allocator, I: IndexWidth> Drop for CompactNomVec<T, A, I> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(&mut self[..]);
+            if let Some((ptr, layout)) = self.current_memory() {
+                self.alloc.deallocate(ptr, layout);
+            }
+        }
+    }
+}
+
+// Fails with the untouched vector if its length or capacity doesn't fit in
+// an `I`.
+impl<T, A: Allocator, I: IndexWidth> TryFrom<NomVec<T, A>>
+    for CompactNomVec<T, A, I>
+{
+    type Error = NomVec<T, A>;
+
+    fn try_from(vec: NomVec<T, A>) -> Result<Self, NomVec<T, A>> {
+        let cap = if mem::size_of::<T>() == 0 {
+            Some(I::MAX)
+        } else {
+            I::from_usize(vec.capacity())
+        };
+        let (len, cap) = match (I::from_usize(vec.len()), cap) {
+            (Some(len), Some(cap)) => (len, cap),
+            _ => return Err(vec),
+        };
+        let (ptr, _, _, alloc) = vec.into_raw_parts_with_alloc();
+        Ok(CompactNomVec {
+            ptr: unsafe { NonNull::new_unchecked(ptr) },
+            len,
+            cap,
+            alloc,
+        })
+    }
+}
+
+impl<T, A: Allocator, I: IndexWidth> From<CompactNomVec<T, A, I>>
+    for NomVec<T, A>
+{
+    fn from(vec: CompactNomVec<T, A, I>) -> Self {
+        vec.into_nomvec()
+    }
+}
+
+impl<T, A: Allocator, I: IndexWidth> IntoIterator for CompactNomVec<T, A, I> {
+    type Item = T;
+    type IntoIter = crate::IntoIter<T, A>;
+
+    fn into_iter(self) -> crate::IntoIter<T, A> {
+        self.into_nomvec().into_iter()
+    }
+}
+
+impl<T, I: IndexWidth> Default for CompactNomVec<T, Global, I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, A: Allocator, I: IndexWidth> Deref for CompactNomVec<T, A, I> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        unsafe {
+            ::std::slice::from_raw_parts(self.ptr.as_ptr(), self.len_usize())
+        }
+    }
+}
+
+impl<T, A: Allocator, I: IndexWidth> DerefMut for CompactNomVec<T, A, I> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe {
+            ::std::slice::from_raw_parts_mut(
+                self.ptr.as_ptr(),
+                self.len_usize(),
+            )
+        }
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone, I: IndexWidth> Clone
+    for CompactNomVec<T, A, I>
+{
+    fn clone(&self) -> Self {
+        let mut vec =
+            CompactNomVec::with_capacity_in(self.len, self.alloc.clone());
+        vec.extend_from_slice(self);
+        vec
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator, I: IndexWidth> fmt::Debug
+    for CompactNomVec<T, A, I>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T, U, A, B, I, J> PartialEq<CompactNomVec<U, B, J>>
+    for CompactNomVec<T, A, I>
+where
+    T: PartialEq<U>,
+    A: Allocator,
+    B: Allocator,
+    I: IndexWidth,
+    J: IndexWidth,
+{
+    fn eq(&self, other: &CompactNomVec<U, B, J>) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T: PartialEq<U>, U, A: Allocator, I: IndexWidth> PartialEq<[U]>
+    for CompactNomVec<T, A, I>
+{
+    fn eq(&self, other: &[U]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T, U, A, I, const N: usize> PartialEq<[U; N]> for CompactNomVec<T, A, I>
+where
+    T: PartialEq<U>,
+    A: Allocator,
+    I: IndexWidth,
+{
+    fn eq(&self, other: &[U; N]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T: Eq, A: Allocator, I: IndexWidth> Eq for CompactNomVec<T, A, I> {}
+
+impl<T: Hash, A: Allocator, I: IndexWidth> Hash for CompactNomVec<T, A, I> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Hash::hash(&**self, state)
+    }
+}
+
+impl<T, A: Allocator, I: IndexWidth> Extend<T> for CompactNomVec<T, A, I> {
+    fn extend<It: IntoIterator<Item = T>>(&mut self, iter: It) {
+        let iter = iter.into_iter();
+        let (lower, _) = iter.size_hint();
+        // the hint is only a guess, so too big a one isn't an error yet
+        let _ = self.try_reserve_usize(lower);
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T, I: IndexWidth> FromIterator<T> for CompactNomVec<T, Global, I> {
+    fn from_iter<It: IntoIterator<Item = T>>(iter: It) -> Self {
+        let mut vec = CompactNomVec::new();
+        vec.extend(iter);
+        vec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_is_16_bytes() {
+        if mem::size_of::<usize>() == 8 {
+            assert_eq!(mem::size_of::<CompactNomVec<u64>>(), 16);
+        }
+    }
+
+    #[test]
+    fn compact_basic_ops() {
+        let mut cv: CompactNomVec<i32> = (0..5).collect();
+        assert_eq!(cv.len(), 5);
+        cv.insert(0, -1);
+        assert_eq!(cv.remove(1), 0);
+        assert_eq!(cv.swap_remove(0), -1);
+        assert_eq!(cv, [4, 1, 2, 3]);
+        cv.truncate(2);
+        cv.shrink_to_fit();
+        assert_eq!(cv.capacity(), 2);
+        assert_eq!(cv.clone(), cv);
+        assert_eq!(cv.into_iter().collect::<Vec<_>>(), [4, 1]);
+    }
+
+    #[test]
+    fn compact_rejects_u32_overflow() {
+        let mut zsts: CompactNomVec<()> = CompactNomVec::new();
+        assert_eq!(zsts.capacity(), u32::MAX);
+        zsts.len = u32::MAX;
+        assert_eq!(zsts.try_push(()), Err(AllocationError::CapacityOverflow));
+        zsts.len = 0;
+
+        let mut bytes: CompactNomVec<u8> = CompactNomVec::new();
+        bytes.push(1);
+        assert_eq!(
+            bytes.try_reserve(u32::MAX),
+            Err(AllocationError::CapacityOverflow)
+        );
+    }
+
+    #[test]
+    fn compact_index_widths() {
+        let mut small: CompactNomVec<u8, Global, u8> = CompactNomVec::new();
+        let len: u8 = small.len();
+        assert_eq!(len, 0);
+        small.extend(0..254);
+        assert_eq!(small.capacity(), 254);
+        // doubling is clamped to what a u8 can count
+        small.push(254);
+        assert_eq!(small.capacity(), u8::MAX);
+        assert_eq!(small.try_push(0), Err(AllocationError::CapacityOverflow));
+        assert_eq!(small.len(), u8::MAX);
+
+        let mut mid: CompactNomVec<u8, Global, u16> = CompactNomVec::new();
+        mid.reserve(1000);
+        let cap: u16 = mid.capacity();
+        assert_eq!(cap, 1000);
+        assert_eq!(mid.try_reserve(u16::MAX), Ok(()));
+        mid.push(0);
+        assert_eq!(
+            mid.try_reserve(u16::MAX),
+            Err(AllocationError::CapacityOverflow)
+        );
+
+        let wide: CompactNomVec<u8, Global, usize> = (0..10).collect();
+        assert_eq!(wide.len(), 10usize);
+        assert!(CompactNomVec::<u8, Global, u8>::try_from(NomVec::from(
+            [0u8; 300]
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn compact_nomvec_roundtrip() {
+        let vec = NomVec::from([1u16, 2, 3]);
+        let ptr = vec.as_ptr();
+        let compact: CompactNomVec<u16> = CompactNomVec::try_from(vec).unwrap();
+        assert_eq!(compact.as_ptr(), ptr);
+        let back: NomVec<u16> = compact.into();
+        assert_eq!(back.as_ptr(), ptr);
+        assert_eq!(back, [1, 2, 3]);
+    }
+}
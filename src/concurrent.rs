@@ -0,0 +1,326 @@
+// An append-only vector that many threads can push to at once, and read
+// from while they do, without locks (the same design as the `boxcar`
+// crate). A push claims an index with one atomic increment, writes its
+// value into that slot and then flags it ready; readers only look at slots
+// that are flagged. Pushes can finish out of order, so a slot below `len`
+// may still be empty for a moment, and reads of it return None until then.
+//
+// Slots live in buckets that are never reallocated, each twice the size of
+// the last, whose pointers sit in a fixed array. A bucket is allocated by
+// whichever thread first needs it (racing threads agree with a CAS), and a
+// push that gets close to the end of its bucket allocates the next one
+// early, so that most pushes never wait on the allocator.
+use crate::{Allocator, Global, NomVec};
+use std::alloc::{self, Layout};
+use std::array;
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ops::Index;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+// log2 of the first bucket's slot count
+const FIRST_BITS: u32 = 5;
+const FIRST: usize = 1 << FIRST_BITS;
+// enough buckets for every index a usize can hold
+const BUCKETS: usize = (usize::BITS - FIRST_BITS + 1) as usize;
+
+struct Slot<T> {
+    ready: AtomicBool,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// The bucket holding `index`, and its position there.
+fn locate(index: usize) -> (usize, usize) {
+    let biased = index / FIRST + 1;
+    let bucket = (usize::BITS - 1 - biased.leading_zeros()) as usize;
+    (bucket, index - FIRST * ((1 << bucket) - 1))
+}
+
+fn bucket_len(bucket: usize) -> usize {
+    FIRST << bucket
+}
+
+fn bucket_layout<T>(bucket: usize) -> Layout {
+    Layout::array::<Slot<T>>(bucket_len(bucket)).expect("capacity overflow")
+}
+
+pub struct ConcurrentNomVec<T, A: Allocator = Global> {
+    buckets: [AtomicPtr<Slot<T>>; BUCKETS],
+    // indices handed out to pushes, some of which may still be writing
+    reserved: AtomicUsize,
+    // pushes that have finished
+    len: AtomicUsize,
+    alloc: A,
+    _marker: PhantomData<T>,
+}
+
+// Pushes move values in from any thread, and reads share them with all of
+// them.
+unsafe impl<T: Send, A: Allocator + Send> Send for ConcurrentNomVec<T, A> {}
+unsafe impl<T: Send + Sync, A: Allocator + Sync> Sync
+    for ConcurrentNomVec<T, A>
+{
+}
+
+impl<T> ConcurrentNomVec<T> {
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<T, A: Allocator> ConcurrentNomVec<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        ConcurrentNomVec {
+            buckets: array::from_fn(|_| AtomicPtr::new(ptr::null_mut())),
+            reserved: AtomicUsize::new(0),
+            len: AtomicUsize::new(0),
+            alloc,
+            _marker: PhantomData,
+        }
+    }
+
+    // allocates every bucket the first `capacity` pushes will need
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        let vec = Self::new_in(alloc);
+        if capacity > 0 {
+            let (last, _) = locate(capacity - 1);
+            for bucket in 0..=last {
+                vec.bucket(bucket);
+            }
+        }
+        vec
+    }
+
+    // the number of finished pushes
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    // The bucket's slots, allocating them if no one has yet.
+    fn bucket(&self, bucket: usize) -> NonNull<Slot<T>> {
+        let current = self.buckets[bucket].load(Ordering::Acquire);
+        if let Some(slots) = NonNull::new(current) {
+            return slots;
+        }
+        let layout = bucket_layout::<T>(bucket);
+        // zeroed memory is a run of slots that aren't ready
+        let new = match self.alloc.allocate_zeroed(layout) {
+            Ok(new) => new.cast::<Slot<T>>(),
+            Err(_) => alloc::handle_alloc_error(layout),
+        };
+        match self.buckets[bucket].compare_exchange(
+            ptr::null_mut(),
+            new.as_ptr(),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => new,
+            Err(winner) => {
+                unsafe { self.alloc.deallocate(new.cast(), layout) };
+                unsafe { NonNull::new_unchecked(winner) }
+            }
+        }
+    }
+
+    // Appends `value`, returning its index.
+    pub fn push(&self, value: T) -> usize {
+        let index = self.reserved.fetch_add(1, Ordering::Relaxed);
+        let (bucket, offset) = locate(index);
+        let slots = self.bucket(bucket);
+        let len = bucket_len(bucket);
+        if offset == len - len / 8 && bucket + 1 < BUCKETS {
+            self.bucket(bucket + 1);
+        }
+        unsafe {
+            // the index is ours alone, so no one else touches this slot
+            // until it's flagged
+            let slot = &*slots.as_ptr().add(offset);
+            (*slot.value.get()).write(value);
+            slot.ready.store(true, Ordering::Release);
+        }
+        self.len.fetch_add(1, Ordering::Release);
+        index
+    }
+
+    fn slot(&self, index: usize) -> Option<&Slot<T>> {
+        let (bucket, offset) = locate(index);
+        let slots = self.buckets[bucket].load(Ordering::Acquire);
+        if slots.is_null() {
+            return None;
+        }
+        let slot = unsafe { &*slots.add(offset) };
+        if slot.ready.load(Ordering::Acquire) {
+            Some(slot)
+        } else {
+            None
+        }
+    }
+
+    // None if nothing has been pushed at `index`, or the push hasn't
+    // finished yet.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let slot = self.slot(index)?;
+        unsafe { Some((*slot.value.get()).assume_init_ref()) }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let slot = self.slot(index)?;
+        unsafe { Some((*slot.value.get()).assume_init_mut()) }
+    }
+
+    // The finished pushes with their indices, in index order, up to the
+    // last index claimed when the iterator was made.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> + '_ {
+        let end = self.reserved.load(Ordering::Acquire);
+        (0..end).filter_map(move |i| Some((i, self.get(i)?)))
+    }
+
+    // The values, in index order.
+    pub fn into_vec(mut self) -> NomVec<T, A>
+    where
+        A: Clone,
+    {
+        let mut vec =
+            NomVec::with_capacity_in(*self.len.get_mut(), self.alloc.clone());
+        for index in 0..*self.reserved.get_mut() {
+            if let Some(slot) = self.slot(index) {
+                slot.ready.store(false, Ordering::Relaxed);
+                vec.push(unsafe { (*slot.value.get()).assume_init_read() });
+            }
+        }
+        vec
+    }
+}
+
+impl<T, A: Allocator> Drop for ConcurrentNomVec<T, A> {
+    fn drop(&mut self) {
+        for (bucket, slots) in self.buckets.iter_mut().enumerate() {
+            let slots = *slots.get_mut();
+            if slots.is_null() {
+                // later buckets may have been made early, so keep looking
+                continue;
+            }
+            unsafe {
+                for offset in 0..bucket_len(bucket) {
+                    let slot = &mut *slots.add(offset);
+                    if *slot.ready.get_mut() {
+                        slot.value.get_mut().assume_init_drop();
+                    }
+                }
+                let slots = NonNull::new_unchecked(slots).cast();
+                self.alloc.deallocate(slots, bucket_layout::<T>(bucket));
+            }
+        }
+    }
+}
+
+impl<T> Default for ConcurrentNomVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator> fmt::Debug for ConcurrentNomVec<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<T, A: Allocator> Index<usize> for ConcurrentNomVec<T, A> {
+    type Output = T;
+
+    // Panics if there's no value at `index` yet.
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("no value at index")
+    }
+}
+
+impl<T, A: Allocator> Extend<T> for ConcurrentNomVec<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for ConcurrentNomVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut cv = ConcurrentNomVec::new();
+        cv.extend(iter);
+        cv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn concurrent_pushes_from_many_threads() {
+        let log = ConcurrentNomVec::new();
+        thread::scope(|s| {
+            for t in 0..8 {
+                let log = &log;
+                s.spawn(move || {
+                    for i in 0..2000 {
+                        let index = log.push((t, i));
+                        assert_eq!(log[index], (t, i));
+                    }
+                });
+            }
+            // read alongside the writers
+            s.spawn(|| {
+                while log.len() < 16000 {
+                    for (index, &(t, i)) in log.iter() {
+                        assert_eq!(log.get(index), Some(&(t, i)));
+                    }
+                }
+            });
+        });
+        assert_eq!(log.len(), 16000);
+        let mut values = log.into_vec();
+        assert_eq!(values.len(), 16000);
+        values.sort_unstable();
+        assert!(values
+            .iter()
+            .copied()
+            .eq((0..8).flat_map(|t| (0..2000).map(move |i| (t, i)))));
+    }
+
+    #[test]
+    fn concurrent_drops_values() {
+        let value = Arc::new(());
+        let cv = ConcurrentNomVec::with_capacity(100);
+        for _ in 0..100 {
+            cv.push(value.clone());
+        }
+        assert_eq!(Arc::strong_count(&value), 101);
+        assert!(cv.get(100).is_none());
+        drop(cv);
+        assert_eq!(Arc::strong_count(&value), 1);
+
+        assert_eq!(locate(31), (0, 31));
+        assert_eq!(locate(32), (1, 0));
+        assert_eq!(locate(usize::MAX).0, BUCKETS - 1);
+        let cv: ConcurrentNomVec<_> = (0..3).collect();
+        assert_eq!(format!("{:?}", cv), "{0: 0, 1: 1, 2: 2}");
+    }
+}
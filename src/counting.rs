@@ -0,0 +1,246 @@
+// An allocator wrapper that counts what goes through it, for tests and
+// profiling: how many allocations, grows, shrinks and deallocations
+// succeeded, and how many bytes were handed out and given back. Grows and
+// shrinks count only the bytes they add or remove. The counters are
+// atomic, so one wrapper can be shared between threads; read them with
+// `stats`, and allocate through `&CountingAllocator` to keep hold of it.
+use crate::{AllocError, Allocator, Global};
+use std::alloc::Layout;
+use std::fmt;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// A snapshot of a CountingAllocator's counters.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    pub allocations: usize,
+    pub deallocations: usize,
+    pub grows: usize,
+    pub shrinks: usize,
+    pub bytes_allocated: usize,
+    pub bytes_deallocated: usize,
+}
+
+impl AllocStats {
+    // the bytes currently allocated, or since a `reset` the net change
+    // (floored at zero)
+    pub fn live_bytes(&self) -> usize {
+        self.bytes_allocated.saturating_sub(self.bytes_deallocated)
+    }
+
+    // grows and shrinks together
+    pub fn reallocations(&self) -> usize {
+        self.grows + self.shrinks
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    allocations: AtomicUsize,
+    deallocations: AtomicUsize,
+    grows: AtomicUsize,
+    shrinks: AtomicUsize,
+    bytes_allocated: AtomicUsize,
+    bytes_deallocated: AtomicUsize,
+}
+
+fn bump(counter: &AtomicUsize, n: usize) {
+    counter.fetch_add(n, Ordering::Relaxed);
+}
+
+#[derive(Default)]
+pub struct CountingAllocator<A: Allocator = Global> {
+    counters: Counters,
+    alloc: A,
+}
+
+impl CountingAllocator {
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<A: Allocator> CountingAllocator<A> {
+    pub fn new_in(alloc: A) -> Self {
+        CountingAllocator {
+            counters: Counters::default(),
+            alloc,
+        }
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    pub fn into_inner(self) -> A {
+        self.alloc
+    }
+
+    pub fn stats(&self) -> AllocStats {
+        let c = &self.counters;
+        let load = |counter: &AtomicUsize| counter.load(Ordering::Relaxed);
+        AllocStats {
+            allocations: load(&c.allocations),
+            deallocations: load(&c.deallocations),
+            grows: load(&c.grows),
+            shrinks: load(&c.shrinks),
+            bytes_allocated: load(&c.bytes_allocated),
+            bytes_deallocated: load(&c.bytes_deallocated),
+        }
+    }
+
+    // Zeroes every counter; blocks allocated before this still count
+    // towards `bytes_deallocated` when they're freed.
+    pub fn reset(&self) {
+        let c = &self.counters;
+        for counter in [
+            &c.allocations,
+            &c.deallocations,
+            &c.grows,
+            &c.shrinks,
+            &c.bytes_allocated,
+            &c.bytes_deallocated,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn count_allocation(
+        &self,
+        layout: Layout,
+        result: Result<NonNull<[u8]>, AllocError>,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if result.is_ok() {
+            bump(&self.counters.allocations, 1);
+            bump(&self.counters.bytes_allocated, layout.size());
+        }
+        result
+    }
+
+    fn count_grow(
+        &self,
+        old_layout: Layout,
+        new_layout: Layout,
+        result: Result<NonNull<[u8]>, AllocError>,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if result.is_ok() {
+            bump(&self.counters.grows, 1);
+            bump(
+                &self.counters.bytes_allocated,
+                new_layout.size() - old_layout.size(),
+            );
+        }
+        result
+    }
+}
+
+impl<A: Allocator> fmt::Debug for CountingAllocator<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CountingAllocator")
+            .field(&self.stats())
+            .finish()
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for CountingAllocator<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.count_allocation(layout, self.alloc.allocate(layout))
+    }
+
+    fn allocate_zeroed(
+        &self,
+        layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.count_allocation(layout, self.alloc.allocate_zeroed(layout))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        bump(&self.counters.deallocations, 1);
+        bump(&self.counters.bytes_deallocated, layout.size());
+        self.alloc.deallocate(ptr, layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.grow(ptr, old_layout, new_layout);
+        self.count_grow(old_layout, new_layout, result)
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.grow_zeroed(ptr, old_layout, new_layout);
+        self.count_grow(old_layout, new_layout, result)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.shrink(ptr, old_layout, new_layout);
+        if result.is_ok() {
+            bump(&self.counters.shrinks, 1);
+            bump(
+                &self.counters.bytes_deallocated,
+                old_layout.size() - new_layout.size(),
+            );
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NomVec;
+
+    #[test]
+    fn counting_one_reallocation() {
+        let counting = CountingAllocator::new();
+        let mut cv = NomVec::with_capacity_in(4, &counting);
+        cv.extend(0u32..4);
+        counting.reset();
+        cv.extend_from_slice(&[4, 5, 6, 7, 8]);
+        let stats = counting.stats();
+        assert_eq!(stats.reallocations(), 1);
+        assert_eq!(stats.allocations, 0);
+        assert_eq!(
+            stats.bytes_allocated,
+            (cv.capacity() - 4) * std::mem::size_of::<u32>()
+        );
+    }
+
+    #[test]
+    fn counting_tracks_live_bytes() {
+        let counting = CountingAllocator::new();
+        let mut cv: NomVec<u8, _> = NomVec::with_capacity_in(100, &counting);
+        cv.push(1);
+        cv.shrink_to_fit();
+        assert_eq!(counting.stats().live_bytes(), 1);
+        drop(cv);
+        let stats = counting.stats();
+        assert_eq!(
+            stats,
+            AllocStats {
+                allocations: 1,
+                deallocations: 1,
+                grows: 0,
+                shrinks: 1,
+                bytes_allocated: 100,
+                bytes_deallocated: 100,
+            }
+        );
+        assert_eq!(stats.live_bytes(), 0);
+        assert!(format!("{:?}", counting)
+            .starts_with("CountingAllocator(AllocStats {"));
+    }
+}
@@ -0,0 +1,273 @@
+// A NomVec behind a reference count: clones share one buffer, and the first
+// mutation through a handle that isn't the only one copies the buffer for
+// it. Reads never copy, so a pipeline that fans one input out to many
+// stages only pays for the stages that actually change it.
+use crate::shared::Shared;
+use crate::{Allocator, Global, NomVec};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::ops::Deref;
+use std::slice;
+
+pub struct CowNomVec<T, A: Allocator + Clone = Global> {
+    inner: Shared<NomVec<T, A>, A>,
+}
+
+impl<T> CowNomVec<T> {
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<T, A: Allocator + Clone> CowNomVec<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        Self::from_vec(NomVec::new_in(alloc))
+    }
+
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        Self::from_vec(NomVec::with_capacity_in(capacity, alloc))
+    }
+
+    pub fn from_vec(vec: NomVec<T, A>) -> Self {
+        let alloc = vec.allocator().clone();
+        CowNomVec {
+            inner: Shared::new_in(vec, alloc),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.inner.allocator()
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.inner
+    }
+
+    // whether some other handle shares this one's buffer
+    pub fn is_shared(&self) -> bool {
+        !self.inner.is_unique()
+    }
+
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.inner.ptr_eq(&other.inner)
+    }
+
+    // The buffer, copied first if it's shared.
+    pub fn to_mut(&mut self) -> &mut NomVec<T, A>
+    where
+        T: Clone,
+    {
+        Shared::make_mut(&mut self.inner)
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T]
+    where
+        T: Clone,
+    {
+        self.to_mut()
+    }
+
+    // The buffer, copied only if it's shared.
+    pub fn into_vec(self) -> NomVec<T, A>
+    where
+        T: Clone,
+    {
+        match Shared::try_unwrap(self.inner) {
+            Ok(vec) => vec,
+            Err(shared) => (*shared).clone(),
+        }
+    }
+
+    pub fn push(&mut self, elem: T)
+    where
+        T: Clone,
+    {
+        self.to_mut().push(elem)
+    }
+
+    pub fn pop(&mut self) -> Option<T>
+    where
+        T: Clone,
+    {
+        if self.is_empty() {
+            return None;
+        }
+        self.to_mut().pop()
+    }
+
+    pub fn insert(&mut self, index: usize, elem: T)
+    where
+        T: Clone,
+    {
+        self.to_mut().insert(index, elem)
+    }
+
+    pub fn remove(&mut self, index: usize) -> T
+    where
+        T: Clone,
+    {
+        self.to_mut().remove(index)
+    }
+
+    pub fn truncate(&mut self, len: usize)
+    where
+        T: Clone,
+    {
+        if len < self.len() {
+            self.to_mut().truncate(len)
+        }
+    }
+
+    // Drops this handle's share of the buffer rather than copying it just to
+    // empty the copy.
+    pub fn clear(&mut self) {
+        if let Some(vec) = Shared::get_mut(&mut self.inner) {
+            vec.clear();
+        } else {
+            *self = Self::new_in(self.allocator().clone());
+        }
+    }
+}
+
+impl<T> Default for CowNomVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// O(1): bumps the reference count
+impl<T, A: Allocator + Clone> Clone for CowNomVec<T, A> {
+    fn clone(&self) -> Self {
+        CowNomVec {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T, A: Allocator + Clone> Deref for CowNomVec<T, A> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        &self.inner
+    }
+}
+
+impl<T, A: Allocator + Clone> AsRef<[T]> for CowNomVec<T, A> {
+    fn as_ref(&self) -> &[T] {
+        &self.inner
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator + Clone> fmt::Debug for CowNomVec<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+// no `ptr_eq` shortcut: a shared buffer holding a NaN is still unequal to
+// itself, as the slices would be
+impl<T: PartialEq, A: Allocator + Clone> PartialEq for CowNomVec<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T: Eq, A: Allocator + Clone> Eq for CowNomVec<T, A> {}
+
+impl<T: Hash, A: Allocator + Clone> Hash for CowNomVec<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self[..].hash(state)
+    }
+}
+
+impl<T, A: Allocator + Clone> From<NomVec<T, A>> for CowNomVec<T, A> {
+    fn from(vec: NomVec<T, A>) -> Self {
+        Self::from_vec(vec)
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Extend<T> for CowNomVec<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.to_mut().extend(iter)
+    }
+}
+
+impl<T> FromIterator<T> for CowNomVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_vec(iter.into_iter().collect())
+    }
+}
+
+impl<'a, T, A: Allocator + Clone> IntoIterator for &'a CowNomVec<T, A> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cow_clones_share_until_written() {
+        let input: CowNomVec<String> = (0..4).map(|i| i.to_string()).collect();
+        let readers: Vec<_> = (0..3).map(|_| input.clone()).collect();
+        assert!(readers.iter().all(|r| r.ptr_eq(&input)));
+        assert!(input.is_shared());
+
+        let mut writer = input.clone();
+        writer.push("4".to_string());
+        writer.as_mut_slice()[0].push('!');
+        assert!(!writer.ptr_eq(&input));
+        assert!(!writer.is_shared());
+        assert_eq!(
+            writer,
+            CowNomVec::from(NomVec::from(
+                ["0!", "1", "2", "3", "4"].map(String::from)
+            ))
+        );
+        assert_eq!(input.len(), 4);
+        assert_eq!(input[0], "0");
+        assert!(readers.iter().all(|r| *r == input));
+    }
+
+    #[test]
+    fn cow_clear_and_into_vec() {
+        let mut a = CowNomVec::from(NomVec::from([1, 2, 3]));
+        let mut b = a.clone();
+        b.clear();
+        assert!(b.is_empty());
+        assert_eq!(a, CowNomVec::from(NomVec::from([1, 2, 3])));
+        let nan = CowNomVec::from(NomVec::from([f64::NAN]));
+        assert!(nan != nan.clone());
+
+        // the only handle gives its buffer back without copying
+        let ptr = a.as_ptr();
+        let vec = a.clone().into_vec();
+        assert_ne!(vec.as_ptr(), ptr);
+        a.truncate(2);
+        assert_eq!(a.as_ptr(), ptr);
+        let vec = a.into_vec();
+        assert_eq!(vec.as_ptr(), ptr);
+        assert_eq!(vec, [1, 2]);
+        assert_eq!(b.pop(), None);
+    }
+}
@@ -0,0 +1,288 @@
+// A cursor for editing a NomVec in place. While it's alive the vector is a
+// gap buffer: the elements before the cursor stay at the front, the ones
+// from the cursor on are moved to the back of the allocation, and the spare
+// capacity sits in between. Moving the cursor by one step, or inserting or
+// removing next to it, only moves a single element; the gap is closed again
+// when the cursor is dropped.
+use crate::{Allocator, GrowthPolicy, NomVec};
+use std::ptr;
+use std::slice;
+
+pub struct CursorMut<'a, T: 'a, A: Allocator, G: GrowthPolicy> {
+    // `vec.len` counts the elements before the gap, as with Drain: if the
+    // cursor is leaked, so are the ones after it
+    vec: &'a mut NomVec<T, A, G>,
+    tail_start: usize,
+    tail_len: usize,
+}
+
+impl<T, A: Allocator, G: GrowthPolicy> NomVec<T, A, G> {
+    // A cursor on the element at `index` (or past the end, if `index` is
+    // the length). Panics if `index > len`.
+    pub fn cursor_mut(&mut self, index: usize) -> CursorMut<'_, T, A, G> {
+        assert!(index <= self.len, "index out of bounds");
+        let tail_len = self.len - index;
+        let tail_start = self.cap() - tail_len;
+        unsafe {
+            ptr::copy(
+                self.ptr().add(index),
+                self.ptr().add(tail_start),
+                tail_len,
+            );
+        }
+        self.len = index;
+        CursorMut {
+            vec: self,
+            tail_start,
+            tail_len,
+        }
+    }
+}
+
+impl<'a, T, A: Allocator, G: GrowthPolicy> CursorMut<'a, T, A, G> {
+    // the position of the current element
+    pub fn index(&self) -> usize {
+        self.vec.len
+    }
+
+    // the number of elements in the vector as a whole
+    pub fn len(&self) -> usize {
+        self.vec.len + self.tail_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn ptr(&self) -> *mut T {
+        self.vec.ptr()
+    }
+
+    // the elements before the cursor
+    pub fn before(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr(), self.vec.len) }
+    }
+
+    // the current element and everything after it
+    pub fn after(&self) -> &[T] {
+        unsafe {
+            slice::from_raw_parts(
+                self.ptr().add(self.tail_start),
+                self.tail_len,
+            )
+        }
+    }
+
+    fn after_mut(&mut self) -> &mut [T] {
+        unsafe {
+            slice::from_raw_parts_mut(
+                self.ptr().add(self.tail_start),
+                self.tail_len,
+            )
+        }
+    }
+
+    // `None` once the cursor is past the end
+    pub fn current(&mut self) -> Option<&mut T> {
+        self.after_mut().first_mut()
+    }
+
+    pub fn peek_next(&mut self) -> Option<&mut T> {
+        self.after_mut().get_mut(1)
+    }
+
+    pub fn peek_prev(&mut self) -> Option<&mut T> {
+        match self.vec.len {
+            0 => None,
+            len => unsafe { Some(&mut *self.ptr().add(len - 1)) },
+        }
+    }
+
+    // Steps to the next element; returns false, staying put, if the cursor
+    // is already past the end.
+    pub fn move_next(&mut self) -> bool {
+        if self.tail_len == 0 {
+            return false;
+        }
+        unsafe {
+            ptr::copy(
+                self.ptr().add(self.tail_start),
+                self.ptr().add(self.vec.len),
+                1,
+            );
+        }
+        self.vec.len += 1;
+        self.tail_start += 1;
+        self.tail_len -= 1;
+        true
+    }
+
+    // Steps to the previous element; returns false if the cursor is at the
+    // start.
+    pub fn move_prev(&mut self) -> bool {
+        if self.vec.len == 0 {
+            return false;
+        }
+        self.vec.len -= 1;
+        self.tail_start -= 1;
+        self.tail_len += 1;
+        unsafe {
+            ptr::copy(
+                self.ptr().add(self.vec.len),
+                self.ptr().add(self.tail_start),
+                1,
+            );
+        }
+        true
+    }
+
+    // makes sure the gap has room for at least one more element
+    fn reserve_gap(&mut self) {
+        if self.vec.len < self.tail_start {
+            return;
+        }
+        let old_cap = self.vec.cap();
+        self.vec.buf.reserve(self.len(), 1);
+        let new_tail_start = self.vec.cap() - self.tail_len;
+        unsafe {
+            ptr::copy(
+                self.ptr().add(self.tail_start),
+                self.ptr().add(new_tail_start),
+                self.tail_len,
+            );
+        }
+        debug_assert!(self.vec.cap() > old_cap);
+        self.tail_start = new_tail_start;
+    }
+
+    // Inserts `elem` just before the cursor, which stays on the same
+    // element.
+    pub fn insert_before(&mut self, elem: T) {
+        self.reserve_gap();
+        unsafe {
+            ptr::write(self.ptr().add(self.vec.len), elem);
+        }
+        self.vec.len += 1;
+    }
+
+    // Inserts `elem` just after the current element, or at the cursor if
+    // it's past the end (in which case `elem` becomes the current element).
+    pub fn insert_after(&mut self, elem: T) {
+        self.reserve_gap();
+        unsafe {
+            self.tail_start -= 1;
+            self.tail_len += 1;
+            let p = self.ptr().add(self.tail_start);
+            if self.tail_len == 1 {
+                ptr::write(p, elem);
+            } else {
+                ptr::copy(p.add(1), p, 1);
+                ptr::write(p.add(1), elem);
+            }
+        }
+    }
+
+    // Removes and returns the current element; the cursor moves on to the
+    // one after it.
+    pub fn remove_current(&mut self) -> Option<T> {
+        if self.tail_len == 0 {
+            return None;
+        }
+        let elem = unsafe { ptr::read(self.ptr().add(self.tail_start)) };
+        self.tail_start += 1;
+        self.tail_len -= 1;
+        Some(elem)
+    }
+
+    // Removes and returns the element before the cursor.
+    pub fn remove_prev(&mut self) -> Option<T> {
+        self.vec.pop()
+    }
+}
+
+impl<'a, T, A: Allocator, G: GrowthPolicy> Drop for CursorMut<'a, T, A, G> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::copy(
+                self.ptr().add(self.tail_start),
+                self.ptr().add(self.vec.len),
+                self.tail_len,
+            );
+        }
+        self.vec.len += self.tail_len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_walk_and_edit() {
+        let mut cv: NomVec<i32> = (0..5).collect();
+        {
+            let mut cursor = cv.cursor_mut(2);
+            assert_eq!(cursor.current(), Some(&mut 2));
+            assert_eq!(cursor.peek_prev(), Some(&mut 1));
+            assert_eq!(cursor.peek_next(), Some(&mut 3));
+            assert_eq!(cursor.before(), [0, 1]);
+            assert_eq!(cursor.after(), [2, 3, 4]);
+
+            cursor.insert_before(10);
+            assert_eq!(cursor.index(), 3);
+            cursor.insert_after(20);
+            assert_eq!(cursor.after(), [2, 20, 3, 4]);
+            assert_eq!(cursor.remove_current(), Some(2));
+            assert_eq!(cursor.current(), Some(&mut 20));
+
+            assert!(cursor.move_next());
+            assert!(cursor.move_next());
+            *cursor.current().unwrap() = 40;
+            assert!(cursor.move_next());
+            assert!(!cursor.move_next());
+            assert_eq!(cursor.current(), None);
+            cursor.insert_after(50);
+            assert_eq!(cursor.current(), Some(&mut 50));
+
+            while cursor.move_prev() {}
+            assert_eq!(cursor.index(), 0);
+            assert_eq!(cursor.remove_prev(), None);
+            assert_eq!(cursor.len(), 7);
+        }
+        assert_eq!(cv, [0, 1, 10, 20, 3, 40, 50]);
+    }
+
+    #[test]
+    fn cursor_grows_through_the_gap() {
+        let mut cv: NomVec<String> = NomVec::new();
+        {
+            let mut cursor = cv.cursor_mut(0);
+            for i in 0..100 {
+                cursor.insert_before(i.to_string());
+                if i % 10 == 0 {
+                    cursor.insert_after(format!("after {}", i));
+                }
+            }
+            assert_eq!(cursor.after().len(), 10);
+            assert_eq!(cursor.after()[0], "after 0");
+            // each one lands right after the current element
+            assert_eq!(cursor.after()[1], "after 90");
+            assert_eq!(cursor.after()[9], "after 10");
+        }
+        assert_eq!(cv.len(), 110);
+        assert_eq!(cv[99], "99");
+        assert_eq!(cv[100], "after 0");
+
+        // a cursor that touches nothing leaves the vector as it was
+        let before = cv.clone();
+        drop(cv.cursor_mut(50));
+        assert_eq!(cv, before);
+
+        let mut zsts = NomVec::from([(), ()]);
+        let mut cursor = zsts.cursor_mut(1);
+        cursor.insert_before(());
+        assert_eq!(cursor.remove_current(), Some(()));
+        drop(cursor);
+        assert_eq!(zsts.len(), 2);
+    }
+}
@@ -0,0 +1,730 @@
+// A double-ended queue: a ring buffer over a `RawVec`, so it takes an
+// allocator just like `NomVec`. The elements occupy `len` slots starting at
+// `head`, wrapping around the end of the buffer, which makes pushes and pops
+// at either end O(1).
+use crate::{
+    handle_error, slice_range, AllocationError, Allocator, Doubling, Global,
+    NomVec, RawVec,
+};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, FusedIterator};
+use std::mem::ManuallyDrop;
+use std::ops::{Index, IndexMut, Range, RangeBounds};
+use std::ptr;
+use std::slice;
+
+pub struct NomVecDeque<T, A: Allocator = Global> {
+    buf: RawVec<T, A>,
+    head: usize,
+    len: usize,
+}
+
+impl<T> NomVecDeque<T> {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<T, A: Allocator> NomVecDeque<T, A> {
+    pub const fn new_in(alloc: A) -> Self {
+        NomVecDeque {
+            buf: RawVec::new_in(alloc, Doubling),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        NomVecDeque {
+            buf: RawVec::with_capacity_in(capacity, alloc, Doubling),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn ptr(&self) -> *mut T {
+        self.buf.ptr.as_ptr()
+    }
+
+    fn cap(&self) -> usize {
+        self.buf.cap
+    }
+
+    // the buffer slot holding logical index `idx`, for `idx <= cap`
+    fn to_physical(&self, idx: usize) -> usize {
+        let idx = self.head.wrapping_add(idx);
+        if idx >= self.cap() {
+            idx.wrapping_sub(self.cap())
+        } else {
+            idx
+        }
+    }
+
+    fn is_contiguous(&self) -> bool {
+        self.head <= self.cap() - self.len
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.cap()
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.buf.alloc
+    }
+
+    // After the RawVec grows, the wrapped-around part of the ring is no
+    // longer next to the rest: move whichever piece is shorter so the
+    // elements are in order again.
+    unsafe fn handle_capacity_increase(&mut self, old_cap: usize) {
+        let new_cap = self.cap();
+        if self.head <= old_cap - self.len {
+            return;
+        }
+        let head_len = old_cap - self.head;
+        let tail_len = self.len - head_len;
+        if tail_len < head_len && tail_len <= new_cap - old_cap {
+            // [D E . . A B C] -> [. . . . A B C D E . . .]
+            ptr::copy_nonoverlapping(
+                self.ptr(),
+                self.ptr().add(old_cap),
+                tail_len,
+            );
+        } else {
+            // [D E F G . A B] -> [D E F G . . . . . A B]
+            let new_head = new_cap - head_len;
+            ptr::copy(
+                self.ptr().add(self.head),
+                self.ptr().add(new_head),
+                head_len,
+            );
+            self.head = new_head;
+        }
+    }
+
+    pub fn try_reserve(
+        &mut self,
+        additional: usize,
+    ) -> Result<(), AllocationError> {
+        let old_cap = self.cap();
+        self.buf.try_reserve(self.len, additional)?;
+        unsafe { self.handle_capacity_increase(old_cap) };
+        Ok(())
+    }
+
+    pub fn try_reserve_exact(
+        &mut self,
+        additional: usize,
+    ) -> Result<(), AllocationError> {
+        let old_cap = self.cap();
+        self.buf.try_reserve_exact(self.len, additional)?;
+        unsafe { self.handle_capacity_increase(old_cap) };
+        Ok(())
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        if let Err(err) = self.try_reserve(additional) {
+            handle_error(err);
+        }
+    }
+
+    pub fn reserve_exact(&mut self, additional: usize) {
+        if let Err(err) = self.try_reserve_exact(additional) {
+            handle_error(err);
+        }
+    }
+
+    fn grow_if_full(&mut self) {
+        if self.len == self.cap() {
+            let old_cap = self.cap();
+            self.buf.grow_one();
+            unsafe { self.handle_capacity_increase(old_cap) };
+        }
+    }
+
+    pub fn push_back(&mut self, elem: T) {
+        self.grow_if_full();
+        unsafe {
+            ptr::write(self.ptr().add(self.to_physical(self.len)), elem);
+        }
+        self.len += 1;
+    }
+
+    pub fn push_front(&mut self, elem: T) {
+        self.grow_if_full();
+        self.head = self.to_physical(self.cap() - 1);
+        unsafe {
+            ptr::write(self.ptr().add(self.head), elem);
+        }
+        self.len += 1;
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        unsafe { Some(ptr::read(self.ptr().add(self.to_physical(self.len)))) }
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let old_head = self.head;
+        self.head = self.to_physical(1);
+        self.len -= 1;
+        unsafe { Some(ptr::read(self.ptr().add(old_head))) }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index < self.len {
+            unsafe { Some(&*self.ptr().add(self.to_physical(index))) }
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index < self.len {
+            unsafe { Some(&mut *self.ptr().add(self.to_physical(index))) }
+        } else {
+            None
+        }
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn front_mut(&mut self) -> Option<&mut T> {
+        self.get_mut(0)
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.len.checked_sub(1).and_then(|i| self.get(i))
+    }
+
+    pub fn back_mut(&mut self) -> Option<&mut T> {
+        match self.len.checked_sub(1) {
+            Some(i) => self.get_mut(i),
+            None => None,
+        }
+    }
+
+    // the (first, second) ranges of buffer slots holding the elements, in
+    // logical order
+    fn slice_ranges(&self) -> ((usize, usize), (usize, usize)) {
+        if self.is_contiguous() {
+            ((self.head, self.len), (0, 0))
+        } else {
+            let head_len = self.cap() - self.head;
+            ((self.head, head_len), (0, self.len - head_len))
+        }
+    }
+
+    // The elements in order, as the part from `head` to the end of the
+    // buffer and the part that wrapped around to the front.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let ((a, a_len), (b, b_len)) = self.slice_ranges();
+        unsafe {
+            (
+                slice::from_raw_parts(self.ptr().add(a), a_len),
+                slice::from_raw_parts(self.ptr().add(b), b_len),
+            )
+        }
+    }
+
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        let ((a, a_len), (b, b_len)) = self.slice_ranges();
+        unsafe {
+            (
+                slice::from_raw_parts_mut(self.ptr().add(a), a_len),
+                slice::from_raw_parts_mut(self.ptr().add(b), b_len),
+            )
+        }
+    }
+
+    // Rearranges the buffer so the elements form a single slice, and
+    // returns it. Doesn't allocate.
+    pub fn make_contiguous(&mut self) -> &mut [T] {
+        if !self.is_contiguous() {
+            let head_len = self.cap() - self.head;
+            let tail_len = self.len - head_len;
+            unsafe {
+                // close the gap between the two parts, then swap them
+                // around: [D E . . A B C] -> [D E A B C . .] -> [A B C D E . .]
+                ptr::copy(
+                    self.ptr().add(self.head),
+                    self.ptr().add(tail_len),
+                    head_len,
+                );
+                self.head = 0;
+                slice::from_raw_parts_mut(self.ptr(), self.len)
+                    .rotate_left(tail_len);
+            }
+        }
+        unsafe {
+            slice::from_raw_parts_mut(self.ptr().add(self.head), self.len)
+        }
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        // drop from the back one at a time, so a panicking destructor
+        // leaves the rest intact
+        while self.len > len {
+            drop(self.pop_back());
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+        self.head = 0;
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        let (a, b) = self.as_slices();
+        Iter {
+            a: a.iter(),
+            b: b.iter(),
+        }
+    }
+
+    // iterates over just the elements in `range`
+    pub fn range<R>(&self, range: R) -> Iter<'_, T>
+    where
+        R: RangeBounds<usize>,
+    {
+        let Range { start, end } = slice_range(range, self.len);
+        let (a, b) = self.as_slices();
+        let (a, b) = if end <= a.len() {
+            (&a[start..end], &b[..0])
+        } else if start >= a.len() {
+            (&a[..0], &b[start - a.len()..end - a.len()])
+        } else {
+            (&a[start..], &b[..end - a.len()])
+        };
+        Iter {
+            a: a.iter(),
+            b: b.iter(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        let (a, b) = self.as_mut_slices();
+        IterMut {
+            a: a.iter_mut(),
+            b: b.iter_mut(),
+        }
+    }
+
+    // Moves the elements to the front of the buffer and hands it over.
+    pub fn into_nomvec(mut self) -> NomVec<T, A> {
+        self.make_contiguous();
+        let this = ManuallyDrop::new(self);
+        unsafe {
+            if this.head != 0 {
+                ptr::copy(this.ptr().add(this.head), this.ptr(), this.len);
+            }
+            NomVec {
+                buf: ptr::read(&this.buf),
+                len: this.len,
+            }
+        }
+    }
+}
+
+impl<T, A: Allocator> Drop for NomVecDeque<T, A> {
+    fn drop(&mut self) {
+        let (a, b) = self.as_mut_slices();
+        unsafe {
+            // make sure the second part goes too, even if dropping the
+            // first panics
+            struct DropSlice<T>(*mut [T]);
+            impl<T> Drop for DropSlice<T> {
+                fn drop(&mut self) {
+                    unsafe { ptr::drop_in_place(self.0) }
+                }
+            }
+            let _back = DropSlice(b as *mut [T]);
+            ptr::drop_in_place(a);
+        }
+        // RawVec handles deallocation
+    }
+}
+
+impl<T, A: Allocator> From<NomVec<T, A>> for NomVecDeque<T, A> {
+    // reuses the vector's buffer as-is
+    fn from(vec: NomVec<T, A>) -> Self {
+        let vec = ManuallyDrop::new(vec);
+        NomVecDeque {
+            buf: unsafe { ptr::read(&vec.buf) },
+            head: 0,
+            len: vec.len,
+        }
+    }
+}
+
+impl<T, A: Allocator> From<NomVecDeque<T, A>> for NomVec<T, A> {
+    fn from(deque: NomVecDeque<T, A>) -> Self {
+        deque.into_nomvec()
+    }
+}
+
+impl<T> Default for NomVecDeque<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Clone for NomVecDeque<T, A> {
+    fn clone(&self) -> Self {
+        let mut deque =
+            NomVecDeque::with_capacity_in(self.len, self.allocator().clone());
+        deque.extend(self.iter().cloned());
+        deque
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator> fmt::Debug for NomVecDeque<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq<U>, U, A: Allocator, B: Allocator>
+    PartialEq<NomVecDeque<U, B>> for NomVecDeque<T, A>
+{
+    fn eq(&self, other: &NomVecDeque<U, B>) -> bool {
+        self.len == other.len
+            && self.iter().zip(other.iter()).all(|(a, b)| a == b)
+    }
+}
+
+impl<T: PartialEq<U>, U, A: Allocator> PartialEq<[U]> for NomVecDeque<T, A> {
+    fn eq(&self, other: &[U]) -> bool {
+        let (a, b) = self.as_slices();
+        self.len == other.len()
+            && a == &other[..a.len()]
+            && b == &other[a.len()..]
+    }
+}
+
+impl<T: PartialEq<U>, U, A: Allocator, const N: usize> PartialEq<[U; N]>
+    for NomVecDeque<T, A>
+{
+    fn eq(&self, other: &[U; N]) -> bool {
+        *self == other[..]
+    }
+}
+
+impl<T: Eq, A: Allocator> Eq for NomVecDeque<T, A> {}
+
+impl<T: PartialOrd, A: Allocator> PartialOrd for NomVecDeque<T, A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Ord, A: Allocator> Ord for NomVecDeque<T, A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<T: Hash, A: Allocator> Hash for NomVecDeque<T, A> {
+    // the length, then both halves through `hash_slice`, as a slice does
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len);
+        let (front, back) = self.as_slices();
+        Hash::hash_slice(front, state);
+        Hash::hash_slice(back, state);
+    }
+}
+
+impl<T, A: Allocator> Index<usize> for NomVecDeque<T, A> {
+    type Output = T;
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("index out of bounds")
+    }
+}
+
+impl<T, A: Allocator> IndexMut<usize> for NomVecDeque<T, A> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("index out of bounds")
+    }
+}
+
+impl<T, A: Allocator> Extend<T> for NomVecDeque<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for elem in iter {
+            self.push_back(elem);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for NomVecDeque<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut deque = NomVecDeque::new();
+        deque.extend(iter);
+        deque
+    }
+}
+
+pub struct Iter<'a, T> {
+    a: slice::Iter<'a, T>,
+    b: slice::Iter<'a, T>,
+}
+
+impl<'a, T> Clone for Iter<'a, T> {
+    fn clone(&self) -> Self {
+        Iter {
+            a: self.a.clone(),
+            b: self.b.clone(),
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        self.a.next().or_else(|| self.b.next())
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.a.len() + self.b.len();
+        (len, Some(len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.b.next_back().or_else(|| self.a.next_back())
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
+pub struct IterMut<'a, T> {
+    a: slice::IterMut<'a, T>,
+    b: slice::IterMut<'a, T>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+    fn next(&mut self) -> Option<&'a mut T> {
+        match self.a.next() {
+            Some(elem) => Some(elem),
+            None => self.b.next(),
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.a.len() + self.b.len();
+        (len, Some(len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
+    fn next_back(&mut self) -> Option<&'a mut T> {
+        match self.b.next_back() {
+            Some(elem) => Some(elem),
+            None => self.a.next_back(),
+        }
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+
+impl<'a, T> FusedIterator for IterMut<'a, T> {}
+
+pub struct IntoIter<T, A: Allocator = Global> {
+    deque: NomVecDeque<T, A>,
+}
+
+impl<T, A: Allocator> Iterator for IntoIter<T, A> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.deque.pop_front()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.deque.len, Some(self.deque.len))
+    }
+}
+
+impl<T, A: Allocator> DoubleEndedIterator for IntoIter<T, A> {
+    fn next_back(&mut self) -> Option<T> {
+        self.deque.pop_back()
+    }
+}
+
+impl<T, A: Allocator> ExactSizeIterator for IntoIter<T, A> {}
+
+impl<T, A: Allocator> FusedIterator for IntoIter<T, A> {}
+
+impl<T, A: Allocator> IntoIterator for NomVecDeque<T, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+    fn into_iter(self) -> IntoIter<T, A> {
+        IntoIter { deque: self }
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a NomVecDeque<T, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a mut NomVecDeque<T, A> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DropCounter;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn deque_push_pop_both_ends() {
+        let mut dq = NomVecDeque::new();
+        assert_eq!(dq.pop_front(), None);
+        assert_eq!(dq.back(), None);
+        for i in 0..5 {
+            dq.push_back(i);
+            dq.push_front(-i - 1);
+        }
+        assert_eq!(dq, [-5, -4, -3, -2, -1, 0, 1, 2, 3, 4]);
+        assert_eq!(dq.front(), Some(&-5));
+        assert_eq!(dq.back(), Some(&4));
+        assert_eq!(dq[5], 0);
+        assert_eq!(dq.pop_front(), Some(-5));
+        assert_eq!(dq.pop_back(), Some(4));
+        *dq.front_mut().unwrap() = 100;
+        assert_eq!(dq.iter().next_back(), Some(&3));
+        assert_eq!(dq.iter().len(), 8);
+        assert!(dq.range(2..6).eq(&[-2, -1, 0, 1]));
+        assert!(dq.range(..2).eq(&[100, -3]));
+        assert!(dq.range(6..).eq(&[2, 3]));
+        assert_eq!(dq.clone(), dq);
+        for x in &mut dq {
+            *x *= 2;
+        }
+        assert_eq!(
+            dq.into_iter().collect::<Vec<_>>(),
+            [200, -6, -4, -2, 0, 2, 4, 6]
+        );
+    }
+
+    #[test]
+    fn deque_wraps_and_grows() {
+        let mut dq = NomVecDeque::with_capacity(4);
+        let cap = dq.capacity();
+        for i in 0..cap {
+            dq.push_back(i);
+        }
+        // free up the front, then wrap the back around into it
+        dq.pop_front();
+        dq.pop_front();
+        dq.push_back(cap);
+        dq.push_back(cap + 1);
+        assert_eq!(dq.capacity(), cap);
+        let (a, b) = dq.as_slices();
+        assert!(!b.is_empty());
+        assert_eq!(a.len() + b.len(), cap);
+        // hashes the same as the elements laid out in one piece
+        fn hash_of<T: Hash>(x: &T) -> u64 {
+            let mut state = std::collections::hash_map::DefaultHasher::new();
+            x.hash(&mut state);
+            state.finish()
+        }
+        let whole: NomVecDeque<usize> = dq.iter().copied().collect();
+        assert_eq!(hash_of(&dq), hash_of(&whole));
+        assert_eq!(
+            hash_of(&dq),
+            hash_of(&dq.iter().copied().collect::<Vec<_>>())
+        );
+
+        // growing while wrapped keeps the order
+        dq.push_back(cap + 2);
+        assert!(dq.capacity() > cap);
+        assert!(dq.iter().copied().eq(2..cap + 3));
+
+        dq.extend(100..120);
+        dq.push_front(1);
+        assert_eq!(dq.make_contiguous()[0], 1);
+        assert_eq!(dq.as_slices().1, &[] as &[usize]);
+
+        let vec = NomVec::from(dq);
+        assert_eq!(vec[0], 1);
+        assert_eq!(vec.len(), cap + 22);
+        let dq = NomVecDeque::from(vec);
+        assert_eq!(dq[1], 2);
+    }
+
+    #[test]
+    fn deque_make_contiguous_when_full() {
+        let mut dq: NomVecDeque<String> = NomVecDeque::with_capacity(8);
+        let cap = dq.capacity();
+        for i in 0..cap {
+            dq.push_back(i.to_string());
+        }
+        for _ in 0..3 {
+            let front = dq.pop_front().unwrap();
+            dq.push_back(front);
+        }
+        let expected: Vec<String> =
+            (3..cap).chain(0..3).map(|i| i.to_string()).collect();
+        assert_eq!(dq.make_contiguous(), &expected[..]);
+        assert_eq!(dq.capacity(), cap);
+    }
+
+    #[test]
+    fn deque_drops_everything() {
+        let drops = Rc::new(Cell::new(0));
+        let mut dq = NomVecDeque::new();
+        for i in 0..10 {
+            if i % 2 == 0 {
+                dq.push_back(DropCounter::new(&drops));
+            } else {
+                dq.push_front(DropCounter::new(&drops));
+            }
+        }
+        dq.truncate(8);
+        assert_eq!(drops.get(), 2);
+        let mut iter = dq.into_iter();
+        drop(iter.next());
+        assert_eq!(drops.get(), 3);
+        drop(iter);
+        assert_eq!(drops.get(), 10);
+
+        let mut zsts = NomVecDeque::new();
+        zsts.push_front(());
+        zsts.push_back(());
+        assert_eq!(zsts.len(), 2);
+        assert_eq!(zsts.make_contiguous().len(), 2);
+    }
+}
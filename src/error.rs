@@ -0,0 +1,29 @@
+use std::alloc::Layout;
+use std::fmt;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum AllocationError {
+    // the requested capacity exceeded `isize::MAX` bytes (or `usize::MAX`
+    // elements) before we even got to ask the allocator.
+    CapacityOverflow,
+    // the allocator returned an error for this layout.
+    AllocFailed { layout: Layout },
+}
+
+impl fmt::Display for AllocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AllocationError::CapacityOverflow => {
+                f.write_str("capacity overflow")
+            }
+            AllocationError::AllocFailed { layout } => write!(
+                f,
+                "memory allocation of {} bytes (align {}) failed",
+                layout.size(),
+                layout.align()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AllocationError {}
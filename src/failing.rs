@@ -0,0 +1,214 @@
+// An allocator wrapper that fails on purpose, for testing what happens when
+// memory runs out. It can be told to fail the nth request for memory from
+// now, or every request above a size, and otherwise passes everything
+// through to `A`. Requests are calls to `allocate`, `allocate_zeroed`,
+// `grow` and `grow_zeroed`; shrinking and freeing always go through.
+//
+// Failing each request of an operation in turn, until one run gets through
+// without a failure, exercises every allocation-failure path it has.
+use crate::{AllocError, Allocator, Global};
+use std::alloc::Layout;
+use std::fmt;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// stands in for "no rule" in both settings
+const NEVER: usize = usize::MAX;
+
+pub struct FailingAllocator<A: Allocator = Global> {
+    requests: AtomicUsize,
+    failures: AtomicUsize,
+    // the request number to fail
+    fail_at: AtomicUsize,
+    // the largest request that can succeed
+    max_size: AtomicUsize,
+    alloc: A,
+}
+
+impl FailingAllocator {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<A: Allocator> FailingAllocator<A> {
+    // Passes everything through until told otherwise.
+    pub const fn new_in(alloc: A) -> Self {
+        FailingAllocator {
+            requests: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            fail_at: AtomicUsize::new(NEVER),
+            max_size: AtomicUsize::new(NEVER),
+            alloc,
+        }
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    // Fails the `n`th request from now, counting the next one as 1, and
+    // only that one. Panics if `n` is zero.
+    pub fn fail_nth(&self, n: usize) {
+        assert!(n > 0, "requests are counted from 1");
+        let at = self.requests.load(Ordering::Relaxed).saturating_add(n);
+        self.fail_at.store(at, Ordering::Relaxed);
+    }
+
+    // Fails every request for more than `bytes`.
+    pub fn fail_above(&self, bytes: usize) {
+        self.max_size.store(bytes, Ordering::Relaxed);
+    }
+
+    // Drops both rules, so everything succeeds again.
+    pub fn clear(&self) {
+        self.fail_at.store(NEVER, Ordering::Relaxed);
+        self.max_size.store(NEVER, Ordering::Relaxed);
+    }
+
+    // the requests made so far, failed or not
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    pub fn failures(&self) -> usize {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    fn check(&self, layout: Layout) -> Result<(), AllocError> {
+        let n = self.requests.fetch_add(1, Ordering::Relaxed) + 1;
+        if n == self.fail_at.load(Ordering::Relaxed)
+            || layout.size() > self.max_size.load(Ordering::Relaxed)
+        {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+            return Err(AllocError);
+        }
+        Ok(())
+    }
+}
+
+impl Default for FailingAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Allocator> fmt::Debug for FailingAllocator<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FailingAllocator")
+            .field("requests", &self.requests())
+            .field("failures", &self.failures())
+            .finish()
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for FailingAllocator<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.check(layout)?;
+        self.alloc.allocate(layout)
+    }
+
+    fn allocate_zeroed(
+        &self,
+        layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.check(layout)?;
+        self.alloc.allocate_zeroed(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.alloc.deallocate(ptr, layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.check(new_layout)?;
+        self.alloc.grow(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.check(new_layout)?;
+        self.alloc.grow_zeroed(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.shrink(ptr, old_layout, new_layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AllocationError, NomVec};
+
+    #[test]
+    fn failing_nth_request() {
+        let failing = FailingAllocator::new();
+        let mut cv = NomVec::new_in(&failing);
+        cv.try_push(1u64).unwrap();
+        failing.fail_nth(1);
+        let err = cv.try_reserve_exact(10).unwrap_err();
+        assert_eq!(
+            err,
+            AllocationError::AllocFailed {
+                layout: Layout::array::<u64>(11).unwrap()
+            }
+        );
+        // the failed grow left the vector as it was
+        assert_eq!(cv, [1]);
+        assert_eq!(cv.capacity(), 1);
+        // and only that request failed
+        cv.try_reserve_exact(10).unwrap();
+        assert_eq!((failing.requests(), failing.failures()), (3, 1));
+
+        failing.fail_above(1024);
+        assert!(cv.try_reserve(1000).is_err());
+        assert!(cv.try_reserve(100).is_ok());
+        failing.clear();
+        assert!(cv.try_reserve(1000).is_ok());
+    }
+
+    #[test]
+    fn failing_every_request_in_turn() {
+        let data: Vec<u32> = (0..100).collect();
+        let mut n = 1;
+        loop {
+            let failing = FailingAllocator::new();
+            failing.fail_nth(n);
+            let mut cv = NomVec::new_in(&failing);
+            let result = (|| {
+                for chunk in data.chunks(7) {
+                    cv.try_extend_from_slice(chunk)?;
+                }
+                cv.try_push(100)
+            })();
+            match result {
+                Ok(()) => {
+                    assert_eq!(failing.failures(), 0);
+                    assert_eq!(cv.len(), 101);
+                    break;
+                }
+                Err(_) => {
+                    assert_eq!(failing.failures(), 1);
+                    assert!(cv.iter().copied().eq(0..cv.len() as u32));
+                }
+            }
+            n += 1;
+        }
+        assert!(n > 2);
+    }
+}
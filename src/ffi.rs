@@ -0,0 +1,103 @@
+// A C-compatible handle for byte buffers, so C callers can create and fill
+// a `NomVec<u8>` that Rust later adopts (or vice versa). Every buffer is
+// owned by exactly one `NomVecRaw` at a time; the functions below take it
+// by pointer when they may reallocate, and by value when they consume it.
+use crate::NomVec;
+use std::ptr;
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct NomVecRaw {
+    pub ptr: *mut u8,
+    pub len: usize,
+    pub cap: usize,
+}
+
+impl NomVecRaw {
+    /// # Safety
+    ///
+    /// `self` must have come from this module (or `From<NomVec<u8>>`) and
+    /// not have been freed or adopted already.
+    pub unsafe fn into_nomvec(self) -> NomVec<u8> {
+        NomVec::from_raw_parts(self.ptr, self.len, self.cap)
+    }
+}
+
+impl From<NomVec<u8>> for NomVecRaw {
+    fn from(vec: NomVec<u8>) -> Self {
+        let (ptr, len, cap) = vec.into_raw_parts();
+        NomVecRaw { ptr, len, cap }
+    }
+}
+
+// runs `f` on the vector behind `raw`, writing the (possibly moved) buffer
+// back afterwards
+unsafe fn with_vec<R>(
+    raw: *mut NomVecRaw,
+    f: impl FnOnce(&mut NomVec<u8>) -> R,
+) -> R {
+    let mut vec = ptr::read(raw).into_nomvec();
+    let out = f(&mut vec);
+    ptr::write(raw, vec.into());
+    out
+}
+
+#[no_mangle]
+pub extern "C" fn nomvec_u8_create(capacity: usize) -> NomVecRaw {
+    NomVec::with_capacity(capacity).into()
+}
+
+/// # Safety
+///
+/// `raw` must point to a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn nomvec_u8_push(raw: *mut NomVecRaw, byte: u8) {
+    with_vec(raw, |vec| vec.push(byte))
+}
+
+/// # Safety
+///
+/// `raw` must point to a live handle, and `data` must be valid for reads of
+/// `len` bytes (it may be null when `len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn nomvec_u8_extend(
+    raw: *mut NomVecRaw,
+    data: *const u8,
+    len: usize,
+) {
+    if len == 0 {
+        return;
+    }
+    let bytes = ::std::slice::from_raw_parts(data, len);
+    with_vec(raw, |vec| vec.extend_from_slice_copy(bytes))
+}
+
+/// # Safety
+///
+/// `raw` must be a live handle; it's dead afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn nomvec_u8_free(raw: NomVecRaw) {
+    drop(raw.into_nomvec());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ffi_create_fill_adopt() {
+        let mut raw = nomvec_u8_create(2);
+        assert_eq!((raw.len, raw.cap), (0, 2));
+        unsafe {
+            nomvec_u8_push(&mut raw, b'h');
+            nomvec_u8_extend(&mut raw, b"ello".as_ptr(), 4);
+            nomvec_u8_extend(&mut raw, ptr::null(), 0);
+        }
+        assert_eq!(raw.len, 5);
+        let vec = unsafe { raw.into_nomvec() };
+        assert_eq!(&vec[..], b"hello");
+
+        let raw = NomVecRaw::from(vec);
+        unsafe { nomvec_u8_free(raw) };
+    }
+}
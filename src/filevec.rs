@@ -0,0 +1,438 @@
+// A vector of `Pod` elements kept in a memory-mapped file, so that a large
+// numeric dataset outlives the process without being serialized: `create`
+// starts an empty file, and `open` maps an existing one back in with its
+// elements where they were left.
+//
+// The file is a 64-byte header (a magic number, the element size and the
+// length) followed by the elements, and the file's length is the capacity.
+// Writes go straight to the mapping; the OS writes dirty pages back
+// whenever it likes, `flush` writes them back now, and `sync` also waits
+// for them to reach the disk. Growing extends the file by doubling and maps
+// it again, so pointers into the elements don't survive a push that grows.
+//
+// The slices handed out point straight at the file's pages, so nothing else
+// may write to or shorten the file while a vector has it open; that's why
+// `create` and `open` are unsafe.
+use bytemuck::Pod;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::ptr::{self, NonNull};
+use std::slice;
+
+const MAGIC: [u8; 8] = *b"NOMVEC\0\x01";
+
+// The header's size, which also lines the elements up for any alignment up
+// to it, the mapping being page-aligned.
+const HEADER: usize = 64;
+
+// capacity on the first growth
+const MIN_CAPACITY: usize = 8;
+
+// Shared file mappings, one module per platform like the one in mmap.rs.
+#[cfg(unix)]
+mod sys {
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use std::ptr::{self, NonNull};
+
+    // Maps the first `size` bytes of `file`, shared, so that writes reach
+    // it.
+    pub(super) fn map(file: &File, size: usize) -> io::Result<NonNull<u8>> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        NonNull::new(ptr as *mut u8).ok_or_else(io::Error::last_os_error)
+    }
+
+    pub(super) unsafe fn unmap(ptr: NonNull<u8>, size: usize) {
+        libc::munmap(ptr.as_ptr() as *mut libc::c_void, size);
+    }
+
+    // Writes a mapping's dirty pages back to its file, and waits for that.
+    pub(super) unsafe fn flush(
+        ptr: NonNull<u8>,
+        size: usize,
+    ) -> io::Result<()> {
+        if libc::msync(ptr.as_ptr() as *mut libc::c_void, size, libc::MS_SYNC)
+            == 0
+        {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::fs::File;
+    use std::io;
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr::{self, NonNull};
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::System::Memory::{
+        CreateFileMappingW, FlushViewOfFile, MapViewOfFile, UnmapViewOfFile,
+        FILE_MAP_WRITE, MEMORY_MAPPED_VIEW_ADDRESS, PAGE_READWRITE,
+    };
+
+    // Maps the first `size` bytes of `file`, shared, so that writes reach
+    // it.
+    pub(super) fn map(file: &File, size: usize) -> io::Result<NonNull<u8>> {
+        let size64 = size as u64;
+        unsafe {
+            let mapping = CreateFileMappingW(
+                file.as_raw_handle() as HANDLE,
+                ptr::null(),
+                PAGE_READWRITE,
+                (size64 >> 32) as u32,
+                size64 as u32,
+                ptr::null(),
+            );
+            if mapping.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let view = MapViewOfFile(mapping, FILE_MAP_WRITE, 0, 0, size);
+            let err = io::Error::last_os_error();
+            // the view keeps the mapping object alive
+            CloseHandle(mapping);
+            NonNull::new(view.Value as *mut u8).ok_or(err)
+        }
+    }
+
+    pub(super) unsafe fn unmap(ptr: NonNull<u8>, _size: usize) {
+        UnmapViewOfFile(MEMORY_MAPPED_VIEW_ADDRESS {
+            Value: ptr.as_ptr() as *mut _,
+        });
+    }
+
+    // Writes a view's dirty pages back to its file. Unlike msync this
+    // doesn't wait for them to reach the disk; `File::sync_all` does.
+    pub(super) unsafe fn flush(
+        ptr: NonNull<u8>,
+        size: usize,
+    ) -> io::Result<()> {
+        if FlushViewOfFile(ptr.as_ptr() as *const _, size) != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Header {
+    magic: [u8; 8],
+    elem_size: u64,
+    len: u64,
+}
+
+pub struct FileNomVec<T: Pod> {
+    file: File,
+    // the whole file, header included
+    map: NonNull<u8>,
+    map_len: usize,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: Pod + Send> Send for FileNomVec<T> {}
+unsafe impl<T: Pod + Sync> Sync for FileNomVec<T> {}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl<T: Pod> FileNomVec<T> {
+    /// Creates an empty vector at `path`, replacing whatever was there.
+    /// Panics if `T` is zero-sized or aligned to more than 64 bytes.
+    ///
+    /// # Safety
+    ///
+    /// Until the vector is dropped, nothing else may map, write to or
+    /// truncate the file: not another `FileNomVec` (calling `create` on the
+    /// same path again truncates it under this one), and not another
+    /// process. Changes made behind the vector's back break the aliasing
+    /// rules for the slices it hands out, and a shortened file turns reads
+    /// into a SIGBUS.
+    pub unsafe fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::check_type();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(HEADER as u64)?;
+        let map = sys::map(&file, HEADER)?;
+        let header = Header {
+            magic: MAGIC,
+            elem_size: mem::size_of::<T>() as u64,
+            len: 0,
+        };
+        unsafe { ptr::write(map.as_ptr() as *mut Header, header) };
+        Ok(FileNomVec {
+            file,
+            map,
+            map_len: HEADER,
+            len: 0,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Opens a vector made by `create`. Fails with `InvalidData` if the file
+    /// isn't one, or holds elements of another size. Panics as `create`
+    /// does.
+    ///
+    /// # Safety
+    ///
+    /// As for `create`: nothing else may map, write to or truncate the file
+    /// until the vector is dropped, including another `open` of the same
+    /// path, which would give out a second `&mut [T]` over the same pages.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::check_type();
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let file_len = file.metadata()?.len();
+        if file_len < HEADER as u64 {
+            return Err(invalid("file too short for a FileNomVec header"));
+        }
+        let map_len = usize::try_from(file_len)
+            .map_err(|_| invalid("file too large to map"))?;
+        let map = sys::map(&file, map_len)?;
+        // dropping this unmaps if the header turns out to be bad
+        let mut vec = FileNomVec {
+            file,
+            map,
+            map_len,
+            len: 0,
+            _marker: PhantomData,
+        };
+        let header = unsafe { ptr::read(map.as_ptr() as *const Header) };
+        if header.magic != MAGIC {
+            return Err(invalid("not a FileNomVec file"));
+        }
+        if header.elem_size != mem::size_of::<T>() as u64 {
+            return Err(invalid("FileNomVec file has another element size"));
+        }
+        let len = usize::try_from(header.len)
+            .ok()
+            .filter(|&len| len <= vec.capacity())
+            .ok_or_else(|| invalid("FileNomVec length past end of file"))?;
+        vec.len = len;
+        Ok(vec)
+    }
+
+    fn check_type() {
+        assert!(mem::size_of::<T>() != 0, "FileNomVec of a zero-sized type");
+        assert!(
+            mem::align_of::<T>() <= HEADER,
+            "FileNomVec element aligned to more than {} bytes",
+            HEADER
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // the elements the file has room for
+    pub fn capacity(&self) -> usize {
+        (self.map_len - HEADER) / mem::size_of::<T>()
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.data(), self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.data(), self.len) }
+    }
+
+    fn data(&self) -> *mut T {
+        unsafe { self.map.as_ptr().add(HEADER) as *mut T }
+    }
+
+    fn set_len(&mut self, len: usize) {
+        self.len = len;
+        unsafe { (*(self.map.as_ptr() as *mut Header)).len = len as u64 };
+    }
+
+    // Makes room for `additional` more elements, extending the file to at
+    // least double its capacity.
+    pub fn reserve(&mut self, additional: usize) -> io::Result<()> {
+        let too_large = || invalid("FileNomVec capacity overflow");
+        let required =
+            self.len.checked_add(additional).ok_or_else(too_large)?;
+        if required <= self.capacity() {
+            return Ok(());
+        }
+        let cap = required.max(self.capacity() * 2).max(MIN_CAPACITY);
+        let map_len = cap
+            .checked_mul(mem::size_of::<T>())
+            .and_then(|bytes| bytes.checked_add(HEADER))
+            .ok_or_else(too_large)?;
+        // Map the longer file before letting go of the old mapping, so
+        // that a failure leaves this as it was. The file can't be
+        // shortened again while either is mapped on Windows, but it's
+        // only ever lengthened.
+        self.file.set_len(map_len as u64)?;
+        let map = sys::map(&self.file, map_len)?;
+        unsafe { sys::unmap(self.map, self.map_len) };
+        self.map = map;
+        self.map_len = map_len;
+        Ok(())
+    }
+
+    pub fn push(&mut self, value: T) -> io::Result<()> {
+        self.reserve(1)?;
+        unsafe { ptr::write(self.data().add(self.len), value) };
+        self.set_len(self.len + 1);
+        Ok(())
+    }
+
+    pub fn extend_from_slice(&mut self, values: &[T]) -> io::Result<()> {
+        self.reserve(values.len())?;
+        unsafe {
+            let end = self.data().add(self.len);
+            ptr::copy_nonoverlapping(values.as_ptr(), end, values.len());
+        }
+        self.set_len(self.len + values.len());
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let last = *self.as_slice().last()?;
+        self.set_len(self.len - 1);
+        Some(last)
+    }
+
+    // Shortens the vector; the file keeps its length.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self.set_len(len);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+
+    // Writes every change so far back to the file, so that another mapping
+    // or a reader of the file sees it.
+    pub fn flush(&self) -> io::Result<()> {
+        unsafe { sys::flush(self.map, self.map_len) }
+    }
+
+    // As `flush`, and waits for the file to reach the disk, so the changes
+    // survive a crash.
+    pub fn sync(&self) -> io::Result<()> {
+        self.flush()?;
+        self.file.sync_all()
+    }
+}
+
+impl<T: Pod> Drop for FileNomVec<T> {
+    fn drop(&mut self) {
+        // the OS still writes the dirty pages back after this
+        unsafe { sys::unmap(self.map, self.map_len) }
+    }
+}
+
+impl<T: Pod> Deref for FileNomVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T: Pod> DerefMut for FileNomVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T: Pod + fmt::Debug> fmt::Debug for FileNomVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let file = format!("nomvec-{}-{}", std::process::id(), name);
+        std::env::temp_dir().join(file)
+    }
+
+    // Each test has a file of its own, so it's the only one mapping it.
+    #[test]
+    fn filevec_persists_across_reopen() {
+        let path = temp_path("persist");
+        {
+            let mut fv = unsafe { FileNomVec::create(&path) }.unwrap();
+            for i in 0..10_000u64 {
+                fv.push(i * 3).unwrap();
+            }
+            fv.extend_from_slice(&[1, 2, 3]).unwrap();
+            assert_eq!(fv.pop(), Some(3));
+            fv[0] = 42;
+            fv.sync().unwrap();
+        }
+        let mut fv = unsafe { FileNomVec::<u64>::open(&path) }.unwrap();
+        assert_eq!(fv.len(), 10_002);
+        assert_eq!(fv[0], 42);
+        assert!(fv[1..10_000].iter().copied().eq((1..10_000).map(|i| i * 3)));
+        assert_eq!(fv[10_000..], [1, 2]);
+        fv.truncate(2);
+        fv.push(7).unwrap();
+        fv.flush().unwrap();
+        drop(fv);
+        let fv = unsafe { FileNomVec::<u64>::open(&path) }.unwrap();
+        assert_eq!(format!("{:?}", fv), "[42, 3, 7]");
+        drop(fv);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn filevec_rejects_foreign_files() {
+        let path = temp_path("foreign");
+        let mut fv = unsafe { FileNomVec::<u32>::create(&path) }.unwrap();
+        fv.extend_from_slice(&[1, 2, 3]).unwrap();
+        drop(fv);
+        let err = unsafe { FileNomVec::<u64>::open(&path) }.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        fs::write(&path, [0u8; 100]).unwrap();
+        let err = unsafe { FileNomVec::<u32>::open(&path) }.unwrap_err();
+        assert_eq!(err.to_string(), "not a FileNomVec file");
+        fs::write(&path, b"short").unwrap();
+        assert!(unsafe { FileNomVec::<u32>::open(&path) }.is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,318 @@
+// An append-only vector that can grow through a shared reference: `push`
+// takes `&self` and returns a `&T` that stays valid for as long as the
+// vector is borrowed, however much it grows afterwards. That suits caches
+// and interners, which hand out references to what they've stored while
+// they're still being filled.
+//
+// Elements live in segments that are never reallocated, each twice the
+// size of the one before, so segment `k` holds indices
+// `FIRST * (2^k - 1)..FIRST * (2^(k+1) - 1)`. Growing only ever adds a
+// segment, which can move the segment headers but none of the elements.
+// Nothing hands out `&mut` access to the storage except through
+// `&mut self`, and the vector isn't `Sync`, so pushes can't race with
+// reads.
+use crate::{Allocator, Global, NomVec};
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::iter::{FromIterator, FusedIterator};
+use std::ops::Index;
+
+// elements in the first segment; a power of two
+const FIRST: usize = 8;
+
+// The segment holding `index`, and its position there.
+fn locate(index: usize) -> (usize, usize) {
+    // biased so that segment boundaries fall on powers of two
+    let biased = index / FIRST + 1;
+    let segment = (usize::BITS - 1 - biased.leading_zeros()) as usize;
+    (segment, index - FIRST * ((1 << segment) - 1))
+}
+
+fn segment_len(segment: usize) -> usize {
+    FIRST << segment
+}
+
+pub struct FrozenNomVec<T, A: Allocator + Clone = Global> {
+    segments: UnsafeCell<NomVec<NomVec<T, A>, A>>,
+}
+
+impl<T> FrozenNomVec<T> {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<T, A: Allocator + Clone> FrozenNomVec<T, A> {
+    pub const fn new_in(alloc: A) -> Self {
+        FrozenNomVec {
+            segments: UnsafeCell::new(NomVec::new_in(alloc)),
+        }
+    }
+
+    // Only ever borrowed for the length of a call, and never while
+    // `push` holds the storage mutably.
+    fn segments(&self) -> &NomVec<NomVec<T, A>, A> {
+        unsafe { &*self.segments.get() }
+    }
+
+    pub fn len(&self) -> usize {
+        let segments = self.segments();
+        match segments.last() {
+            Some(last) => {
+                FIRST * ((1 << (segments.len() - 1)) - 1) + last.len()
+            }
+            None => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.segments().allocator()
+    }
+
+    pub fn push(&self, value: T) -> &T {
+        // Only the segment headers are borrowed mutably here, never the
+        // elements behind them, which earlier `&T`s may point into.
+        let segments = unsafe { &mut *self.segments.get() };
+        let full = match segments.last() {
+            Some(last) => last.len() == segment_len(segments.len() - 1),
+            None => true,
+        };
+        if full {
+            let len = segment_len(segments.len());
+            let alloc = segments.allocator().clone();
+            segments.push(NomVec::with_capacity_in(len, alloc));
+        }
+        let segment = segments.last_mut().unwrap();
+        // Growing the segment would move what earlier `&T`s point at; every
+        // segment is allocated at its full length up front, clones included.
+        if segment.push_within_capacity(value).is_err() {
+            panic!("FrozenNomVec segment allocated short");
+        }
+        unsafe { &*segment.as_ptr().add(segment.len() - 1) }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let (segment, offset) = locate(index);
+        self.segments().get(segment)?.get(offset)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let (segment, offset) = locate(index);
+        self.segments.get_mut().get_mut(segment)?.get_mut(offset)
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.get(self.len().checked_sub(1)?)
+    }
+
+    // Keeps going over anything pushed while it's running.
+    pub fn iter(&self) -> Iter<'_, T, A> {
+        Iter {
+            vec: self,
+            index: 0,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.segments
+            .get_mut()
+            .iter_mut()
+            .flat_map(|s| s.iter_mut())
+    }
+
+    pub fn clear(&mut self) {
+        self.segments.get_mut().clear()
+    }
+
+    // The elements, moved into one contiguous NomVec.
+    pub fn into_vec(self) -> NomVec<T, A> {
+        let segments = self.segments.into_inner();
+        let mut vec = NomVec::new_in(segments.allocator().clone());
+        let len = segments.iter().map(|s| s.len()).sum();
+        vec.reserve_exact(len);
+        for segment in segments {
+            vec.extend(segment);
+        }
+        vec
+    }
+}
+
+impl<T> Default for FrozenNomVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Clone for FrozenNomVec<T, A> {
+    fn clone(&self) -> Self {
+        // `NomVec::clone` would size a partly filled last segment to its
+        // length, leaving `push` no room in it
+        let segments = self.segments();
+        let alloc = segments.allocator();
+        let mut cloned =
+            NomVec::with_capacity_in(segments.len(), alloc.clone());
+        for (k, segment) in segments.iter().enumerate() {
+            let mut copy =
+                NomVec::with_capacity_in(segment_len(k), alloc.clone());
+            copy.extend_from_slice(segment);
+            cloned.push(copy);
+        }
+        FrozenNomVec {
+            segments: UnsafeCell::new(cloned),
+        }
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator + Clone> fmt::Debug for FrozenNomVec<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq, A: Allocator + Clone> PartialEq for FrozenNomVec<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, A: Allocator + Clone> Eq for FrozenNomVec<T, A> {}
+
+impl<T, A: Allocator + Clone> Index<usize> for FrozenNomVec<T, A> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("index out of bounds")
+    }
+}
+
+impl<T, A: Allocator + Clone> Extend<T> for FrozenNomVec<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for FrozenNomVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut fv = FrozenNomVec::new();
+        fv.extend(iter);
+        fv
+    }
+}
+
+pub struct Iter<'a, T, A: Allocator + Clone = Global> {
+    vec: &'a FrozenNomVec<T, A>,
+    index: usize,
+}
+
+impl<'a, T, A: Allocator + Clone> Iterator for Iter<'a, T, A> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let elem = self.vec.get(self.index)?;
+        self.index += 1;
+        Some(elem)
+    }
+}
+
+impl<'a, T, A: Allocator + Clone> FusedIterator for Iter<'a, T, A> {}
+
+impl<'a, T, A: Allocator + Clone> IntoIterator for &'a FrozenNomVec<T, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, A>;
+    fn into_iter(self) -> Iter<'a, T, A> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn frozen_references_survive_pushes() {
+        let fv = FrozenNomVec::new();
+        let first = fv.push(String::from("first"));
+        let mut held = Vec::new();
+        for i in 0..1000 {
+            held.push(fv.push(i.to_string()));
+        }
+        assert_eq!(first, "first");
+        assert!(held.iter().enumerate().all(|(i, s)| **s == i.to_string()));
+        assert_eq!(fv.len(), 1001);
+        assert_eq!(fv[1000], "999");
+        assert_eq!(fv.get(1001), None);
+        assert_eq!(fv.last().map(String::as_str), Some("999"));
+
+        // an iterator picks up what's pushed behind it
+        let mut iter = fv.iter().skip(1000);
+        fv.push(String::from("late"));
+        assert_eq!(iter.next().map(String::as_str), Some("999"));
+        assert_eq!(iter.next().map(String::as_str), Some("late"));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn frozen_as_an_interner() {
+        struct Interner<'a> {
+            strings: &'a FrozenNomVec<String>,
+            ids: HashMap<&'a str, usize>,
+        }
+
+        impl<'a> Interner<'a> {
+            fn intern(&mut self, s: &str) -> usize {
+                if let Some(&id) = self.ids.get(s) {
+                    return id;
+                }
+                let id = self.strings.len();
+                self.ids.insert(self.strings.push(s.to_string()), id);
+                id
+            }
+        }
+
+        let strings = FrozenNomVec::new();
+        let mut interner = Interner {
+            strings: &strings,
+            ids: HashMap::new(),
+        };
+        let words = ["a", "b", "a", "c", "b"];
+        let ids: Vec<_> = words.iter().map(|w| interner.intern(w)).collect();
+        assert_eq!(ids, [0, 1, 0, 2, 1]);
+        drop(interner);
+
+        let mut strings = strings;
+        for s in strings.iter_mut() {
+            s.push('!');
+        }
+        assert_eq!(strings.into_vec(), ["a!", "b!", "c!"]);
+        assert_eq!(locate(7), (0, 7));
+        assert_eq!(locate(8), (1, 0));
+        assert_eq!(locate(24), (2, 0));
+    }
+
+    #[test]
+    fn frozen_clone_keeps_room_to_push() {
+        let fv: FrozenNomVec<u32> = (0..10).collect();
+        let cloned = fv.clone();
+        // in the partly filled last segment
+        let last = &cloned[9];
+        let before = last as *const u32;
+        for i in 10..100 {
+            cloned.push(i);
+        }
+        assert_eq!(*last, 9);
+        assert_eq!(&cloned[9] as *const u32, before);
+        assert!(cloned.iter().copied().eq(0..100));
+        assert_eq!(fv.len(), 10);
+    }
+}
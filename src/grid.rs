@@ -0,0 +1,446 @@
+// A two-dimensional grid stored row-major in one NomVec: cell `(x, y)`
+// lives at `y * width + x`, so a row is a contiguous slice and a column is
+// a strided walk over the same buffer.
+use crate::{Allocator, Global, NomVec};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::StepBy;
+use std::mem;
+use std::ops::{Index, IndexMut};
+use std::slice;
+
+pub struct NomGrid<T, A: Allocator = Global> {
+    cells: NomVec<T, A>,
+    width: usize,
+    height: usize,
+}
+
+// `width * height`, panicking if it overflows
+fn area(width: usize, height: usize) -> usize {
+    width.checked_mul(height).expect("grid size overflow")
+}
+
+impl<T> NomGrid<T> {
+    pub fn from_elem(width: usize, height: usize, value: T) -> Self
+    where
+        T: Clone,
+    {
+        Self::from_elem_in(width, height, value, Global)
+    }
+
+    pub fn from_fn<F>(width: usize, height: usize, f: F) -> Self
+    where
+        F: FnMut(usize, usize) -> T,
+    {
+        Self::from_fn_in(width, height, f, Global)
+    }
+}
+
+impl<T, A: Allocator> NomGrid<T, A> {
+    pub fn from_elem_in(width: usize, height: usize, value: T, alloc: A) -> Self
+    where
+        T: Clone,
+    {
+        let mut cells = NomVec::with_capacity_in(area(width, height), alloc);
+        cells.resize(area(width, height), value);
+        NomGrid {
+            cells,
+            width,
+            height,
+        }
+    }
+
+    // Fills cell `(x, y)` with `f(x, y)`, row by row.
+    pub fn from_fn_in<F>(
+        width: usize,
+        height: usize,
+        mut f: F,
+        alloc: A,
+    ) -> Self
+    where
+        F: FnMut(usize, usize) -> T,
+    {
+        let mut cells = NomVec::with_capacity_in(area(width, height), alloc);
+        for y in 0..height {
+            for x in 0..width {
+                cells.push(f(x, y));
+            }
+        }
+        NomGrid {
+            cells,
+            width,
+            height,
+        }
+    }
+
+    // Reads `cells` as rows of `width`; hands it back unless its length is
+    // a multiple of `width` (a `width` of 0 needs an empty `cells`).
+    pub fn from_vec(
+        cells: NomVec<T, A>,
+        width: usize,
+    ) -> Result<Self, NomVec<T, A>> {
+        let height = match (cells.len(), width) {
+            (0, 0) => 0,
+            (_, 0) => return Err(cells),
+            (len, width) if len % width == 0 => len / width,
+            _ => return Err(cells),
+        };
+        Ok(NomGrid {
+            cells,
+            width,
+            height,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // `(width, height)`
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    // the number of cells
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.cells.allocator()
+    }
+
+    // the cells, row by row
+    pub fn as_slice(&self) -> &[T] {
+        &self.cells
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.cells
+    }
+
+    pub fn into_vec(self) -> NomVec<T, A> {
+        self.cells
+    }
+
+    fn offset(&self, x: usize, y: usize) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y * self.width + x)
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&T> {
+        let offset = self.offset(x, y)?;
+        Some(&self.cells[offset])
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut T> {
+        let offset = self.offset(x, y)?;
+        Some(&mut self.cells[offset])
+    }
+
+    // Panics if `y` is out of bounds.
+    pub fn row(&self, y: usize) -> &[T] {
+        assert!(y < self.height, "row out of bounds");
+        &self.cells[y * self.width..][..self.width]
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut [T] {
+        assert!(y < self.height, "row out of bounds");
+        &mut self.cells[y * self.width..][..self.width]
+    }
+
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[T]> + '_ {
+        (0..self.height).map(move |y| self.row(y))
+    }
+
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [T]> + '_ {
+        let width = self.width;
+        let mut rest = &mut self.cells[..];
+        (0..self.height).map(move |_| {
+            let (row, tail) = mem::take(&mut rest).split_at_mut(width);
+            rest = tail;
+            row
+        })
+    }
+
+    // The cells of column `x`, top to bottom. Panics if `x` is out of
+    // bounds.
+    pub fn column(&self, x: usize) -> StepBy<slice::Iter<'_, T>> {
+        assert!(x < self.width, "column out of bounds");
+        // a grid with no rows has no cells at all to slice from
+        let cells = self.cells.get(x..).unwrap_or(&[]);
+        cells.iter().step_by(self.width)
+    }
+
+    pub fn column_mut(&mut self, x: usize) -> StepBy<slice::IterMut<'_, T>> {
+        assert!(x < self.width, "column out of bounds");
+        let cells = self.cells.get_mut(x..).unwrap_or(&mut []);
+        cells.iter_mut().step_by(self.width)
+    }
+
+    // the cells, row by row
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.cells.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, T> {
+        self.cells.iter_mut()
+    }
+
+    // every cell with its `(x, y)`, row by row
+    pub fn indexed_iter(
+        &self,
+    ) -> impl Iterator<Item = ((usize, usize), &T)> + '_ {
+        let width = self.width;
+        self.cells
+            .iter()
+            .enumerate()
+            .map(move |(i, cell)| ((i % width, i / width), cell))
+    }
+
+    pub fn fill(&mut self, value: T)
+    where
+        T: Clone,
+    {
+        self.cells.fill(value)
+    }
+
+    pub fn fill_with<F: FnMut() -> T>(&mut self, f: F) {
+        self.cells.fill_with(f)
+    }
+
+    // Changes the size to `width` by `height`. Cells that are still in
+    // bounds keep their contents, new ones are set to `value`, and the rest
+    // are dropped.
+    pub fn resize(&mut self, width: usize, height: usize, value: T)
+    where
+        T: Clone,
+        A: Clone,
+    {
+        if width == self.width {
+            self.height = self.height.min(height);
+            self.cells.truncate(area(width, self.height));
+            let guard = WholeRows(self);
+            guard.0.cells.resize(area(width, height), value);
+            mem::forget(guard);
+            self.height = height;
+            return;
+        }
+        let alloc = self.allocator().clone();
+        let mut cells =
+            NomVec::with_capacity_in(area(width, height), alloc.clone());
+        let (old_width, old_height) = (self.width, self.height);
+        let mut old =
+            mem::replace(&mut self.cells, NomVec::new_in(alloc)).into_iter();
+        // with no cells left, only an empty grid is consistent should
+        // `value.clone()` panic
+        self.height = 0;
+        for y in 0..height.max(old_height) {
+            for x in 0..old_width.max(width) {
+                let cell = if y < old_height && x < old_width {
+                    old.next()
+                } else {
+                    None
+                };
+                if y < height && x < width {
+                    cells.push(cell.unwrap_or_else(|| value.clone()));
+                }
+            }
+        }
+        self.cells = cells;
+        self.width = width;
+        self.height = height;
+    }
+}
+
+// Cuts `cells` back to whole rows and counts them when dropped, so that a
+// `value.clone()` panicking partway through a row leaves a consistent grid.
+struct WholeRows<'a, T, A: Allocator>(&'a mut NomGrid<T, A>);
+
+impl<T, A: Allocator> Drop for WholeRows<'_, T, A> {
+    fn drop(&mut self) {
+        let grid = &mut *self.0;
+        grid.height = grid.cells.len().checked_div(grid.width).unwrap_or(0);
+        grid.cells.truncate(area(grid.width, grid.height));
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Clone for NomGrid<T, A> {
+    fn clone(&self) -> Self {
+        NomGrid {
+            cells: self.cells.clone(),
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator> fmt::Debug for NomGrid<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.rows()).finish()
+    }
+}
+
+impl<T: PartialEq, A: Allocator, B: Allocator> PartialEq<NomGrid<T, B>>
+    for NomGrid<T, A>
+{
+    fn eq(&self, other: &NomGrid<T, B>) -> bool {
+        self.size() == other.size() && self.cells[..] == other.cells[..]
+    }
+}
+
+impl<T: Eq, A: Allocator> Eq for NomGrid<T, A> {}
+
+impl<T: Hash, A: Allocator> Hash for NomGrid<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.size().hash(state);
+        self.cells[..].hash(state);
+    }
+}
+
+impl<T, A: Allocator> Index<(usize, usize)> for NomGrid<T, A> {
+    type Output = T;
+
+    // Panics if `(x, y)` is out of bounds.
+    fn index(&self, (x, y): (usize, usize)) -> &T {
+        match self.get(x, y) {
+            Some(cell) => cell,
+            None => panic!("cell ({}, {}) out of bounds", x, y),
+        }
+    }
+}
+
+impl<T, A: Allocator> IndexMut<(usize, usize)> for NomGrid<T, A> {
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut T {
+        match self.get_mut(x, y) {
+            Some(cell) => cell,
+            None => panic!("cell ({}, {}) out of bounds", x, y),
+        }
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a NomGrid<T, A> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_index_rows_columns() {
+        let mut grid = NomGrid::from_fn(4, 3, |x, y| x + 10 * y);
+        assert_eq!(grid.size(), (4, 3));
+        assert_eq!(grid[(2, 1)], 12);
+        assert_eq!(grid.get(4, 0), None);
+        assert_eq!(grid.get(0, 3), None);
+        assert_eq!(grid.row(2), [20, 21, 22, 23]);
+        assert!(grid.column(1).eq(&[1, 11, 21]));
+        assert!(grid.column(3).rev().eq(&[23, 13, 3]));
+
+        for cell in grid.column_mut(0) {
+            *cell = 0;
+        }
+        for (y, row) in grid.rows_mut().enumerate() {
+            row[3] = y;
+        }
+        grid[(1, 1)] = 99;
+        assert!(grid.rows().eq([
+            &[0, 1, 2, 0][..],
+            &[0, 99, 12, 1],
+            &[0, 21, 22, 2],
+        ]));
+        assert_eq!(
+            grid.indexed_iter().find(|&(_, &v)| v == 99),
+            Some(((1, 1), &99))
+        );
+        grid.fill(7);
+        assert!(grid.iter().all(|&v| v == 7));
+
+        let flat = NomVec::from([1, 2, 3, 4, 5, 6]);
+        let grid = NomGrid::from_vec(flat, 3).unwrap();
+        assert_eq!(grid.height(), 2);
+        assert!(NomGrid::from_vec(grid.into_vec(), 4).is_err());
+    }
+
+    #[test]
+    fn grid_resize_keeps_contents() {
+        let mut grid = NomGrid::from_fn(3, 2, |x, y| (x, y));
+        grid.resize(3, 3, (9, 9));
+        assert_eq!(grid.row(2), [(9, 9); 3]);
+        grid.resize(2, 4, (8, 8));
+        assert!(grid.rows().eq([
+            &[(0, 0), (1, 0)][..],
+            &[(0, 1), (1, 1)],
+            &[(9, 9), (9, 9)],
+            &[(8, 8), (8, 8)],
+        ]));
+        grid.resize(4, 1, (7, 7));
+        assert_eq!(grid.row(0), [(0, 0), (1, 0), (7, 7), (7, 7)]);
+        grid.resize(0, 5, (0, 0));
+        assert!(grid.is_empty());
+        assert_eq!(grid.rows().count(), 5);
+        assert_eq!(format!("{:?}", NomGrid::from_elem(2, 1, 0)), "[[0, 0]]");
+    }
+
+    #[test]
+    fn grid_columns_of_an_empty_grid() {
+        let mut flat = NomGrid::from_elem(3, 0, 0);
+        assert_eq!(flat.column(1).count(), 0);
+        assert_eq!(flat.column_mut(2).count(), 0);
+    }
+
+    #[test]
+    fn grid_resize_survives_a_panicking_clone() {
+        struct Bomb(bool);
+        impl Clone for Bomb {
+            fn clone(&self) -> Self {
+                assert!(!self.0, "clone failed");
+                Bomb(self.0)
+            }
+        }
+        let mut grid = NomGrid::from_fn(2, 2, |_, _| Bomb(false));
+        let result =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                grid.resize(3, 3, Bomb(true))
+            }));
+        assert!(result.is_err());
+        // whatever was lost, every coordinate in bounds has a cell
+        assert_eq!(grid.iter().count(), grid.width() * grid.height());
+        assert!(grid.get(1, 1).is_none());
+
+        // the second clone panics, partway through the first new row
+        let clones = std::cell::Cell::new(0);
+        struct Fuse<'a>(&'a std::cell::Cell<usize>);
+        impl Clone for Fuse<'_> {
+            fn clone(&self) -> Self {
+                self.0.set(self.0.get() + 1);
+                assert!(self.0.get() < 2, "clone failed");
+                Fuse(self.0)
+            }
+        }
+        let mut grid = NomGrid::from_fn(3, 2, |_, _| Fuse(&clones));
+        let result =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                grid.resize(3, 4, Fuse(&clones))
+            }));
+        assert!(result.is_err());
+        assert_eq!((grid.width(), grid.height()), (3, 2));
+        assert_eq!(grid.iter().count(), 6);
+    }
+}
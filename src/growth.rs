@@ -0,0 +1,150 @@
+// How a vector picks its next capacity once it runs out of room. The vector
+// clamps whatever comes back to at least `required`, and capacities too
+// large to allocate still surface as `CapacityOverflow`, so a policy only
+// has to describe the shape of the curve. Policies are plain values (the
+// third type parameter of `NomVec`) and compose by wrapping each other:
+//
+//     let policy = PageRounded::new(4096, MinCapacity::new(64, Exponential::new(3, 2)));
+use std::cmp;
+
+pub trait GrowthPolicy: Clone {
+    // `cap` is the current capacity, `required` the smallest capacity that
+    // satisfies the pending request, and `elem_size` the size of one
+    // element in bytes (never 0: zero-sized types don't grow).
+    fn grow(&self, cap: usize, required: usize, elem_size: usize) -> usize;
+}
+
+// The default: double the capacity, or jump straight to `required` if that's
+// bigger.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct Doubling;
+
+impl GrowthPolicy for Doubling {
+    fn grow(&self, cap: usize, required: usize, _elem_size: usize) -> usize {
+        cmp::max(cap.saturating_mul(2), required)
+    }
+}
+
+// Grows by a factor of `num / den`, e.g. `Exponential::new(3, 2)` for 1.5x.
+// Always grows by at least one element, so small capacities still move.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Exponential {
+    num: usize,
+    den: usize,
+}
+
+impl Exponential {
+    pub const fn new(num: usize, den: usize) -> Self {
+        assert!(
+            den != 0 && num > den,
+            "growth factor must be greater than 1"
+        );
+        Exponential { num, den }
+    }
+}
+
+impl GrowthPolicy for Exponential {
+    fn grow(&self, cap: usize, required: usize, _elem_size: usize) -> usize {
+        let grown = cap as u128 * self.num as u128 / self.den as u128;
+        let grown = cmp::min(grown, usize::MAX as u128) as usize;
+        cmp::max(cmp::max(grown, cap.saturating_add(1)), required)
+    }
+}
+
+// Never allocates fewer than `min` elements, which skips the tiny
+// reallocations at the start of a vector's life.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MinCapacity<P = Doubling> {
+    min: usize,
+    inner: P,
+}
+
+impl<P: GrowthPolicy> MinCapacity<P> {
+    pub const fn new(min: usize, inner: P) -> Self {
+        MinCapacity { min, inner }
+    }
+}
+
+impl<P: GrowthPolicy> GrowthPolicy for MinCapacity<P> {
+    fn grow(&self, cap: usize, required: usize, elem_size: usize) -> usize {
+        cmp::max(self.inner.grow(cap, required, elem_size), self.min)
+    }
+}
+
+// Rounds every allocation up to a whole number of `page_size` bytes, so the
+// slack the allocator would hand out anyway becomes usable capacity.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PageRounded<P = Doubling> {
+    page_size: usize,
+    inner: P,
+}
+
+impl<P: GrowthPolicy> PageRounded<P> {
+    pub const fn new(page_size: usize, inner: P) -> Self {
+        assert!(
+            page_size.is_power_of_two(),
+            "page size must be a power of two"
+        );
+        PageRounded { page_size, inner }
+    }
+}
+
+impl<P: GrowthPolicy> GrowthPolicy for PageRounded<P> {
+    fn grow(&self, cap: usize, required: usize, elem_size: usize) -> usize {
+        let cap = self.inner.grow(cap, required, elem_size);
+        // on overflow just pass the inner answer through and let the vector
+        // report it
+        match cap
+            .checked_mul(elem_size)
+            .and_then(|bytes| bytes.checked_add(self.page_size - 1))
+        {
+            Some(bytes) => (bytes & !(self.page_size - 1)) / elem_size,
+            None => cap,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NomVec;
+
+    #[test]
+    fn growth_policies() {
+        assert_eq!(Doubling.grow(4, 5, 8), 8);
+        assert_eq!(Doubling.grow(4, 20, 8), 20);
+
+        let half = Exponential::new(3, 2);
+        assert_eq!(half.grow(0, 1, 8), 1);
+        assert_eq!(half.grow(1, 2, 8), 2);
+        assert_eq!(half.grow(10, 11, 8), 15);
+        assert_eq!(half.grow(usize::MAX - 1, usize::MAX, 1), usize::MAX);
+
+        let min = MinCapacity::new(16, Doubling);
+        assert_eq!(min.grow(0, 1, 4), 16);
+        assert_eq!(min.grow(16, 17, 4), 32);
+
+        let paged = PageRounded::new(4096, Doubling);
+        assert_eq!(paged.grow(0, 1, 8), 512);
+        assert_eq!(paged.grow(512, 513, 8), 1024);
+        assert_eq!(paged.grow(0, 1, 3000), 1);
+    }
+
+    #[test]
+    fn growth_policy_drives_vec() {
+        let mut cv: NomVec<u32, _, _> = NomVec::with_policy_in(
+            crate::Global,
+            MinCapacity::new(10, Exponential::new(3, 2)),
+        );
+        cv.push(1);
+        assert_eq!(cv.capacity(), 10);
+        cv.extend(0..10);
+        assert_eq!(cv.capacity(), 15);
+        assert_eq!(cv.len(), 11);
+
+        // the policy survives splitting and cloning
+        let tail = cv.split_off(5);
+        assert_eq!(tail.policy(), cv.policy());
+        assert_eq!(cv.clone().policy(), cv.policy());
+    }
+}
@@ -0,0 +1,376 @@
+// A binary heap priority queue stored in a NomVec, so it can live in any
+// allocator. The ordering comes from a `Compare` value rather than `Ord`
+// alone: the default `MaxFirst` pops the greatest element first like std's
+// BinaryHeap, `MinFirst` reverses that, and any `Fn(&T, &T) -> Ordering`
+// closure works too. Whatever compares greatest is popped first.
+use crate::{Allocator, Global, NomVec};
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::{FromIterator, FusedIterator};
+use std::mem;
+use std::slice;
+
+pub trait Compare<T: ?Sized> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+// pops the greatest element first
+#[derive(Copy, Clone, Default, Debug)]
+pub struct MaxFirst;
+
+// pops the smallest element first
+#[derive(Copy, Clone, Default, Debug)]
+pub struct MinFirst;
+
+impl<T: Ord + ?Sized> Compare<T> for MaxFirst {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+impl<T: Ord + ?Sized> Compare<T> for MinFirst {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        b.cmp(a)
+    }
+}
+
+impl<T: ?Sized, F: Fn(&T, &T) -> Ordering> Compare<T> for F {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
+
+pub struct NomBinaryHeap<T, A: Allocator = Global, C: Compare<T> = MaxFirst> {
+    data: NomVec<T, A>,
+    cmp: C,
+}
+
+impl<T: Ord> NomBinaryHeap<T> {
+    pub const fn new() -> Self {
+        Self::with_comparator_in(MaxFirst, Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        NomBinaryHeap {
+            data: NomVec::with_capacity(capacity),
+            cmp: MaxFirst,
+        }
+    }
+}
+
+impl<T, C: Compare<T>> NomBinaryHeap<T, Global, C> {
+    pub const fn with_comparator(cmp: C) -> Self {
+        Self::with_comparator_in(cmp, Global)
+    }
+}
+
+impl<T: Ord, A: Allocator> NomBinaryHeap<T, A> {
+    pub const fn new_in(alloc: A) -> Self {
+        Self::with_comparator_in(MaxFirst, alloc)
+    }
+}
+
+impl<T, A: Allocator, C: Compare<T>> NomBinaryHeap<T, A, C> {
+    pub const fn with_comparator_in(cmp: C, alloc: A) -> Self {
+        NomBinaryHeap {
+            data: NomVec::new_in(alloc),
+            cmp,
+        }
+    }
+
+    // Heapifies `vec` in place, in O(n).
+    pub fn from_vec_with_comparator(vec: NomVec<T, A>, cmp: C) -> Self {
+        let mut heap = NomBinaryHeap { data: vec, cmp };
+        heap.rebuild();
+        heap
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.data.allocator()
+    }
+
+    pub fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.data.reserve(additional)
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.data.shrink_to_fit()
+    }
+
+    fn greater(&self, a: usize, b: usize) -> bool {
+        self.cmp.compare(&self.data[a], &self.data[b]) == Ordering::Greater
+    }
+
+    fn sift_up(&mut self, mut pos: usize) {
+        while pos > 0 {
+            let parent = (pos - 1) / 2;
+            if !self.greater(pos, parent) {
+                break;
+            }
+            self.data.swap(pos, parent);
+            pos = parent;
+        }
+    }
+
+    // sifts `pos` down within the first `end` elements
+    fn sift_down(&mut self, mut pos: usize, end: usize) {
+        loop {
+            let mut child = 2 * pos + 1;
+            if child >= end {
+                break;
+            }
+            if child + 1 < end && self.greater(child + 1, child) {
+                child += 1;
+            }
+            if !self.greater(child, pos) {
+                break;
+            }
+            self.data.swap(pos, child);
+            pos = child;
+        }
+    }
+
+    fn rebuild(&mut self) {
+        let len = self.len();
+        for pos in (0..len / 2).rev() {
+            self.sift_down(pos, len);
+        }
+    }
+
+    pub fn push(&mut self, elem: T) {
+        self.data.push(elem);
+        self.sift_up(self.len() - 1);
+    }
+
+    // removes the element that compares greatest
+    pub fn pop(&mut self) -> Option<T> {
+        let mut elem = self.data.pop()?;
+        if !self.is_empty() {
+            mem::swap(&mut elem, &mut self.data[0]);
+            self.sift_down(0, self.len());
+        }
+        Some(elem)
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.data.first()
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear()
+    }
+
+    // moves all of `other`'s elements into this heap
+    pub fn append(&mut self, other: &mut Self) {
+        self.reserve(other.len());
+        for elem in other.data.drain(..) {
+            self.push(elem);
+        }
+    }
+
+    // in no particular order
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    // the underlying vector, in no particular order
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn into_vec(self) -> NomVec<T, A> {
+        self.data
+    }
+
+    // Sorts in place, in ascending order by the comparator (so the element
+    // `pop` would have returned first ends up last).
+    pub fn into_sorted_vec(mut self) -> NomVec<T, A> {
+        for end in (1..self.len()).rev() {
+            self.data.swap(0, end);
+            self.sift_down(0, end);
+        }
+        self.data
+    }
+
+    // Removes everything, in no particular order.
+    pub fn drain(&mut self) -> crate::Drain<'_, T, A> {
+        self.data.drain(..)
+    }
+
+    // Removes elements in heap order, greatest first; any left when the
+    // iterator is dropped are removed too.
+    pub fn drain_sorted(&mut self) -> DrainSorted<'_, T, A, C> {
+        DrainSorted { heap: self }
+    }
+}
+
+impl<T: Ord, A: Allocator> From<NomVec<T, A>> for NomBinaryHeap<T, A> {
+    fn from(vec: NomVec<T, A>) -> Self {
+        NomBinaryHeap::from_vec_with_comparator(vec, MaxFirst)
+    }
+}
+
+impl<T, A: Allocator, C: Compare<T>> From<NomBinaryHeap<T, A, C>>
+    for NomVec<T, A>
+{
+    fn from(heap: NomBinaryHeap<T, A, C>) -> Self {
+        heap.into_vec()
+    }
+}
+
+impl<T: Ord> Default for NomBinaryHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone, C: Compare<T> + Clone> Clone
+    for NomBinaryHeap<T, A, C>
+{
+    fn clone(&self) -> Self {
+        NomBinaryHeap {
+            data: self.data.clone(),
+            cmp: self.cmp.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator, C: Compare<T>> fmt::Debug
+    for NomBinaryHeap<T, A, C>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T, A: Allocator, C: Compare<T>> Extend<T> for NomBinaryHeap<T, A, C> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T: Ord> FromIterator<T> for NomBinaryHeap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        NomBinaryHeap::from(iter.into_iter().collect::<NomVec<T>>())
+    }
+}
+
+// in no particular order
+impl<T, A: Allocator, C: Compare<T>> IntoIterator for NomBinaryHeap<T, A, C> {
+    type Item = T;
+    type IntoIter = crate::IntoIter<T, A>;
+    fn into_iter(self) -> crate::IntoIter<T, A> {
+        self.data.into_iter()
+    }
+}
+
+impl<'a, T, A: Allocator, C: Compare<T>> IntoIterator
+    for &'a NomBinaryHeap<T, A, C>
+{
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+pub struct DrainSorted<'a, T, A: Allocator, C: Compare<T>> {
+    heap: &'a mut NomBinaryHeap<T, A, C>,
+}
+
+impl<'a, T, A: Allocator, C: Compare<T>> Iterator for DrainSorted<'a, T, A, C> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.heap.pop()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+impl<'a, T, A: Allocator, C: Compare<T>> ExactSizeIterator
+    for DrainSorted<'a, T, A, C>
+{
+}
+
+impl<'a, T, A: Allocator, C: Compare<T>> FusedIterator
+    for DrainSorted<'a, T, A, C>
+{
+}
+
+impl<'a, T, A: Allocator, C: Compare<T>> Drop for DrainSorted<'a, T, A, C> {
+    fn drop(&mut self) {
+        // the order no longer matters
+        self.heap.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heap_pops_in_order() {
+        let mut heap = NomBinaryHeap::new();
+        assert_eq!(heap.pop(), None);
+        for &x in &[5, 1, 8, 3, 9, 2, 8] {
+            heap.push(x);
+        }
+        assert_eq!(heap.peek(), Some(&9));
+        assert_eq!(heap.len(), 7);
+        let popped: Vec<_> = std::iter::from_fn(|| heap.pop()).collect();
+        assert_eq!(popped, [9, 8, 8, 5, 3, 2, 1]);
+
+        let heap: NomBinaryHeap<_> = vec![4, 7, 1, 0].into_iter().collect();
+        assert_eq!(heap.clone().into_sorted_vec(), [0, 1, 4, 7]);
+        let mut heap = heap;
+        let mut other = NomBinaryHeap::from(NomVec::from([10, -1]));
+        heap.append(&mut other);
+        assert!(other.is_empty());
+        assert!(heap.drain_sorted().eq([10, 7, 4, 1, 0, -1]));
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn heap_custom_comparators() {
+        let mut min = NomBinaryHeap::with_comparator(MinFirst);
+        min.extend(vec![3, 1, 2]);
+        assert_eq!(min.pop(), Some(1));
+        assert_eq!(min.into_sorted_vec(), [3, 2]);
+
+        // by string length, longest first
+        let mut by_len =
+            NomBinaryHeap::with_comparator(|a: &&str, b: &&str| {
+                a.len().cmp(&b.len())
+            });
+        by_len.extend(vec!["ccc", "a", "dddd", "bb"]);
+        assert_eq!(by_len.peek(), Some(&"dddd"));
+        {
+            let mut drain = by_len.drain_sorted();
+            assert_eq!(drain.next(), Some("dddd"));
+            assert_eq!(drain.len(), 3);
+        }
+        // dropping the iterator early still empties the heap
+        assert!(by_len.is_empty());
+    }
+}
@@ -0,0 +1,327 @@
+// An allocator that backs large blocks with huge pages, so that a NomVec of
+// hundreds of millions of elements takes one TLB entry per 2 MiB (or 1 GiB)
+// rather than per 4 KiB. Blocks of at least one huge page are rounded up to
+// whole huge pages; smaller ones, and layouts aligned to more than a huge
+// page (on Windows, more than 64 KiB, all the fallback can promise), go to
+// `A`.
+//
+// Huge pages are asked for explicitly first (`MAP_HUGETLB` on Linux, which
+// needs pages reserved in `/proc/sys/vm/nr_hugepages`; `MEM_LARGE_PAGES`
+// on Windows, which needs SeLockMemoryPrivilege and has the one size).
+// When that fails the block falls back to ordinary pages, aligned to the
+// huge page size and on Linux advised for transparent huge pages, which the
+// kernel may or may not provide. `huge_blocks` and `fallback_blocks` say
+// how that went.
+//
+// Growing past the block's huge pages maps a new block and copies, so
+// reserve up front.
+use crate::mmap::{exact, round_up, sys};
+use crate::{AllocError, Allocator, Global};
+use std::alloc::Layout;
+use std::fmt;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HugePageSize {
+    Size2MiB,
+    // only Linux has these; elsewhere they fall back to ordinary pages
+    Size1GiB,
+}
+
+impl HugePageSize {
+    pub const fn bytes(self) -> usize {
+        match self {
+            HugePageSize::Size2MiB => 1 << 21,
+            HugePageSize::Size1GiB => 1 << 30,
+        }
+    }
+}
+
+pub struct HugePageAllocator<A: Allocator = Global> {
+    page_size: HugePageSize,
+    huge: AtomicUsize,
+    fallbacks: AtomicUsize,
+    alloc: A,
+}
+
+impl HugePageAllocator {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub const fn with_page_size(page_size: HugePageSize) -> Self {
+        Self::with_page_size_in(page_size, Global)
+    }
+}
+
+impl<A: Allocator> HugePageAllocator<A> {
+    // uses 2 MiB pages
+    pub const fn new_in(alloc: A) -> Self {
+        Self::with_page_size_in(HugePageSize::Size2MiB, alloc)
+    }
+
+    pub const fn with_page_size_in(page_size: HugePageSize, alloc: A) -> Self {
+        HugePageAllocator {
+            page_size,
+            huge: AtomicUsize::new(0),
+            fallbacks: AtomicUsize::new(0),
+            alloc,
+        }
+    }
+
+    pub fn page_size(&self) -> HugePageSize {
+        self.page_size
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    // the blocks mapped so far that got explicit huge pages
+    pub fn huge_blocks(&self) -> usize {
+        self.huge.load(Ordering::Relaxed)
+    }
+
+    // the blocks mapped so far that fell back to ordinary pages
+    pub fn fallback_blocks(&self) -> usize {
+        self.fallbacks.load(Ordering::Relaxed)
+    }
+
+    fn is_mapped(&self, layout: Layout) -> bool {
+        let page = self.page_size.bytes();
+        layout.size() >= page
+            && layout.align() <= page
+            && sys::can_align(layout.align())
+    }
+
+    fn rounded(&self, layout: Layout) -> Result<usize, AllocError> {
+        round_up(layout.size(), self.page_size.bytes())
+    }
+
+    fn map(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let page = self.page_size.bytes();
+        let size = self.rounded(layout)?;
+        let ptr = match sys::map_huge(size, page) {
+            Some(ptr) => {
+                self.huge.fetch_add(1, Ordering::Relaxed);
+                ptr
+            }
+            None => {
+                let ptr = sys::map_aligned(size, page).ok_or(AllocError)?;
+                unsafe { sys::advise_huge(ptr, size) };
+                self.fallbacks.fetch_add(1, Ordering::Relaxed);
+                ptr
+            }
+        };
+        Ok(NonNull::slice_from_raw_parts(ptr, size))
+    }
+
+    // Moves a block to a new one, copying `len` bytes.
+    unsafe fn relocate(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        len: usize,
+        zeroed: bool,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let new = if zeroed {
+            self.allocate_zeroed(new_layout)?
+        } else {
+            self.allocate(new_layout)?
+        };
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new.as_ptr() as *mut u8, len);
+        self.deallocate(ptr, old_layout);
+        Ok(new)
+    }
+
+    unsafe fn grow_impl(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        zeroed: bool,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        match (self.is_mapped(old_layout), self.is_mapped(new_layout)) {
+            (true, true)
+                if self.rounded(new_layout)? == self.rounded(old_layout)? =>
+            {
+                let size = self.rounded(old_layout)?;
+                if zeroed {
+                    // the rest of the block may have been written to
+                    let slack = size - old_layout.size();
+                    ptr::write_bytes(
+                        ptr.as_ptr().add(old_layout.size()),
+                        0,
+                        slack,
+                    );
+                }
+                Ok(NonNull::slice_from_raw_parts(ptr, size))
+            }
+            (false, false) if zeroed => exact(
+                self.alloc.grow_zeroed(ptr, old_layout, new_layout),
+                new_layout,
+            ),
+            (false, false) => {
+                exact(self.alloc.grow(ptr, old_layout, new_layout), new_layout)
+            }
+            _ => {
+                let len = old_layout.size();
+                self.relocate(ptr, old_layout, new_layout, len, zeroed)
+            }
+        }
+    }
+}
+
+impl Default for HugePageAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Allocator> fmt::Debug for HugePageAllocator<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HugePageAllocator")
+            .field("page_size", &self.page_size)
+            .field("huge_blocks", &self.huge_blocks())
+            .field("fallback_blocks", &self.fallback_blocks())
+            .finish()
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for HugePageAllocator<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if self.is_mapped(layout) {
+            self.map(layout)
+        } else {
+            exact(self.alloc.allocate(layout), layout)
+        }
+    }
+
+    // mapped blocks come zeroed either way
+    fn allocate_zeroed(
+        &self,
+        layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if self.is_mapped(layout) {
+            self.map(layout)
+        } else {
+            exact(self.alloc.allocate_zeroed(layout), layout)
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if self.is_mapped(layout) {
+            // the layout was good for `map`, so rounding can't overflow
+            sys::unmap(ptr, self.rounded(layout).unwrap());
+        } else {
+            self.alloc.deallocate(ptr, layout)
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.grow_impl(ptr, old_layout, new_layout, false)
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.grow_impl(ptr, old_layout, new_layout, true)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        match (self.is_mapped(old_layout), self.is_mapped(new_layout)) {
+            (true, true) => {
+                // both sizes are whole huge pages, so this never splits one
+                let old_size = self.rounded(old_layout)?;
+                let new_size = self.rounded(new_layout)?;
+                sys::trim(ptr, old_size, new_size);
+                Ok(NonNull::slice_from_raw_parts(ptr, new_size))
+            }
+            (false, false) => exact(
+                self.alloc.shrink(ptr, old_layout, new_layout),
+                new_layout,
+            ),
+            _ => {
+                let len = new_layout.size();
+                self.relocate(ptr, old_layout, new_layout, len, false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CountingAllocator, NomVec};
+
+    const MIB: usize = 1 << 20;
+
+    #[test]
+    fn hugepage_maps_large_buffers() {
+        let counting = CountingAllocator::new();
+        let huge = HugePageAllocator::new_in(&counting);
+        let mut cv: NomVec<u64, _> = NomVec::with_capacity_in(MIB, &huge);
+        // 8 MiB, whichever way it was mapped
+        assert_eq!(huge.huge_blocks() + huge.fallback_blocks(), 1);
+        if cfg!(unix) {
+            assert_eq!(cv.as_ptr() as usize % (2 * MIB), 0);
+        }
+        cv.extend(0..MIB as u64);
+        cv.push(1);
+        assert!(cv[..MIB].iter().copied().eq(0..MIB as u64));
+        assert_eq!(huge.huge_blocks() + huge.fallback_blocks(), 2);
+        assert_eq!(counting.stats().allocations, 0);
+
+        cv.truncate(10);
+        cv.shrink_to_fit();
+        assert!(cv.iter().copied().eq(0..10));
+        assert_eq!(counting.stats().live_bytes(), 80);
+    }
+
+    #[test]
+    fn hugepage_zeroed_growth_in_place() {
+        let huge = HugePageAllocator::new();
+        let layout = Layout::from_size_align(2 * MIB + 1, 8).unwrap();
+        let bigger = Layout::from_size_align(3 * MIB, 8).unwrap();
+        let smaller = Layout::from_size_align(2 * MIB, 8).unwrap();
+        unsafe {
+            let block = huge.allocate_zeroed(layout).unwrap();
+            assert_eq!(block.len(), 4 * MIB);
+            let bytes = block.as_ptr() as *mut u8;
+            // scribble past the layout, into the rest of the block
+            ptr::write_bytes(bytes, 0xff, block.len());
+            let grown = huge.grow_zeroed(block.cast(), layout, bigger).unwrap();
+            assert_eq!(grown.as_ptr() as *mut u8, bytes);
+            let all = std::slice::from_raw_parts(bytes, 4 * MIB);
+            assert!(all[..layout.size()].iter().all(|&b| b == 0xff));
+            assert!(all[layout.size()..].iter().all(|&b| b == 0));
+            let shrunk = huge.shrink(grown.cast(), bigger, smaller).unwrap();
+            assert_eq!(shrunk.len(), 2 * MIB);
+            assert_eq!(*bytes.add(2 * MIB - 1), 0xff);
+            huge.deallocate(shrunk.cast(), smaller);
+        }
+        assert_eq!(huge.huge_blocks() + huge.fallback_blocks(), 1);
+        let gib = HugePageAllocator::with_page_size(HugePageSize::Size1GiB);
+        assert_eq!(gib.page_size().bytes(), 1 << 30);
+        assert_eq!(
+            format!("{:?}", gib),
+            "HugePageAllocator { page_size: Size1GiB, huge_blocks: 0, \
+             fallback_blocks: 0 }"
+        );
+    }
+}
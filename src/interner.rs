@@ -0,0 +1,225 @@
+// A string interner: every distinct string is stored once, back to back in
+// a single byte buffer, and named by a `Symbol` (a `u32` index into a table
+// of spans). Resolving a symbol is an index plus a slice. Deduplication uses
+// an open-addressed table of symbols, so every allocation the interner makes
+// goes through its allocator.
+use crate::{Allocator, Global, NomVec};
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::str;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    // symbols are numbered from 0 in the order they were first interned
+    pub const fn as_u32(self) -> u32 {
+        self.0
+    }
+
+    pub const fn as_usize(self) -> usize {
+        self.0 as usize
+    }
+}
+
+pub struct Interner<A: Allocator + Clone = Global> {
+    bytes: NomVec<u8, A>,
+    // end offset of each symbol's bytes; it starts where the previous one
+    // ends
+    ends: NomVec<usize, A>,
+    // 0 for an empty slot, otherwise symbol + 1; the length is 0 or a power
+    // of two, and at most half the slots are used
+    table: NomVec<u32, A>,
+    hasher: RandomState,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<A: Allocator + Clone> Interner<A> {
+    pub fn new_in(alloc: A) -> Self {
+        Interner {
+            bytes: NomVec::new_in(alloc.clone()),
+            ends: NomVec::new_in(alloc.clone()),
+            table: NomVec::new_in(alloc),
+            hasher: RandomState::new(),
+        }
+    }
+
+    // the number of distinct strings interned
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    // the total size of the interned strings, in bytes
+    pub fn bytes_len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.bytes.allocator()
+    }
+
+    fn hash(&self, s: &str) -> usize {
+        self.hasher.hash_one(s) as usize
+    }
+
+    fn span(&self, index: usize) -> &str {
+        let start = match index {
+            0 => 0,
+            _ => self.ends[index - 1],
+        };
+        // only whole `&str`s are ever appended
+        unsafe {
+            str::from_utf8_unchecked(&self.bytes[start..self.ends[index]])
+        }
+    }
+
+    // the table slot holding `s`, or the empty one where it would go
+    fn find_slot(&self, s: &str, hash: usize) -> usize {
+        let mask = self.table.len() - 1;
+        let mut slot = hash & mask;
+        loop {
+            match self.table[slot] {
+                0 => return slot,
+                entry if self.span(entry as usize - 1) == s => return slot,
+                _ => slot = (slot + 1) & mask,
+            }
+        }
+    }
+
+    fn grow_table(&mut self) {
+        let new_len = if self.table.is_empty() {
+            16
+        } else {
+            self.table.len() * 2
+        };
+        let mut table =
+            NomVec::with_capacity_in(new_len, self.allocator().clone());
+        table.resize(new_len, 0);
+        let old = std::mem::replace(&mut self.table, table);
+        for entry in old.iter().copied().filter(|&entry| entry != 0) {
+            let hash = self.hash(self.span(entry as usize - 1));
+            let mask = new_len - 1;
+            let mut slot = hash & mask;
+            while self.table[slot] != 0 {
+                slot = (slot + 1) & mask;
+            }
+            self.table[slot] = entry;
+        }
+    }
+
+    // Returns the symbol for `s`, storing it first if it's new. Panics once
+    // more than `u32::MAX - 1` distinct strings have been interned.
+    pub fn intern(&mut self, s: &str) -> Symbol {
+        if (self.len() + 1) * 2 > self.table.len() {
+            self.grow_table();
+        }
+        let slot = self.find_slot(s, self.hash(s));
+        if self.table[slot] != 0 {
+            return Symbol(self.table[slot] - 1);
+        }
+        let symbol = match u32::try_from(self.len()) {
+            Ok(symbol) if symbol < u32::MAX => symbol,
+            _ => panic!("too many interned strings"),
+        };
+        self.bytes.extend_from_slice(s.as_bytes());
+        self.ends.push(self.bytes.len());
+        self.table[slot] = symbol + 1;
+        Symbol(symbol)
+    }
+
+    // the symbol for `s`, if it has been interned
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        if self.table.is_empty() {
+            return None;
+        }
+        match self.table[self.find_slot(s, self.hash(s))] {
+            0 => None,
+            entry => Some(Symbol(entry - 1)),
+        }
+    }
+
+    // Panics if `symbol` didn't come from this interner.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        match self.try_resolve(symbol) {
+            Some(s) => s,
+            None => panic!("unknown symbol {:?}", symbol),
+        }
+    }
+
+    pub fn try_resolve(&self, symbol: Symbol) -> Option<&str> {
+        if symbol.as_usize() < self.len() {
+            Some(self.span(symbol.as_usize()))
+        } else {
+            None
+        }
+    }
+
+    // every symbol with its string, in the order they were interned
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &str)> + '_ {
+        (0..self.len()).map(move |i| (Symbol(i as u32), self.span(i)))
+    }
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Allocator + Clone> fmt::Debug for Interner<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interner_dedups() {
+        let mut interner = Interner::new();
+        assert_eq!(interner.get("fn"), None);
+        let a = interner.intern("fn");
+        let b = interner.intern("let");
+        let empty = interner.intern("");
+        assert_eq!(interner.intern("fn"), a);
+        assert_eq!(interner.intern(""), empty);
+        assert_ne!(a, b);
+        assert_eq!(interner.len(), 3);
+        assert_eq!(interner.bytes_len(), 5);
+
+        assert_eq!(interner.resolve(a), "fn");
+        assert_eq!(interner.resolve(b), "let");
+        assert_eq!(interner.resolve(empty), "");
+        assert_eq!(interner.get("let"), Some(b));
+        assert_eq!(interner.try_resolve(Symbol(3)), None);
+        assert_eq!(a.as_u32(), 0);
+    }
+
+    #[test]
+    fn interner_many_symbols() {
+        let mut interner = Interner::new();
+        let symbols: Vec<_> = (0..1000)
+            .map(|i| interner.intern(&format!("ident_{}", i)))
+            .collect();
+        for (i, &symbol) in symbols.iter().enumerate() {
+            assert_eq!(symbol.as_usize(), i);
+            assert_eq!(interner.resolve(symbol), format!("ident_{}", i));
+            assert_eq!(interner.intern(&format!("ident_{}", i)), symbol);
+        }
+        assert_eq!(interner.len(), 1000);
+        assert!(interner.iter().map(|(s, _)| s).eq(symbols.iter().copied()));
+    }
+}
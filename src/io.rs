@@ -0,0 +1,153 @@
+use crate::{Allocator, NomVec};
+use std::io::{self, IoSlice, Read, Write};
+use std::mem::MaybeUninit;
+use std::ptr;
+
+// appends, just like `Write for Vec<u8>`; writes never come up short
+impl<A: Allocator> Write for NomVec<u8, A> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.extend_from_slice_copy(buf);
+        Ok(buf.len())
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let len = bufs.iter().map(|b| b.len()).sum();
+        self.reserve(len);
+        for buf in bufs {
+            self.extend_from_slice_copy(buf);
+        }
+        Ok(len)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.extend_from_slice_copy(buf);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// A safe `Read` impl is allowed to look at the buffer it's handed, so it
+// can't be given uninitialized memory. Instead of zero-filling up front like
+// `resize` would, spare bytes are zeroed the first time they're exposed and
+// reused from then on; the length only ever covers bytes actually read.
+unsafe fn zero_tail(
+    spare: &mut [MaybeUninit<u8>],
+    initialized: usize,
+) -> &mut [u8] {
+    let len = spare.len();
+    if initialized < len {
+        ptr::write_bytes(
+            spare.as_mut_ptr().add(initialized),
+            0,
+            len - initialized,
+        );
+    }
+    &mut *(spare as *mut [MaybeUninit<u8>] as *mut [u8])
+}
+
+impl<A: Allocator> NomVec<u8, A> {
+    // Performs a single read of at most `max` bytes directly into the spare
+    // capacity, returning how many bytes were appended.
+    pub fn read_from<R: Read>(
+        &mut self,
+        mut reader: R,
+        max: usize,
+    ) -> io::Result<usize> {
+        self.reserve(max);
+        let buf =
+            unsafe { zero_tail(&mut self.spare_capacity_mut()[..max], 0) };
+        let n = loop {
+            match reader.read(buf) {
+                Ok(n) => break n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        assert!(n <= max, "reader reported more bytes than it was given");
+        unsafe { self.set_len(self.len() + n) };
+        Ok(n)
+    }
+
+    // Reads until EOF, appending everything to the vector. Returns the number
+    // of bytes read.
+    pub fn read_to_end_from<R: Read>(
+        &mut self,
+        mut reader: R,
+    ) -> io::Result<usize> {
+        let start_len = self.len();
+        // how much of the spare capacity has already been zeroed
+        let mut initialized = 0;
+        loop {
+            if self.len() == self.capacity() {
+                self.reserve(32);
+            }
+            let spare = self.spare_capacity_mut();
+            let spare_len = spare.len();
+            let buf = unsafe { zero_tail(spare, initialized) };
+            match reader.read(buf) {
+                Ok(0) => return Ok(self.len() - start_len),
+                Ok(n) => {
+                    assert!(
+                        n <= spare_len,
+                        "reader reported more bytes than it was given"
+                    );
+                    initialized = spare_len - n;
+                    unsafe { self.set_len(self.len() + n) };
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    initialized = spare_len;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_write() {
+        let mut cv: NomVec<u8> = NomVec::new();
+        assert_eq!(cv.write(b"ab").unwrap(), 2);
+        write!(cv, "-{}-", 42).unwrap();
+        let bufs = [IoSlice::new(b"x"), IoSlice::new(b"yz")];
+        assert_eq!(cv.write_vectored(&bufs).unwrap(), 3);
+        cv.flush().unwrap();
+        assert_eq!(&cv[..], b"ab-42-xyz");
+    }
+
+    #[test]
+    fn io_read_from() {
+        let mut cv: NomVec<u8> = NomVec::from(*b">");
+        let mut src = &b"hello world"[..];
+        assert_eq!(cv.read_from(&mut src, 5).unwrap(), 5);
+        assert_eq!(&cv[..], b">hello");
+        assert_eq!(cv.read_from(&mut src, 100).unwrap(), 6);
+        assert_eq!(cv.read_from(&mut src, 100).unwrap(), 0);
+        assert_eq!(&cv[..], b">hello world");
+    }
+
+    #[test]
+    fn io_read_to_end_from() {
+        // hands out at most 3 bytes per read to exercise the loop
+        struct Trickle<'a>(&'a [u8]);
+        impl<'a> Read for Trickle<'a> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let n = buf.len().min(3).min(self.0.len());
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+
+        let data: Vec<u8> = (0..200).collect();
+        let mut cv: NomVec<u8> = NomVec::new();
+        assert_eq!(cv.read_to_end_from(Trickle(&data)).unwrap(), 200);
+        assert_eq!(cv, data);
+    }
+}
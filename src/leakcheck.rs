@@ -0,0 +1,243 @@
+// A test allocator that remembers every block it hands out until it's
+// freed, and panics when it's dropped with any still outstanding. That
+// catches the leaks that `mem::forget` on a Drain or IntoIter, or a
+// `ManuallyDrop` that's never dropped, leaves behind in code built on
+// NomVec. It also panics right away on a free of a block it never
+// handed out, or with a layout other than the block's.
+//
+// Blocks are looked up by address, so zero-sized ones, which all get the
+// same dangling pointer, aren't tracked.
+use crate::{AllocError, Allocator, Global, NomVec};
+use std::alloc::Layout;
+use std::collections::HashMap;
+use std::fmt;
+use std::ptr::NonNull;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+pub struct LeakCheckAllocator<A: Allocator = Global> {
+    // the live blocks' layouts, by address
+    live: Mutex<HashMap<usize, Layout>>,
+    alloc: A,
+}
+
+impl LeakCheckAllocator {
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<A: Allocator> LeakCheckAllocator<A> {
+    pub fn new_in(alloc: A) -> Self {
+        LeakCheckAllocator {
+            live: Mutex::new(HashMap::new()),
+            alloc,
+        }
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    fn live(&self) -> MutexGuard<'_, HashMap<usize, Layout>> {
+        // a poisoned lock is one of our own panics, the map is fine
+        self.live.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // the blocks handed out and not yet freed
+    pub fn outstanding(&self) -> usize {
+        self.live().len()
+    }
+
+    pub fn outstanding_bytes(&self) -> usize {
+        self.live().values().map(|layout| layout.size()).sum()
+    }
+
+    // The layouts of the outstanding blocks, biggest first.
+    pub fn report(&self) -> NomVec<Layout> {
+        let mut layouts: NomVec<Layout> =
+            self.live().values().copied().collect();
+        layouts.sort_unstable_by_key(|layout| (layout.size(), layout.align()));
+        layouts.reverse();
+        layouts
+    }
+
+    // Panics if anything is outstanding.
+    pub fn assert_no_leaks(&self) {
+        let (count, bytes) = (self.outstanding(), self.outstanding_bytes());
+        assert!(
+            count == 0,
+            "{} allocation(s) totalling {} bytes were never freed",
+            count,
+            bytes
+        );
+    }
+
+    // Forgets the outstanding blocks, so that dropping the allocator
+    // accepts them as intentional leaks.
+    pub fn forget_leaks(&self) {
+        self.live().clear()
+    }
+
+    fn track(
+        &self,
+        result: Result<NonNull<[u8]>, AllocError>,
+        layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if let Ok(block) = result {
+            if layout.size() != 0 {
+                self.live()
+                    .insert(block.as_ptr() as *mut u8 as usize, layout);
+            }
+        }
+        result
+    }
+
+    fn untrack(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
+        match self.live().remove(&(ptr.as_ptr() as usize)) {
+            Some(tracked) => assert!(
+                tracked == layout,
+                "block at {:p} allocated as {:?} but freed as {:?}",
+                ptr,
+                tracked,
+                layout
+            ),
+            None => panic!("freed a block at {:p} that isn't live", ptr),
+        }
+    }
+}
+
+impl Default for LeakCheckAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Allocator> Drop for LeakCheckAllocator<A> {
+    fn drop(&mut self) {
+        // don't turn another failure into an abort
+        if !thread::panicking() {
+            self.assert_no_leaks();
+        }
+    }
+}
+
+impl<A: Allocator> fmt::Debug for LeakCheckAllocator<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LeakCheckAllocator")
+            .field("outstanding", &self.outstanding())
+            .field("outstanding_bytes", &self.outstanding_bytes())
+            .finish()
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for LeakCheckAllocator<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.track(self.alloc.allocate(layout), layout)
+    }
+
+    fn allocate_zeroed(
+        &self,
+        layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.track(self.alloc.allocate_zeroed(layout), layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.untrack(ptr, layout);
+        self.alloc.deallocate(ptr, layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.grow(ptr, old_layout, new_layout);
+        if result.is_ok() {
+            self.untrack(ptr, old_layout);
+        }
+        self.track(result, new_layout)
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.grow_zeroed(ptr, old_layout, new_layout);
+        if result.is_ok() {
+            self.untrack(ptr, old_layout);
+        }
+        self.track(result, new_layout)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.shrink(ptr, old_layout, new_layout);
+        if result.is_ok() {
+            self.untrack(ptr, old_layout);
+        }
+        self.track(result, new_layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn leakcheck_catches_forgotten_iterators() {
+        let alloc = LeakCheckAllocator::new();
+        let mut cv = NomVec::from_iter_in(0u32..10, &alloc);
+        cv.shrink_to_fit();
+        mem::forget(cv.into_iter());
+        let kept: NomVec<u8, _> = NomVec::with_capacity_in(3, &alloc);
+        assert_eq!(alloc.outstanding(), 2);
+        assert_eq!(alloc.outstanding_bytes(), 43);
+        assert_eq!(
+            alloc.report(),
+            [Layout::array::<u32>(10).unwrap(), Layout::new::<[u8; 3]>()]
+        );
+        drop(kept);
+        let err = panic::catch_unwind(AssertUnwindSafe(|| drop(alloc)));
+        let message = *err.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(
+            message,
+            "1 allocation(s) totalling 40 bytes were never freed"
+        );
+    }
+
+    #[test]
+    fn leakcheck_passes_clean_use() {
+        let alloc = LeakCheckAllocator::new();
+        {
+            let mut cv = NomVec::new_in(&alloc);
+            cv.extend(0..1000);
+            cv.drain(..500).for_each(drop);
+            cv.truncate(10);
+            cv.shrink_to_fit();
+            let zsts: NomVec<(), _> = NomVec::from_iter_in([(); 4], &alloc);
+            assert_eq!(zsts.len(), 4);
+        }
+        alloc.assert_no_leaks();
+        let forgotten: NomVec<u8, _> = NomVec::with_capacity_in(8, &alloc);
+        mem::forget(forgotten);
+        alloc.forget_leaks();
+        assert_eq!(
+            format!("{:?}", alloc),
+            "LeakCheckAllocator { outstanding: 0, outstanding_bytes: 0 }"
+        );
+    }
+}
@@ -1,19 +1,178 @@
+#![cfg_attr(
+    feature = "nightly",
+    feature(allocator_api, coerce_unsized, unsize)
+)]
+
+// The `Allocator` trait comes from one of three places: std's unstable one
+// on nightly, the `allocator-api2` polyfill (so allocators written against
+// it plug straight in), or our own stable stand-in. All three share the same
+// signatures, so the rest of the crate doesn't care which it gets.
+#[cfg(not(any(feature = "nightly", feature = "allocator-api2")))]
+mod allocator;
+#[cfg(feature = "arbitrary")]
+mod arbitrary_impl;
+mod arena;
+pub mod array;
+pub mod bitvec;
+#[cfg(feature = "borsh")]
+mod borsh_impl;
+mod builder;
+mod bump;
+#[cfg(feature = "bytemuck")]
+mod bytemuck_impl;
+#[cfg(feature = "bytes")]
+mod bytes_impl;
+mod compact;
+mod concurrent;
+mod counting;
+mod cow;
+mod cursor;
+pub mod deque;
+mod error;
+mod failing;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(all(feature = "mmap", feature = "bytemuck", any(unix, windows)))]
+mod filevec;
+pub mod frozen;
+mod grid;
+mod growth;
+pub mod heap;
+#[cfg(all(feature = "mmap", any(unix, windows)))]
+mod hugepage;
+mod interner;
+mod io;
+mod leakcheck;
+mod limited;
+#[cfg(all(feature = "mmap", any(unix, windows)))]
+mod mmap;
+pub mod multi;
+mod nombox;
+mod nombytes;
+mod ordermap;
+pub mod persistent;
+mod pinned;
+mod pool;
+#[cfg(feature = "proptest")]
+pub mod proptest;
+#[cfg(feature = "rayon")]
+mod rayon_impl;
+mod ring;
+#[cfg(feature = "rkyv")]
+mod rkyv_impl;
+pub mod rope;
+pub mod segmented;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod sharded;
+mod shared;
+pub mod slotmap;
+pub mod small;
+mod snapshot;
+mod sorted;
+mod sparse;
+pub mod stable;
+mod stack;
+mod string;
+#[cfg(test)]
+mod testing;
+mod thin;
+pub mod vecmap;
+
+#[cfg(not(any(feature = "nightly", feature = "allocator-api2")))]
+pub use allocator::{AllocError, Allocator, Global};
+#[cfg(all(feature = "allocator-api2", not(feature = "nightly")))]
+pub use allocator_api2::alloc::{AllocError, Allocator, Global};
+pub use arena::TypedArena;
+pub use array::ArrayNomVec;
+pub use bitvec::BitVec;
+pub use builder::NomVecBuilder;
+pub use bump::Bump;
+#[cfg(feature = "bytemuck")]
+pub use bytemuck_impl::{cast_nomvec, try_cast_nomvec};
+pub use compact::{CompactNomVec, IndexWidth};
+pub use concurrent::ConcurrentNomVec;
+pub use counting::{AllocStats, CountingAllocator};
+pub use cow::CowNomVec;
+pub use cursor::CursorMut;
+pub use deque::NomVecDeque;
+pub use error::AllocationError;
+pub use failing::FailingAllocator;
+#[cfg(all(feature = "mmap", feature = "bytemuck", any(unix, windows)))]
+pub use filevec::FileNomVec;
+pub use frozen::FrozenNomVec;
+pub use grid::NomGrid;
+pub use growth::{
+    Doubling, Exponential, GrowthPolicy, MinCapacity, PageRounded,
+};
+pub use heap::{Compare, MaxFirst, MinFirst, NomBinaryHeap};
+#[cfg(all(feature = "mmap", any(unix, windows)))]
+pub use hugepage::{HugePageAllocator, HugePageSize};
+pub use interner::{Interner, Symbol};
+pub use leakcheck::LeakCheckAllocator;
+pub use limited::LimitedAllocator;
+#[cfg(all(feature = "mmap", any(unix, windows)))]
+pub use mmap::MmapAllocator;
+pub use multi::{Columns, MultiNomVec};
+pub use nombox::NomBox;
+pub use nombytes::NomBytes;
+pub use ordermap::OrderMap;
+pub use persistent::PersistentNomVec;
+pub use pinned::PinnedNomVec;
+pub use pool::PoolAllocator;
+pub use ring::RingBuffer;
+pub use rope::Rope;
+pub use segmented::SegmentedNomVec;
+#[cfg(feature = "serde")]
+pub use serde_impl::NomVecSeed;
+pub use sharded::ShardedNomVec;
+pub use slotmap::{SlotKey, SlotMap};
+pub use small::SmallNomVec;
+pub use snapshot::{ArcNomVec, NomVecSnapshot};
+pub use sorted::SortedNomVec;
+pub use sparse::SparseVec;
+pub use stable::StableVec;
+pub use stack::StackAllocator;
+#[cfg(feature = "nightly")]
+pub use std::alloc::{AllocError, Allocator, Global};
+pub use string::{FromUtf8Error, NomString};
+pub use thin::ThinNomVec;
+pub use vecmap::{VecMap, VecSet};
+
 use std::alloc::{self, Layout};
+use std::cmp::{self, Ordering};
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, FusedIterator};
 use std::marker::PhantomData;
-use std::mem;
-use std::ops::{Deref, DerefMut};
+use std::mem::{self, MaybeUninit};
+use std::ops::{Bound, Deref, DerefMut, Range, RangeBounds};
 use std::ptr::{self, NonNull};
 
-struct RawVec<T> {
+struct RawVec<T, A: Allocator = Global, G: GrowthPolicy = Doubling> {
     ptr: NonNull<T>,
     cap: usize,
+    alloc: A,
+    policy: G,
     _marker: PhantomData<T>,
 }
 
-impl<T> RawVec<T> {
-    fn new() -> Self {
+// turns a fallible result into the panic/abort behavior of the infallible
+// APIs, mirroring what std does on allocation failure.
+fn handle_error(err: AllocationError) -> ! {
+    match err {
+        AllocationError::CapacityOverflow => panic!("capacity overflow"),
+        AllocationError::AllocFailed { layout } => {
+            alloc::handle_alloc_error(layout)
+        }
+    }
+}
+
+impl<T, A: Allocator, G: GrowthPolicy> RawVec<T, A, G> {
+    const fn new_in(alloc: A, policy: G) -> Self {
         let cap = if mem::size_of::<T>() == 0 {
-            ::std::usize::MAX
+            usize::MAX
         } else {
             0
         };
@@ -21,66 +180,266 @@ impl<T> RawVec<T> {
         RawVec {
             ptr: NonNull::dangling(),
             cap,
+            alloc,
+            policy,
             _marker: PhantomData,
         }
     }
 
-    // unchanged from Vec
-    fn grow(&mut self) {
-        // since we set the capacity to usize::MAX when elem_size is
-        // 0, getting to here necessarily means the Vec is overfull.
-        assert!(mem::size_of::<T>() != 0, "capacity overflow");
-
-        let (new_cap, new_layout) = if self.cap == 0 {
-            (1, Layout::array::<T>(1).unwrap())
+    // Safety: `ptr` must have been allocated by `alloc` with room for `cap`
+    // elements (or be dangling with `cap == 0`).
+    unsafe fn from_raw_parts_in(
+        ptr: *mut T,
+        cap: usize,
+        alloc: A,
+        policy: G,
+    ) -> Self {
+        let cap = if mem::size_of::<T>() == 0 {
+            usize::MAX
         } else {
-            // this cant overflow because we ensure self.cap <= isize::MAX
-            let new_cap = self.cap * 2;
-            (new_cap, Layout::array::<T>(new_cap).unwrap())
+            cap
         };
+        RawVec {
+            ptr: NonNull::new_unchecked(ptr),
+            cap,
+            alloc,
+            policy,
+            _marker: PhantomData,
+        }
+    }
 
-        assert!(
-            new_layout.size() <= ::std::isize::MAX as usize,
-            "Allocation too large"
-        );
+    fn with_capacity_in(capacity: usize, alloc: A, policy: G) -> Self {
+        match Self::try_with_capacity_in(capacity, alloc, policy) {
+            Ok(buf) => buf,
+            Err(err) => handle_error(err),
+        }
+    }
+
+    fn try_with_capacity_in(
+        capacity: usize,
+        alloc: A,
+        policy: G,
+    ) -> Result<Self, AllocationError> {
+        if mem::size_of::<T>() == 0 || capacity == 0 {
+            return Ok(Self::new_in(alloc, policy));
+        }
+        // Layout::array rejects anything over isize::MAX bytes for us
+        let layout = Layout::array::<T>(capacity)
+            .map_err(|_| AllocationError::CapacityOverflow)?;
+        let ptr = alloc
+            .allocate(layout)
+            .map_err(|_| AllocationError::AllocFailed { layout })?;
+        Ok(RawVec {
+            ptr: ptr.cast(),
+            cap: capacity,
+            alloc,
+            policy,
+            _marker: PhantomData,
+        })
+    }
 
-        let new_ptr = if self.cap == 0 {
-            unsafe { alloc::alloc(new_layout) }
+    fn current_memory(&self) -> Option<(NonNull<u8>, Layout)> {
+        if mem::size_of::<T>() == 0 || self.cap == 0 {
+            None
         } else {
-            let old_layout = Layout::array::<T>(self.cap).unwrap();
-            let old_ptr = self.ptr.as_ptr() as *mut u8;
-            unsafe { alloc::realloc(old_ptr, old_layout, new_layout.size()) }
-        };
+            // this was already checked when the buffer was allocated
+            let layout = Layout::array::<T>(self.cap).unwrap();
+            Some((self.ptr.cast(), layout))
+        }
+    }
+
+    fn needs_to_grow(&self, len: usize, additional: usize) -> bool {
+        additional > self.cap.wrapping_sub(len)
+    }
+
+    // the push/insert slow path: room for exactly one more element
+    #[cold]
+    fn grow_one(&mut self) {
+        if let Err(err) = self.grow_amortized(self.cap, 1) {
+            handle_error(err);
+        }
+    }
+
+    fn try_reserve(
+        &mut self,
+        len: usize,
+        additional: usize,
+    ) -> Result<(), AllocationError> {
+        if self.needs_to_grow(len, additional) {
+            self.grow_amortized(len, additional)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn reserve(&mut self, len: usize, additional: usize) {
+        if let Err(err) = self.try_reserve(len, additional) {
+            handle_error(err);
+        }
+    }
+
+    fn try_reserve_exact(
+        &mut self,
+        len: usize,
+        additional: usize,
+    ) -> Result<(), AllocationError> {
+        if self.needs_to_grow(len, additional) {
+            self.grow_exact(len, additional)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Both grow paths go from the current capacity straight to one that
+    // fits `len + additional`, in a single reallocation: bulk operations
+    // reserve their whole size upfront instead of stepping up one growth at
+    // a time. The policy may round the amortized target up further.
+    #[cold]
+    fn grow_amortized(
+        &mut self,
+        len: usize,
+        additional: usize,
+    ) -> Result<(), AllocationError> {
+        // since we set the capacity to usize::MAX when elem_size is
+        // 0, getting to here necessarily means the Vec is overfull.
+        let required = len
+            .checked_add(additional)
+            .ok_or(AllocationError::CapacityOverflow)?;
+        let new_cap = self.policy.grow(self.cap, required, mem::size_of::<T>());
+        self.finish_grow(cmp::max(new_cap, required))
+    }
 
-        // if allocation fails, `new_ptr` will be null in which case we will abort
-        self.ptr = match NonNull::new(new_ptr as *mut _) {
-            Some(p) => p,
-            None => alloc::handle_alloc_error(new_layout),
+    #[cold]
+    fn grow_exact(
+        &mut self,
+        len: usize,
+        additional: usize,
+    ) -> Result<(), AllocationError> {
+        let required = len
+            .checked_add(additional)
+            .ok_or(AllocationError::CapacityOverflow)?;
+        self.finish_grow(required)
+    }
+
+    fn finish_grow(&mut self, new_cap: usize) -> Result<(), AllocationError> {
+        // Layout::array rejects anything over isize::MAX bytes for us
+        let new_layout = Layout::array::<T>(new_cap)
+            .map_err(|_| AllocationError::CapacityOverflow)?;
+
+        let new_ptr = match self.current_memory() {
+            Some((ptr, old_layout)) => unsafe {
+                self.alloc.grow(ptr, old_layout, new_layout)
+            },
+            None => self.alloc.allocate(new_layout),
         };
+
+        // on failure the old allocation is left untouched
+        self.ptr = new_ptr
+            .map_err(|_| AllocationError::AllocFailed { layout: new_layout })?
+            .cast();
         self.cap = new_cap;
+        Ok(())
+    }
+
+    fn shrink(&mut self, cap: usize) {
+        if let Err(err) = self.try_shrink(cap) {
+            handle_error(err);
+        }
     }
+
+    fn try_shrink(&mut self, cap: usize) -> Result<(), AllocationError> {
+        assert!(cap <= self.cap, "Tried to shrink to a larger capacity");
+
+        // ZSTs and unallocated buffers have nothing to give back
+        let (ptr, layout) = match self.current_memory() {
+            Some(mem) => mem,
+            None => return Ok(()),
+        };
+
+        if cap == 0 {
+            unsafe { self.alloc.deallocate(ptr, layout) };
+            self.ptr = NonNull::dangling();
+        } else {
+            let new_layout = Layout::array::<T>(cap).unwrap();
+            let new_ptr = unsafe { self.alloc.shrink(ptr, layout, new_layout) }
+                .map_err(|_| AllocationError::AllocFailed {
+                    layout: new_layout,
+                })?;
+            self.ptr = new_ptr.cast();
+        }
+        self.cap = cap;
+        Ok(())
+    }
+}
+
+// RawVec owns its elements just like a Box<[T]> would, so it's exactly as
+// thread-safe as they (and the allocator) are.
+unsafe impl<T: Send, A: Allocator + Send, G: GrowthPolicy + Send> Send
+    for RawVec<T, A, G>
+{
+}
+unsafe impl<T: Sync, A: Allocator + Sync, G: GrowthPolicy + Sync> Sync
+    for RawVec<T, A, G>
+{
 }
 
-impl<T> Drop for RawVec<T> {
+impl<T, A: Allocator, G: GrowthPolicy> Drop for RawVec<T, A, G> {
     fn drop(&mut self) {
-        if self.cap != 0 {
-            let elem_size = mem::size_of::<T>();
-
-            // don't free zero-sized allocations, as they were never allocated.
-            if self.cap != 0 && elem_size != 0 {
-                let align = mem::align_of::<T>();
-                let num_bytes = elem_size * self.cap;
-                let layout = Layout::from_size_align(num_bytes, align).unwrap();
-                unsafe {
-                    alloc::dealloc(self.ptr.as_ptr() as *mut _, layout);
-                }
+        let elem_size = mem::size_of::<T>();
+
+        // don't free zero-sized allocations, as they were never allocated.
+        if self.cap != 0 && elem_size != 0 {
+            let layout = Layout::array::<T>(self.cap).unwrap();
+            unsafe {
+                self.alloc.deallocate(self.ptr.cast(), layout);
             }
         }
     }
 }
 
-pub struct NomVec<T> {
-    buf: RawVec<T>,
+// resolves any `RangeBounds` against a slice of length `len`, panicking on
+// ranges that are inverted or out of bounds.
+fn slice_range<R: RangeBounds<usize>>(range: R, len: usize) -> Range<usize> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start
+            .checked_add(1)
+            .expect("attempted to index slice from after maximum usize"),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end
+            .checked_add(1)
+            .expect("attempted to index slice up to maximum usize"),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+    assert!(
+        start <= end,
+        "slice index starts at {} but ends at {}",
+        start,
+        end
+    );
+    assert!(
+        end <= len,
+        "range end index {} out of range for slice of length {}",
+        end,
+        len
+    );
+    start..end
+}
+
+// never trust a length prefix from the wire for more than this many bytes
+// of up-front allocation; anything beyond it grows as elements arrive.
+#[cfg(any(feature = "serde", feature = "borsh"))]
+pub(crate) fn cautious_capacity<T>(hint: Option<usize>) -> usize {
+    const MAX_PREALLOC_BYTES: usize = 1024 * 1024;
+    let elem_size = cmp::max(mem::size_of::<T>(), 1);
+    cmp::min(hint.unwrap_or(0), MAX_PREALLOC_BYTES / elem_size)
+}
+
+pub struct NomVec<T, A: Allocator = Global, G: GrowthPolicy = Doubling> {
+    buf: RawVec<T, A, G>,
     len: usize,
 }
 
@@ -91,24 +450,237 @@ impl<T> Default for NomVec<T> {
 }
 
 impl<T> NomVec<T> {
+    // Nothing is allocated until the first push, so this can initialize
+    // `static`s and consts directly.
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+
+    // Decomposes the vector into `(ptr, len, capacity)`, e.g. to pass the
+    // buffer across an FFI boundary. The caller becomes responsible for the
+    // memory; `from_raw_parts` is the only way to free it.
+    pub fn into_raw_parts(self) -> (*mut T, usize, usize) {
+        let vec = mem::ManuallyDrop::new(self);
+        (vec.ptr(), vec.len, vec.cap())
+    }
+
+    /// # Safety
+    ///
+    /// See [`NomVec::from_raw_parts_in`]; the buffer must have come from the
+    /// global allocator.
+    pub unsafe fn from_raw_parts(ptr: *mut T, len: usize, cap: usize) -> Self {
+        Self::from_raw_parts_in(ptr, len, cap, Global)
+    }
+
+    // `Box<[T], A>` needs the unstable allocator API, so on stable only
+    // the global allocator case can be turned into a box; with `nightly`
+    // every allocator can, below.
+    #[cfg(not(feature = "nightly"))]
+    pub fn into_boxed_slice(mut self) -> Box<[T]> {
+        self.shrink_to_fit();
+        let vec = mem::ManuallyDrop::new(self);
+        unsafe {
+            Box::from_raw(ptr::slice_from_raw_parts_mut(vec.ptr(), vec.len))
+        }
+    }
+}
+
+impl<T, A: Allocator> NomVec<T, A> {
+    pub const fn new_in(alloc: A) -> Self {
+        Self {
+            buf: RawVec::new_in(alloc, Doubling),
+            len: 0,
+        }
+    }
+
+    // The box owns the buffer and frees it through the same allocator.
+    #[cfg(feature = "nightly")]
+    pub fn into_boxed_slice(mut self) -> Box<[T], A> {
+        self.shrink_to_fit();
+        let vec = mem::ManuallyDrop::new(self);
+        unsafe {
+            let alloc = ptr::read(&vec.buf.alloc);
+            let slice = ptr::slice_from_raw_parts_mut(vec.ptr(), vec.len);
+            Box::from_raw_in(slice, alloc)
+        }
+    }
+
+    // Like `into_raw_parts`, but for any allocator, which is handed back
+    // as well so the buffer can later be reassembled with `from_raw_parts_in`.
+    pub fn into_raw_parts_with_alloc(self) -> (*mut T, usize, usize, A) {
+        let vec = mem::ManuallyDrop::new(self);
+        let alloc = unsafe { ptr::read(&vec.buf.alloc) };
+        (vec.ptr(), vec.len, vec.cap(), alloc)
+    }
+
+    /// # Safety
+    ///
+    /// - `ptr` must be non-null and either allocated by `alloc` for exactly
+    ///   `cap` elements of `T` (the same `Layout` it will be freed with), or
+    ///   dangling and well-aligned with `cap == 0`. For zero-sized `T`,
+    ///   `cap` is ignored.
+    /// - `len` must be at most `cap`, and the first `len` elements must be
+    ///   initialized values of `T`.
+    /// - Ownership of the buffer moves into the vector: nothing else may use
+    ///   or free it afterwards.
+    ///
+    /// The parts returned by `into_raw_parts_with_alloc` always satisfy this.
+    pub unsafe fn from_raw_parts_in(
+        ptr: *mut T,
+        len: usize,
+        cap: usize,
+        alloc: A,
+    ) -> Self {
+        debug_assert!(!ptr.is_null());
+        debug_assert!(mem::size_of::<T>() == 0 || len <= cap);
+        NomVec {
+            buf: RawVec::from_raw_parts_in(ptr, cap, alloc, Doubling),
+            len,
+        }
+    }
+
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        Self {
+            buf: RawVec::with_capacity_in(capacity, alloc, Doubling),
+            len: 0,
+        }
+    }
+
+    pub fn try_with_capacity_in(
+        capacity: usize,
+        alloc: A,
+    ) -> Result<Self, AllocationError> {
+        Ok(Self {
+            buf: RawVec::try_with_capacity_in(capacity, alloc, Doubling)?,
+            len: 0,
+        })
+    }
+
+    pub fn from_iter_in<I>(iter: I, alloc: A) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let iter = iter.into_iter();
+        let (lower, _) = iter.size_hint();
+        let mut vec = Self::with_capacity_in(lower, alloc);
+        vec.extend(iter);
+        vec
+    }
+}
+
+impl<T, A: Allocator, G: GrowthPolicy> NomVec<T, A, G> {
     fn ptr(&self) -> *mut T {
         self.buf.ptr.as_ptr()
     }
 
-    fn cap(&self) -> usize {
+    const fn cap(&self) -> usize {
         self.buf.cap
     }
 
-    pub fn new() -> Self {
+    // Like `new_in`, but grows according to `policy` instead of doubling.
+    pub const fn with_policy_in(alloc: A, policy: G) -> Self {
         Self {
-            buf: RawVec::new(),
+            buf: RawVec::new_in(alloc, policy),
             len: 0,
         }
     }
 
+    pub fn policy(&self) -> &G {
+        &self.buf.policy
+    }
+
+    pub const fn capacity(&self) -> usize {
+        self.cap()
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.buf.alloc
+    }
+
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.ptr()
+    }
+
+    /// # Safety
+    ///
+    /// `new_len` must be at most `capacity()`, and the elements at
+    /// `old_len..new_len` must have been initialized (e.g. through
+    /// `spare_capacity_mut`). Shrinking this way leaks the cut-off elements
+    /// rather than dropping them.
+    pub unsafe fn set_len(&mut self, new_len: usize) {
+        debug_assert!(new_len <= self.cap());
+        self.len = new_len;
+    }
+
+    // the uninitialized remainder of the buffer, for external writers to
+    // fill before committing the length with `set_len`.
+    pub fn spare_capacity_mut(&mut self) -> &mut [MaybeUninit<T>] {
+        self.split_at_spare_mut().1
+    }
+
+    // the initialized elements and the spare capacity at once, so a decoder
+    // can read from the front while appending to the back.
+    pub fn split_at_spare_mut(&mut self) -> (&mut [T], &mut [MaybeUninit<T>]) {
+        // the two halves never overlap: one ends where the other begins
+        unsafe {
+            let init = ::std::slice::from_raw_parts_mut(self.ptr(), self.len);
+            let spare = ::std::slice::from_raw_parts_mut(
+                self.ptr().add(self.len) as *mut MaybeUninit<T>,
+                self.cap() - self.len,
+            );
+            (init, spare)
+        }
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        if let Err(err) = self.try_reserve(additional) {
+            handle_error(err);
+        }
+    }
+
+    pub fn reserve_exact(&mut self, additional: usize) {
+        if let Err(err) = self.try_reserve_exact(additional) {
+            handle_error(err);
+        }
+    }
+
+    pub fn try_reserve(
+        &mut self,
+        additional: usize,
+    ) -> Result<(), AllocationError> {
+        self.buf.try_reserve(self.len, additional)
+    }
+
+    pub fn try_reserve_exact(
+        &mut self,
+        additional: usize,
+    ) -> Result<(), AllocationError> {
+        self.buf.try_reserve_exact(self.len, additional)
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        if self.cap() > self.len {
+            self.buf.shrink(self.len);
+        }
+    }
+
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        if self.cap() > min_capacity {
+            self.buf.shrink(cmp::max(self.len, min_capacity));
+        }
+    }
+
     pub fn push(&mut self, elem: T) {
         if self.len == self.cap() {
-            self.buf.grow();
+            self.buf.grow_one();
         }
         unsafe {
             ptr::write(self.ptr().add(self.len), elem);
@@ -117,6 +689,27 @@ impl<T> NomVec<T> {
         self.len += 1;
     }
 
+    pub fn try_push(&mut self, elem: T) -> Result<(), AllocationError> {
+        self.try_reserve(1)?;
+        unsafe {
+            ptr::write(self.ptr().add(self.len), elem);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    // never allocates; hands the element back if the buffer is full
+    pub fn push_within_capacity(&mut self, elem: T) -> Result<(), T> {
+        if self.len == self.cap() {
+            return Err(elem);
+        }
+        unsafe {
+            ptr::write(self.ptr().add(self.len), elem);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
     pub fn pop(&mut self) -> Option<T> {
         if self.len == 0 {
             None
@@ -126,20 +719,41 @@ impl<T> NomVec<T> {
         }
     }
 
-    pub fn is_empty(&self) -> bool {
+    pub const fn is_empty(&self) -> bool {
         self.len == 0
     }
 
-    pub fn len(&self) -> usize {
+    pub const fn len(&self) -> usize {
         self.len
     }
 
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        unsafe {
+            let tail = ptr::slice_from_raw_parts_mut(
+                self.ptr().add(len),
+                self.len - len,
+            );
+            // update len first, so a panicking destructor can't leave us
+            // pointing at elements that were already dropped. drop_in_place
+            // keeps dropping the rest of the slice if one of them panics.
+            self.len = len;
+            ptr::drop_in_place(tail);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+
     pub fn insert(&mut self, index: usize, elem: T) {
         // Note: `<=` because it's valid to insert after everything
         // which would be equivalent to push.
         assert!(index <= self.len, "index out of bounds");
         if self.cap() == self.len {
-            self.buf.grow();
+            self.buf.grow_one();
         }
         unsafe {
             if index < self.len {
@@ -154,6 +768,23 @@ impl<T> NomVec<T> {
         }
     }
 
+    // splicing into an empty range opens the gap with a single ptr::copy
+    // when the length is known up front, and is panic safe.
+    pub fn insert_slice(&mut self, index: usize, other: &[T])
+    where
+        T: Clone,
+    {
+        self.insert_from_iter(index, other.iter().cloned())
+    }
+
+    pub fn insert_from_iter<I>(&mut self, index: usize, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        assert!(index <= self.len, "index out of bounds");
+        self.splice(index..index, iter);
+    }
+
     pub fn remove(&mut self, index: usize) -> T {
         assert!(index < self.len, "index out of bounds");
         unsafe {
@@ -168,255 +799,2101 @@ impl<T> NomVec<T> {
         }
     }
 
-    pub fn drain(&mut self) -> Drain<T> {
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "index out of bounds");
         unsafe {
-            let iter = RawValIter::new(self);
-            // this is a mem::forget safety thing. If Drain is forgotten, we just
-            // leak the whole Vec's contents. Also we need to do this *eventually*
-            // anyway, so why not do it now?
-            self.len = 0;
-            Drain {
-                iter,
-                vec: PhantomData,
-            }
+            // move the last element into the hole. if `index` is the last
+            // element the copy is a no-op overlap, which ptr::copy allows.
+            self.len -= 1;
+            let result = ptr::read(self.ptr().add(index));
+            ptr::copy(self.ptr().add(self.len), self.ptr().add(index), 1);
+            result
         }
     }
-}
 
-impl<T> Drop for NomVec<T> {
-    fn drop(&mut self) {
-        // deallocation is handled by RawVec
-        while self.pop().is_some() {}
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.retain_mut(|elem| f(elem))
     }
-}
 
-impl<T> Deref for NomVec<T> {
-    type Target = [T];
-    fn deref(&self) -> &[T] {
-        unsafe { ::std::slice::from_raw_parts(self.ptr(), self.len) }
+    pub fn retain_mut<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let original_len = self.len;
+        // if the guard below is leaked we leak the elements rather than
+        // risk exposing dropped or duplicated ones.
+        self.len = 0;
+
+        // Elements in `[0, processed - deleted)` are kept, `[processed,
+        // original_len)` are still unvisited, and the gap in between holds
+        // moved-out or dropped values. On drop (including unwinding out of
+        // `f` or a destructor) the unvisited tail is shifted over the gap.
+        struct BackshiftOnDrop<'a, T, A: Allocator, G: GrowthPolicy> {
+            vec: &'a mut NomVec<T, A, G>,
+            processed: usize,
+            deleted: usize,
+            original_len: usize,
+        }
+
+        impl<'a, T, A: Allocator, G: GrowthPolicy> Drop
+            for BackshiftOnDrop<'a, T, A, G>
+        {
+            fn drop(&mut self) {
+                if self.deleted > 0 {
+                    unsafe {
+                        ptr::copy(
+                            self.vec.ptr().add(self.processed),
+                            self.vec.ptr().add(self.processed - self.deleted),
+                            self.original_len - self.processed,
+                        );
+                    }
+                }
+                self.vec.len = self.original_len - self.deleted;
+            }
+        }
+
+        let mut g = BackshiftOnDrop {
+            vec: self,
+            processed: 0,
+            deleted: 0,
+            original_len,
+        };
+
+        while g.processed != original_len {
+            let cur = unsafe { &mut *g.vec.ptr().add(g.processed) };
+            if !f(cur) {
+                // bump the counters first so a panicking destructor doesn't
+                // get this element dropped a second time by the guard.
+                g.processed += 1;
+                g.deleted += 1;
+                unsafe { ptr::drop_in_place(cur) };
+                continue;
+            }
+            if g.deleted > 0 {
+                unsafe {
+                    let hole = g.vec.ptr().add(g.processed - g.deleted);
+                    ptr::copy_nonoverlapping(cur, hole, 1);
+                }
+            }
+            g.processed += 1;
+        }
     }
-}
 
-impl<T> DerefMut for NomVec<T> {
-    fn deref_mut(&mut self) -> &mut [T] {
-        unsafe { ::std::slice::from_raw_parts_mut(self.ptr(), self.len) }
+    pub fn dedup(&mut self)
+    where
+        T: PartialEq,
+    {
+        self.dedup_by(|a, b| a == b)
     }
-}
 
-impl<T> IntoIterator for NomVec<T> {
-    type Item = T;
-    type IntoIter = IntoIter<T>;
+    pub fn dedup_by_key<F, K>(&mut self, mut key: F)
+    where
+        F: FnMut(&mut T) -> K,
+        K: PartialEq,
+    {
+        self.dedup_by(|a, b| key(a) == key(b))
+    }
 
-    fn into_iter(self) -> IntoIter<T> {
-        unsafe {
-            // need to use ptr::read to unsafely move the buf out since it's
-            // not Copy, and Vec implements Drop (so we can't destructure it).
-            let iter = RawValIter::new(&self);
-            let buf = ptr::read(&self.buf);
-            mem::forget(self);
-            IntoIter { iter, _buf: buf }
+    pub fn dedup_by<F>(&mut self, mut same_bucket: F)
+    where
+        F: FnMut(&mut T, &mut T) -> bool,
+    {
+        let len = self.len;
+        if len <= 1 {
+            return;
         }
-    }
-}
 
-struct RawValIter<T> {
-    start: *const T,
-    end: *const T,
-}
+        // `[0, write)` holds the deduplicated prefix and `[read, len)` the
+        // unvisited suffix. If `same_bucket` or a destructor panics, the
+        // guard closes the gap between them so the vec stays valid.
+        struct FillGapOnDrop<'a, T, A: Allocator, G: GrowthPolicy> {
+            read: usize,
+            write: usize,
+            vec: &'a mut NomVec<T, A, G>,
+        }
 
-impl<T> RawValIter<T> {
-    // unsafe to construct because it has no associated lifetimes.
-    // This is necessary to store a RawValIter in the same struct as
-    // its actual allocation. OK since it's a private implementation
-    // detail.
-    unsafe fn new(slice: &[T]) -> Self {
-        RawValIter {
-            start: slice.as_ptr(),
-            end: if mem::size_of::<T>() == 0 {
-                ((slice.as_ptr() as usize) + slice.len()) as *const _
-            } else if slice.is_empty() {
-                // if `len = 0`, then this is not actually allocated memory.
-                // Need to avoid offsetting because that will give wrong
-                // information to LLVM via GEP.
-                slice.as_ptr()
-            } else {
-                slice.as_ptr().add(slice.len())
-            },
+        impl<'a, T, A: Allocator, G: GrowthPolicy> Drop for FillGapOnDrop<'a, T, A, G> {
+            fn drop(&mut self) {
+                let len = self.vec.len;
+                unsafe {
+                    ptr::copy(
+                        self.vec.ptr().add(self.read),
+                        self.vec.ptr().add(self.write),
+                        len - self.read,
+                    );
+                }
+                self.vec.len = self.write + (len - self.read);
+            }
         }
-    }
-}
 
-impl<T> Iterator for RawValIter<T> {
-    type Item = T;
-    fn next(&mut self) -> Option<T> {
-        if self.start == self.end {
-            None
-        } else {
-            unsafe {
-                let result = ptr::read(self.start);
-                self.start = if mem::size_of::<T>() == 0 {
-                    (self.start as usize + 1) as *const _
+        let ptr = self.ptr();
+        let mut gap = FillGapOnDrop {
+            read: 1,
+            write: 1,
+            vec: self,
+        };
+        unsafe {
+            while gap.read < len {
+                let read_ptr = ptr.add(gap.read);
+                let prev_ptr = ptr.add(gap.write - 1);
+                if same_bucket(&mut *read_ptr, &mut *prev_ptr) {
+                    // advance first so a panicking drop isn't repeated
+                    gap.read += 1;
+                    ptr::drop_in_place(read_ptr);
                 } else {
-                    self.start.offset(1)
-                };
-                Some(result)
+                    let write_ptr = ptr.add(gap.write);
+                    ptr::copy(read_ptr, write_ptr, 1);
+                    gap.write += 1;
+                    gap.read += 1;
+                }
             }
+            gap.vec.len = gap.write;
+            mem::forget(gap);
         }
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        let len =
-            (self.end as usize - self.start as usize) / mem::size_of::<T>();
-        (len, Some(len))
-    }
+    pub fn append(&mut self, other: &mut Self) {
+        let count = other.len;
+        self.reserve(count);
+        unsafe {
+            ptr::copy_nonoverlapping(
+                other.ptr(),
+                self.ptr().add(self.len),
+                count,
+            );
+            // ownership of the elements moved to self; other keeps its
+            // allocation for reuse.
+            other.len = 0;
+        }
+        self.len += count;
+    }
+
+    pub fn extend_from_slice(&mut self, other: &[T])
+    where
+        T: Clone,
+    {
+        self.reserve(other.len());
+        for elem in other {
+            unsafe {
+                ptr::write(self.ptr().add(self.len), elem.clone());
+            }
+            // bump len as we go so a panicking clone leaves us consistent
+            self.len += 1;
+        }
+    }
+
+    pub fn try_extend_from_slice(
+        &mut self,
+        other: &[T],
+    ) -> Result<(), AllocationError>
+    where
+        T: Clone,
+    {
+        // once the space is reserved the infallible path can't allocate
+        self.try_reserve(other.len())?;
+        self.extend_from_slice(other);
+        Ok(())
+    }
+
+    // `Copy` types can't have side effects when duplicated, so the whole
+    // slice is a single memcpy.
+    pub fn extend_from_slice_copy(&mut self, other: &[T])
+    where
+        T: Copy,
+    {
+        self.reserve(other.len());
+        unsafe {
+            ptr::copy_nonoverlapping(
+                other.as_ptr(),
+                self.ptr().add(self.len),
+                other.len(),
+            );
+        }
+        self.len += other.len();
+    }
+
+    // `Clone` without the per-element loop: one allocation and one memcpy.
+    pub fn clone_copy(&self) -> Self
+    where
+        T: Copy,
+        A: Clone,
+    {
+        let mut vec = NomVec::with_policy_in(
+            self.buf.alloc.clone(),
+            self.buf.policy.clone(),
+        );
+        vec.extend_from_slice_copy(self);
+        vec
+    }
+
+    pub fn extend_from_within<R>(&mut self, src: R)
+    where
+        R: RangeBounds<usize>,
+        T: Clone,
+    {
+        let range = slice_range(src, self.len);
+        self.reserve(range.len());
+        // the source region sits below `len` and we only ever write above
+        // it, so reading through the raw pointer never aliases a write.
+        for i in range {
+            unsafe {
+                let elem = (*self.ptr().add(i)).clone();
+                ptr::write(self.ptr().add(self.len), elem);
+            }
+            self.len += 1;
+        }
+    }
+
+    pub fn resize(&mut self, new_len: usize, value: T)
+    where
+        T: Clone,
+    {
+        if new_len > self.len {
+            self.extend_with(new_len - self.len, value)
+        } else {
+            self.truncate(new_len)
+        }
+    }
+
+    pub fn try_resize(
+        &mut self,
+        new_len: usize,
+        value: T,
+    ) -> Result<(), AllocationError>
+    where
+        T: Clone,
+    {
+        if new_len > self.len {
+            self.try_reserve(new_len - self.len)?;
+        }
+        self.resize(new_len, value);
+        Ok(())
+    }
+
+    // `resize` for `Copy` types. Nothing can panic while filling, so the
+    // length is bumped once at the end and the loop becomes a memset.
+    pub fn resize_copy(&mut self, new_len: usize, value: T)
+    where
+        T: Copy,
+    {
+        if new_len <= self.len {
+            self.truncate(new_len);
+            return;
+        }
+        let n = new_len - self.len;
+        self.reserve(n);
+        unsafe {
+            let dst = self.ptr().add(self.len);
+            for i in 0..n {
+                ptr::write(dst.add(i), value);
+            }
+        }
+        self.len = new_len;
+    }
+
+    pub fn resize_with<F>(&mut self, new_len: usize, mut f: F)
+    where
+        F: FnMut() -> T,
+    {
+        if new_len > self.len {
+            self.reserve(new_len - self.len);
+            while self.len < new_len {
+                unsafe {
+                    ptr::write(self.ptr().add(self.len), f());
+                }
+                self.len += 1;
+            }
+        } else {
+            self.truncate(new_len)
+        }
+    }
+
+    fn extend_with(&mut self, n: usize, value: T)
+    where
+        T: Clone,
+    {
+        self.reserve(n);
+        if n == 0 {
+            return;
+        }
+        for _ in 1..n {
+            unsafe {
+                ptr::write(self.ptr().add(self.len), value.clone());
+            }
+            self.len += 1;
+        }
+        // the last slot takes the original value, saving a clone
+        unsafe {
+            ptr::write(self.ptr().add(self.len), value);
+        }
+        self.len += 1;
+    }
+
+    pub fn leak<'a>(self) -> &'a mut [T]
+    where
+        A: 'a,
+    {
+        let vec = mem::ManuallyDrop::new(self);
+        unsafe { ::std::slice::from_raw_parts_mut(vec.ptr(), vec.len) }
+    }
+
+    pub fn split_off(&mut self, at: usize) -> Self
+    where
+        A: Clone,
+    {
+        let alloc = self.buf.alloc.clone();
+        self.split_off_in(at, alloc)
+    }
+
+    pub fn split_off_in<B: Allocator>(
+        &mut self,
+        at: usize,
+        alloc: B,
+    ) -> NomVec<T, B, G> {
+        assert!(at <= self.len, "`at` split index out of bounds");
+        let other_len = self.len - at;
+        let mut other = NomVec::with_policy_in(alloc, self.buf.policy.clone());
+        other.reserve_exact(other_len);
+        unsafe {
+            // the elements are moved, not copied: shortening self first
+            // means they're only ever owned by one of the two vectors.
+            self.len = at;
+            ptr::copy_nonoverlapping(
+                self.ptr().add(at),
+                other.ptr(),
+                other_len,
+            );
+            other.len = other_len;
+        }
+        other
+    }
+
+    // Maps every element through `f`. When `U` has the same size and
+    // alignment as `T` each result is written over the slot it came from,
+    // so the buffer is reused and peak memory stays at one allocation;
+    // otherwise the results are collected into a new buffer from the same
+    // allocator.
+    pub fn map_in_place<U, F>(self, mut f: F) -> NomVec<U, A, G>
+    where
+        F: FnMut(T) -> U,
+    {
+        if mem::size_of::<T>() != mem::size_of::<U>()
+            || mem::align_of::<T>() != mem::align_of::<U>()
+        {
+            return self.map_into_new(f);
+        }
+
+        // Slots `[0, mapped)` hold `U`s and `[taken, len)` still hold `T`s;
+        // while `f` runs, the slot in between has been moved out. Unwinding
+        // drops both halves, and `vec` (emptied) frees the buffer after.
+        struct MapGuard<T, U> {
+            ptr: *mut T,
+            len: usize,
+            mapped: usize,
+            taken: usize,
+            _marker: PhantomData<U>,
+        }
+
+        impl<T, U> Drop for MapGuard<T, U> {
+            fn drop(&mut self) {
+                unsafe {
+                    ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                        self.ptr as *mut U,
+                        self.mapped,
+                    ));
+                    ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                        self.ptr.add(self.taken),
+                        self.len - self.taken,
+                    ));
+                }
+            }
+        }
+
+        let mut vec = self;
+        let len = vec.len;
+        vec.len = 0;
+        let mut g = MapGuard::<T, U> {
+            ptr: vec.ptr(),
+            len,
+            mapped: 0,
+            taken: 0,
+            _marker: PhantomData,
+        };
+        while g.taken != len {
+            unsafe {
+                let slot = g.ptr.add(g.taken);
+                let elem = ptr::read(slot);
+                g.taken += 1;
+                ptr::write(slot as *mut U, f(elem));
+            }
+            g.mapped += 1;
+        }
+        mem::forget(g);
+
+        // same size and alignment means the same Layout on free
+        let vec = mem::ManuallyDrop::new(vec);
+        unsafe {
+            NomVec {
+                buf: RawVec::from_raw_parts_in(
+                    vec.ptr() as *mut U,
+                    vec.cap(),
+                    ptr::read(&vec.buf.alloc),
+                    ptr::read(&vec.buf.policy),
+                ),
+                len,
+            }
+        }
+    }
+
+    fn map_into_new<U, F>(self, mut f: F) -> NomVec<U, A, G>
+    where
+        F: FnMut(T) -> U,
+    {
+        let mut src = self.into_iter();
+        let (ptr, cap, len) = {
+            // borrowing the allocator and the elements separately lets the
+            // new buffer come from the same allocator while we still own it
+            let mut out = NomVec::with_capacity_in(src.len(), &src.buf.alloc);
+            for elem in &mut src.iter {
+                out.push(f(elem));
+            }
+            let out = mem::ManuallyDrop::new(out);
+            (out.ptr(), out.cap(), out.len)
+        };
+        // every element has been moved out, so only the old buffer is left
+        // to free before the allocator can be handed to the new vector
+        let src = mem::ManuallyDrop::new(src);
+        unsafe {
+            if let Some((ptr, layout)) = src.buf.current_memory() {
+                src.buf.alloc.deallocate(ptr, layout);
+            }
+            NomVec {
+                buf: RawVec::from_raw_parts_in(
+                    ptr,
+                    cap,
+                    ptr::read(&src.buf.alloc),
+                    ptr::read(&src.buf.policy),
+                ),
+                len,
+            }
+        }
+    }
+
+    // Replaces `range` with the contents of `replace_with`, yielding the
+    // removed elements. The replacement happens when the Splice is dropped.
+    pub fn splice<R, I>(
+        &mut self,
+        range: R,
+        replace_with: I,
+    ) -> Splice<'_, I::IntoIter, A, G>
+    where
+        R: RangeBounds<usize>,
+        I: IntoIterator<Item = T>,
+    {
+        Splice {
+            drain: self.drain(range),
+            replace_with: replace_with.into_iter(),
+        }
+    }
+
+    // Lazily removes and yields every element matching `filter`. Elements
+    // left unvisited when the iterator is dropped early are retained.
+    pub fn extract_if<F>(&mut self, filter: F) -> ExtractIf<'_, T, F, A, G>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let old_len = self.len;
+        // guard against us getting leaked (leak amplification)
+        self.len = 0;
+        ExtractIf {
+            vec: self,
+            idx: 0,
+            del: 0,
+            old_len,
+            pred: filter,
+        }
+    }
+
+    pub fn drain<R>(&mut self, range: R) -> Drain<'_, T, A, G>
+    where
+        R: RangeBounds<usize>,
+    {
+        let len = self.len;
+        let Range { start, end } = slice_range(range, len);
+        unsafe {
+            // this is a mem::forget safety thing. If Drain is forgotten, we
+            // just leak the drained range and the tail. Drain puts the tail
+            // back (and fixes up len) when it's dropped.
+            self.len = start;
+            let range_slice = ::std::slice::from_raw_parts(
+                self.ptr().add(start),
+                end - start,
+            );
+            Drain {
+                tail_start: end,
+                tail_len: len - end,
+                iter: RawValIter::new(range_slice),
+                vec: NonNull::from(self),
+                _marker: PhantomData,
+            }
+        }
+    }
+}
+
+impl<T, A: Allocator, G: GrowthPolicy> Drop for NomVec<T, A, G> {
+    fn drop(&mut self) {
+        // Drop the elements in one pass; if one of their destructors
+        // panics, the slice drop glue still drops the rest. Deallocation is
+        // handled by RawVec.
+        if mem::needs_drop::<T>() {
+            unsafe {
+                ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                    self.ptr(),
+                    self.len,
+                ));
+            }
+        }
+    }
+}
+
+impl<T, A: Allocator, G: GrowthPolicy> Deref for NomVec<T, A, G> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        unsafe { ::std::slice::from_raw_parts(self.ptr(), self.len) }
+    }
+}
+
+impl<T, A: Allocator, G: GrowthPolicy> DerefMut for NomVec<T, A, G> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { ::std::slice::from_raw_parts_mut(self.ptr(), self.len) }
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone, G: GrowthPolicy> Clone
+    for NomVec<T, A, G>
+{
+    fn clone(&self) -> Self {
+        let mut vec = NomVec::with_policy_in(
+            self.buf.alloc.clone(),
+            self.buf.policy.clone(),
+        );
+        vec.reserve_exact(self.len);
+        vec.extend_from_slice(self);
+        vec
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        // drop anything that won't be overwritten
+        self.truncate(source.len);
+
+        // self.len <= source.len now, so this split can't panic. the
+        // overlapping prefix goes through T::clone_from so elements can
+        // reuse their own resources too.
+        let (init, tail) = source.split_at(self.len);
+        self.clone_from_slice(init);
+        self.extend_from_slice(tail);
+    }
+}
+
+macro_rules! impl_slice_eq {
+    ([$($vars:tt)*] $lhs:ty, $rhs:ty) => {
+        impl<T, U, $($vars)*> PartialEq<$rhs> for $lhs
+        where
+            T: PartialEq<U>,
+        {
+            #[inline]
+            fn eq(&self, other: &$rhs) -> bool {
+                self[..] == other[..]
+            }
+        }
+    };
+}
+
+impl_slice_eq! { [A: Allocator, G: GrowthPolicy, B: Allocator, H: GrowthPolicy] NomVec<T, A, G>, NomVec<U, B, H> }
+impl_slice_eq! { [A: Allocator, G: GrowthPolicy] NomVec<T, A, G>, [U] }
+impl_slice_eq! { [A: Allocator, G: GrowthPolicy] NomVec<T, A, G>, &[U] }
+impl_slice_eq! { [A: Allocator, G: GrowthPolicy] NomVec<T, A, G>, &mut [U] }
+impl_slice_eq! { [A: Allocator, G: GrowthPolicy] NomVec<T, A, G>, Vec<U> }
+impl_slice_eq! { [A: Allocator, G: GrowthPolicy, const N: usize] NomVec<T, A, G>, [U; N] }
+impl_slice_eq! { [A: Allocator, G: GrowthPolicy, const N: usize] NomVec<T, A, G>, &[U; N] }
+impl_slice_eq! { [A: Allocator, G: GrowthPolicy] [T], NomVec<U, A, G> }
+impl_slice_eq! { [A: Allocator, G: GrowthPolicy] &[T], NomVec<U, A, G> }
+impl_slice_eq! { [A: Allocator, G: GrowthPolicy] &mut [T], NomVec<U, A, G> }
+impl_slice_eq! { [A: Allocator, G: GrowthPolicy] Vec<T>, NomVec<U, A, G> }
+impl_slice_eq! { [A: Allocator, G: GrowthPolicy, const N: usize] [T; N], NomVec<U, A, G> }
+
+impl<T: Eq, A: Allocator, G: GrowthPolicy> Eq for NomVec<T, A, G> {}
+
+impl<T: PartialOrd, A: Allocator, G: GrowthPolicy> PartialOrd
+    for NomVec<T, A, G>
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        PartialOrd::partial_cmp(&**self, &**other)
+    }
+}
+
+impl<T: Ord, A: Allocator, G: GrowthPolicy> Ord for NomVec<T, A, G> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        Ord::cmp(&**self, &**other)
+    }
+}
+
+// hashes exactly like the equivalent slice (and therefore std's Vec)
+impl<T: Hash, A: Allocator, G: GrowthPolicy> Hash for NomVec<T, A, G> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Hash::hash(&**self, state)
+    }
+}
+
+// `{:?}` prints just like a slice; `{:#?}` additionally reports the
+// buffer's bookkeeping, which is handy when chasing capacity issues.
+impl<T: fmt::Debug, A: Allocator, G: GrowthPolicy> fmt::Debug
+    for NomVec<T, A, G>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            f.debug_struct("NomVec")
+                .field("len", &self.len)
+                .field("capacity", &self.cap())
+                .field("elements", &&**self)
+                .finish()
+        } else {
+            fmt::Debug::fmt(&**self, f)
+        }
+    }
+}
+
+// std's Vec also allocates through the global allocator, so its buffer can
+// be adopted (and handed back) as-is.
+impl<T> From<Vec<T>> for NomVec<T> {
+    fn from(vec: Vec<T>) -> Self {
+        let mut vec = mem::ManuallyDrop::new(vec);
+        unsafe {
+            NomVec {
+                buf: RawVec::from_raw_parts_in(
+                    vec.as_mut_ptr(),
+                    vec.capacity(),
+                    Global,
+                    Doubling,
+                ),
+                len: vec.len(),
+            }
+        }
+    }
+}
+
+impl<T> From<NomVec<T>> for Vec<T> {
+    fn from(vec: NomVec<T>) -> Self {
+        let vec = mem::ManuallyDrop::new(vec);
+        unsafe { Vec::from_raw_parts(vec.ptr(), vec.len, vec.cap()) }
+    }
+}
+
+impl<T: Clone> From<&[T]> for NomVec<T> {
+    fn from(slice: &[T]) -> Self {
+        let mut vec = NomVec::with_capacity(slice.len());
+        vec.extend_from_slice(slice);
+        vec
+    }
+}
+
+impl<T, const N: usize> From<[T; N]> for NomVec<T> {
+    fn from(array: [T; N]) -> Self {
+        let array = mem::ManuallyDrop::new(array);
+        let mut vec = NomVec::with_capacity(N);
+        unsafe {
+            ptr::copy_nonoverlapping(array.as_ptr(), vec.ptr(), N);
+        }
+        vec.len = N;
+        vec
+    }
+}
+
+// a boxed slice is exactly a full buffer from the global allocator
+impl<T> From<Box<[T]>> for NomVec<T> {
+    fn from(boxed: Box<[T]>) -> Self {
+        let len = boxed.len();
+        let ptr = Box::into_raw(boxed) as *mut T;
+        unsafe {
+            NomVec {
+                buf: RawVec::from_raw_parts_in(ptr, len, Global, Doubling),
+                len,
+            }
+        }
+    }
+}
+
+// fails with the untouched vector if the length doesn't match
+impl<T, A: Allocator, G: GrowthPolicy, const N: usize> TryFrom<NomVec<T, A, G>>
+    for [T; N]
+{
+    type Error = NomVec<T, A, G>;
+
+    fn try_from(mut vec: NomVec<T, A, G>) -> Result<[T; N], NomVec<T, A, G>> {
+        if vec.len != N {
+            return Err(vec);
+        }
+        // the elements are moved into the array; the buffer itself is
+        // still freed when `vec` goes out of scope.
+        vec.len = 0;
+        Ok(unsafe { ptr::read(vec.ptr() as *const [T; N]) })
+    }
+}
+
+impl<T, A: Allocator, G: GrowthPolicy> IntoIterator for NomVec<T, A, G> {
+    type Item = T;
+    type IntoIter = IntoIter<T, A, G>;
+
+    fn into_iter(self) -> IntoIter<T, A, G> {
+        unsafe {
+            // need to use ptr::read to unsafely move the buf out since it's
+            // not Copy, and Vec implements Drop (so we can't destructure it).
+            let iter = RawValIter::new(&self);
+            let buf = ptr::read(&self.buf);
+            mem::forget(self);
+            IntoIter { iter, buf }
+        }
+    }
+}
+
+impl<T, A: Allocator, G: GrowthPolicy> Extend<T> for NomVec<T, A, G> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let mut iter = iter.into_iter();
+        let (lower, _) = iter.size_hint();
+        self.reserve(lower);
+        while let Some(elem) = iter.next() {
+            if self.len == self.cap() {
+                // the hint was too low; ask again for what's left
+                let (lower, _) = iter.size_hint();
+                self.reserve(lower.saturating_add(1));
+            }
+            unsafe {
+                ptr::write(self.ptr().add(self.len), elem);
+            }
+            self.len += 1;
+        }
+    }
+}
+
+impl<'a, T: Copy + 'a, A: Allocator, G: GrowthPolicy> Extend<&'a T>
+    for NomVec<T, A, G>
+{
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+impl<T> FromIterator<T> for NomVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        NomVec::from_iter_in(iter, Global)
+    }
+}
+
+struct RawValIter<T> {
+    start: *const T,
+    end: *const T,
+}
+
+impl<T> RawValIter<T> {
+    // unsafe to construct because it has no associated lifetimes.
+    // This is necessary to store a RawValIter in the same struct as
+    // its actual allocation. OK since it's a private implementation
+    // detail.
+    unsafe fn new(slice: &[T]) -> Self {
+        RawValIter {
+            start: slice.as_ptr(),
+            end: if mem::size_of::<T>() == 0 {
+                ((slice.as_ptr() as usize) + slice.len()) as *const _
+            } else if slice.is_empty() {
+                // if `len = 0`, then this is not actually allocated memory.
+                // Need to avoid offsetting because that will give wrong
+                // information to LLVM via GEP.
+                slice.as_ptr()
+            } else {
+                slice.as_ptr().add(slice.len())
+            },
+        }
+    }
+
+    fn len(&self) -> usize {
+        let elem_size = mem::size_of::<T>();
+        let diff = self.end as usize - self.start as usize;
+        // ZSTs are counted one "byte" at a time
+        diff / if elem_size == 0 { 1 } else { elem_size }
+    }
+
+    fn as_slice(&self) -> &[T] {
+        // the counting pointers used for ZSTs aren't necessarily aligned
+        let ptr = if mem::size_of::<T>() == 0 {
+            NonNull::dangling().as_ptr()
+        } else {
+            self.start
+        };
+        unsafe { ::std::slice::from_raw_parts(ptr, self.len()) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        let ptr = if mem::size_of::<T>() == 0 {
+            NonNull::dangling().as_ptr()
+        } else {
+            self.start as *mut T
+        };
+        unsafe { ::std::slice::from_raw_parts_mut(ptr, self.len()) }
+    }
+}
+
+impl<T> Iterator for RawValIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.end {
+            None
+        } else {
+            unsafe {
+                if mem::size_of::<T>() == 0 {
+                    self.start = (self.start as usize + 1) as *const _;
+                    Some(ptr::read(NonNull::dangling().as_ptr()))
+                } else {
+                    let result = ptr::read(self.start);
+                    self.start = self.start.offset(1);
+                    Some(result)
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+impl<T> DoubleEndedIterator for RawValIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.start == self.end {
+            None
+        } else {
+            unsafe {
+                if mem::size_of::<T>() == 0 {
+                    self.end = (self.end as usize - 1) as *const _;
+                    Some(ptr::read(NonNull::dangling().as_ptr()))
+                } else {
+                    self.end = self.end.offset(-1);
+                    Some(ptr::read(self.end))
+                }
+            }
+        }
+    }
+}
+
+pub struct IntoIter<T, A: Allocator = Global, G: GrowthPolicy = Doubling> {
+    buf: RawVec<T, A, G>,
+    iter: RawValIter<T>,
+}
+
+unsafe impl<T: Send, A: Allocator + Send, G: GrowthPolicy + Send> Send
+    for IntoIter<T, A, G>
+{
+}
+unsafe impl<T: Sync, A: Allocator + Sync, G: GrowthPolicy + Sync> Sync
+    for IntoIter<T, A, G>
+{
+}
+
+impl<T, A: Allocator, G: GrowthPolicy> IntoIter<T, A, G> {
+    pub fn as_slice(&self) -> &[T] {
+        self.iter.as_slice()
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        self.iter.as_mut_slice()
+    }
+
+    // The stable stand-in for std's in-place `map(..).collect()`: maps the
+    // remaining elements through `f` and collects them into a vector that
+    // reuses this iterator's buffer whenever `U` has the same size and
+    // alignment as `T` (see `NomVec::map_in_place`).
+    pub fn collect_in_place<U, F>(self, f: F) -> NomVec<U, A, G>
+    where
+        F: FnMut(T) -> U,
+    {
+        self.into_vec().map_in_place(f)
+    }
+
+    // hands the remaining elements back as a vector, shifting them to the
+    // front of the buffer if some were already consumed
+    fn into_vec(self) -> NomVec<T, A, G> {
+        let this = mem::ManuallyDrop::new(self);
+        let len = this.iter.len();
+        unsafe {
+            let buf = ptr::read(&this.buf);
+            if mem::size_of::<T>() != 0 {
+                ptr::copy(this.iter.start, buf.ptr.as_ptr(), len);
+            }
+            NomVec { buf, len }
+        }
+    }
+}
+
+impl<T, A: Allocator, G: GrowthPolicy> Iterator for IntoIter<T, A, G> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.iter.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T, A: Allocator, G: GrowthPolicy> DoubleEndedIterator
+    for IntoIter<T, A, G>
+{
+    fn next_back(&mut self) -> Option<T> {
+        self.iter.next_back()
+    }
+}
+
+// forks the iterator by cloning just the remaining elements into a fresh
+// buffer from the same allocator
+impl<T: Clone, A: Allocator + Clone, G: GrowthPolicy> Clone
+    for IntoIter<T, A, G>
+{
+    fn clone(&self) -> Self {
+        let mut vec = NomVec::with_policy_in(
+            self.buf.alloc.clone(),
+            self.buf.policy.clone(),
+        );
+        vec.reserve_exact(self.iter.len());
+        vec.extend_from_slice(self.as_slice());
+        vec.into_iter()
+    }
+}
+
+impl<T, A: Allocator, G: GrowthPolicy> ExactSizeIterator for IntoIter<T, A, G> {}
+
+// once start meets end it stays there
+impl<T, A: Allocator, G: GrowthPolicy> FusedIterator for IntoIter<T, A, G> {}
+
+impl<T, A: Allocator, G: GrowthPolicy> Drop for IntoIter<T, A, G> {
+    fn drop(&mut self) {
+        // only need to ensure all our elements are read;
+        // buffer will clean itself up afterwards.
+        for _ in &mut self.iter {}
+    }
+}
+
+pub struct Drain<'a, T: 'a, A: Allocator = Global, G: GrowthPolicy = Doubling> {
+    // Need to bound the lifetime here, so we do it with `&'a mut Vec<T>`
+    // because that's semantically what we contain. We keep a raw pointer
+    // to the vec so the tail can be moved back into place on drop.
+    vec: NonNull<NomVec<T, A, G>>,
+    _marker: PhantomData<&'a mut NomVec<T, A, G>>,
+    tail_start: usize,
+    tail_len: usize,
+    iter: RawValIter<T>,
+}
+
+impl<'a, T, A: Allocator, G: GrowthPolicy> Iterator for Drain<'a, T, A, G> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.iter.next()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T, A: Allocator, G: GrowthPolicy> DoubleEndedIterator
+    for Drain<'a, T, A, G>
+{
+    fn next_back(&mut self) -> Option<T> {
+        self.iter.next_back()
+    }
+}
+
+impl<'a, T, A: Allocator, G: GrowthPolicy> Drop for Drain<'a, T, A, G> {
+    fn drop(&mut self) {
+        // moves the tail back even if dropping a drained element panics
+        struct DropGuard<'r, 'a, T, A: Allocator, G: GrowthPolicy>(
+            &'r mut Drain<'a, T, A, G>,
+        );
+
+        impl<'r, 'a, T, A: Allocator, G: GrowthPolicy> Drop
+            for DropGuard<'r, 'a, T, A, G>
+        {
+            fn drop(&mut self) {
+                let drain = &mut *self.0;
+                if drain.tail_len > 0 {
+                    unsafe {
+                        let vec = drain.vec.as_mut();
+                        let start = vec.len;
+                        if drain.tail_start != start {
+                            ptr::copy(
+                                vec.ptr().add(drain.tail_start),
+                                vec.ptr().add(start),
+                                drain.tail_len,
+                            );
+                        }
+                        vec.len = start + drain.tail_len;
+                    }
+                }
+            }
+        }
+
+        let guard = DropGuard(self);
+        // pre-drain the iter
+        for _ in &mut guard.0.iter {}
+    }
+}
+
+unsafe impl<'a, T: Send, A: Allocator + Send, G: GrowthPolicy + Send> Send
+    for Drain<'a, T, A, G>
+{
+}
+unsafe impl<'a, T: Sync, A: Allocator + Sync, G: GrowthPolicy + Sync> Sync
+    for Drain<'a, T, A, G>
+{
+}
+
+impl<'a, T, A: Allocator, G: GrowthPolicy> Drain<'a, T, A, G> {
+    pub fn as_slice(&self) -> &[T] {
+        self.iter.as_slice()
+    }
+
+    // Stops draining: the unyielded elements stay in the vec, between the
+    // kept prefix and the tail, in their original order.
+    pub fn keep_rest(self) {
+        let mut this = mem::ManuallyDrop::new(self);
+        unsafe {
+            let vec = this.vec.as_mut();
+            let start = vec.len;
+            let unyielded_len = this.iter.len();
+
+            // ZSTs have no identity, so there's nothing to move around
+            if mem::size_of::<T>() != 0 {
+                let unyielded_ptr = this.iter.start as *mut T;
+                let start_ptr = vec.ptr().add(start);
+                if unyielded_ptr != start_ptr {
+                    ptr::copy(unyielded_ptr, start_ptr, unyielded_len);
+                }
+                if this.tail_start != start + unyielded_len {
+                    ptr::copy(
+                        vec.ptr().add(this.tail_start),
+                        start_ptr.add(unyielded_len),
+                        this.tail_len,
+                    );
+                }
+            }
+            vec.len = start + unyielded_len + this.tail_len;
+        }
+    }
+
+    // Fills the gap between the vec's len and the tail with elements from
+    // `replace_with`, returning whether the whole gap was filled.
+    unsafe fn fill<I: Iterator<Item = T>>(
+        &mut self,
+        replace_with: &mut I,
+    ) -> bool {
+        let vec = self.vec.as_mut();
+        while vec.len < self.tail_start {
+            match replace_with.next() {
+                Some(elem) => {
+                    ptr::write(vec.ptr().add(vec.len), elem);
+                    vec.len += 1;
+                }
+                None => return false,
+            }
+        }
+        true
+    }
+
+    // Opens the gap up by `additional` more slots by shifting the tail.
+    unsafe fn move_tail(&mut self, additional: usize) {
+        let vec = self.vec.as_mut();
+        let len = self.tail_start + self.tail_len;
+        vec.buf.reserve(len, additional);
+
+        let new_tail_start = self.tail_start + additional;
+        ptr::copy(
+            vec.ptr().add(self.tail_start),
+            vec.ptr().add(new_tail_start),
+            self.tail_len,
+        );
+        self.tail_start = new_tail_start;
+    }
+}
+
+pub struct Splice<
+    'a,
+    I: Iterator + 'a,
+    A: Allocator + 'a = Global,
+    G: GrowthPolicy + 'a = Doubling,
+> {
+    drain: Drain<'a, I::Item, A, G>,
+    replace_with: I,
+}
+
+impl<'a, I: Iterator, A: Allocator, G: GrowthPolicy> Iterator
+    for Splice<'a, I, A, G>
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        self.drain.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.drain.size_hint()
+    }
+}
+
+impl<'a, I: Iterator, A: Allocator, G: GrowthPolicy> DoubleEndedIterator
+    for Splice<'a, I, A, G>
+{
+    fn next_back(&mut self) -> Option<I::Item> {
+        self.drain.next_back()
+    }
+}
+
+impl<'a, I: Iterator, A: Allocator, G: GrowthPolicy> Drop
+    for Splice<'a, I, A, G>
+{
+    fn drop(&mut self) {
+        // drop whatever the caller didn't take out of the removed range
+        self.drain.by_ref().for_each(drop);
+
+        unsafe {
+            if self.drain.tail_len == 0 {
+                self.drain.vec.as_mut().extend(self.replace_with.by_ref());
+                return;
+            }
+
+            // the common case: the replacement fits in the drained range
+            if !self.drain.fill(&mut self.replace_with) {
+                return;
+            }
+
+            // there may be more elements. use the lower bound as an estimate
+            // so the tail only has to move once in the usual case.
+            let (lower_bound, _) = self.replace_with.size_hint();
+            if lower_bound > 0 {
+                self.drain.move_tail(lower_bound);
+                if !self.drain.fill(&mut self.replace_with) {
+                    return;
+                }
+            }
+
+            // collect whatever is left so we know exactly how far to move
+            // the tail for the last stretch.
+            let collected: NomVec<I::Item> =
+                self.replace_with.by_ref().collect();
+            let mut collected = collected.into_iter();
+            let (remaining, _) = collected.size_hint();
+            if remaining > 0 {
+                self.drain.move_tail(remaining);
+                let filled = self.drain.fill(&mut collected);
+                debug_assert!(filled);
+            }
+        }
+        // Drain::drop moves the tail back after the inserted elements
+    }
+}
+
+pub struct ExtractIf<
+    'a,
+    T,
+    F,
+    A: Allocator = Global,
+    G: GrowthPolicy = Doubling,
+> where
+    F: FnMut(&mut T) -> bool,
+{
+    vec: &'a mut NomVec<T, A, G>,
+    // the next element to visit
+    idx: usize,
+    // how many elements have been extracted so far
+    del: usize,
+    old_len: usize,
+    pred: F,
 }
 
-impl<T> DoubleEndedIterator for RawValIter<T> {
-    fn next_back(&mut self) -> Option<T> {
-        if self.start == self.end {
-            None
-        } else {
-            unsafe {
-                self.end = self.end.offset(-1);
-                Some(ptr::read(self.end))
+impl<'a, T, F, A: Allocator, G: GrowthPolicy> Iterator
+    for ExtractIf<'a, T, F, A, G>
+where
+    F: FnMut(&mut T) -> bool,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        unsafe {
+            while self.idx < self.old_len {
+                let i = self.idx;
+                let cur = self.vec.ptr().add(i);
+                let extracted = (self.pred)(&mut *cur);
+                // bump idx only after the predicate returns; if it panics
+                // the current element is treated as unvisited.
+                self.idx += 1;
+                if extracted {
+                    self.del += 1;
+                    return Some(ptr::read(cur));
+                } else if self.del > 0 {
+                    let hole = self.vec.ptr().add(i - self.del);
+                    ptr::copy_nonoverlapping(cur, hole, 1);
+                }
+            }
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.old_len - self.idx))
+    }
+}
+
+impl<'a, T, F, A: Allocator, G: GrowthPolicy> Drop for ExtractIf<'a, T, F, A, G>
+where
+    F: FnMut(&mut T) -> bool,
+{
+    fn drop(&mut self) {
+        unsafe {
+            if self.idx < self.old_len && self.del > 0 {
+                let src = self.vec.ptr().add(self.idx);
+                let dst = src.sub(self.del);
+                ptr::copy(src, dst, self.old_len - self.idx);
+            }
+        }
+        self.vec.len = self.old_len - self.del;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DropCounter;
+    use std::cell::Cell;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    #[test]
+    fn vec_const_new() {
+        static EMPTY: NomVec<String> = NomVec::new();
+        const WORDS: NomVec<u64> = NomVec::new();
+        const ZSTS: NomVec<()> = NomVec::new_in(Global);
+        assert!(EMPTY.is_empty());
+        assert_eq!(EMPTY.iter().count(), 0);
+        assert_eq!(WORDS.capacity(), 0);
+        assert_eq!(ZSTS.capacity(), usize::MAX);
+
+        let mut cv = WORDS;
+        cv.push(1);
+        assert_eq!(cv, [1]);
+    }
+
+    #[test]
+    fn vec_bulk_growth_reallocates_once() {
+        use std::alloc::Layout;
+
+        #[derive(Default)]
+        struct CountGrows(Cell<usize>);
+
+        unsafe impl Allocator for CountGrows {
+            fn allocate(
+                &self,
+                layout: Layout,
+            ) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.set(self.0.get() + 1);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                Global.deallocate(ptr, layout)
+            }
+
+            unsafe fn grow(
+                &self,
+                ptr: NonNull<u8>,
+                old_layout: Layout,
+                new_layout: Layout,
+            ) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.set(self.0.get() + 1);
+                Global.grow(ptr, old_layout, new_layout)
+            }
+        }
+
+        let alloc = CountGrows::default();
+        let mut cv = NomVec::new_in(&alloc);
+        cv.push(0u32);
+        cv.extend(1..10_000);
+        assert_eq!(alloc.0.get(), 2);
+        cv.extend_from_slice(&[0; 20_000]);
+        assert_eq!(alloc.0.get(), 3);
+        assert_eq!(cv.capacity(), 30_000);
+
+        cv.reserve_exact(1);
+        assert_eq!(cv.capacity(), 30_001);
+        assert_eq!(alloc.0.get(), 4);
+    }
+
+    #[test]
+    fn vec_drop_panic_drops_everything() {
+        let drops = Rc::new(Cell::new(0));
+        let mut cv = NomVec::new();
+        for i in 0..5 {
+            cv.push(if i == 1 {
+                DropCounter::panicking(&drops)
+            } else {
+                DropCounter::new(&drops)
+            });
+        }
+        let result = panic::catch_unwind(AssertUnwindSafe(|| drop(cv)));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 5);
+    }
+
+    #[test]
+    fn vec_copy_fast_paths() {
+        let mut bytes = NomVec::from([1u8, 2]);
+        bytes.resize_copy(5, 0xAA);
+        assert_eq!(bytes, [1, 2, 0xAA, 0xAA, 0xAA]);
+        bytes.resize_copy(1, 0);
+        assert_eq!(bytes, [1]);
+        // bytes that may be uninitialized are copied, never read as a u8
+        let mut raw: NomVec<MaybeUninit<u8>> = NomVec::new();
+        raw.resize_copy(4, MaybeUninit::uninit());
+        assert_eq!(raw.len(), 4);
+
+        let mut words: NomVec<(u16, bool)> = NomVec::new();
+        words.resize_copy(3, (7, true));
+        assert_eq!(words, [(7, true); 3]);
+
+        let copy = words.clone_copy();
+        assert_eq!(copy, words);
+        assert_eq!(copy.capacity(), 3);
+        assert_ne!(copy.as_ptr(), words.as_ptr());
+    }
+
+    #[test]
+    fn vec_push() {
+        let mut cv = NomVec::new();
+        cv.push(2);
+        assert_eq!(cv.len(), 1);
+        cv.push(3);
+        assert_eq!(cv.len(), 2);
+    }
+
+    #[test]
+    fn vec_iter() {
+        let mut cv = NomVec::new();
+        cv.push(2);
+        cv.push(3);
+        let mut accum = 0;
+        for x in cv.iter() {
+            accum += x;
+        }
+        assert_eq!(accum, 5);
+    }
+
+    #[test]
+    fn vec_into_iter() {
+        let mut cv = NomVec::new();
+        cv.push(2);
+        cv.push(3);
+        assert_eq!(cv.into_iter().collect::<Vec<i32>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn vec_into_iter_as_slice() {
+        let cv: NomVec<i32> = (0..5).collect();
+        let mut iter = cv.into_iter();
+        assert_eq!(iter.len(), 5);
+        iter.next();
+        iter.next_back();
+        assert_eq!(iter.as_slice(), &[1, 2, 3]);
+        iter.as_mut_slice()[0] = 10;
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.next(), Some(10));
+
+        let mut fused = iter.fuse();
+        assert_eq!(fused.by_ref().count(), 2);
+        assert_eq!(fused.next(), None);
+    }
+
+    #[test]
+    fn vec_into_iter_collect_in_place() {
+        let cv = NomVec::from([1i32, -2, 3, -4]);
+        let ptr = cv.as_ptr() as usize;
+        let mut iter = cv.into_iter();
+        assert_eq!(iter.next(), Some(1));
+        let abs = iter.collect_in_place(|x| x.unsigned_abs());
+        assert_eq!(abs.as_ptr() as usize, ptr);
+        assert_eq!(abs, [2, 3, 4]);
+
+        let zsts = NomVec::from([(), (), ()]).into_iter();
+        assert_eq!(zsts.collect_in_place(|()| ()).len(), 3);
+    }
+
+    #[test]
+    fn vec_raw_parts() {
+        let mut cv = NomVec::with_capacity(8);
+        cv.extend_from_slice(&[String::from("a"), String::from("b")]);
+        let (ptr, len, cap) = cv.into_raw_parts();
+        assert_eq!((len, cap), (2, 8));
+        let mut cv = unsafe { NomVec::from_raw_parts(ptr, len, cap) };
+        cv.push(String::from("c"));
+        assert_eq!(cv, ["a", "b", "c"]);
+
+        let (ptr, len, cap, alloc) = cv.into_raw_parts_with_alloc();
+        let cv = unsafe { NomVec::from_raw_parts_in(ptr, len, cap, alloc) };
+        assert_eq!(cv.len(), 3);
+
+        let (ptr, len, cap) = NomVec::from([(); 4]).into_raw_parts();
+        let zsts = unsafe { NomVec::from_raw_parts(ptr, len, cap) };
+        assert_eq!(zsts.len(), 4);
+    }
+
+    #[test]
+    fn vec_into_iter_clone() {
+        let cv = NomVec::from([String::from("a"), String::from("b")]);
+        let mut iter = cv.into_iter();
+        assert_eq!(iter.next().as_deref(), Some("a"));
+        let fork = iter.clone();
+        assert_eq!(iter.collect::<Vec<_>>(), vec!["b"]);
+        assert_eq!(fork.collect::<Vec<_>>(), vec!["b"]);
+    }
+
+    #[test]
+    fn vec_into_double_ended_iter() {
+        let mut cv = NomVec::new();
+        cv.push(2);
+        cv.push(3);
+        assert_eq!(*cv.iter().next_back().unwrap(), 3);
+    }
+
+    #[test]
+    fn vec_push_within_capacity() {
+        let mut cv = NomVec::with_capacity(2);
+        assert_eq!(cv.push_within_capacity(1), Ok(()));
+        assert_eq!(cv.push_within_capacity(2), Ok(()));
+        assert_eq!(cv.push_within_capacity(3), Err(3));
+        assert_eq!(cv.capacity(), 2);
+        assert_eq!(cv, [1, 2]);
+
+        let mut empty = NomVec::new();
+        assert_eq!(empty.push_within_capacity(1), Err(1));
+    }
+
+    #[test]
+    fn vec_try_mutations() {
+        let mut cv = NomVec::new();
+        assert!(cv.try_push(1u64).is_ok());
+        assert!(cv.try_extend_from_slice(&[2, 3]).is_ok());
+        assert!(cv.try_resize(5, 0).is_ok());
+        assert_eq!(cv, [1, 2, 3, 0, 0]);
+        assert!(cv.try_resize(1, 0).is_ok());
+        assert_eq!(cv, [1]);
+
+        assert_eq!(
+            cv.try_resize(usize::MAX, 0),
+            Err(AllocationError::CapacityOverflow)
+        );
+        assert_eq!(cv, [1]);
+
+        let mut zst = NomVec::new();
+        unsafe { zst.set_len(usize::MAX) };
+        assert_eq!(zst.try_push(()), Err(AllocationError::CapacityOverflow));
+        unsafe { zst.set_len(0) };
+    }
+
+    #[test]
+    fn vec_pop() {
+        let mut cv = NomVec::new();
+        cv.push(2);
+        assert_eq!(cv.len(), 1);
+        cv.pop();
+        assert_eq!(cv.len(), 0);
+        assert!(cv.pop().is_none());
+    }
+
+    #[test]
+    fn vec_insert() {
+        let mut cv: NomVec<i32> = NomVec::new();
+        cv.insert(0, 2); // test insert at end
+        cv.insert(0, 1); // test insert at beginning
+        assert_eq!(cv.pop().unwrap(), 2);
+    }
+
+    #[test]
+    fn vec_insert_slice() {
+        let mut cv: NomVec<i32> = (0..4).collect();
+        cv.insert_slice(2, &[10, 11, 12]);
+        assert_eq!(cv, [0, 1, 10, 11, 12, 2, 3]);
+        cv.insert_slice(7, &[5]);
+        cv.insert_slice(0, &[]);
+        assert_eq!(cv, [0, 1, 10, 11, 12, 2, 3, 5]);
+
+        cv.insert_from_iter(1, (20..23).filter(|_| true));
+        assert_eq!(cv, [0, 20, 21, 22, 1, 10, 11, 12, 2, 3, 5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds")]
+    fn vec_cant_insert_slice() {
+        let mut cv: NomVec<i32> = NomVec::new();
+        cv.insert_slice(1, &[1]);
+    }
+
+    #[test]
+    fn vec_remove() {
+        let mut cv = NomVec::new();
+        cv.push(2);
+        assert_eq!(cv.remove(0), 2);
+        assert_eq!(cv.len(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds")]
+    fn vec_cant_remove() {
+        let mut cv: NomVec<i32> = NomVec::new();
+        cv.remove(0);
+    }
+
+    #[test]
+    fn vec_swap_remove() {
+        let mut cv = NomVec::new();
+        for i in 0..4 {
+            cv.push(i);
+        }
+        assert_eq!(cv.swap_remove(0), 0);
+        assert_eq!(&cv[..], &[3, 1, 2]);
+        assert_eq!(cv.swap_remove(2), 2);
+        assert_eq!(&cv[..], &[3, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds")]
+    fn vec_cant_swap_remove() {
+        let mut cv: NomVec<i32> = NomVec::new();
+        cv.swap_remove(0);
+    }
+
+    #[test]
+    fn vec_retain() {
+        let mut cv = NomVec::new();
+        for i in 0..10 {
+            cv.push(i);
+        }
+        cv.retain(|x| x % 3 == 0);
+        assert_eq!(&cv[..], &[0, 3, 6, 9]);
+
+        cv.retain_mut(|x| {
+            *x += 1;
+            *x < 5
+        });
+        assert_eq!(&cv[..], &[1, 4]);
+    }
+
+    #[test]
+    fn vec_retain_drops() {
+        let drops = Rc::new(Cell::new(0));
+        let mut cv = NomVec::new();
+        for _ in 0..6 {
+            cv.push(DropCounter::new(&drops));
+        }
+        let mut i = 0;
+        cv.retain(|_| {
+            i += 1;
+            i % 2 == 0
+        });
+        assert_eq!(cv.len(), 3);
+        assert_eq!(drops.get(), 3);
+    }
+
+    #[test]
+    fn vec_retain_panic_safety() {
+        let drops = Rc::new(Cell::new(0));
+        let mut cv = NomVec::new();
+        for _ in 0..5 {
+            cv.push(DropCounter::new(&drops));
+        }
+        let mut i = 0;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            cv.retain(|_| {
+                i += 1;
+                if i == 4 {
+                    panic!("predicate panicked");
+                }
+                i != 2
+            })
+        }));
+        assert!(result.is_err());
+        // the rejected element is gone, the unvisited ones are kept
+        assert_eq!(drops.get(), 1);
+        assert_eq!(cv.len(), 4);
+        drop(cv);
+        assert_eq!(drops.get(), 5);
+    }
+
+    #[test]
+    fn vec_map_in_place() {
+        let cv = NomVec::from([1u32, 2, 3]);
+        let ptr = cv.as_ptr() as usize;
+        let mapped = cv.map_in_place(|x| x as f32 * 0.5);
+        assert_eq!(mapped.as_ptr() as usize, ptr);
+        assert_eq!(mapped, [0.5, 1.0, 1.5]);
+
+        // a different layout falls back to a fresh buffer
+        let widened = mapped.map_in_place(|x| (x * 2.0) as u64);
+        assert_eq!(widened, [1, 2, 3]);
+
+        let strings = widened.map_in_place(|x| x.to_string());
+        assert_eq!(strings, ["1", "2", "3"]);
+    }
+
+    #[test]
+    fn vec_map_in_place_panic_safety() {
+        let drops = Rc::new(Cell::new(0));
+        let mut cv = NomVec::new();
+        for _ in 0..5 {
+            cv.push(DropCounter::new(&drops));
+        }
+        let mut i = 0;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            cv.map_in_place(|d| {
+                i += 1;
+                if i == 3 {
+                    panic!("map panicked");
+                }
+                d
+            })
+        }));
+        assert!(result.is_err());
+        // two mapped, one consumed by the panicking call, two untouched
+        assert_eq!(drops.get(), 5);
+    }
+
+    #[test]
+    fn vec_dedup() {
+        let mut cv = NomVec::new();
+        for x in &[1, 1, 2, 3, 3, 3, 1] {
+            cv.push(*x);
+        }
+        cv.dedup();
+        assert_eq!(&cv[..], &[1, 2, 3, 1]);
+
+        cv.dedup_by_key(|x| *x / 2);
+        assert_eq!(&cv[..], &[1, 2, 1]);
+
+        cv.dedup_by(|_, _| true);
+        assert_eq!(&cv[..], &[1]);
+    }
+
+    #[test]
+    fn vec_dedup_drops() {
+        let drops = Rc::new(Cell::new(0));
+        let mut cv = NomVec::new();
+        for _ in 0..4 {
+            cv.push(DropCounter::new(&drops));
+        }
+        cv.dedup_by(|_, _| true);
+        assert_eq!(cv.len(), 1);
+        assert_eq!(drops.get(), 3);
+        drop(cv);
+        assert_eq!(drops.get(), 4);
+    }
+
+    #[test]
+    fn vec_dedup_panic_safety() {
+        let drops = Rc::new(Cell::new(0));
+        let mut cv = NomVec::new();
+        for _ in 0..4 {
+            cv.push(DropCounter::new(&drops));
+        }
+        let mut calls = 0;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            cv.dedup_by(|_, _| {
+                calls += 1;
+                if calls == 2 {
+                    panic!("same_bucket panicked");
+                }
+                true
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 1);
+        assert_eq!(cv.len(), 3);
+        drop(cv);
+        assert_eq!(drops.get(), 4);
+    }
+
+    #[test]
+    fn vec_append() {
+        let mut cv = NomVec::new();
+        let mut other = NomVec::new();
+        cv.push(1);
+        for i in 2..5 {
+            other.push(i);
+        }
+        let other_cap = other.capacity();
+        cv.append(&mut other);
+        assert_eq!(&cv[..], &[1, 2, 3, 4]);
+        assert!(other.is_empty());
+        assert_eq!(other.capacity(), other_cap);
+
+        let mut zst = NomVec::new();
+        let mut more = NomVec::new();
+        zst.push(());
+        more.push(());
+        more.push(());
+        zst.append(&mut more);
+        assert_eq!(zst.len(), 3);
+        assert_eq!(more.len(), 0);
+    }
+
+    #[test]
+    fn vec_extend_from_slice() {
+        let mut cv = NomVec::new();
+        cv.push(String::from("a"));
+        cv.extend_from_slice(&[String::from("b"), String::from("c")]);
+        assert_eq!(cv.len(), 3);
+        assert_eq!(cv[2], "c");
+
+        let mut bytes: NomVec<u8> = NomVec::new();
+        bytes.extend_from_slice_copy(b"hello ");
+        let cap = bytes.capacity();
+        bytes.extend_from_slice_copy(b"");
+        assert_eq!(bytes.capacity(), cap);
+        bytes.extend_from_slice_copy(b"world");
+        assert_eq!(&bytes[..], b"hello world");
+    }
+
+    #[test]
+    fn vec_extend_from_slice_panic_safety() {
+        struct PanicOnClone(Rc<Cell<usize>>);
+        impl Clone for PanicOnClone {
+            fn clone(&self) -> Self {
+                if self.0.get() == 2 {
+                    panic!("clone panicked");
+                }
+                self.0.set(self.0.get() + 1);
+                PanicOnClone(self.0.clone())
             }
         }
+
+        let clones = Rc::new(Cell::new(0));
+        let src: Vec<_> =
+            (0..4).map(|_| PanicOnClone(clones.clone())).collect();
+        let mut cv = NomVec::new();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            cv.extend_from_slice(&src);
+        }));
+        assert!(result.is_err());
+        assert_eq!(cv.len(), 2);
     }
-}
 
-pub struct IntoIter<T> {
-    _buf: RawVec<T>,
-    iter: RawValIter<T>,
-}
+    #[test]
+    fn vec_extend_from_within() {
+        let mut cv = NomVec::new();
+        cv.extend_from_slice(&[String::from("a"), String::from("b")]);
+        cv.extend_from_within(..);
+        cv.extend_from_within(1..=2);
+        cv.extend_from_within(5..);
+        let strs: Vec<&str> = cv.iter().map(|s| s.as_str()).collect();
+        assert_eq!(strs, vec!["a", "b", "a", "b", "b", "a", "a"]);
+    }
 
-impl<T> Iterator for IntoIter<T> {
-    type Item = T;
-    fn next(&mut self) -> Option<T> {
-        self.iter.next()
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn vec_cant_extend_from_within() {
+        let mut cv = NomVec::new();
+        cv.push(1);
+        cv.extend_from_within(0..2);
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.iter.size_hint()
+    #[test]
+    fn vec_resize() {
+        let mut cv = NomVec::new();
+        cv.resize(3, String::from("x"));
+        assert_eq!(cv.len(), 3);
+        assert!(cv.iter().all(|s| s == "x"));
+        cv.resize(1, String::from("y"));
+        assert_eq!(cv.len(), 1);
+
+        let mut n = 0;
+        let mut counted = NomVec::new();
+        counted.resize_with(4, || {
+            n += 1;
+            n
+        });
+        assert_eq!(&counted[..], &[1, 2, 3, 4]);
+        counted.resize_with(2, || unreachable!());
+        assert_eq!(&counted[..], &[1, 2]);
     }
-}
 
-impl<T> DoubleEndedIterator for IntoIter<T> {
-    fn next_back(&mut self) -> Option<T> {
-        self.iter.next_back()
+    #[test]
+    fn vec_resize_drops() {
+        let drops = Rc::new(Cell::new(0));
+        let mut cv = NomVec::new();
+        cv.resize_with(3, || DropCounter::new(&drops));
+        cv.resize_with(1, || DropCounter::new(&drops));
+        assert_eq!(drops.get(), 2);
+        cv.clear();
+        assert_eq!(drops.get(), 3);
     }
-}
 
-impl<T> Drop for IntoIter<T> {
-    fn drop(&mut self) {
-        // only need to ensure all our elements are read;
-        // buffer will clean itself up afterwards.
-        for _ in &mut self.iter {}
+    #[test]
+    fn vec_extend() {
+        let mut cv = NomVec::new();
+        cv.extend(0..100);
+        // an exact size hint means a single allocation of exactly that size
+        assert_eq!(cv.capacity(), 100);
+        assert_eq!(cv.iter().sum::<i32>(), 4950);
+
+        // filter() only gives a lower bound of 0
+        cv.extend((0..10).filter(|x| x % 2 == 0));
+        assert_eq!(cv.len(), 105);
+
+        let mut copied: NomVec<u8> = NomVec::new();
+        copied.extend(&[1u8, 2, 3]);
+        copied.extend([4u8].iter());
+        assert_eq!(&copied[..], &[1, 2, 3, 4]);
     }
-}
 
-pub struct Drain<'a, T: 'a> {
-    // Need to bound the lifetime here, so we do it with `&'a mut Vec<T>`
-    // because that's semantically what we contain. We're "just" calling
-    // `pop()` and `remove(0)`.
-    vec: PhantomData<&'a mut NomVec<T>>,
-    iter: RawValIter<T>,
-}
+    #[test]
+    fn vec_collect() {
+        let cv: NomVec<i32> = (1..=4).collect();
+        assert_eq!(&cv[..], &[1, 2, 3, 4]);
+        assert_eq!(cv.capacity(), 4);
 
-impl<'a, T> Iterator for Drain<'a, T> {
-    type Item = T;
-    fn next(&mut self) -> Option<T> {
-        self.iter.next()
+        let evens =
+            NomVec::from_iter_in((0..10).filter(|x| x % 2 == 0), Global);
+        assert_eq!(&evens[..], &[0, 2, 4, 6, 8]);
     }
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.iter.size_hint()
+
+    #[test]
+    fn vec_clone() {
+        let mut cv = NomVec::new();
+        cv.extend_from_slice(&[String::from("a"), String::from("b")]);
+        let copy = cv.clone();
+        assert_eq!(copy.len(), 2);
+        assert_eq!(copy[1], "b");
+        assert_eq!(copy.capacity(), 2);
     }
-}
 
-impl<'a, T> DoubleEndedIterator for Drain<'a, T> {
-    fn next_back(&mut self) -> Option<T> {
-        self.iter.next_back()
+    #[test]
+    fn vec_clone_from() {
+        let mut src = NomVec::new();
+        src.extend(0..3);
+
+        let mut dst = NomVec::with_capacity(16);
+        dst.extend(10..20);
+        let ptr = dst.as_ptr();
+        dst.clone_from(&src);
+        assert_eq!(&dst[..], &[0, 1, 2]);
+        // the existing allocation was reused
+        assert_eq!(dst.as_ptr(), ptr);
+        assert_eq!(dst.capacity(), 16);
+
+        let mut small = NomVec::new();
+        small.push(7);
+        src.clone_from(&small);
+        assert_eq!(&src[..], &[7]);
+        small.clone_from(&dst);
+        assert_eq!(&small[..], &[0, 1, 2]);
     }
-}
 
-impl<'a, T> Drop for Drain<'a, T> {
-    fn drop(&mut self) {
-        // pre-drain the iter
-        for _ in &mut self.iter {}
+    #[test]
+    fn vec_eq() {
+        let cv: NomVec<i32> = (1..=3).collect();
+        let other: NomVec<i32> = (1..=3).collect();
+        assert!(cv == other);
+        let array_ref = &[1, 2, 3];
+        let slice: &[i32] = &[1, 2, 3];
+        assert!(cv == [1, 2, 3]);
+        assert!(cv == array_ref);
+        assert!(cv == vec![1, 2, 3]);
+        assert!(cv == slice);
+        assert!(vec![1, 2, 3] == cv);
+        assert!([1, 2, 3] == cv);
+        assert!(slice == cv);
+        assert!(cv != [1, 2]);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn vec_ord_and_hash() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::collections::HashMap;
+
+        let a: NomVec<i32> = (1..=3).collect();
+        let b: NomVec<i32> = (1..=4).collect();
+        assert!(a < b);
+        assert_eq!(a.cmp(&a.clone()), Ordering::Equal);
+
+        let hash = |x: &dyn Fn(&mut DefaultHasher)| {
+            let mut hasher = DefaultHasher::new();
+            x(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&|h| a.hash(h)), hash(&|h| vec![1, 2, 3].hash(h)));
+
+        let mut map = HashMap::new();
+        map.insert(a.clone(), "a");
+        assert_eq!(map.get(&a), Some(&"a"));
+    }
 
     #[test]
-    fn vec_push() {
-        let mut cv = NomVec::new();
-        cv.push(2);
-        assert_eq!(cv.len(), 1);
-        cv.push(3);
-        assert_eq!(cv.len(), 2);
+    fn vec_debug() {
+        let mut cv = NomVec::with_capacity(4);
+        cv.extend(1..=2);
+        assert_eq!(format!("{:?}", cv), "[1, 2]");
+        assert_eq!(
+            format!("{:#?}", cv),
+            "NomVec {\n    len: 2,\n    capacity: 4,\n    elements: [\n        1,\n        2,\n    ],\n}"
+        );
+        assert_eq!(cv, [1, 2]);
     }
 
     #[test]
-    fn vec_iter() {
-        let mut cv = NomVec::new();
-        cv.push(2);
-        cv.push(3);
-        let mut accum = 0;
-        for x in cv.iter() {
-            accum += x;
+    fn vec_from_std_vec() {
+        let mut std_vec = Vec::with_capacity(10);
+        std_vec.extend_from_slice(&[1, 2, 3]);
+        let ptr = std_vec.as_ptr();
+
+        let mut cv = NomVec::from(std_vec);
+        assert_eq!(cv, [1, 2, 3]);
+        assert_eq!(cv.as_ptr(), ptr);
+        assert_eq!(cv.capacity(), 10);
+        cv.push(4);
+
+        let back: Vec<i32> = cv.into();
+        assert_eq!(back, vec![1, 2, 3, 4]);
+        assert_eq!(back.as_ptr(), ptr);
+
+        let empty: NomVec<String> = Vec::new().into();
+        assert!(empty.is_empty());
+        let zst: Vec<()> = NomVec::from(vec![(), ()]).into();
+        assert_eq!(zst.len(), 2);
+    }
+
+    #[test]
+    fn vec_from_slices_and_arrays() {
+        let slice: &[&str] = &["a", "b"];
+        assert_eq!(NomVec::from(slice), ["a", "b"]);
+
+        let cv = NomVec::from([String::from("x"), String::from("y")]);
+        assert_eq!(cv, ["x", "y"]);
+        assert_eq!(cv.capacity(), 2);
+        assert!(NomVec::<u8>::from([]).is_empty());
+
+        let boxed: Box<[i32]> = vec![1, 2, 3].into_boxed_slice();
+        let ptr = boxed.as_ptr();
+        let mut cv = NomVec::from(boxed);
+        assert_eq!(cv.as_ptr(), ptr);
+        cv.push(4);
+        assert_eq!(cv, [1, 2, 3, 4]);
+        let empty: Box<[i32]> = Box::new([]);
+        assert!(NomVec::from(empty).is_empty());
+    }
+
+    #[test]
+    fn vec_into_boxed_slice() {
+        let mut cv = NomVec::with_capacity(10);
+        cv.extend_from_slice(&[1, 2, 3]);
+        let boxed = cv.into_boxed_slice();
+        assert_eq!(&*boxed, &[1, 2, 3]);
+        // and back again without copying
+        let ptr = boxed.as_ptr();
+        let cv = NomVec::from(boxed);
+        assert_eq!(cv.as_ptr(), ptr);
+
+        let zst: Box<[()]> = NomVec::from([(), ()]).into_boxed_slice();
+        assert_eq!(zst.len(), 2);
+
+        #[cfg(feature = "nightly")]
+        {
+            let counting = crate::CountingAllocator::new();
+            let mut cv = NomVec::with_capacity_in(8, &counting);
+            cv.extend_from_slice(&[1u32, 2, 3]);
+            let boxed: Box<[u32], _> = cv.into_boxed_slice();
+            assert_eq!(&*boxed, &[1, 2, 3]);
+            drop(boxed);
+            assert_eq!(counting.stats().live_bytes(), 0);
         }
-        assert_eq!(accum, 5);
     }
 
     #[test]
-    fn vec_into_iter() {
-        let mut cv = NomVec::new();
-        cv.push(2);
-        cv.push(3);
-        assert_eq!(cv.into_iter().collect::<Vec<i32>>(), vec![2, 3]);
+    fn vec_leak() {
+        let cv = NomVec::from([1, 2, 3]);
+        let slice: &'static mut [i32] = cv.leak();
+        slice[0] = 10;
+        assert_eq!(slice, &[10, 2, 3]);
+        // give the memory back so the test stays leak-free
+        unsafe { drop(Box::from_raw(slice as *mut [i32])) };
     }
 
     #[test]
-    fn vec_into_double_ended_iter() {
-        let mut cv = NomVec::new();
-        cv.push(2);
-        cv.push(3);
-        assert_eq!(*cv.iter().next_back().unwrap(), 3);
+    fn vec_try_into_array() {
+        let cv = NomVec::from([String::from("a"), String::from("b")]);
+        let array: [String; 2] = <[String; 2]>::try_from(cv).unwrap();
+        assert_eq!(array, ["a", "b"]);
+
+        let cv: NomVec<i32> = (0..3).collect();
+        let err = <[i32; 4]>::try_from(cv).unwrap_err();
+        assert_eq!(err, [0, 1, 2]);
     }
 
     #[test]
-    fn vec_pop() {
-        let mut cv = NomVec::new();
-        cv.push(2);
-        assert_eq!(cv.len(), 1);
-        cv.pop();
-        assert_eq!(cv.len(), 0);
-        assert!(cv.pop().is_none());
+    fn vec_spare_capacity() {
+        let mut cv: NomVec<u8> = NomVec::with_capacity(8);
+        cv.push(1);
+        let spare = cv.spare_capacity_mut();
+        assert_eq!(spare.len(), 7);
+        spare[0] = MaybeUninit::new(2);
+        spare[1] = MaybeUninit::new(3);
+        unsafe { cv.set_len(3) };
+        assert_eq!(cv, [1, 2, 3]);
+
+        let mut full: NomVec<u8> = NomVec::new();
+        assert!(full.spare_capacity_mut().is_empty());
     }
 
     #[test]
-    fn vec_insert() {
-        let mut cv: NomVec<i32> = NomVec::new();
-        cv.insert(0, 2); // test insert at end
-        cv.insert(0, 1); // test insert at beginning
-        assert_eq!(cv.pop().unwrap(), 2);
+    fn vec_split_at_spare_mut() {
+        let mut cv: NomVec<u32> = NomVec::with_capacity(6);
+        cv.extend_from_slice(&[1, 2, 3]);
+        let (init, spare) = cv.split_at_spare_mut();
+        for (src, dst) in init.iter_mut().zip(spare.iter_mut()) {
+            *dst = MaybeUninit::new(*src * 10);
+            *src += 1;
+        }
+        unsafe { cv.set_len(6) };
+        assert_eq!(cv, [2, 3, 4, 10, 20, 30]);
     }
 
     #[test]
-    fn vec_remove() {
+    fn vec_split_off() {
         let mut cv = NomVec::new();
-        cv.push(2);
-        assert_eq!(cv.remove(0), 2);
-        assert_eq!(cv.len(), 0);
+        for i in 0..5 {
+            cv.push(i);
+        }
+        let tail = cv.split_off(2);
+        assert_eq!(&cv[..], &[0, 1]);
+        assert_eq!(&tail[..], &[2, 3, 4]);
+
+        let empty = cv.split_off(2);
+        assert!(empty.is_empty());
+        let all = cv.split_off_in(0, Global);
+        assert!(cv.is_empty());
+        assert_eq!(&all[..], &[0, 1]);
     }
 
     #[test]
-    #[should_panic(expected = "index out of bounds")]
-    fn vec_cant_remove() {
+    #[should_panic(expected = "`at` split index out of bounds")]
+    fn vec_cant_split_off() {
         let mut cv: NomVec<i32> = NomVec::new();
-        cv.remove(0);
+        cv.split_off(1);
     }
 
     #[test]
@@ -427,13 +2904,317 @@ mod tests {
         cv.push(3);
         assert_eq!(cv.len(), 3);
         {
-            let mut drain = cv.drain();
+            let mut drain = cv.drain(..);
             assert_eq!(drain.next().unwrap(), 1);
             assert_eq!(drain.next_back().unwrap(), 3);
         }
         assert_eq!(cv.len(), 0);
     }
 
+    #[test]
+    fn vec_drain_range() {
+        let mut cv: NomVec<i32> = (0..6).collect();
+        {
+            let mut drain = cv.drain(1..3);
+            assert_eq!(drain.size_hint(), (2, Some(2)));
+            assert_eq!(drain.next(), Some(1));
+            // dropped before being fully consumed
+        }
+        assert_eq!(cv, [0, 3, 4, 5]);
+
+        assert_eq!(cv.drain(2..).collect::<Vec<_>>(), vec![4, 5]);
+        assert_eq!(cv.drain(..=0).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(cv.drain(1..1).count(), 0);
+        assert_eq!(cv, [3]);
+    }
+
+    #[test]
+    fn vec_drain_drops_and_forget() {
+        let drops = Rc::new(Cell::new(0));
+        let mut cv = NomVec::new();
+        cv.resize_with(5, || DropCounter::new(&drops));
+        drop(cv.drain(1..4));
+        assert_eq!(drops.get(), 3);
+        assert_eq!(cv.len(), 2);
+
+        // a forgotten drain leaks instead of exposing moved-out elements
+        mem::forget(cv.drain(1..));
+        assert_eq!(cv.len(), 1);
+        drop(cv);
+        assert_eq!(drops.get(), 4);
+    }
+
+    #[test]
+    fn vec_extract_if() {
+        let mut cv: NomVec<i32> = (1..=10).collect();
+        let evens: Vec<i32> = cv.extract_if(|x| *x % 2 == 0).collect();
+        assert_eq!(evens, vec![2, 4, 6, 8, 10]);
+        assert_eq!(cv, [1, 3, 5, 7, 9]);
+
+        // stopping early keeps the rest
+        let mut iter = cv.extract_if(|x| *x > 2);
+        assert_eq!(iter.next(), Some(3));
+        drop(iter);
+        assert_eq!(cv, [1, 5, 7, 9]);
+    }
+
+    #[test]
+    fn vec_extract_if_panic_safety() {
+        let drops = Rc::new(Cell::new(0));
+        let mut cv = NomVec::new();
+        cv.resize_with(4, || DropCounter::new(&drops));
+        let mut calls = 0;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            for _ in cv.extract_if(|_| {
+                calls += 1;
+                if calls == 3 {
+                    panic!("filter panicked");
+                }
+                calls == 1
+            }) {}
+        }));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 1);
+        assert_eq!(cv.len(), 3);
+        drop(cv);
+        assert_eq!(drops.get(), 4);
+    }
+
+    #[test]
+    fn vec_splice() {
+        let mut cv: NomVec<i32> = (0..6).collect();
+        // same size: elements are swapped in place
+        let removed: Vec<i32> = cv.splice(1..3, vec![10, 20]).collect();
+        assert_eq!(removed, vec![1, 2]);
+        assert_eq!(cv, [0, 10, 20, 3, 4, 5]);
+
+        // growing, with an exact size hint
+        cv.splice(..1, vec![7, 8, 9]);
+        assert_eq!(cv, [7, 8, 9, 10, 20, 3, 4, 5]);
+
+        // growing, with no size hint to go on
+        cv.splice(3..5, (0..4).filter(|_| true));
+        assert_eq!(cv, [7, 8, 9, 0, 1, 2, 3, 3, 4, 5]);
+
+        // shrinking
+        cv.splice(0..7, Some(42));
+        assert_eq!(cv, [42, 3, 4, 5]);
+
+        // at the end
+        cv.splice(4.., vec![6, 7]);
+        assert_eq!(cv, [42, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn vec_drain_as_slice_and_keep_rest() {
+        let mut cv: NomVec<i32> = (0..8).collect();
+        let mut drain = cv.drain(2..6);
+        assert_eq!(drain.as_slice(), &[2, 3, 4, 5]);
+        assert_eq!(drain.next(), Some(2));
+        assert_eq!(drain.next_back(), Some(5));
+        assert_eq!(drain.as_slice(), &[3, 4]);
+        drain.keep_rest();
+        assert_eq!(cv, [0, 1, 3, 4, 6, 7]);
+
+        let drain = cv.drain(..);
+        drain.keep_rest();
+        assert_eq!(cv, [0, 1, 3, 4, 6, 7]);
+    }
+
+    #[test]
+    fn vec_drain_zst_both_ends() {
+        let mut cv = NomVec::new();
+        cv.resize(4, ());
+        let mut drain = cv.drain(..3);
+        assert_eq!(drain.size_hint(), (3, Some(3)));
+        assert_eq!(drain.next_back(), Some(()));
+        assert_eq!(drain.next(), Some(()));
+        assert_eq!(drain.as_slice().len(), 1);
+        drain.keep_rest();
+        assert_eq!(cv.len(), 2);
+    }
+
+    #[test]
+    fn vec_drain_zst() {
+        let mut cv = NomVec::new();
+        cv.resize(5, ());
+        assert_eq!(cv.drain(1..3).count(), 2);
+        assert_eq!(cv.len(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn vec_cant_drain() {
+        let mut cv: NomVec<i32> = NomVec::new();
+        cv.drain(..1);
+    }
+
+    #[test]
+    fn vec_new_in() {
+        let mut cv = NomVec::new_in(Global);
+        assert_eq!(cv.capacity(), 0);
+        cv.push(1u8);
+        assert_eq!(cv, [1]);
+
+        let by_ref: NomVec<u8, &Global> = NomVec::new_in(&Global);
+        assert!(by_ref.is_empty());
+    }
+
+    #[test]
+    fn vec_with_capacity() {
+        let mut cv = NomVec::with_capacity(10);
+        assert_eq!(cv.capacity(), 10);
+        for i in 0..10 {
+            cv.push(i);
+        }
+        // no reallocation needed to fill the preallocated buffer
+        assert_eq!(cv.capacity(), 10);
+        assert_eq!(cv.len(), 10);
+    }
+
+    #[test]
+    fn vec_with_capacity_in() {
+        let cv: NomVec<u64> = NomVec::with_capacity_in(1_000, Global);
+        assert_eq!(cv.capacity(), 1_000);
+        assert!(cv.is_empty());
+
+        let zst: NomVec<()> = NomVec::with_capacity_in(5, Global);
+        assert_eq!(zst.capacity(), usize::MAX);
+    }
+
+    #[test]
+    fn vec_try_with_capacity_in() {
+        let cv: NomVec<u8> = NomVec::try_with_capacity_in(16, Global).unwrap();
+        assert_eq!(cv.capacity(), 16);
+
+        let err = NomVec::<u64>::try_with_capacity_in(usize::MAX, Global)
+            .err()
+            .unwrap();
+        assert_eq!(err, AllocationError::CapacityOverflow);
+    }
+
+    #[test]
+    fn vec_reserve() {
+        let mut cv = NomVec::new();
+        cv.push(1);
+        cv.reserve(10);
+        assert!(cv.capacity() >= 11);
+        let cap = cv.capacity();
+        cv.reserve(1);
+        assert_eq!(cv.capacity(), cap);
+
+        let mut exact: NomVec<i32> = NomVec::new();
+        exact.reserve_exact(7);
+        assert_eq!(exact.capacity(), 7);
+    }
+
+    #[test]
+    fn vec_try_reserve() {
+        let mut cv: NomVec<u32> = NomVec::new();
+        assert!(cv.try_reserve(4).is_ok());
+        assert!(cv.capacity() >= 4);
+        assert!(cv.try_reserve_exact(4).is_ok());
+
+        cv.push(1);
+        assert_eq!(
+            cv.try_reserve(usize::MAX),
+            Err(AllocationError::CapacityOverflow)
+        );
+        assert_eq!(
+            cv.try_reserve_exact(isize::MAX as usize),
+            Err(AllocationError::CapacityOverflow)
+        );
+        // a failed reservation leaves the vector intact
+        assert_eq!(cv.pop(), Some(1));
+
+        let mut zst: NomVec<()> = NomVec::new();
+        zst.push(());
+        assert_eq!(
+            zst.try_reserve(usize::MAX),
+            Err(AllocationError::CapacityOverflow)
+        );
+    }
+
+    #[test]
+    fn vec_shrink() {
+        let mut cv = NomVec::with_capacity(100);
+        for i in 0..10 {
+            cv.push(i);
+        }
+        cv.shrink_to(50);
+        assert_eq!(cv.capacity(), 50);
+        cv.shrink_to(0);
+        assert_eq!(cv.capacity(), 10);
+        // shrink_to never grows
+        cv.shrink_to(20);
+        assert_eq!(cv.capacity(), 10);
+        assert_eq!(cv.iter().sum::<i32>(), 45);
+
+        while cv.pop().is_some() {}
+        cv.shrink_to_fit();
+        assert_eq!(cv.capacity(), 0);
+        cv.push(1);
+        assert_eq!(cv.pop(), Some(1));
+    }
+
+    #[test]
+    fn vec_shrink_zst() {
+        let mut cv = NomVec::new();
+        cv.push(());
+        cv.shrink_to_fit();
+        assert_eq!(cv.capacity(), usize::MAX);
+        assert_eq!(cv.len(), 1);
+    }
+
+    #[test]
+    fn vec_truncate() {
+        let drops = Rc::new(Cell::new(0));
+        let mut cv = NomVec::new();
+        for _ in 0..5 {
+            cv.push(DropCounter::new(&drops));
+        }
+        let cap = cv.capacity();
+        cv.truncate(7);
+        assert_eq!(cv.len(), 5);
+        cv.truncate(2);
+        assert_eq!(cv.len(), 2);
+        assert_eq!(drops.get(), 3);
+        cv.clear();
+        assert!(cv.is_empty());
+        assert_eq!(drops.get(), 5);
+        assert_eq!(cv.capacity(), cap);
+    }
+
+    #[test]
+    fn vec_truncate_panic_safety() {
+        let drops = Rc::new(Cell::new(0));
+        let mut cv = NomVec::new();
+        cv.push(DropCounter::new(&drops));
+        cv.push(DropCounter::panicking(&drops));
+        cv.push(DropCounter::new(&drops));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| cv.truncate(1)));
+        assert!(result.is_err());
+        // every element of the tail was dropped exactly once
+        assert_eq!(drops.get(), 2);
+        assert_eq!(cv.len(), 1);
+        drop(cv);
+        assert_eq!(drops.get(), 3);
+    }
+
+    #[test]
+    fn vec_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<NomVec<i32>>();
+        assert_send_sync::<IntoIter<String>>();
+        assert_send_sync::<Drain<'static, u8>>();
+
+        let cv: NomVec<i32> = (0..4).collect();
+        let sum = std::thread::spawn(move || cv.iter().sum::<i32>())
+            .join()
+            .unwrap();
+        assert_eq!(sum, 6);
+    }
+
     #[test]
     fn vec_zst() {
         let mut v = NomVec::new();
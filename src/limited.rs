@@ -0,0 +1,192 @@
+// An allocator wrapper with a budget: it keeps a running total of the
+// bytes it has live, and fails any allocation or grow that would take the
+// total past `limit`. Giving each request of a parser for untrusted input
+// its own LimitedAllocator bounds what that input can make it allocate;
+// with the `try_*` APIs the overrun comes back as an error instead of an
+// abort.
+//
+// The total is kept with a compare-and-swap, so a budget can be shared
+// between threads and is never overrun, even briefly.
+use crate::{AllocError, Allocator, Global};
+use std::alloc::Layout;
+use std::fmt;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub struct LimitedAllocator<A: Allocator = Global> {
+    live: AtomicUsize,
+    limit: usize,
+    alloc: A,
+}
+
+impl LimitedAllocator {
+    pub const fn new(limit: usize) -> Self {
+        Self::new_in(limit, Global)
+    }
+}
+
+impl<A: Allocator> LimitedAllocator<A> {
+    pub const fn new_in(limit: usize, alloc: A) -> Self {
+        LimitedAllocator {
+            live: AtomicUsize::new(0),
+            limit,
+            alloc,
+        }
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    // the bytes allocated and not yet freed
+    pub fn live_bytes(&self) -> usize {
+        self.live.load(Ordering::Relaxed)
+    }
+
+    // what's left of the budget
+    pub fn remaining(&self) -> usize {
+        self.limit - self.live_bytes()
+    }
+
+    // Takes `bytes` out of the budget, if there's that much left.
+    fn charge(&self, bytes: usize) -> Result<(), AllocError> {
+        self.live
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |live| {
+                live.checked_add(bytes).filter(|&total| total <= self.limit)
+            })
+            .map(drop)
+            .map_err(|_| AllocError)
+    }
+
+    fn refund(&self, bytes: usize) {
+        self.live.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    // Charges `bytes` for `f`, refunding them if it fails.
+    fn charged(
+        &self,
+        bytes: usize,
+        f: impl FnOnce() -> Result<NonNull<[u8]>, AllocError>,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.charge(bytes)?;
+        let result = f();
+        if result.is_err() {
+            self.refund(bytes);
+        }
+        result
+    }
+}
+
+impl<A: Allocator> fmt::Debug for LimitedAllocator<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LimitedAllocator")
+            .field("live_bytes", &self.live_bytes())
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for LimitedAllocator<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.charged(layout.size(), || self.alloc.allocate(layout))
+    }
+
+    fn allocate_zeroed(
+        &self,
+        layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.charged(layout.size(), || self.alloc.allocate_zeroed(layout))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.alloc.deallocate(ptr, layout);
+        self.refund(layout.size());
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let extra = new_layout.size() - old_layout.size();
+        self.charged(extra, || self.alloc.grow(ptr, old_layout, new_layout))
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let extra = new_layout.size() - old_layout.size();
+        self.charged(extra, || {
+            self.alloc.grow_zeroed(ptr, old_layout, new_layout)
+        })
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.shrink(ptr, old_layout, new_layout);
+        if result.is_ok() {
+            self.refund(old_layout.size() - new_layout.size());
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AllocationError, NomVec};
+
+    #[test]
+    fn limited_rejects_over_budget() {
+        let budget = LimitedAllocator::new(1024);
+        let mut fields: NomVec<NomVec<u8, _>, _> = NomVec::new_in(&budget);
+        fields.try_reserve_exact(4).unwrap();
+        let mut input = (0..).map(|i| vec![i as u8; 100]);
+        let err = loop {
+            let mut field = NomVec::new_in(&budget);
+            if let Err(err) =
+                field.try_extend_from_slice(&input.next().unwrap())
+            {
+                break err;
+            }
+            if let Err(err) = fields.try_push(field) {
+                break err;
+            }
+        };
+        assert!(matches!(err, AllocationError::AllocFailed { .. }));
+        assert!(budget.live_bytes() <= 1024);
+        assert!(fields.len() >= 4);
+        drop(fields);
+        assert_eq!(budget.live_bytes(), 0);
+        assert_eq!(budget.remaining(), 1024);
+    }
+
+    #[test]
+    fn limited_refunds_shrinks() {
+        let budget = LimitedAllocator::new(100);
+        let mut cv: NomVec<u8, _> = NomVec::with_capacity_in(100, &budget);
+        assert_eq!(budget.remaining(), 0);
+        assert!(NomVec::<u8, _>::try_with_capacity_in(1, &budget).is_err());
+        cv.push(1);
+        cv.shrink_to_fit();
+        assert_eq!(budget.live_bytes(), 1);
+        cv.try_reserve_exact(99).unwrap();
+        assert!(cv.try_reserve_exact(100).is_err());
+        assert_eq!(
+            format!("{:?}", budget),
+            "LimitedAllocator { live_bytes: 100, limit: 100 }"
+        );
+    }
+}
@@ -0,0 +1,545 @@
+// An allocator that takes large blocks straight from the OS as anonymous
+// memory maps (`mmap` on unix, `VirtualAlloc` on Windows), and passes
+// anything smaller than `threshold` on to `A`. On Linux, growing a mapped
+// block is an `mremap`, which moves page table entries rather than bytes,
+// so a multi-gigabyte NomVec can keep doubling without ever copying its
+// contents; elsewhere it maps a new block and copies.
+//
+// Mapped blocks are rounded up to whole pages and come back zeroed, which
+// makes `allocate_zeroed` free. Shrinking one unmaps (or on Windows
+// decommits) the pages it no longer needs. Layouts aligned to more than a
+// page go to `A`.
+use crate::{AllocError, Allocator, Global};
+use std::alloc::Layout;
+use std::fmt;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// The OS calls, behind the same few functions on each platform.
+#[cfg(unix)]
+pub(crate) mod sys {
+    use std::ptr::{self, NonNull};
+
+    pub(crate) fn page_size() -> usize {
+        unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+    }
+
+    pub(crate) fn map(size: usize, flags: libc::c_int) -> Option<NonNull<u8>> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | flags,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            None
+        } else {
+            NonNull::new(ptr as *mut u8)
+        }
+    }
+
+    pub(crate) unsafe fn unmap(ptr: NonNull<u8>, size: usize) {
+        libc::munmap(ptr.as_ptr() as *mut libc::c_void, size);
+    }
+
+    // Maps `size` bytes of huge pages of `page_size` bytes each; None if
+    // there aren't that many reserved, or this platform has no way to ask.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn map_huge(
+        size: usize,
+        page_size: usize,
+    ) -> Option<NonNull<u8>> {
+        let shift = page_size.trailing_zeros() as libc::c_int;
+        map(size, libc::MAP_HUGETLB | (shift << libc::MAP_HUGE_SHIFT))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(crate) fn map_huge(
+        _size: usize,
+        _page_size: usize,
+    ) -> Option<NonNull<u8>> {
+        None
+    }
+
+    // whether `map_aligned` can meet `align`; here it always can
+    pub(crate) fn can_align(_align: usize) -> bool {
+        true
+    }
+
+    // Maps `size` bytes starting on a multiple of `align`, a power of two
+    // of at least a page, by mapping `align` more and unmapping the ends.
+    pub(crate) fn map_aligned(
+        size: usize,
+        align: usize,
+    ) -> Option<NonNull<u8>> {
+        let padded = size.checked_add(align)?;
+        let ptr = map(padded, 0)?;
+        let addr = ptr.as_ptr() as usize;
+        let start = (addr + align - 1) & !(align - 1);
+        let head = start - addr;
+        let tail = padded - head - size;
+        unsafe {
+            if head > 0 {
+                unmap(ptr, head);
+            }
+            if tail > 0 {
+                unmap(NonNull::new_unchecked((start + size) as *mut u8), tail);
+            }
+            Some(NonNull::new_unchecked(start as *mut u8))
+        }
+    }
+
+    // Asks for the mapping to be backed by transparent huge pages. Only a
+    // hint: the kernel may not have them enabled.
+    #[cfg(target_os = "linux")]
+    pub(crate) unsafe fn advise_huge(ptr: NonNull<u8>, size: usize) {
+        libc::madvise(
+            ptr.as_ptr() as *mut libc::c_void,
+            size,
+            libc::MADV_HUGEPAGE,
+        );
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(crate) unsafe fn advise_huge(_ptr: NonNull<u8>, _size: usize) {}
+
+    // Resizes the mapping, moving it if it has to; None if it can't, or
+    // this platform has no way to.
+    #[cfg(target_os = "linux")]
+    pub(crate) unsafe fn remap(
+        ptr: NonNull<u8>,
+        old_size: usize,
+        new_size: usize,
+    ) -> Option<NonNull<u8>> {
+        let ptr = libc::mremap(
+            ptr.as_ptr() as *mut libc::c_void,
+            old_size,
+            new_size,
+            libc::MREMAP_MAYMOVE,
+        );
+        if ptr == libc::MAP_FAILED {
+            None
+        } else {
+            NonNull::new(ptr as *mut u8)
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(crate) unsafe fn remap(
+        _ptr: NonNull<u8>,
+        _old_size: usize,
+        _new_size: usize,
+    ) -> Option<NonNull<u8>> {
+        None
+    }
+
+    // Gives back the pages past `new_size`; both sizes are whole pages.
+    pub(crate) unsafe fn trim(
+        ptr: NonNull<u8>,
+        old_size: usize,
+        new_size: usize,
+    ) {
+        if new_size < old_size {
+            unmap(
+                NonNull::new_unchecked(ptr.as_ptr().add(new_size)),
+                old_size - new_size,
+            );
+        }
+    }
+}
+
+#[cfg(windows)]
+pub(crate) mod sys {
+    use std::mem::MaybeUninit;
+    use std::ptr::{self, NonNull};
+    use windows_sys::Win32::System::Memory::{
+        GetLargePageMinimum, VirtualAlloc, VirtualFree, MEM_COMMIT,
+        MEM_DECOMMIT, MEM_LARGE_PAGES, MEM_RELEASE, MEM_RESERVE,
+        PAGE_READWRITE,
+    };
+    use windows_sys::Win32::System::SystemInformation::{
+        GetSystemInfo, SYSTEM_INFO,
+    };
+
+    pub(crate) fn page_size() -> usize {
+        unsafe {
+            let mut info = MaybeUninit::<SYSTEM_INFO>::uninit();
+            GetSystemInfo(info.as_mut_ptr());
+            info.assume_init().dwPageSize as usize
+        }
+    }
+
+    pub(crate) fn map(size: usize, flags: u32) -> Option<NonNull<u8>> {
+        let ptr = unsafe {
+            VirtualAlloc(
+                ptr::null(),
+                size,
+                MEM_COMMIT | MEM_RESERVE | flags,
+                PAGE_READWRITE,
+            )
+        };
+        NonNull::new(ptr as *mut u8)
+    }
+
+    // a reservation can only be released whole, whatever its size now
+    pub(crate) unsafe fn unmap(ptr: NonNull<u8>, _size: usize) {
+        VirtualFree(ptr.as_ptr() as *mut _, 0, MEM_RELEASE);
+    }
+
+    // Maps `size` bytes of large pages, which come in the one size the
+    // system supports; None if it has none, or the process lacks the
+    // SeLockMemoryPrivilege they need.
+    pub(crate) fn map_huge(
+        size: usize,
+        _page_size: usize,
+    ) -> Option<NonNull<u8>> {
+        let minimum = unsafe { GetLargePageMinimum() };
+        if minimum == 0 || size % minimum != 0 {
+            return None;
+        }
+        map(size, MEM_LARGE_PAGES)
+    }
+
+    // Whether `map_aligned` can meet `align`: only up to the allocation
+    // granularity, since reservations can't be trimmed to line them up.
+    pub(crate) fn can_align(align: usize) -> bool {
+        align <= 1 << 16
+    }
+
+    // Aligned to 64 KiB whatever `align` is; callers check `can_align`.
+    pub(crate) fn map_aligned(
+        size: usize,
+        _align: usize,
+    ) -> Option<NonNull<u8>> {
+        map(size, 0)
+    }
+
+    // Windows has no transparent huge pages.
+    pub(crate) unsafe fn advise_huge(_ptr: NonNull<u8>, _size: usize) {}
+
+    pub(crate) unsafe fn remap(
+        _ptr: NonNull<u8>,
+        _old_size: usize,
+        _new_size: usize,
+    ) -> Option<NonNull<u8>> {
+        None
+    }
+
+    // Decommits the pages past `new_size`; both sizes are whole pages.
+    pub(crate) unsafe fn trim(
+        ptr: NonNull<u8>,
+        old_size: usize,
+        new_size: usize,
+    ) {
+        if new_size < old_size {
+            let tail = ptr.as_ptr().add(new_size) as *mut _;
+            VirtualFree(tail, old_size - new_size, MEM_DECOMMIT);
+        }
+    }
+}
+
+pub(crate) fn page_size() -> usize {
+    static PAGE_SIZE: AtomicUsize = AtomicUsize::new(0);
+    match PAGE_SIZE.load(Ordering::Relaxed) {
+        0 => {
+            let size = sys::page_size();
+            PAGE_SIZE.store(size, Ordering::Relaxed);
+            size
+        }
+        size => size,
+    }
+}
+
+// `size` rounded up to a multiple of `unit`, a power of two.
+pub(crate) fn round_up(size: usize, unit: usize) -> Result<usize, AllocError> {
+    size.checked_add(unit - 1)
+        .map(|size| size & !(unit - 1))
+        .ok_or(AllocError)
+}
+
+// Cuts a block from the wrapped allocator down to the size asked for. A
+// caller can free a block with any size up to the length it was handed,
+// and one at or over the threshold would then be taken for a mapping.
+pub(crate) fn exact(
+    block: Result<NonNull<[u8]>, AllocError>,
+    layout: Layout,
+) -> Result<NonNull<[u8]>, AllocError> {
+    block
+        .map(|block| NonNull::slice_from_raw_parts(block.cast(), layout.size()))
+}
+
+// the default threshold: 1 MiB
+const THRESHOLD: usize = 1 << 20;
+
+pub struct MmapAllocator<A: Allocator = Global> {
+    threshold: usize,
+    alloc: A,
+}
+
+impl MmapAllocator {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub const fn with_threshold(threshold: usize) -> Self {
+        Self::with_threshold_in(threshold, Global)
+    }
+}
+
+impl<A: Allocator> MmapAllocator<A> {
+    // maps blocks of 1 MiB and up
+    pub const fn new_in(alloc: A) -> Self {
+        Self::with_threshold_in(THRESHOLD, alloc)
+    }
+
+    // Maps blocks of at least `threshold` bytes; a threshold of 0 maps
+    // every block that isn't zero-sized.
+    pub const fn with_threshold_in(threshold: usize, alloc: A) -> Self {
+        MmapAllocator { threshold, alloc }
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    fn is_mapped(&self, layout: Layout) -> bool {
+        layout.size() != 0
+            && layout.size() >= self.threshold
+            && layout.align() <= page_size()
+    }
+
+    fn map(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let size = round_up(layout.size(), page_size())?;
+        let ptr = sys::map(size, 0).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, size))
+    }
+
+    // Moves a block between `A` and a mapping, copying `len` bytes.
+    unsafe fn relocate(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        len: usize,
+        zeroed: bool,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let new = if zeroed {
+            self.allocate_zeroed(new_layout)?
+        } else {
+            self.allocate(new_layout)?
+        };
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new.as_ptr() as *mut u8, len);
+        self.deallocate(ptr, old_layout);
+        Ok(new)
+    }
+
+    unsafe fn grow_mapped(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        zeroed: bool,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let old_size = round_up(old_layout.size(), page_size())?;
+        let new_size = round_up(new_layout.size(), page_size())?;
+        if zeroed {
+            // the rest of the last page may have been written to; pages
+            // past it come zeroed
+            let slack = old_size - old_layout.size();
+            ptr::write_bytes(ptr.as_ptr().add(old_layout.size()), 0, slack);
+        }
+        if new_size == old_size {
+            return Ok(NonNull::slice_from_raw_parts(ptr, old_size));
+        }
+        match sys::remap(ptr, old_size, new_size) {
+            Some(new) => Ok(NonNull::slice_from_raw_parts(new, new_size)),
+            None => {
+                let len = old_layout.size();
+                self.relocate(ptr, old_layout, new_layout, len, zeroed)
+            }
+        }
+    }
+}
+
+impl Default for MmapAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Allocator> fmt::Debug for MmapAllocator<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MmapAllocator")
+            .field("threshold", &self.threshold)
+            .finish()
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for MmapAllocator<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if self.is_mapped(layout) {
+            self.map(layout)
+        } else {
+            exact(self.alloc.allocate(layout), layout)
+        }
+    }
+
+    fn allocate_zeroed(
+        &self,
+        layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if self.is_mapped(layout) {
+            self.map(layout)
+        } else {
+            exact(self.alloc.allocate_zeroed(layout), layout)
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if self.is_mapped(layout) {
+            // the layout was good for `map`, so rounding can't overflow
+            sys::unmap(ptr, round_up(layout.size(), page_size()).unwrap());
+        } else {
+            self.alloc.deallocate(ptr, layout)
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        match (self.is_mapped(old_layout), self.is_mapped(new_layout)) {
+            (true, true) => {
+                self.grow_mapped(ptr, old_layout, new_layout, false)
+            }
+            (false, false) => {
+                exact(self.alloc.grow(ptr, old_layout, new_layout), new_layout)
+            }
+            _ => {
+                let len = old_layout.size();
+                self.relocate(ptr, old_layout, new_layout, len, false)
+            }
+        }
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        match (self.is_mapped(old_layout), self.is_mapped(new_layout)) {
+            (true, true) => self.grow_mapped(ptr, old_layout, new_layout, true),
+            (false, false) => exact(
+                self.alloc.grow_zeroed(ptr, old_layout, new_layout),
+                new_layout,
+            ),
+            _ => {
+                let len = old_layout.size();
+                self.relocate(ptr, old_layout, new_layout, len, true)
+            }
+        }
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        match (self.is_mapped(old_layout), self.is_mapped(new_layout)) {
+            (true, true) => {
+                let old_size = round_up(old_layout.size(), page_size())?;
+                let new_size = round_up(new_layout.size(), page_size())?;
+                sys::trim(ptr, old_size, new_size);
+                Ok(NonNull::slice_from_raw_parts(ptr, new_size))
+            }
+            (false, false) => exact(
+                self.alloc.shrink(ptr, old_layout, new_layout),
+                new_layout,
+            ),
+            _ => {
+                let len = new_layout.size();
+                self.relocate(ptr, old_layout, new_layout, len, false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CountingAllocator, NomVec, PoolAllocator};
+
+    #[test]
+    fn mmap_maps_large_blocks() {
+        let counting = CountingAllocator::new();
+        let mmap = MmapAllocator::new_in(&counting);
+        let mut cv = NomVec::new_in(&mmap);
+        cv.extend(0u64..10_000_000);
+        assert_eq!(cv.as_ptr() as usize % page_size(), 0);
+        assert!(cv.iter().copied().eq(0..10_000_000));
+        // only the early, small capacities went to the fallback
+        let stats = counting.stats();
+        assert!(stats.bytes_allocated < THRESHOLD);
+        assert_eq!(stats.live_bytes(), 0);
+
+        cv.truncate(1000);
+        cv.shrink_to_fit();
+        assert!(cv.iter().copied().eq(0..1000));
+        assert_eq!(counting.stats().live_bytes(), 8000);
+        drop(cv);
+        assert_eq!(counting.stats().live_bytes(), 0);
+    }
+
+    #[test]
+    fn mmap_zeroed_growth() {
+        let mmap = MmapAllocator::with_threshold(0);
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let bigger = Layout::from_size_align(3 * page_size(), 8).unwrap();
+        unsafe {
+            let block = mmap.allocate_zeroed(layout).unwrap();
+            let bytes = block.as_ptr() as *mut u8;
+            assert_eq!(block.len(), page_size());
+            // scribble past the layout, into the rest of the page
+            ptr::write_bytes(bytes, 0xff, block.len());
+            let grown = mmap.grow_zeroed(block.cast(), layout, bigger).unwrap();
+            let grown = grown.as_ptr() as *mut u8;
+            let all = std::slice::from_raw_parts(grown, bigger.size());
+            assert!(all[..100].iter().all(|&b| b == 0xff));
+            assert!(all[100..].iter().all(|&b| b == 0));
+            let shrunk =
+                mmap.shrink(NonNull::new(grown).unwrap(), bigger, layout);
+            let shrunk = shrunk.unwrap().as_ptr() as *mut u8;
+            assert_eq!(*shrunk.add(99), 0xff);
+            mmap.deallocate(NonNull::new(shrunk).unwrap(), layout);
+        }
+        assert_eq!(format!("{:?}", mmap), "MmapAllocator { threshold: 0 }");
+    }
+
+    #[test]
+    fn mmap_hands_back_exact_fallback_blocks() {
+        // the pool rounds 80 bytes up to a 128-byte block
+        let mmap = MmapAllocator::with_threshold_in(100, PoolAllocator::new());
+        let layout = Layout::from_size_align(80, 8).unwrap();
+        unsafe {
+            let block = mmap.allocate(layout).unwrap();
+            assert_eq!(block.len(), 80);
+            // freeing with the length handed out is allowed, and has to
+            // reach the pool rather than munmap
+            let freed = Layout::from_size_align(block.len(), 8).unwrap();
+            mmap.deallocate(block.cast(), freed);
+        }
+        assert_eq!(mmap.allocator().cached_blocks(), 1);
+    }
+}
@@ -0,0 +1,639 @@
+// A struct-of-arrays vector: each field of a tuple row lives in its own
+// NomVec column, and the columns always share a length. Reading one field
+// across every row touches only that field's memory, and `columns_mut`
+// hands out every column at once so systems can borrow them independently.
+//
+// The per-arity plumbing lives in the `Columns` trait, implemented for
+// tuples of up to eight fields.
+use crate::{Allocator, Global, IntoIter as VecIntoIter, NomVec};
+use std::fmt;
+use std::iter::{FromIterator, FusedIterator};
+use std::mem;
+
+pub trait Columns: Sized {
+    // one NomVec per field
+    type Vecs<A: Allocator + Clone>;
+    type Refs<'a>
+    where
+        Self: 'a;
+    type Muts<'a>
+    where
+        Self: 'a;
+    type Slices<'a>: Copy
+    where
+        Self: 'a;
+    type SlicesMut<'a>
+    where
+        Self: 'a;
+    type IntoIters<A: Allocator + Clone>;
+
+    fn with_capacity_in<A: Allocator + Clone>(
+        capacity: usize,
+        alloc: A,
+    ) -> Self::Vecs<A>;
+    fn len<A: Allocator + Clone>(vecs: &Self::Vecs<A>) -> usize;
+    fn capacity<A: Allocator + Clone>(vecs: &Self::Vecs<A>) -> usize;
+    fn allocator<A: Allocator + Clone>(vecs: &Self::Vecs<A>) -> &A;
+    fn reserve<A: Allocator + Clone>(
+        vecs: &mut Self::Vecs<A>,
+        additional: usize,
+    );
+    fn shrink_to_fit<A: Allocator + Clone>(vecs: &mut Self::Vecs<A>);
+    fn push<A: Allocator + Clone>(vecs: &mut Self::Vecs<A>, row: Self);
+    fn pop<A: Allocator + Clone>(vecs: &mut Self::Vecs<A>) -> Option<Self>;
+    fn insert<A: Allocator + Clone>(
+        vecs: &mut Self::Vecs<A>,
+        index: usize,
+        row: Self,
+    );
+    fn remove<A: Allocator + Clone>(
+        vecs: &mut Self::Vecs<A>,
+        index: usize,
+    ) -> Self;
+    fn swap_remove<A: Allocator + Clone>(
+        vecs: &mut Self::Vecs<A>,
+        index: usize,
+    ) -> Self;
+    fn truncate<A: Allocator + Clone>(vecs: &mut Self::Vecs<A>, len: usize);
+    fn slices<A: Allocator + Clone>(vecs: &Self::Vecs<A>) -> Self::Slices<'_>;
+    fn slices_mut<A: Allocator + Clone>(
+        vecs: &mut Self::Vecs<A>,
+    ) -> Self::SlicesMut<'_>;
+    fn get(slices: Self::Slices<'_>, index: usize) -> Option<Self::Refs<'_>>;
+    fn get_mut(
+        slices: Self::SlicesMut<'_>,
+        index: usize,
+    ) -> Option<Self::Muts<'_>>;
+    fn split_first<'a>(slices: &mut Self::Slices<'a>)
+        -> Option<Self::Refs<'a>>;
+    fn split_last<'a>(slices: &mut Self::Slices<'a>) -> Option<Self::Refs<'a>>;
+    fn split_first_mut<'a>(
+        slices: &mut Self::SlicesMut<'a>,
+    ) -> Option<Self::Muts<'a>>;
+    fn split_last_mut<'a>(
+        slices: &mut Self::SlicesMut<'a>,
+    ) -> Option<Self::Muts<'a>>;
+    fn into_iters<A: Allocator + Clone>(
+        vecs: Self::Vecs<A>,
+    ) -> Self::IntoIters<A>;
+    fn next<A: Allocator + Clone>(
+        iters: &mut Self::IntoIters<A>,
+    ) -> Option<Self>;
+    fn next_back<A: Allocator + Clone>(
+        iters: &mut Self::IntoIters<A>,
+    ) -> Option<Self>;
+}
+
+// Every column has the same length, so checking the first one is enough
+// before touching the rest.
+macro_rules! impl_columns {
+    ($($T:ident $idx:tt),+) => {
+        impl<$($T),+> Columns for ($($T,)+) {
+            type Vecs<A: Allocator + Clone> = ($(NomVec<$T, A>,)+);
+            type Refs<'a> = ($(&'a $T,)+) where Self: 'a;
+            type Muts<'a> = ($(&'a mut $T,)+) where Self: 'a;
+            type Slices<'a> = ($(&'a [$T],)+) where Self: 'a;
+            type SlicesMut<'a> = ($(&'a mut [$T],)+) where Self: 'a;
+            type IntoIters<A: Allocator + Clone> = ($(VecIntoIter<$T, A>,)+);
+
+            fn with_capacity_in<A: Allocator + Clone>(
+                capacity: usize,
+                alloc: A,
+            ) -> Self::Vecs<A> {
+                ($(NomVec::<$T, A>::with_capacity_in(capacity, alloc.clone()),)+)
+            }
+
+            fn len<A: Allocator + Clone>(vecs: &Self::Vecs<A>) -> usize {
+                vecs.0.len()
+            }
+
+            fn capacity<A: Allocator + Clone>(vecs: &Self::Vecs<A>) -> usize {
+                let mut capacity = usize::MAX;
+                $(capacity = capacity.min(vecs.$idx.capacity());)+
+                capacity
+            }
+
+            fn allocator<A: Allocator + Clone>(vecs: &Self::Vecs<A>) -> &A {
+                vecs.0.allocator()
+            }
+
+            fn reserve<A: Allocator + Clone>(
+                vecs: &mut Self::Vecs<A>,
+                additional: usize,
+            ) {
+                $(vecs.$idx.reserve(additional);)+
+            }
+
+            fn shrink_to_fit<A: Allocator + Clone>(vecs: &mut Self::Vecs<A>) {
+                $(vecs.$idx.shrink_to_fit();)+
+            }
+
+            fn push<A: Allocator + Clone>(vecs: &mut Self::Vecs<A>, row: Self) {
+                // grow every column before writing any, so a failed
+                // allocation can't leave them with different lengths
+                Self::reserve(vecs, 1);
+                $(vecs.$idx.push(row.$idx);)+
+            }
+
+            fn pop<A: Allocator + Clone>(vecs: &mut Self::Vecs<A>) -> Option<Self> {
+                if vecs.0.is_empty() {
+                    return None;
+                }
+                Some(($(vecs.$idx.pop().unwrap(),)+))
+            }
+
+            fn insert<A: Allocator + Clone>(
+                vecs: &mut Self::Vecs<A>,
+                index: usize,
+                row: Self,
+            ) {
+                assert!(index <= vecs.0.len(), "index out of bounds");
+                Self::reserve(vecs, 1);
+                $(vecs.$idx.insert(index, row.$idx);)+
+            }
+
+            fn remove<A: Allocator + Clone>(
+                vecs: &mut Self::Vecs<A>,
+                index: usize,
+            ) -> Self {
+                assert!(index < vecs.0.len(), "index out of bounds");
+                ($(vecs.$idx.remove(index),)+)
+            }
+
+            fn swap_remove<A: Allocator + Clone>(
+                vecs: &mut Self::Vecs<A>,
+                index: usize,
+            ) -> Self {
+                assert!(index < vecs.0.len(), "index out of bounds");
+                ($(vecs.$idx.swap_remove(index),)+)
+            }
+
+            fn truncate<A: Allocator + Clone>(vecs: &mut Self::Vecs<A>, len: usize) {
+                $(vecs.$idx.truncate(len);)+
+            }
+
+            fn slices<A: Allocator + Clone>(vecs: &Self::Vecs<A>) -> Self::Slices<'_> {
+                ($(&vecs.$idx[..],)+)
+            }
+
+            fn slices_mut<A: Allocator + Clone>(
+                vecs: &mut Self::Vecs<A>,
+            ) -> Self::SlicesMut<'_> {
+                ($(&mut vecs.$idx[..],)+)
+            }
+
+            fn get(slices: Self::Slices<'_>, index: usize) -> Option<Self::Refs<'_>> {
+                Some(($(slices.$idx.get(index)?,)+))
+            }
+
+            fn get_mut(
+                slices: Self::SlicesMut<'_>,
+                index: usize,
+            ) -> Option<Self::Muts<'_>> {
+                if index >= slices.0.len() {
+                    return None;
+                }
+                Some(($(&mut slices.$idx[index],)+))
+            }
+
+            fn split_first<'a>(slices: &mut Self::Slices<'a>) -> Option<Self::Refs<'a>> {
+                if slices.0.is_empty() {
+                    return None;
+                }
+                Some(($({
+                    let (first, rest) = slices.$idx.split_first().unwrap();
+                    slices.$idx = rest;
+                    first
+                },)+))
+            }
+
+            fn split_last<'a>(slices: &mut Self::Slices<'a>) -> Option<Self::Refs<'a>> {
+                if slices.0.is_empty() {
+                    return None;
+                }
+                Some(($({
+                    let (last, rest) = slices.$idx.split_last().unwrap();
+                    slices.$idx = rest;
+                    last
+                },)+))
+            }
+
+            fn split_first_mut<'a>(
+                slices: &mut Self::SlicesMut<'a>,
+            ) -> Option<Self::Muts<'a>> {
+                if slices.0.is_empty() {
+                    return None;
+                }
+                Some(($({
+                    let (first, rest) =
+                        mem::take(&mut slices.$idx).split_first_mut().unwrap();
+                    slices.$idx = rest;
+                    first
+                },)+))
+            }
+
+            fn split_last_mut<'a>(
+                slices: &mut Self::SlicesMut<'a>,
+            ) -> Option<Self::Muts<'a>> {
+                if slices.0.is_empty() {
+                    return None;
+                }
+                Some(($({
+                    let (last, rest) =
+                        mem::take(&mut slices.$idx).split_last_mut().unwrap();
+                    slices.$idx = rest;
+                    last
+                },)+))
+            }
+
+            fn into_iters<A: Allocator + Clone>(
+                vecs: Self::Vecs<A>,
+            ) -> Self::IntoIters<A> {
+                ($(vecs.$idx.into_iter(),)+)
+            }
+
+            fn next<A: Allocator + Clone>(
+                iters: &mut Self::IntoIters<A>,
+            ) -> Option<Self> {
+                Some(($(iters.$idx.next()?,)+))
+            }
+
+            fn next_back<A: Allocator + Clone>(
+                iters: &mut Self::IntoIters<A>,
+            ) -> Option<Self> {
+                Some(($(iters.$idx.next_back()?,)+))
+            }
+        }
+    };
+}
+
+impl_columns!(T0 0);
+impl_columns!(T0 0, T1 1);
+impl_columns!(T0 0, T1 1, T2 2);
+impl_columns!(T0 0, T1 1, T2 2, T3 3);
+impl_columns!(T0 0, T1 1, T2 2, T3 3, T4 4);
+impl_columns!(T0 0, T1 1, T2 2, T3 3, T4 4, T5 5);
+impl_columns!(T0 0, T1 1, T2 2, T3 3, T4 4, T5 5, T6 6);
+impl_columns!(T0 0, T1 1, T2 2, T3 3, T4 4, T5 5, T6 6, T7 7);
+
+pub struct MultiNomVec<T: Columns, A: Allocator + Clone = Global> {
+    columns: T::Vecs<A>,
+}
+
+impl<T: Columns> MultiNomVec<T> {
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<T: Columns, A: Allocator + Clone> MultiNomVec<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        Self::with_capacity_in(0, alloc)
+    }
+
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        MultiNomVec {
+            columns: T::with_capacity_in(capacity, alloc),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        T::len(&self.columns)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // the number of rows every column can hold without reallocating
+    pub fn capacity(&self) -> usize {
+        T::capacity(&self.columns)
+    }
+
+    pub fn allocator(&self) -> &A {
+        T::allocator(&self.columns)
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        T::reserve(&mut self.columns, additional)
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        T::shrink_to_fit(&mut self.columns)
+    }
+
+    pub fn push(&mut self, row: T) {
+        T::push(&mut self.columns, row)
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        T::pop(&mut self.columns)
+    }
+
+    pub fn insert(&mut self, index: usize, row: T) {
+        T::insert(&mut self.columns, index, row)
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        T::remove(&mut self.columns, index)
+    }
+
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        T::swap_remove(&mut self.columns, index)
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        T::truncate(&mut self.columns, len)
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+
+    // the fields of row `index`, one reference per column
+    pub fn get(&self, index: usize) -> Option<T::Refs<'_>> {
+        T::get(self.columns(), index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<T::Muts<'_>> {
+        T::get_mut(self.columns_mut(), index)
+    }
+
+    // one slice per column, e.g. `(&[T0], &[T1])`
+    pub fn columns(&self) -> T::Slices<'_> {
+        T::slices(&self.columns)
+    }
+
+    pub fn columns_mut(&mut self) -> T::SlicesMut<'_> {
+        T::slices_mut(&mut self.columns)
+    }
+
+    // the columns themselves, as a tuple of NomVecs
+    pub fn into_columns(self) -> T::Vecs<A> {
+        self.columns
+    }
+
+    // the rows, with each column zipped into a tuple of references
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            slices: self.columns(),
+            len: self.len(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            len: self.len(),
+            slices: self.columns_mut(),
+        }
+    }
+}
+
+impl<T: Columns> Default for MultiNomVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Columns, A: Allocator + Clone> Clone for MultiNomVec<T, A>
+where
+    T::Vecs<A>: Clone,
+{
+    fn clone(&self) -> Self {
+        MultiNomVec {
+            columns: self.columns.clone(),
+        }
+    }
+}
+
+// prints the columns, not the rows
+impl<T: Columns, A: Allocator + Clone> fmt::Debug for MultiNomVec<T, A>
+where
+    T::Vecs<A>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MultiNomVec").field(&self.columns).finish()
+    }
+}
+
+impl<T: Columns, A: Allocator + Clone> PartialEq for MultiNomVec<T, A>
+where
+    T::Vecs<A>: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.columns == other.columns
+    }
+}
+
+impl<T: Columns, A: Allocator + Clone> Eq for MultiNomVec<T, A> where
+    T::Vecs<A>: Eq
+{
+}
+
+impl<T: Columns, A: Allocator + Clone> Extend<T> for MultiNomVec<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for row in iter {
+            self.push(row);
+        }
+    }
+}
+
+impl<T: Columns> FromIterator<T> for MultiNomVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut mv = MultiNomVec::new();
+        mv.extend(iter);
+        mv
+    }
+}
+
+pub struct Iter<'a, T: Columns + 'a> {
+    slices: T::Slices<'a>,
+    len: usize,
+}
+
+impl<'a, T: Columns> Iterator for Iter<'a, T> {
+    type Item = T::Refs<'a>;
+
+    fn next(&mut self) -> Option<T::Refs<'a>> {
+        let row = T::split_first(&mut self.slices)?;
+        self.len -= 1;
+        Some(row)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T: Columns> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<T::Refs<'a>> {
+        let row = T::split_last(&mut self.slices)?;
+        self.len -= 1;
+        Some(row)
+    }
+}
+
+impl<'a, T: Columns> ExactSizeIterator for Iter<'a, T> {}
+impl<'a, T: Columns> FusedIterator for Iter<'a, T> {}
+
+impl<'a, T: Columns> Clone for Iter<'a, T> {
+    fn clone(&self) -> Self {
+        Iter {
+            slices: self.slices,
+            len: self.len,
+        }
+    }
+}
+
+pub struct IterMut<'a, T: Columns + 'a> {
+    slices: T::SlicesMut<'a>,
+    len: usize,
+}
+
+impl<'a, T: Columns> Iterator for IterMut<'a, T> {
+    type Item = T::Muts<'a>;
+
+    fn next(&mut self) -> Option<T::Muts<'a>> {
+        let row = T::split_first_mut(&mut self.slices)?;
+        self.len -= 1;
+        Some(row)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T: Columns> DoubleEndedIterator for IterMut<'a, T> {
+    fn next_back(&mut self) -> Option<T::Muts<'a>> {
+        let row = T::split_last_mut(&mut self.slices)?;
+        self.len -= 1;
+        Some(row)
+    }
+}
+
+impl<'a, T: Columns> ExactSizeIterator for IterMut<'a, T> {}
+impl<'a, T: Columns> FusedIterator for IterMut<'a, T> {}
+
+pub struct IntoIter<T: Columns, A: Allocator + Clone = Global> {
+    iters: T::IntoIters<A>,
+    len: usize,
+}
+
+impl<T: Columns, A: Allocator + Clone> Iterator for IntoIter<T, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let row = T::next(&mut self.iters)?;
+        self.len -= 1;
+        Some(row)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T: Columns, A: Allocator + Clone> DoubleEndedIterator for IntoIter<T, A> {
+    fn next_back(&mut self) -> Option<T> {
+        let row = T::next_back(&mut self.iters)?;
+        self.len -= 1;
+        Some(row)
+    }
+}
+
+impl<T: Columns, A: Allocator + Clone> ExactSizeIterator for IntoIter<T, A> {}
+impl<T: Columns, A: Allocator + Clone> FusedIterator for IntoIter<T, A> {}
+
+impl<T: Columns, A: Allocator + Clone> IntoIterator for MultiNomVec<T, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+    fn into_iter(self) -> IntoIter<T, A> {
+        IntoIter {
+            len: self.len(),
+            iters: T::into_iters(self.columns),
+        }
+    }
+}
+
+impl<'a, T: Columns, A: Allocator + Clone> IntoIterator
+    for &'a MultiNomVec<T, A>
+{
+    type Item = T::Refs<'a>;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T: Columns, A: Allocator + Clone> IntoIterator
+    for &'a mut MultiNomVec<T, A>
+{
+    type Item = T::Muts<'a>;
+    type IntoIter = IterMut<'a, T>;
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multi_columns_stay_in_step() {
+        let mut mv: MultiNomVec<(u32, &str, f32)> = MultiNomVec::new();
+        mv.push((1, "a", 0.5));
+        mv.push((2, "b", 1.5));
+        mv.insert(0, (0, "z", 2.5));
+        assert_eq!(mv.len(), 3);
+        assert_eq!(mv.get(1), Some((&1, &"a", &0.5)));
+        assert_eq!(mv.get(3), None);
+
+        let (ids, names, weights) = mv.columns_mut();
+        ids[2] = 20;
+        names[0] = "zz";
+        for w in weights.iter_mut() {
+            *w *= 2.0;
+        }
+        assert_eq!(
+            mv.columns(),
+            (&[0, 1, 20][..], &["zz", "a", "b"][..], &[5.0, 1.0, 3.0][..])
+        );
+
+        assert_eq!(mv.swap_remove(0), (0, "zz", 5.0));
+        assert_eq!(mv.remove(0), (20, "b", 3.0));
+        assert_eq!(mv.pop(), Some((1, "a", 1.0)));
+        assert_eq!(mv.pop(), None);
+        assert!(mv.capacity() >= 3);
+    }
+
+    #[test]
+    fn multi_zipped_iteration() {
+        let mut mv: MultiNomVec<(usize, String)> =
+            (0..5).map(|i| (i, i.to_string())).collect();
+        for (n, s) in &mut mv {
+            *n *= 10;
+            s.push('!');
+        }
+        assert!(mv.iter().rev().map(|(n, _)| *n).eq([40, 30, 20, 10, 0]));
+        let mut iter = mv.iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.nth(1), Some((&10, &"1!".to_string())));
+        assert_eq!(iter.next_back().map(|(_, s)| s.as_str()), Some("4!"));
+        assert_eq!(iter.len(), 2);
+
+        let clone = mv.clone();
+        assert_eq!(clone, mv);
+        let rows: Vec<_> = mv.into_iter().rev().take(2).collect();
+        assert_eq!(rows, [(40, "4!".to_string()), (30, "3!".to_string())]);
+        let (ids, _) = clone.into_columns();
+        assert_eq!(ids, [0, 10, 20, 30, 40]);
+    }
+}
@@ -0,0 +1,401 @@
+// A single heap value allocated through `A`, for the places std's `Box`
+// would force the global allocator. It owns the value exactly like a Box
+// does: dropping it drops the value and frees the block with the layout the
+// value was allocated with, which `Layout::for_value` recovers even after
+// the box has been unsized to a slice or trait object.
+//
+// Unsizing coercions (`NomBox<T>` to `NomBox<dyn Trait>`) need the unstable
+// `CoerceUnsized`, so they're implicit only with the `nightly` feature; on
+// stable, `nombox_unsize!` does the same thing explicitly.
+use crate::{AllocationError, Allocator, Global, NomVec};
+use std::alloc::Layout;
+use std::borrow::{Borrow, BorrowMut};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::ptr::{self, NonNull};
+
+pub struct NomBox<T: ?Sized, A: Allocator = Global> {
+    ptr: NonNull<T>,
+    alloc: A,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: ?Sized + Send, A: Allocator + Send> Send for NomBox<T, A> {}
+unsafe impl<T: ?Sized + Sync, A: Allocator + Sync> Sync for NomBox<T, A> {}
+
+// Like Box, moving the box never moves the value.
+impl<T: ?Sized, A: Allocator> Unpin for NomBox<T, A> {}
+
+#[cfg(feature = "nightly")]
+impl<T, U, A> std::ops::CoerceUnsized<NomBox<U, A>> for NomBox<T, A>
+where
+    T: ?Sized + std::marker::Unsize<U>,
+    U: ?Sized,
+    A: Allocator,
+{
+}
+
+// Unsizes a `NomBox<T, A>` to a `NomBox<U, A>`, e.g. to a trait object or
+// a slice, keeping the allocation and the allocator.
+//
+//     let shape: NomBox<dyn Shape> = nombox_unsize!(NomBox::new(Circle(1.0)), dyn Shape);
+#[macro_export]
+macro_rules! nombox_unsize {
+    ($boxed:expr, $ty:ty) => {{
+        let (ptr, alloc) = $crate::NomBox::into_raw_with_allocator($boxed);
+        // an implicit coercion, so only ever an unsizing one
+        let ptr: *mut $ty = ptr;
+        unsafe { $crate::NomBox::from_raw_in(ptr, alloc) }
+    }};
+}
+
+impl<T> NomBox<T> {
+    pub fn new(value: T) -> Self {
+        Self::new_in(value, Global)
+    }
+
+    pub fn pin(value: T) -> Pin<Self> {
+        Self::pin_in(value, Global)
+    }
+}
+
+impl<T: ?Sized> NomBox<T> {
+    /// # Safety
+    ///
+    /// See [`NomBox::from_raw_in`]; the value must have come from the global
+    /// allocator.
+    pub unsafe fn from_raw(ptr: *mut T) -> Self {
+        Self::from_raw_in(ptr, Global)
+    }
+
+    pub fn into_raw(b: Self) -> *mut T {
+        Self::into_raw_with_allocator(b).0
+    }
+}
+
+impl<T, A: Allocator> NomBox<T, A> {
+    pub fn new_in(value: T, alloc: A) -> Self {
+        match Self::try_new_in(value, alloc) {
+            Ok(b) => b,
+            Err(err) => crate::handle_error(err),
+        }
+    }
+
+    pub fn try_new_in(value: T, alloc: A) -> Result<Self, AllocationError> {
+        let layout = Layout::new::<T>();
+        let ptr: NonNull<T> = if layout.size() == 0 {
+            NonNull::dangling()
+        } else {
+            alloc
+                .allocate(layout)
+                .map_err(|_| AllocationError::AllocFailed { layout })?
+                .cast()
+        };
+        unsafe { ptr.as_ptr().write(value) };
+        Ok(NomBox {
+            ptr,
+            alloc,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn pin_in(value: T, alloc: A) -> Pin<Self> {
+        Self::into_pin(Self::new_in(value, alloc))
+    }
+
+    // Moves the value back out, freeing the block.
+    pub fn into_inner(b: Self) -> T {
+        let (ptr, alloc) = Self::into_raw_with_allocator(b);
+        unsafe {
+            let value = ptr::read(ptr);
+            let layout = Layout::new::<T>();
+            if layout.size() != 0 {
+                alloc.deallocate(NonNull::new_unchecked(ptr).cast(), layout);
+            }
+            value
+        }
+    }
+}
+
+// Associated functions rather than methods, so they can't shadow methods of
+// the value behind the box.
+impl<T: ?Sized, A: Allocator> NomBox<T, A> {
+    /// # Safety
+    ///
+    /// `ptr` must point to a valid value allocated by `alloc` with the
+    /// layout `Layout::for_value` gives for it (or be dangling and aligned
+    /// if that layout has size 0), and ownership of the value moves into the
+    /// box. The parts `into_raw_with_allocator` returns always satisfy this.
+    pub unsafe fn from_raw_in(ptr: *mut T, alloc: A) -> Self {
+        NomBox {
+            ptr: NonNull::new_unchecked(ptr),
+            alloc,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn into_raw_with_allocator(b: Self) -> (*mut T, A) {
+        let b = mem::ManuallyDrop::new(b);
+        let alloc = unsafe { ptr::read(&b.alloc) };
+        (b.ptr.as_ptr(), alloc)
+    }
+
+    pub fn allocator(b: &Self) -> &A {
+        &b.alloc
+    }
+
+    pub fn into_pin(b: Self) -> Pin<Self> {
+        // the value stays in its block until the box drops it there, and a
+        // pinned box only hands out `Pin<&mut T>`
+        unsafe { Pin::new_unchecked(b) }
+    }
+
+    // Gives up ownership, never freeing the value.
+    pub fn leak<'a>(b: Self) -> &'a mut T
+    where
+        A: 'a,
+    {
+        unsafe { &mut *Self::into_raw_with_allocator(b).0 }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Drop for NomBox<T, A> {
+    fn drop(&mut self) {
+        unsafe {
+            let layout = Layout::for_value(self.ptr.as_ref());
+            ptr::drop_in_place(self.ptr.as_ptr());
+            if layout.size() != 0 {
+                self.alloc.deallocate(self.ptr.cast(), layout);
+            }
+        }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Deref for NomBox<T, A> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> DerefMut for NomBox<T, A> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> AsRef<T> for NomBox<T, A> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized, A: Allocator> AsMut<T> for NomBox<T, A> {
+    fn as_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Borrow<T> for NomBox<T, A> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized, A: Allocator> BorrowMut<T> for NomBox<T, A> {
+    fn borrow_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T: Default> Default for NomBox<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Clone for NomBox<T, A> {
+    fn clone(&self) -> Self {
+        Self::new_in((**self).clone(), self.alloc.clone())
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Clone for NomBox<[T], A> {
+    fn clone(&self) -> Self {
+        let mut vec = NomVec::with_capacity_in(self.len(), self.alloc.clone());
+        vec.extend_from_slice(self);
+        vec.into()
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, A: Allocator> fmt::Debug for NomBox<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display, A: Allocator> fmt::Display for NomBox<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized, A: Allocator> fmt::Pointer for NomBox<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&self.ptr, f)
+    }
+}
+
+impl<T: ?Sized + PartialEq, A: Allocator> PartialEq for NomBox<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: ?Sized + Eq, A: Allocator> Eq for NomBox<T, A> {}
+
+impl<T: ?Sized + PartialOrd, A: Allocator> PartialOrd for NomBox<T, A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Ord, A: Allocator> Ord for NomBox<T, A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Hash, A: Allocator> Hash for NomBox<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<T> From<T> for NomBox<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+// Shrinks the buffer to fit, then hands it over without copying.
+impl<T, A: Allocator> From<NomVec<T, A>> for NomBox<[T], A> {
+    fn from(mut vec: NomVec<T, A>) -> Self {
+        vec.shrink_to_fit();
+        let (ptr, len, _, alloc) = vec.into_raw_parts_with_alloc();
+        unsafe {
+            NomBox::from_raw_in(ptr::slice_from_raw_parts_mut(ptr, len), alloc)
+        }
+    }
+}
+
+impl<T, A: Allocator> From<NomBox<[T], A>> for NomVec<T, A> {
+    fn from(b: NomBox<[T], A>) -> Self {
+        let (ptr, alloc) = NomBox::into_raw_with_allocator(b);
+        unsafe {
+            let len = ptr.len();
+            NomVec::from_raw_parts_in(ptr as *mut T, len, len, alloc)
+        }
+    }
+}
+
+impl<I: Iterator + ?Sized, A: Allocator> Iterator for NomBox<I, A> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        (**self).next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (**self).size_hint()
+    }
+}
+
+impl<I: DoubleEndedIterator + ?Sized, A: Allocator> DoubleEndedIterator
+    for NomBox<I, A>
+{
+    fn next_back(&mut self) -> Option<I::Item> {
+        (**self).next_back()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    trait Shape {
+        fn area(&self) -> f64;
+    }
+
+    struct Square(f64, Rc<Cell<usize>>);
+
+    impl Shape for Square {
+        fn area(&self) -> f64 {
+            self.0 * self.0
+        }
+    }
+
+    impl Drop for Square {
+        fn drop(&mut self) {
+            self.1.set(self.1.get() + 1);
+        }
+    }
+
+    #[test]
+    fn nombox_unsizes_to_trait_objects() {
+        let drops = Rc::new(Cell::new(0));
+        let shapes: Vec<NomBox<dyn Shape>> = (1..4)
+            .map(|side| {
+                nombox_unsize!(
+                    NomBox::new(Square(side as f64, drops.clone())),
+                    dyn Shape
+                )
+            })
+            .collect();
+        assert_eq!(shapes.iter().map(|s| s.area()).sum::<f64>(), 14.0);
+        drop(shapes);
+        assert_eq!(drops.get(), 3);
+        #[cfg(feature = "nightly")]
+        {
+            let shape: NomBox<dyn Shape> =
+                NomBox::new(Square(2.0, drops.clone()));
+            assert_eq!(shape.area(), 4.0);
+        }
+
+        let mut counter: NomBox<dyn Iterator<Item = u32>> =
+            nombox_unsize!(NomBox::new(0..3), dyn Iterator<Item = u32>);
+        assert_eq!(counter.next(), Some(0));
+        assert_eq!(counter.sum::<u32>(), 3);
+        let slice: NomBox<[u8]> = nombox_unsize!(NomBox::new([1, 2, 3]), [u8]);
+        assert_eq!(NomVec::from(slice), [1, 2, 3]);
+    }
+
+    #[test]
+    fn nombox_owns_its_value() {
+        let b = NomBox::new(String::from("hi"));
+        let mut c = b.clone();
+        c.push('!');
+        assert_eq!(NomBox::into_inner(c), "hi!");
+        assert_eq!(*b, "hi");
+
+        let pinned = NomBox::pin(5u64);
+        assert_eq!(*pinned, 5);
+        let zst = NomBox::new(());
+        assert_eq!(NomBox::into_inner(zst), ());
+
+        let slice = NomBox::<[i32]>::from(NomVec::from([3, 1, 2]));
+        assert_eq!(&*slice, [3, 1, 2]);
+        assert_eq!(slice.clone(), slice);
+        let leaked = NomBox::leak(NomBox::new(7));
+        *leaked += 1;
+        assert_eq!(*leaked, 8);
+        unsafe { drop(NomBox::from_raw(leaked)) };
+    }
+}
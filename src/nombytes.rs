@@ -0,0 +1,347 @@
+// An immutable byte buffer in the style of `bytes::Bytes`: a window onto a
+// reference-counted NomVec<u8>. Clones, `slice`, `split_to` and `split_off`
+// all share the buffer and just move the window, so a protocol parser can
+// carve a received frame into header and body pieces without copying any
+// of it.
+use crate::shared::Shared;
+use crate::{slice_range, Allocator, Global, NomVec};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::ops::{Deref, RangeBounds};
+use std::slice;
+
+pub struct NomBytes<A: Allocator + Clone = Global> {
+    buf: Shared<NomVec<u8, A>, A>,
+    // the window onto `buf`
+    start: usize,
+    len: usize,
+}
+
+impl NomBytes {
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn copy_from_slice(data: &[u8]) -> Self {
+        Self::copy_from_slice_in(data, Global)
+    }
+}
+
+impl<A: Allocator + Clone> NomBytes<A> {
+    pub fn new_in(alloc: A) -> Self {
+        Self::from_vec(NomVec::new_in(alloc))
+    }
+
+    pub fn copy_from_slice_in(data: &[u8], alloc: A) -> Self {
+        let mut vec = NomVec::with_capacity_in(data.len(), alloc);
+        vec.extend_from_slice_copy(data);
+        Self::from_vec(vec)
+    }
+
+    // Takes over `vec`'s buffer without copying it.
+    pub fn from_vec(vec: NomVec<u8, A>) -> Self {
+        let len = vec.len();
+        let alloc = vec.allocator().clone();
+        NomBytes {
+            buf: Shared::new_in(vec, alloc),
+            start: 0,
+            len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.buf.allocator()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf[self.start..][..self.len]
+    }
+
+    // A new handle onto `range` of this one's bytes. Panics if the range is
+    // out of bounds.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> Self {
+        let range = slice_range(range, self.len);
+        NomBytes {
+            buf: self.buf.clone(),
+            start: self.start + range.start,
+            len: range.end - range.start,
+        }
+    }
+
+    // Narrows `sub`, which must borrow from these bytes, to a handle of its
+    // own. Panics if it doesn't.
+    pub fn slice_ref(&self, sub: &[u8]) -> Self {
+        if sub.is_empty() {
+            return self.slice(0..0);
+        }
+        let base = self.as_slice().as_ptr() as usize;
+        let addr = sub.as_ptr() as usize;
+        assert!(
+            addr >= base && addr + sub.len() <= base + self.len,
+            "subslice is not within these bytes"
+        );
+        self.slice(addr - base..addr - base + sub.len())
+    }
+
+    // Splits off and returns the first `at` bytes, keeping the rest.
+    // Panics if `at > len`.
+    #[must_use = "use `advance` to discard the front bytes"]
+    pub fn split_to(&mut self, at: usize) -> Self {
+        assert!(at <= self.len, "split_to out of bounds");
+        let front = self.slice(..at);
+        self.start += at;
+        self.len -= at;
+        front
+    }
+
+    // Splits off and returns the bytes from `at` on, keeping the first
+    // `at`. Panics if `at > len`.
+    #[must_use = "use `truncate` to discard the back bytes"]
+    pub fn split_off(&mut self, at: usize) -> Self {
+        assert!(at <= self.len, "split_off out of bounds");
+        let back = self.slice(at..);
+        self.len = at;
+        back
+    }
+
+    // Drops the first `cnt` bytes. Panics if `cnt > len`.
+    pub fn advance(&mut self, cnt: usize) {
+        assert!(cnt <= self.len, "cannot advance past the end");
+        self.start += cnt;
+        self.len -= cnt;
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+
+    // The bytes as a NomVec: the buffer itself if nothing else shares it,
+    // otherwise a copy of the window.
+    pub fn into_vec(self) -> NomVec<u8, A> {
+        let NomBytes { buf, start, len } = self;
+        match Shared::try_unwrap(buf) {
+            Ok(mut vec) => {
+                vec.truncate(start + len);
+                vec.drain(..start);
+                vec
+            }
+            Err(buf) => {
+                let mut vec =
+                    NomVec::with_capacity_in(len, buf.allocator().clone());
+                vec.extend_from_slice_copy(&buf[start..][..len]);
+                vec
+            }
+        }
+    }
+}
+
+impl Default for NomBytes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// O(1): the clone shares the buffer
+impl<A: Allocator + Clone> Clone for NomBytes<A> {
+    fn clone(&self) -> Self {
+        NomBytes {
+            buf: self.buf.clone(),
+            start: self.start,
+            len: self.len,
+        }
+    }
+}
+
+impl<A: Allocator + Clone> Deref for NomBytes<A> {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl<A: Allocator + Clone> AsRef<[u8]> for NomBytes<A> {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl<A: Allocator + Clone> Borrow<[u8]> for NomBytes<A> {
+    fn borrow(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+// b"..." with non-printable bytes escaped
+impl<A: Allocator + Clone> fmt::Debug for NomBytes<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("b\"")?;
+        for &byte in self.as_slice() {
+            if byte == b'"' {
+                f.write_str("\\\"")?;
+            } else {
+                fmt::Display::fmt(&std::ascii::escape_default(byte), f)?;
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+impl<A: Allocator + Clone, B: Allocator + Clone> PartialEq<NomBytes<B>>
+    for NomBytes<A>
+{
+    fn eq(&self, other: &NomBytes<B>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<A: Allocator + Clone> PartialEq<[u8]> for NomBytes<A> {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl<A: Allocator + Clone> PartialEq<&[u8]> for NomBytes<A> {
+    fn eq(&self, other: &&[u8]) -> bool {
+        self.as_slice() == *other
+    }
+}
+
+impl<A: Allocator + Clone, const N: usize> PartialEq<[u8; N]> for NomBytes<A> {
+    fn eq(&self, other: &[u8; N]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl<A: Allocator + Clone, const N: usize> PartialEq<&[u8; N]> for NomBytes<A> {
+    fn eq(&self, other: &&[u8; N]) -> bool {
+        self.as_slice() == *other
+    }
+}
+
+impl<A: Allocator + Clone> Eq for NomBytes<A> {}
+
+impl<A: Allocator + Clone> PartialOrd for NomBytes<A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<A: Allocator + Clone> Ord for NomBytes<A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_slice().cmp(other.as_slice())
+    }
+}
+
+// hashes like `[u8]`, to go with the `Borrow` impl
+impl<A: Allocator + Clone> Hash for NomBytes<A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+impl<A: Allocator + Clone> From<NomVec<u8, A>> for NomBytes<A> {
+    fn from(vec: NomVec<u8, A>) -> Self {
+        Self::from_vec(vec)
+    }
+}
+
+impl<A: Allocator + Clone> From<NomBytes<A>> for NomVec<u8, A> {
+    fn from(bytes: NomBytes<A>) -> Self {
+        bytes.into_vec()
+    }
+}
+
+impl From<&[u8]> for NomBytes {
+    fn from(data: &[u8]) -> Self {
+        Self::copy_from_slice(data)
+    }
+}
+
+impl From<&str> for NomBytes {
+    fn from(s: &str) -> Self {
+        Self::copy_from_slice(s.as_bytes())
+    }
+}
+
+impl From<Vec<u8>> for NomBytes {
+    fn from(vec: Vec<u8>) -> Self {
+        Self::from_vec(NomVec::from(vec))
+    }
+}
+
+impl FromIterator<u8> for NomBytes {
+    fn from_iter<I: IntoIterator<Item = u8>>(iter: I) -> Self {
+        Self::from_vec(iter.into_iter().collect())
+    }
+}
+
+impl<'a, A: Allocator + Clone> IntoIterator for &'a NomBytes<A> {
+    type Item = &'a u8;
+    type IntoIter = slice::Iter<'a, u8>;
+    fn into_iter(self) -> slice::Iter<'a, u8> {
+        self.as_slice().iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nombytes_split_without_copying() {
+        let mut frame = NomBytes::from(NomVec::from(*b"LEN5hello world"));
+        let base = frame.as_ptr();
+        let header = frame.split_to(4);
+        assert_eq!(header, b"LEN5");
+        let tail = frame.split_off(5);
+        assert_eq!(frame, b"hello");
+        assert_eq!(tail, b" world");
+        assert_eq!(tail.as_ptr(), unsafe { base.add(9) });
+
+        let world = tail.slice(1..);
+        assert_eq!(world, b"world");
+        assert_eq!(world.slice_ref(&world[1..3]), b"or");
+        let mut copy = world.clone();
+        copy.advance(3);
+        assert_eq!(copy, b"ld");
+        assert_eq!(world.as_ptr(), unsafe { base.add(10) });
+        assert_eq!(
+            format!("{:?}", NomBytes::from("a\"\n\x01")),
+            r#"b"a\"\n\x01""#
+        );
+    }
+
+    #[test]
+    fn nombytes_into_vec() {
+        let vec = NomVec::from(*b"abcdef");
+        let ptr = vec.as_ptr();
+        let mut bytes = NomBytes::from(vec);
+        let shared = bytes.slice(2..4);
+        bytes.advance(1);
+        // shared, so the window is copied out
+        let copied = bytes.clone().into_vec();
+        assert_eq!(copied, *b"bcdef");
+        assert_ne!(copied.as_ptr(), ptr);
+        drop(shared);
+        bytes.truncate(3);
+        let vec: NomVec<u8> = bytes.into();
+        assert_eq!(vec, *b"bcd");
+        assert_eq!(vec.as_ptr(), ptr);
+        assert!(NomBytes::new().into_vec().is_empty());
+    }
+}
@@ -0,0 +1,605 @@
+// A hash map that remembers insertion order. The entries live in a NomVec
+// of pairs, so iteration is a slice walk in the order they were inserted
+// and every entry also has a stable position until something is removed.
+// The hash index beside it is an open-addressed table of positions, like
+// the interner's; removals use backward-shift deletion, so there are no
+// tombstones.
+use crate::vecmap::{Iter, IterMut};
+use crate::{Allocator, Global, NomVec};
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::{Index, IndexMut};
+
+pub struct OrderMap<K, V, A: Allocator + Clone = Global> {
+    entries: NomVec<(K, V), A>,
+    // 0 for an empty slot, otherwise position + 1; the length is 0 or a
+    // power of two, and at most half the slots are used
+    table: NomVec<usize, A>,
+    hasher: RandomState,
+}
+
+impl<K, V> OrderMap<K, V> {
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<K, V, A: Allocator + Clone> OrderMap<K, V, A> {
+    pub fn new_in(alloc: A) -> Self {
+        OrderMap {
+            entries: NomVec::new_in(alloc.clone()),
+            table: NomVec::new_in(alloc),
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        OrderMap {
+            entries: NomVec::with_capacity_in(capacity, alloc.clone()),
+            table: NomVec::new_in(alloc),
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries.capacity()
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.entries.allocator()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.entries.reserve(additional)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.table.clear();
+    }
+
+    // the entries, in insertion order
+    pub fn as_slice(&self) -> &[(K, V)] {
+        &self.entries
+    }
+
+    pub fn into_vec(self) -> NomVec<(K, V), A> {
+        self.entries
+    }
+
+    pub fn get_index(&self, index: usize) -> Option<(&K, &V)> {
+        self.entries.get(index).map(|(k, v)| (k, v))
+    }
+
+    pub fn get_index_mut(&mut self, index: usize) -> Option<(&K, &mut V)> {
+        self.entries.get_mut(index).map(|(k, v)| (&*k, v))
+    }
+
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.get_index(0)
+    }
+
+    pub fn last(&self) -> Option<(&K, &V)> {
+        self.entries.last().map(|(k, v)| (k, v))
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter::new(&self.entries)
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut::new(&mut self.entries)
+    }
+
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> + '_ {
+        self.entries.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> + '_ {
+        self.entries.iter().map(|(_, v)| v)
+    }
+
+    pub fn values_mut(
+        &mut self,
+    ) -> impl DoubleEndedIterator<Item = &mut V> + '_ {
+        self.entries.iter_mut().map(|(_, v)| v)
+    }
+}
+
+impl<K: Hash + Eq, V, A: Allocator + Clone> OrderMap<K, V, A> {
+    fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        self.hasher.hash_one(key) as usize
+    }
+
+    // the table slot holding `key`'s position, or the empty one where it
+    // would go
+    fn find_slot<Q>(&self, key: &Q, hash: usize) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mask = self.table.len() - 1;
+        let mut slot = hash & mask;
+        loop {
+            match self.table[slot] {
+                0 => return Err(slot),
+                entry if self.entries[entry - 1].0.borrow() == key => {
+                    return Ok(slot)
+                }
+                _ => slot = (slot + 1) & mask,
+            }
+        }
+    }
+
+    // the slot pointing at the entry at `index`
+    fn slot_of(&self, index: usize) -> usize {
+        let mask = self.table.len() - 1;
+        let mut slot = self.hash(&self.entries[index].0) & mask;
+        while self.table[slot] != index + 1 {
+            slot = (slot + 1) & mask;
+        }
+        slot
+    }
+
+    fn rebuild_table(&mut self, table_len: usize) {
+        let mut table =
+            NomVec::with_capacity_in(table_len, self.allocator().clone());
+        table.resize(table_len, 0);
+        self.table = table;
+        let mask = table_len.wrapping_sub(1);
+        for index in 0..self.len() {
+            let mut slot = self.hash(&self.entries[index].0) & mask;
+            while self.table[slot] != 0 {
+                slot = (slot + 1) & mask;
+            }
+            self.table[slot] = index + 1;
+        }
+    }
+
+    // the smallest table that holds `len` entries
+    fn table_len_for(len: usize) -> usize {
+        match len {
+            0 => 0,
+            len => (len * 2).next_power_of_two().max(16),
+        }
+    }
+
+    // Empties `slot`, shifting later entries of its probe run back so
+    // lookups never stop early.
+    fn erase_slot(&mut self, mut slot: usize) {
+        let mask = self.table.len() - 1;
+        let mut next = (slot + 1) & mask;
+        while self.table[next] != 0 {
+            let ideal = self.hash(&self.entries[self.table[next] - 1].0) & mask;
+            // move it unless its ideal slot lies in `(slot, next]`
+            if (next.wrapping_sub(ideal) & mask)
+                >= (next.wrapping_sub(slot) & mask)
+            {
+                self.table[slot] = self.table[next];
+                slot = next;
+            }
+            next = (next + 1) & mask;
+        }
+        self.table[slot] = 0;
+    }
+
+    // The position `key` now has, and its old value if it was already
+    // present; an existing entry keeps its position.
+    pub fn insert_full(&mut self, key: K, value: V) -> (usize, Option<V>) {
+        if (self.len() + 1) * 2 > self.table.len() {
+            self.rebuild_table(Self::table_len_for(self.len() + 1));
+        }
+        match self.find_slot(&key, self.hash(&key)) {
+            Ok(slot) => {
+                let index = self.table[slot] - 1;
+                let old = mem::replace(&mut self.entries[index].1, value);
+                (index, Some(old))
+            }
+            Err(slot) => {
+                self.entries.push((key, value));
+                self.table[slot] = self.len();
+                (self.len() - 1, None)
+            }
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.insert_full(key, value).1
+    }
+
+    pub fn get_index_of<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.table.is_empty() {
+            return None;
+        }
+        let slot = self.find_slot(key, self.hash(key)).ok()?;
+        Some(self.table[slot] - 1)
+    }
+
+    pub fn get_full<Q>(&self, key: &Q) -> Option<(usize, &K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.get_index_of(key)?;
+        let (k, v) = &self.entries[index];
+        Some((index, k, v))
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_full(key).map(|(_, _, v)| v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.get_index_of(key)?;
+        Some(&mut self.entries[index].1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_index_of(key).is_some()
+    }
+
+    // Removes the entry at `index`, moving the last entry into its place.
+    // O(1), but it changes the last entry's position.
+    pub fn swap_remove_index(&mut self, index: usize) -> Option<(K, V)> {
+        if index >= self.len() {
+            return None;
+        }
+        let slot = self.slot_of(index);
+        self.erase_slot(slot);
+        let last = self.len() - 1;
+        if index != last {
+            let slot = self.slot_of(last);
+            self.table[slot] = index + 1;
+        }
+        Some(self.entries.swap_remove(index))
+    }
+
+    pub fn swap_remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.get_index_of(key)?;
+        self.swap_remove_index(index)
+    }
+
+    pub fn swap_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.swap_remove_entry(key).map(|(_, v)| v)
+    }
+
+    // Removes the entry at `index`, keeping the order of the rest. O(n):
+    // every later entry moves down one position.
+    pub fn shift_remove_index(&mut self, index: usize) -> Option<(K, V)> {
+        if index >= self.len() {
+            return None;
+        }
+        let slot = self.slot_of(index);
+        self.erase_slot(slot);
+        for entry in self.table.iter_mut() {
+            if *entry > index + 1 {
+                *entry -= 1;
+            }
+        }
+        Some(self.entries.remove(index))
+    }
+
+    pub fn shift_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.get_index_of(key)?;
+        self.shift_remove_index(index).map(|(_, v)| v)
+    }
+
+    // removes the most recently inserted entry
+    pub fn pop(&mut self) -> Option<(K, V)> {
+        match self.len() {
+            0 => None,
+            len => self.swap_remove_index(len - 1),
+        }
+    }
+
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let len = self.len();
+        let guard = RebuildOnDrop(self);
+        guard.0.entries.retain_mut(|(k, v)| f(k, v));
+        if guard.0.len() == len {
+            // nothing moved, so the table is still right
+            mem::forget(guard);
+        }
+    }
+
+    // sorts the entries by key, changing their positions
+    pub fn sort_keys(&mut self)
+    where
+        K: Ord,
+    {
+        self.sort_by(|a, _, b, _| a.cmp(b))
+    }
+
+    pub fn sort_by<F>(&mut self, mut cmp: F)
+    where
+        F: FnMut(&K, &V, &K, &V) -> std::cmp::Ordering,
+    {
+        let guard = RebuildOnDrop(self);
+        guard.0.entries.sort_by(|a, b| cmp(&a.0, &a.1, &b.0, &b.1));
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.entries.shrink_to_fit();
+        self.rebuild_table(Self::table_len_for(self.len()));
+    }
+}
+
+// Rebuilds the table when dropped, so that it matches the entries again
+// even if the closure that rearranged them panicked partway.
+struct RebuildOnDrop<'a, K: Hash + Eq, V, A: Allocator + Clone>(
+    &'a mut OrderMap<K, V, A>,
+);
+
+impl<K: Hash + Eq, V, A: Allocator + Clone> Drop
+    for RebuildOnDrop<'_, K, V, A>
+{
+    fn drop(&mut self) {
+        let table_len = self.0.table.len();
+        self.0.rebuild_table(table_len);
+    }
+}
+
+impl<K, V> Default for OrderMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone, V: Clone, A: Allocator + Clone> Clone for OrderMap<K, V, A> {
+    fn clone(&self) -> Self {
+        OrderMap {
+            entries: self.entries.clone(),
+            table: self.table.clone(),
+            hasher: self.hasher.clone(),
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, A: Allocator + Clone> fmt::Debug
+    for OrderMap<K, V, A>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+// Maps are equal if they hold the same entries, in any order.
+impl<K, V, A, B> PartialEq<OrderMap<K, V, B>> for OrderMap<K, V, A>
+where
+    K: Hash + Eq,
+    V: PartialEq,
+    A: Allocator + Clone,
+    B: Allocator + Clone,
+{
+    fn eq(&self, other: &OrderMap<K, V, B>) -> bool {
+        self.len() == other.len()
+            && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<K: Hash + Eq, V: Eq, A: Allocator + Clone> Eq for OrderMap<K, V, A> {}
+
+impl<K, V, A, Q> Index<&Q> for OrderMap<K, V, A>
+where
+    K: Hash + Eq + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    A: Allocator + Clone,
+{
+    type Output = V;
+
+    // Panics if `key` isn't present.
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("key not found")
+    }
+}
+
+impl<K, V, A: Allocator + Clone> Index<usize> for OrderMap<K, V, A> {
+    type Output = V;
+
+    // Panics if `index` is out of bounds.
+    fn index(&self, index: usize) -> &V {
+        &self.entries[index].1
+    }
+}
+
+impl<K, V, A: Allocator + Clone> IndexMut<usize> for OrderMap<K, V, A> {
+    fn index_mut(&mut self, index: usize) -> &mut V {
+        &mut self.entries[index].1
+    }
+}
+
+impl<K: Hash + Eq, V, A: Allocator + Clone> Extend<(K, V)>
+    for OrderMap<K, V, A>
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> FromIterator<(K, V)> for OrderMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = OrderMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Hash + Eq, V, const N: usize> From<[(K, V); N]> for OrderMap<K, V> {
+    fn from(arr: [(K, V); N]) -> Self {
+        IntoIterator::into_iter(arr).collect()
+    }
+}
+
+impl<K, V, A: Allocator + Clone> IntoIterator for OrderMap<K, V, A> {
+    type Item = (K, V);
+    type IntoIter = crate::IntoIter<(K, V), A>;
+    fn into_iter(self) -> crate::IntoIter<(K, V), A> {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a, K, V, A: Allocator + Clone> IntoIterator for &'a OrderMap<K, V, A> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, A: Allocator + Clone> IntoIterator
+    for &'a mut OrderMap<K, V, A>
+{
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ordermap_keeps_insertion_order() {
+        let mut map = OrderMap::new();
+        assert_eq!(map.get("x"), None);
+        for word in ["pear", "apple", "fig", "apple", "kiwi"] {
+            let count = map.get(word).copied().unwrap_or(0);
+            map.insert(word, count + 1);
+        }
+        assert!(map.keys().eq(&["pear", "apple", "fig", "kiwi"]));
+        assert_eq!(map["apple"], 2);
+        assert_eq!(map.get_index(2), Some((&"fig", &1)));
+        assert_eq!(map.get_full("kiwi"), Some((3, &"kiwi", &1)));
+        assert_eq!(map.insert_full("fig", 9), (2, Some(1)));
+        map[0] = 7;
+        assert_eq!(map["pear"], 7);
+
+        // the last entry takes the removed one's place
+        assert_eq!(map.swap_remove("pear"), Some(7));
+        assert!(map.keys().eq(&["kiwi", "apple", "fig"]));
+        assert_eq!(map.get_index_of("kiwi"), Some(0));
+        assert_eq!(map.shift_remove("kiwi"), Some(1));
+        assert!(map.keys().eq(&["apple", "fig"]));
+        assert_eq!(map.get_index_of("fig"), Some(1));
+        assert_eq!(map.pop(), Some(("fig", 9)));
+        assert_eq!(map.swap_remove("fig"), None);
+        assert_eq!(format!("{:?}", map), r#"{"apple": 2}"#);
+    }
+
+    #[test]
+    fn ordermap_many_removals() {
+        let mut map: OrderMap<u32, u32> =
+            (0..1000).map(|i| (i, i * 10)).collect();
+        for i in (0..1000).step_by(3) {
+            assert_eq!(map.swap_remove(&i), Some(i * 10));
+        }
+        assert_eq!(map.len(), 666);
+        for i in 0..1000 {
+            assert_eq!(map.get(&i), (i % 3 != 0).then(|| i * 10).as_ref());
+        }
+        for (index, (k, _)) in map.iter().enumerate() {
+            assert_eq!(map.get_index_of(k), Some(index));
+        }
+
+        map.retain(|k, _| k % 2 == 0);
+        map.sort_keys();
+        assert!(map
+            .keys()
+            .copied()
+            .eq((0..1000).filter(|i| i % 6 == 2 || i % 6 == 4)));
+        assert_eq!(map.get(&4), Some(&40));
+
+        let other: OrderMap<u32, u32> = map.clone().into_iter().rev().collect();
+        assert_eq!(map, other);
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(map.get(&4), None);
+    }
+
+    #[test]
+    fn ordermap_index_survives_panicking_closures() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        let mut map: OrderMap<u32, u32> = (0..100).map(|i| (i, i)).collect();
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            map.retain(|&k, _| {
+                assert!(k < 50, "retain failed");
+                k % 2 == 0
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(map.get(&1), None);
+        assert_eq!(map.get(&50), Some(&50));
+
+        let mut map: OrderMap<u32, u32> =
+            (0..100).map(|i| (i * 37 % 100, i)).collect();
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let mut calls = 0;
+            map.sort_by(|a, _, b, _| {
+                calls += 1;
+                assert!(calls < 300, "sort failed");
+                a.cmp(b)
+            })
+        }));
+        assert!(result.is_err());
+        // every entry is found where it now is
+        for index in 0..map.len() {
+            let key = map.as_slice()[index].0;
+            assert_eq!(map.get_index_of(&key), Some(index));
+        }
+        map.insert(1000, 1000);
+        assert_eq!(map[&1000], 1000);
+    }
+}
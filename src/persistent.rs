@@ -0,0 +1,533 @@
+// A persistent vector: a 32-way trie of shared nodes plus a tail leaf, as
+// in Clojure's PersistentVector. Cloning copies two pointers; `push`, `set`
+// and `pop` copy only the path from the root to the leaf they touch and
+// share everything else with the old version, so keeping every version
+// around (undo stacks, snapshots handed to reader threads) is cheap. The
+// `_mut` variants update in place, skipping the copy for nodes no other
+// version can see.
+//
+// Elements live in the tail until it fills up, then the whole tail moves
+// into the trie as a leaf. Every level of the trie is packed to the left, so
+// element `i` is found by reading five bits of `i` per level.
+use crate::shared::Shared;
+use crate::{Allocator, Global, NomVec};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, FusedIterator};
+use std::mem;
+use std::ops::Index;
+
+const BITS: u32 = 5;
+const WIDTH: usize = 1 << BITS;
+const MASK: usize = WIDTH - 1;
+
+enum Node<T, A: Allocator + Clone> {
+    Branch(NomVec<Shared<Node<T, A>, A>, A>),
+    Leaf(NomVec<T, A>),
+}
+
+impl<T, A: Allocator + Clone> Node<T, A> {
+    fn children(&self) -> &NomVec<Shared<Node<T, A>, A>, A> {
+        match self {
+            Node::Branch(children) => children,
+            Node::Leaf(_) => unreachable!("leaf where a branch belongs"),
+        }
+    }
+
+    fn children_mut(&mut self) -> &mut NomVec<Shared<Node<T, A>, A>, A> {
+        match self {
+            Node::Branch(children) => children,
+            Node::Leaf(_) => unreachable!("leaf where a branch belongs"),
+        }
+    }
+
+    fn elems(&self) -> &NomVec<T, A> {
+        match self {
+            Node::Leaf(elems) => elems,
+            Node::Branch(_) => unreachable!("branch where a leaf belongs"),
+        }
+    }
+
+    fn elems_mut(&mut self) -> &mut NomVec<T, A> {
+        match self {
+            Node::Leaf(elems) => elems,
+            Node::Branch(_) => unreachable!("branch where a leaf belongs"),
+        }
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Clone for Node<T, A> {
+    fn clone(&self) -> Self {
+        match self {
+            Node::Branch(children) => Node::Branch(children.clone()),
+            Node::Leaf(elems) => Node::Leaf(elems.clone()),
+        }
+    }
+}
+
+pub struct PersistentNomVec<T, A: Allocator + Clone = Global> {
+    // None until the first leaf moves out of the tail
+    root: Option<Shared<Node<T, A>, A>>,
+    // None only while empty
+    tail: Option<Shared<Node<T, A>, A>>,
+    // bits of the index consumed by the root's level
+    shift: u32,
+    len: usize,
+    alloc: A,
+}
+
+impl<T> PersistentNomVec<T> {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<T, A: Allocator + Clone> PersistentNomVec<T, A> {
+    pub const fn new_in(alloc: A) -> Self {
+        PersistentNomVec {
+            root: None,
+            tail: None,
+            shift: BITS,
+            len: 0,
+            alloc,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    // Whether the two share their whole structure, which is always true of
+    // a clone that neither side has changed since.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        fn same<N, A: Allocator>(
+            a: &Option<Shared<N, A>>,
+            b: &Option<Shared<N, A>>,
+        ) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => a.ptr_eq(b),
+                (None, None) => true,
+                _ => false,
+            }
+        }
+        self.len == other.len
+            && same(&self.root, &other.root)
+            && same(&self.tail, &other.tail)
+    }
+
+    // index of the tail's first element
+    fn tail_offset(&self) -> usize {
+        if self.len == 0 {
+            0
+        } else {
+            (self.len - 1) & !MASK
+        }
+    }
+
+    fn new_node(&self, node: Node<T, A>) -> Shared<Node<T, A>, A> {
+        Shared::new_in(node, self.alloc.clone())
+    }
+
+    // The leaf holding element `index`, and the index of its first element.
+    fn leaf(&self, index: usize) -> (&[T], usize) {
+        let offset = self.tail_offset();
+        if index >= offset {
+            let tail = self.tail.as_ref().expect("empty vector has no leaves");
+            return (tail.elems(), offset);
+        }
+        let mut node = &**self.root.as_ref().unwrap();
+        let mut level = self.shift;
+        while level > 0 {
+            node = &node.children()[(index >> level) & MASK];
+            level -= BITS;
+        }
+        (node.elems(), index & !MASK)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+        let (leaf, start) = self.leaf(index);
+        Some(&leaf[index - start])
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.get(self.len.checked_sub(1)?)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, A> {
+        Iter {
+            vec: self,
+            front: 0,
+            back: self.len,
+            front_leaf: &[],
+            back_leaf: &[],
+        }
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> PersistentNomVec<T, A> {
+    // a new version with `value` appended
+    #[must_use]
+    pub fn push(&self, value: T) -> Self {
+        let mut next = self.clone();
+        next.push_mut(value);
+        next
+    }
+
+    // a new version with element `index` replaced. Panics if `index` is out
+    // of bounds.
+    #[must_use]
+    pub fn set(&self, index: usize, value: T) -> Self {
+        let mut next = self.clone();
+        next.set_mut(index, value);
+        next
+    }
+
+    // a new version without the last element, or None if this one is empty
+    #[must_use]
+    pub fn pop(&self) -> Option<Self> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut next = self.clone();
+        next.pop_mut();
+        Some(next)
+    }
+
+    pub fn push_mut(&mut self, value: T) {
+        let offset = self.tail_offset();
+        if self.len > 0 && self.len - offset < WIDTH {
+            let tail = self.tail.as_mut().unwrap();
+            Shared::make_mut(tail).elems_mut().push(value);
+        } else {
+            let mut elems = NomVec::with_capacity_in(WIDTH, self.alloc.clone());
+            elems.push(value);
+            let tail = self.new_node(Node::Leaf(elems));
+            if let Some(full) = self.tail.replace(tail) {
+                self.push_leaf(full, offset);
+            }
+        }
+        self.len += 1;
+    }
+
+    // Moves the full leaf starting at element `offset` into the trie.
+    fn push_leaf(&mut self, leaf: Shared<Node<T, A>, A>, offset: usize) {
+        let root = match self.root.take() {
+            None => {
+                let mut children = NomVec::new_in(self.alloc.clone());
+                children.push(leaf);
+                self.root = Some(self.new_node(Node::Branch(children)));
+                return;
+            }
+            Some(root) => root,
+        };
+        // a full trie grows a level, with the old root as its first child
+        let mut root = if offset == 1 << (self.shift + BITS) {
+            let mut children = NomVec::new_in(self.alloc.clone());
+            children.push(root);
+            self.shift += BITS;
+            self.new_node(Node::Branch(children))
+        } else {
+            root
+        };
+        let mut node = Shared::make_mut(&mut root);
+        let mut level = self.shift;
+        loop {
+            let sub = (offset >> level) & MASK;
+            let children = node.children_mut();
+            if level == BITS {
+                children.push(leaf);
+                break;
+            }
+            if sub == children.len() {
+                // a fresh path of single-child branches down to the leaf
+                let mut path = leaf;
+                for _ in 1..level / BITS {
+                    let mut children = NomVec::new_in(self.alloc.clone());
+                    children.push(path);
+                    path = self.new_node(Node::Branch(children));
+                }
+                node.children_mut().push(path);
+                break;
+            }
+            node = Shared::make_mut(&mut node.children_mut()[sub]);
+            level -= BITS;
+        }
+        self.root = Some(root);
+    }
+
+    // Replaces element `index`, returning the old one. Panics if `index` is
+    // out of bounds.
+    pub fn set_mut(&mut self, index: usize, value: T) -> T {
+        assert!(index < self.len, "index out of bounds");
+        let offset = self.tail_offset();
+        let leaf = if index >= offset {
+            Shared::make_mut(self.tail.as_mut().unwrap())
+        } else {
+            let mut node = Shared::make_mut(self.root.as_mut().unwrap());
+            let mut level = self.shift;
+            while level > 0 {
+                node = Shared::make_mut(
+                    &mut node.children_mut()[(index >> level) & MASK],
+                );
+                level -= BITS;
+            }
+            node
+        };
+        mem::replace(&mut leaf.elems_mut()[index & MASK], value)
+    }
+
+    pub fn pop_mut(&mut self) -> Option<T> {
+        let tail = self.tail.as_mut()?;
+        let value = Shared::make_mut(tail).elems_mut().pop().unwrap();
+        self.len -= 1;
+        if !tail.elems().is_empty() {
+            return Some(value);
+        }
+        // the tail ran dry, so the trie's last leaf takes its place
+        self.tail = None;
+        let mut root = match self.root.take() {
+            Some(root) => root,
+            None => return Some(value),
+        };
+        self.tail = Some(Self::pop_leaf(&mut root, self.shift));
+        if root.children().is_empty() {
+            self.shift = BITS;
+        } else if self.shift > BITS && root.children().len() == 1 {
+            self.shift -= BITS;
+            self.root =
+                Some(Shared::make_mut(&mut root).children_mut().pop().unwrap());
+        } else {
+            self.root = Some(root);
+        }
+        Some(value)
+    }
+
+    // Removes and returns the trie's last leaf, leaving behind no empty
+    // branches below `node`.
+    fn pop_leaf(
+        node: &mut Shared<Node<T, A>, A>,
+        level: u32,
+    ) -> Shared<Node<T, A>, A> {
+        let children = Shared::make_mut(node).children_mut();
+        if level == BITS {
+            return children.pop().unwrap();
+        }
+        let last = children.last_mut().unwrap();
+        let leaf = Self::pop_leaf(last, level - BITS);
+        if last.children().is_empty() {
+            children.pop();
+        }
+        leaf
+    }
+}
+
+impl<T> Default for PersistentNomVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// O(1): the new handle shares every node
+impl<T, A: Allocator + Clone> Clone for PersistentNomVec<T, A> {
+    fn clone(&self) -> Self {
+        PersistentNomVec {
+            root: self.root.clone(),
+            tail: self.tail.clone(),
+            shift: self.shift,
+            len: self.len,
+            alloc: self.alloc.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator + Clone> fmt::Debug
+    for PersistentNomVec<T, A>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq, A: Allocator + Clone> PartialEq for PersistentNomVec<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other)
+            || (self.len == other.len && self.iter().eq(other.iter()))
+    }
+}
+
+impl<T: Eq, A: Allocator + Clone> Eq for PersistentNomVec<T, A> {}
+
+impl<T: Hash, A: Allocator + Clone> Hash for PersistentNomVec<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        for elem in self {
+            elem.hash(state);
+        }
+    }
+}
+
+impl<T, A: Allocator + Clone> Index<usize> for PersistentNomVec<T, A> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("index out of bounds")
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Extend<T> for PersistentNomVec<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push_mut(value);
+        }
+    }
+}
+
+impl<T: Clone> FromIterator<T> for PersistentNomVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut pv = PersistentNomVec::new();
+        pv.extend(iter);
+        pv
+    }
+}
+
+// Walks a leaf at a time from either end.
+pub struct Iter<'a, T, A: Allocator + Clone = Global> {
+    vec: &'a PersistentNomVec<T, A>,
+    front: usize,
+    back: usize,
+    // the rest of the leaves `front` and `back` are in
+    front_leaf: &'a [T],
+    back_leaf: &'a [T],
+}
+
+impl<'a, T, A: Allocator + Clone> Iterator for Iter<'a, T, A> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.front == self.back {
+            return None;
+        }
+        if self.front_leaf.is_empty() {
+            let (leaf, start) = self.vec.leaf(self.front);
+            self.front_leaf = &leaf[self.front - start..];
+        }
+        let (elem, rest) = self.front_leaf.split_first().unwrap();
+        self.front_leaf = rest;
+        self.front += 1;
+        Some(elem)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl<'a, T, A: Allocator + Clone> DoubleEndedIterator for Iter<'a, T, A> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.front == self.back {
+            return None;
+        }
+        if self.back_leaf.is_empty() {
+            let (leaf, start) = self.vec.leaf(self.back - 1);
+            self.back_leaf = &leaf[..self.back - start];
+        }
+        let (elem, rest) = self.back_leaf.split_last().unwrap();
+        self.back_leaf = rest;
+        self.back -= 1;
+        Some(elem)
+    }
+}
+
+impl<'a, T, A: Allocator + Clone> ExactSizeIterator for Iter<'a, T, A> {}
+impl<'a, T, A: Allocator + Clone> FusedIterator for Iter<'a, T, A> {}
+
+impl<'a, T, A: Allocator + Clone> Clone for Iter<'a, T, A> {
+    fn clone(&self) -> Self {
+        Iter { ..*self }
+    }
+}
+
+impl<'a, T, A: Allocator + Clone> IntoIterator for &'a PersistentNomVec<T, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, A>;
+    fn into_iter(self) -> Iter<'a, T, A> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn persistent_versions_share_structure() {
+        // enough for a three-level trie
+        let n = WIDTH * WIDTH * 2 + 40;
+        let mut versions = vec![PersistentNomVec::new()];
+        for i in 0..n {
+            let next = versions.last().unwrap().push(i);
+            versions.push(next);
+        }
+        for (len, v) in versions.iter().enumerate().step_by(97) {
+            assert_eq!(v.len(), len);
+            assert!(v.iter().copied().eq(0..len));
+            assert!(v.iter().rev().copied().eq((0..len).rev()));
+        }
+
+        let full = versions.last().unwrap();
+        let edited = full.set(1000, 0).set(n - 1, 0);
+        assert_eq!(full[1000], 1000);
+        assert_eq!(edited[1000], 0);
+        assert_eq!(edited.last(), Some(&0));
+        assert!(full.clone().ptr_eq(full));
+        assert!(!edited.ptr_eq(full));
+
+        let mut popped = full.clone();
+        for len in (0..n).rev() {
+            assert_eq!(popped.pop_mut(), Some(len));
+            assert_eq!(popped.len(), len);
+            if len % 500 == 0 {
+                assert_eq!(popped, versions[len]);
+            }
+        }
+        assert_eq!(popped.pop(), None);
+        assert!(full.iter().copied().eq(0..n));
+    }
+
+    #[test]
+    fn persistent_snapshots_across_threads() {
+        let mut live: PersistentNomVec<String> =
+            (0..100).map(|i| i.to_string()).collect();
+        let snapshot = live.clone();
+        let reader = thread::spawn(move || {
+            snapshot.iter().map(|s| s.len()).sum::<usize>()
+        });
+        for i in 0..100 {
+            live.set_mut(i, String::new());
+        }
+        live.push_mut("x".to_string());
+        assert_eq!(reader.join().unwrap(), 190);
+        assert_eq!(live.len(), 101);
+        assert_eq!(
+            format!("{:?}", live.iter().rev().take(2).collect::<Vec<_>>()),
+            r#"["x", ""]"#
+        );
+    }
+}
@@ -0,0 +1,188 @@
+// A vector whose elements are pinned: `push` hands back a `Pin<&mut T>`,
+// and an element never moves again until it's dropped, in place. It's a
+// SegmentedNomVec underneath, whose segments are never reallocated; nothing
+// here moves an element out unless `T: Unpin`, so self-referential futures
+// and intrusive list nodes can live inside it.
+use crate::segmented::{self, SegmentedNomVec};
+use crate::{Allocator, Global};
+use std::fmt;
+use std::pin::Pin;
+
+pub struct PinnedNomVec<T, const N: usize = 32, A: Allocator + Clone = Global> {
+    inner: SegmentedNomVec<T, N, A>,
+}
+
+impl<T, const N: usize> PinnedNomVec<T, N> {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<T, const N: usize, A: Allocator + Clone> PinnedNomVec<T, N, A> {
+    // Panics if `N` is 0.
+    pub const fn new_in(alloc: A) -> Self {
+        PinnedNomVec {
+            inner: SegmentedNomVec::new_in(alloc),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.inner.allocator()
+    }
+
+    pub fn push(&mut self, elem: T) -> Pin<&mut T> {
+        // the element's segment is never reallocated, and it's only ever
+        // dropped in place
+        unsafe { Pin::new_unchecked(self.inner.push(elem)) }
+    }
+
+    pub fn get(&self, index: usize) -> Option<Pin<&T>> {
+        let elem = self.inner.get(index)?;
+        unsafe { Some(Pin::new_unchecked(elem)) }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<Pin<&mut T>> {
+        let elem = self.inner.get_mut(index)?;
+        unsafe { Some(Pin::new_unchecked(elem)) }
+    }
+
+    // Moving an element out is only allowed when it doesn't care.
+    pub fn pop(&mut self) -> Option<T>
+    where
+        T: Unpin,
+    {
+        self.inner.pop()
+    }
+
+    // drops the elements past `len`, in place
+    pub fn truncate(&mut self, len: usize) {
+        self.inner.truncate(len)
+    }
+
+    pub fn clear(&mut self) {
+        self.inner.clear()
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.inner.shrink_to_fit()
+    }
+
+    pub fn iter(&self) -> segmented::Iter<'_, T, A> {
+        self.inner.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = Pin<&mut T>> + '_ {
+        self.inner
+            .iter_mut()
+            .map(|elem| unsafe { Pin::new_unchecked(elem) })
+    }
+}
+
+impl<T, const N: usize> Default for PinnedNomVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug, const N: usize, A: Allocator + Clone> fmt::Debug
+    for PinnedNomVec<T, N, A>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<'a, T, const N: usize, A: Allocator + Clone> IntoIterator
+    for &'a PinnedNomVec<T, N, A>
+{
+    type Item = &'a T;
+    type IntoIter = segmented::Iter<'a, T, A>;
+    fn into_iter(self) -> segmented::Iter<'a, T, A> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::marker::PhantomPinned;
+    use std::ptr;
+
+    // a node that points at itself once it's pinned
+    struct Node<'a> {
+        this: *const Node<'a>,
+        drops: &'a Cell<usize>,
+        _pin: PhantomPinned,
+    }
+
+    impl<'a> Node<'a> {
+        fn new(drops: &'a Cell<usize>) -> Self {
+            Node {
+                this: ptr::null(),
+                drops,
+                _pin: PhantomPinned,
+            }
+        }
+
+        fn init(self: Pin<&mut Self>) {
+            let this = unsafe { self.get_unchecked_mut() };
+            this.this = this;
+        }
+    }
+
+    impl<'a> Drop for Node<'a> {
+        fn drop(&mut self) {
+            // still where it was pinned
+            assert!(self.this.is_null() || ptr::eq(self.this, self));
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    #[test]
+    fn pinned_elements_never_move() {
+        let drops = Cell::new(0);
+        let mut pv: PinnedNomVec<Node<'_>, 4> = PinnedNomVec::new();
+        for _ in 0..50 {
+            pv.push(Node::new(&drops)).init();
+        }
+        for i in 0..50 {
+            let node = pv.get(i).unwrap();
+            assert!(ptr::eq(node.this, &*node));
+        }
+        pv.truncate(10);
+        assert_eq!(drops.get(), 40);
+        for node in pv.iter_mut() {
+            assert!(!node.this.is_null());
+        }
+        assert_eq!(pv.len(), 10);
+        drop(pv);
+        assert_eq!(drops.get(), 50);
+    }
+
+    #[test]
+    fn pinned_unpin_values() {
+        let mut pv = PinnedNomVec::<String, 2>::new();
+        pv.push("a".to_string()).push('!');
+        pv.push("b".to_string());
+        pv.push("c".to_string());
+        *pv.get_mut(1).unwrap() = "B".to_string();
+        assert_eq!(pv.pop().as_deref(), Some("c"));
+        assert!(pv.iter().eq(&["a!", "B"]));
+        pv.clear();
+        assert!(pv.is_empty());
+        assert_eq!(pv.capacity(), 4);
+    }
+}
@@ -0,0 +1,300 @@
+// An allocator that keeps freed blocks for reuse instead of handing them
+// straight back. Blocks are rounded up to power-of-two size classes from
+// 16 bytes to 1 MiB, and each class keeps a free list of up to
+// `max_cached` blocks, so a server that allocates a fresh buffer per
+// request settles into reusing the same few blocks rather than going back
+// to malloc every time. Rounding up also lets a NomVec grow in place while
+// it stays within its block's class.
+//
+// Requests bigger than the largest class, or aligned to more than 16,
+// bypass the pool. The free lists are threaded through the cached blocks
+// themselves, each behind its own lock, so one pool can serve every
+// thread.
+use crate::{AllocError, Allocator, Global};
+use std::alloc::Layout;
+use std::array;
+use std::fmt;
+use std::ptr::{self, NonNull};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+// log2 of the smallest and largest size classes
+const MIN_BITS: u32 = 4;
+const MAX_BITS: u32 = 20;
+const CLASSES: usize = (MAX_BITS - MIN_BITS + 1) as usize;
+// every pooled block's alignment; a block this small can hold a pointer
+const ALIGN: usize = 1 << MIN_BITS;
+
+// The class `layout`'s blocks come from, if it's pooled at all.
+fn class_of(layout: Layout) -> Option<usize> {
+    if layout.align() > ALIGN || layout.size() > 1 << MAX_BITS {
+        return None;
+    }
+    let bits = layout.size().next_power_of_two().trailing_zeros();
+    Some((bits.max(MIN_BITS) - MIN_BITS) as usize)
+}
+
+fn class_layout(class: usize) -> Layout {
+    unsafe {
+        Layout::from_size_align_unchecked(1 << (class as u32 + MIN_BITS), ALIGN)
+    }
+}
+
+fn dangling(layout: Layout) -> NonNull<[u8]> {
+    let ptr = unsafe { NonNull::new_unchecked(layout.align() as *mut u8) };
+    NonNull::slice_from_raw_parts(ptr, 0)
+}
+
+struct FreeList {
+    // each cached block starts with a pointer to the next
+    head: Option<NonNull<u8>>,
+    len: usize,
+}
+
+pub struct PoolAllocator<A: Allocator = Global> {
+    classes: [Mutex<FreeList>; CLASSES],
+    max_cached: usize,
+    alloc: A,
+}
+
+// The cached blocks are owned, and only touched under their class's lock.
+unsafe impl<A: Allocator + Send> Send for PoolAllocator<A> {}
+unsafe impl<A: Allocator + Sync> Sync for PoolAllocator<A> {}
+
+impl PoolAllocator {
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_max_cached(max_cached: usize) -> Self {
+        Self::with_max_cached_in(max_cached, Global)
+    }
+}
+
+impl<A: Allocator> PoolAllocator<A> {
+    // caches up to 32 blocks of each size class
+    pub fn new_in(alloc: A) -> Self {
+        Self::with_max_cached_in(32, alloc)
+    }
+
+    pub fn with_max_cached_in(max_cached: usize, alloc: A) -> Self {
+        PoolAllocator {
+            classes: array::from_fn(|_| {
+                Mutex::new(FreeList { head: None, len: 0 })
+            }),
+            max_cached,
+            alloc,
+        }
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    // the freed blocks held for reuse, in all classes
+    pub fn cached_blocks(&self) -> usize {
+        (0..CLASSES).map(|class| self.list(class).len).sum()
+    }
+
+    pub fn cached_bytes(&self) -> usize {
+        (0..CLASSES)
+            .map(|class| self.list(class).len * class_layout(class).size())
+            .sum()
+    }
+
+    // Hands every cached block back to `A`.
+    pub fn trim(&self) {
+        for class in 0..CLASSES {
+            let mut next = {
+                let mut list = self.list(class);
+                list.len = 0;
+                list.head.take()
+            };
+            while let Some(block) = next {
+                unsafe {
+                    next = block.cast::<Option<NonNull<u8>>>().as_ptr().read();
+                    self.alloc.deallocate(block, class_layout(class));
+                }
+            }
+        }
+    }
+
+    fn list(&self, class: usize) -> MutexGuard<'_, FreeList> {
+        // nothing under the lock can panic halfway
+        self.classes[class]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn take_cached(&self, class: usize) -> Option<NonNull<u8>> {
+        let mut list = self.list(class);
+        let block = list.head?;
+        list.head =
+            unsafe { block.cast::<Option<NonNull<u8>>>().as_ptr().read() };
+        list.len -= 1;
+        Some(block)
+    }
+
+    // Moves a block to a fresh allocation, copying `len` bytes.
+    unsafe fn relocate(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        len: usize,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let new = self.allocate(new_layout)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new.as_ptr() as *mut u8, len);
+        self.deallocate(ptr, old_layout);
+        Ok(new)
+    }
+}
+
+impl Default for PoolAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Allocator> Drop for PoolAllocator<A> {
+    fn drop(&mut self) {
+        self.trim()
+    }
+}
+
+impl<A: Allocator> fmt::Debug for PoolAllocator<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoolAllocator")
+            .field("cached_blocks", &self.cached_blocks())
+            .field("max_cached", &self.max_cached)
+            .finish()
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for PoolAllocator<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            return Ok(dangling(layout));
+        }
+        let class = match class_of(layout) {
+            Some(class) => class,
+            None => return self.alloc.allocate(layout),
+        };
+        let size = class_layout(class).size();
+        match self.take_cached(class) {
+            Some(block) => Ok(NonNull::slice_from_raw_parts(block, size)),
+            None => self.alloc.allocate(class_layout(class)),
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
+        let class = match class_of(layout) {
+            Some(class) => class,
+            None => return self.alloc.deallocate(ptr, layout),
+        };
+        let mut list = self.list(class);
+        if list.len < self.max_cached {
+            ptr.cast::<Option<NonNull<u8>>>().as_ptr().write(list.head);
+            list.head = Some(ptr);
+            list.len += 1;
+        } else {
+            drop(list);
+            self.alloc.deallocate(ptr, class_layout(class));
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if old_layout.size() == 0 {
+            return self.allocate(new_layout);
+        }
+        match (class_of(old_layout), class_of(new_layout)) {
+            // still fits the block it's in
+            (Some(old), Some(new)) if old == new => {
+                let size = class_layout(new).size();
+                Ok(NonNull::slice_from_raw_parts(ptr, size))
+            }
+            (None, None) => self.alloc.grow(ptr, old_layout, new_layout),
+            _ => self.relocate(ptr, old_layout, new_layout, old_layout.size()),
+        }
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if new_layout.size() == 0 {
+            self.deallocate(ptr, old_layout);
+            return Ok(dangling(new_layout));
+        }
+        match (class_of(old_layout), class_of(new_layout)) {
+            (Some(old), Some(new)) if old == new => {
+                let size = class_layout(new).size();
+                Ok(NonNull::slice_from_raw_parts(ptr, size))
+            }
+            (None, None) => self.alloc.shrink(ptr, old_layout, new_layout),
+            _ => self.relocate(ptr, old_layout, new_layout, new_layout.size()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NomVec;
+
+    #[test]
+    fn pool_reuses_freed_blocks() {
+        let pool = PoolAllocator::new();
+        let first: NomVec<u8, _> = NomVec::with_capacity_in(100, &pool);
+        let ptr = first.as_ptr();
+        drop(first);
+        assert_eq!(pool.cached_blocks(), 1);
+        assert_eq!(pool.cached_bytes(), 128);
+        // any size in the same class gets the block back
+        let mut second: NomVec<u8, _> = NomVec::with_capacity_in(80, &pool);
+        assert_eq!(second.as_ptr(), ptr);
+        assert_eq!(pool.cached_blocks(), 0);
+        // and grows in place up to the class size
+        second.reserve_exact(128);
+        assert_eq!(second.as_ptr(), ptr);
+        second.reserve_exact(129);
+        assert_ne!(second.as_ptr(), ptr);
+        drop(second);
+        assert_eq!(pool.cached_bytes(), 128 + 256);
+        pool.trim();
+        assert_eq!(pool.cached_blocks(), 0);
+
+        // oversized and over-aligned requests bypass the pool
+        drop(NomVec::<u8, _>::with_capacity_in(2 << 20, &pool));
+        assert_eq!(class_of(Layout::from_size_align(8, 32).unwrap()), None);
+        assert_eq!(pool.cached_blocks(), 0);
+    }
+
+    #[test]
+    fn pool_shared_between_threads() {
+        let pool = PoolAllocator::with_max_cached(4);
+        std::thread::scope(|s| {
+            for t in 0..4u32 {
+                let pool = &pool;
+                s.spawn(move || {
+                    for i in 0..1000 {
+                        let mut buf = NomVec::new_in(pool);
+                        buf.extend(0..i % 64 + t);
+                        assert_eq!(buf.len() as u32, i % 64 + t);
+                    }
+                });
+            }
+        });
+        assert!(pool.cached_blocks() <= 4 * CLASSES);
+        assert!(format!("{:?}", pool).contains("max_cached: 4"));
+    }
+}
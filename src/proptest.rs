@@ -0,0 +1,68 @@
+// proptest strategies for NomVec. Generation and shrinking are delegated to
+// proptest's own vec strategy; each value is adopted without copying.
+use crate::NomVec;
+use ::proptest::arbitrary::{any_with, Arbitrary};
+use ::proptest::collection::{self, SizeRange, VecStrategy};
+use ::proptest::strategy::{Map, Strategy};
+
+pub type NomVecStrategy<S> = Map<
+    VecStrategy<S>,
+    fn(Vec<<S as Strategy>::Value>) -> NomVec<<S as Strategy>::Value>,
+>;
+
+// Vectors whose length lies in `size` and whose elements come from
+// `element`. Shrinks by removing elements and then shrinking each one.
+pub fn nomvec<S: Strategy>(
+    element: S,
+    size: impl Into<SizeRange>,
+) -> NomVecStrategy<S> {
+    collection::vec(element, size).prop_map(NomVec::from)
+}
+
+impl<T: Arbitrary> Arbitrary for NomVec<T> {
+    type Parameters = (SizeRange, T::Parameters);
+    type Strategy = NomVecStrategy<T::Strategy>;
+
+    fn arbitrary_with((size, args): Self::Parameters) -> Self::Strategy {
+        nomvec(any_with::<T>(args), size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::proptest::prelude::*;
+    use ::proptest::test_runner::{TestCaseError, TestRunner};
+
+    proptest! {
+        #[test]
+        fn proptest_respects_size(vec in nomvec(0u8..10, 2..5)) {
+            prop_assert!(vec.len() >= 2 && vec.len() < 5);
+            prop_assert!(vec.iter().all(|x| *x < 10));
+        }
+
+        #[test]
+        fn proptest_any(vec in any::<NomVec<u16>>()) {
+            let std_vec: Vec<u16> = vec.clone().into();
+            prop_assert_eq!(vec, std_vec);
+        }
+    }
+
+    #[test]
+    fn proptest_shrinks() {
+        let mut runner = TestRunner::default();
+        let result = runner.run(&nomvec(0u32..1000, 0..50), |vec| {
+            if vec.iter().any(|x| *x >= 500) {
+                Err(TestCaseError::fail("too big"))
+            } else {
+                Ok(())
+            }
+        });
+        match result {
+            Err(::proptest::test_runner::TestError::Fail(_, minimal)) => {
+                assert_eq!(minimal, [500]);
+            }
+            other => panic!("expected a failure, got {:?}", other),
+        }
+    }
+}
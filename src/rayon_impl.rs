@@ -0,0 +1,291 @@
+use crate::{Allocator, NomVec};
+use rayon::iter::{
+    FromParallelIterator, IntoParallelIterator, ParallelExtend,
+    ParallelIterator,
+};
+use rayon::slice::{Iter, IterMut};
+use std::cmp::Ordering;
+use std::mem::{self, MaybeUninit};
+use std::ptr;
+
+// rayon's owning vec iterator needs a std Vec, which shares our global
+// allocator, so the buffer is handed over without copying.
+impl<T: Send> IntoParallelIterator for NomVec<T> {
+    type Item = T;
+    type Iter = rayon::vec::IntoIter<T>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        Vec::from(self).into_par_iter()
+    }
+}
+
+impl<'a, T: Sync, A: Allocator> IntoParallelIterator for &'a NomVec<T, A> {
+    type Item = &'a T;
+    type Iter = Iter<'a, T>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        self[..].into_par_iter()
+    }
+}
+
+impl<'a, T: Send, A: Allocator> IntoParallelIterator for &'a mut NomVec<T, A> {
+    type Item = &'a mut T;
+    type Iter = IterMut<'a, T>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        (&mut self[..]).into_par_iter()
+    }
+}
+
+impl<T: Send> FromParallelIterator<T> for NomVec<T> {
+    fn from_par_iter<I: IntoParallelIterator<Item = T>>(par_iter: I) -> Self {
+        Vec::from_par_iter(par_iter).into()
+    }
+}
+
+impl<T: Send, A: Allocator> ParallelExtend<T> for NomVec<T, A> {
+    fn par_extend<I: IntoParallelIterator<Item = T>>(&mut self, par_iter: I) {
+        // rayon stitches the pieces together in order; we then move them
+        // over with a single reservation.
+        let collected: Vec<T> = par_iter.into_par_iter().collect();
+        self.extend(collected);
+    }
+}
+
+impl<'a, T: Copy + Send + Sync + 'a, A: Allocator> ParallelExtend<&'a T>
+    for NomVec<T, A>
+{
+    fn par_extend<I: IntoParallelIterator<Item = &'a T>>(
+        &mut self,
+        par_iter: I,
+    ) {
+        self.par_extend(par_iter.into_par_iter().copied())
+    }
+}
+
+// runs below this length are insertion sorted
+const INSERTION_LEN: usize = 20;
+// runs below this length are sorted on the current thread
+const PARALLEL_LEN: usize = 4096;
+
+impl<T: Send, A: Allocator> NomVec<T, A> {
+    // Stable parallel merge sort. Unlike rayon's slice sorts, the merge
+    // scratch space is allocated from this vector's own allocator.
+    pub fn par_sort(&mut self)
+    where
+        T: Ord,
+    {
+        self.par_merge_sort(&|a: &T, b: &T| a.lt(b))
+    }
+
+    pub fn par_sort_by<F>(&mut self, compare: F)
+    where
+        F: Fn(&T, &T) -> Ordering + Sync,
+    {
+        self.par_merge_sort(&|a: &T, b: &T| compare(a, b) == Ordering::Less)
+    }
+
+    pub fn par_sort_by_key<K, F>(&mut self, f: F)
+    where
+        K: Ord,
+        F: Fn(&T) -> K + Sync,
+    {
+        self.par_merge_sort(&|a: &T, b: &T| f(a).lt(&f(b)))
+    }
+
+    fn par_merge_sort<F>(&mut self, is_less: &F)
+    where
+        F: Fn(&T, &T) -> bool + Sync,
+    {
+        let len = self.len();
+        // ZSTs are all equal; short runs don't need any scratch space
+        if mem::size_of::<T>() == 0 || len < 2 {
+            return;
+        }
+        if len <= INSERTION_LEN {
+            insertion_sort(self, is_less);
+            return;
+        }
+        // Taken through `&mut self` before the allocator is borrowed, so the
+        // elements can be sorted in place while the scratch space borrows it.
+        let ptr = self.as_mut_ptr();
+        // the scratch vec never has a len, so dropping it only frees memory
+        let mut scratch: NomVec<T, &A> =
+            NomVec::with_capacity_in(len, self.allocator());
+        let buf = &mut scratch.spare_capacity_mut()[..len];
+        let v = unsafe { std::slice::from_raw_parts_mut(ptr, len) };
+        merge_sort(v, buf, is_less);
+    }
+}
+
+fn insertion_sort<T, F: Fn(&T, &T) -> bool>(v: &mut [T], is_less: &F) {
+    for i in 1..v.len() {
+        let mut j = i;
+        while j > 0 && is_less(&v[j], &v[j - 1]) {
+            v.swap(j, j - 1);
+            j -= 1;
+        }
+    }
+}
+
+fn merge_sort<T, F>(v: &mut [T], buf: &mut [MaybeUninit<T>], is_less: &F)
+where
+    T: Send,
+    F: Fn(&T, &T) -> bool + Sync,
+{
+    let len = v.len();
+    if len <= INSERTION_LEN {
+        insertion_sort(v, is_less);
+        return;
+    }
+    let mid = len / 2;
+    {
+        let (left, right) = v.split_at_mut(mid);
+        let (left_buf, right_buf) = buf.split_at_mut(mid);
+        if len >= PARALLEL_LEN {
+            rayon::join(
+                || merge_sort(left, left_buf, is_less),
+                || merge_sort(right, right_buf, is_less),
+            );
+        } else {
+            merge_sort(left, left_buf, is_less);
+            merge_sort(right, right_buf, is_less);
+        }
+    }
+    unsafe { merge(v, mid, buf.as_mut_ptr() as *mut T, is_less) }
+}
+
+// Merges the sorted runs `v[..mid]` and `v[mid..]`. The left run is moved
+// into `buf` first; if `is_less` panics, whatever is left of it is copied
+// back into the gap, which is always exactly the right size.
+unsafe fn merge<T, F: Fn(&T, &T) -> bool>(
+    v: &mut [T],
+    mid: usize,
+    buf: *mut T,
+    is_less: &F,
+) {
+    struct MergeHole<T> {
+        start: *mut T,
+        end: *mut T,
+        dest: *mut T,
+    }
+
+    impl<T> Drop for MergeHole<T> {
+        fn drop(&mut self) {
+            unsafe {
+                let len = self.end.offset_from(self.start) as usize;
+                ptr::copy_nonoverlapping(self.start, self.dest, len);
+            }
+        }
+    }
+
+    let len = v.len();
+    let v = v.as_mut_ptr();
+    ptr::copy_nonoverlapping(v, buf, mid);
+    let mut hole = MergeHole {
+        start: buf,
+        end: buf.add(mid),
+        dest: v,
+    };
+    let mut right = v.add(mid);
+    let v_end = v.add(len);
+    while hole.start < hole.end && right < v_end {
+        // only take from the right run when strictly smaller, which keeps
+        // the sort stable
+        let src = if is_less(&*right, &*hole.start) {
+            let src = right;
+            right = right.add(1);
+            src
+        } else {
+            let src = hole.start;
+            hole.start = hole.start.add(1);
+            src
+        };
+        ptr::copy_nonoverlapping(src, hole.dest, 1);
+        hole.dest = hole.dest.add(1);
+    }
+    // dropping the hole moves any remaining left run into place
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+
+    #[test]
+    fn rayon_par_iter() {
+        let mut cv: NomVec<u64> = (0..1000).collect();
+        assert_eq!(cv.par_iter().sum::<u64>(), 499_500);
+
+        cv.par_iter_mut().for_each(|x| *x *= 2);
+        assert_eq!(cv[999], 1998);
+
+        let strings: Vec<String> =
+            cv.into_par_iter().map(|x| x.to_string()).collect();
+        assert_eq!(strings[1], "2");
+    }
+
+    #[test]
+    fn rayon_collect_and_extend() {
+        let mut cv: NomVec<u32> =
+            (0..100u32).into_par_iter().map(|x| x * x).collect();
+        assert_eq!(cv.len(), 100);
+        assert_eq!(cv[10], 100);
+
+        cv.par_extend((0..10u32).into_par_iter());
+        cv.par_extend(&[7, 8][..]);
+        assert_eq!(cv.len(), 112);
+        assert_eq!(&cv[108..], &[8, 9, 7, 8]);
+    }
+
+    #[test]
+    fn rayon_par_sort() {
+        // a simple LCG keeps the input deterministic
+        let mut seed = 12345u64;
+        let mut next = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) as u32 % 1000
+        };
+        let mut cv: NomVec<u32> = (0..20_000).map(|_| next()).collect();
+        let mut expected: Vec<u32> = cv.clone().into();
+        expected.sort();
+        cv.par_sort();
+        assert_eq!(cv, expected);
+
+        // stability: equal keys keep their original order
+        let mut pairs: NomVec<(u32, usize)> =
+            (0..10_000).map(|i| (next() % 10, i)).collect();
+        pairs.par_sort_by_key(|p| p.0);
+        assert!(pairs
+            .windows(2)
+            .all(|w| w[0].0 < w[1].0 || (w[0].0 == w[1].0 && w[0].1 < w[1].1)));
+
+        let mut small = NomVec::from([3, 1, 2]);
+        small.par_sort_by(|a, b| b.cmp(a));
+        assert_eq!(small, [3, 2, 1]);
+    }
+
+    #[test]
+    fn rayon_par_sort_panic_safety() {
+        use std::panic::{self, AssertUnwindSafe};
+        use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+        let mut cv: NomVec<String> =
+            (0..500).rev().map(|i| i.to_string()).collect();
+        let calls = AtomicUsize::new(0);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            cv.par_sort_by(|a, b| {
+                if calls.fetch_add(1, AtomicOrdering::Relaxed) == 2000 {
+                    panic!("comparator panicked");
+                }
+                a.cmp(b)
+            })
+        }));
+        assert!(result.is_err());
+        // every element is still there exactly once
+        let mut seen: Vec<usize> =
+            cv.iter().map(|s| s.parse().unwrap()).collect();
+        seen.sort();
+        assert_eq!(seen, (0..500).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,239 @@
+// A bounded circular buffer for "keep the last N" workloads (audio frames,
+// telemetry samples): once it's full, every push overwrites the oldest
+// element instead of growing. It's a `NomVecDeque` that allocates its whole
+// capacity upfront and never reallocates after that.
+use crate::{Allocator, Global, NomVecDeque};
+use std::cmp;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::deque::{IntoIter, Iter, IterMut};
+
+pub struct RingBuffer<T, A: Allocator = Global> {
+    deque: NomVecDeque<T, A>,
+    capacity: usize,
+}
+
+impl<T> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self::new_in(capacity, Global)
+    }
+}
+
+impl<T, A: Allocator> RingBuffer<T, A> {
+    pub fn new_in(capacity: usize, alloc: A) -> Self {
+        RingBuffer {
+            deque: NomVecDeque::with_capacity_in(capacity, alloc),
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.deque.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deque.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.deque.len() == self.capacity
+    }
+
+    // the bound, which the buffer never grows past
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.deque.allocator()
+    }
+
+    // Appends `elem`, handing back the oldest element if it had to make
+    // room. With a capacity of 0 that's `elem` itself.
+    pub fn push(&mut self, elem: T) -> Option<T> {
+        if self.capacity == 0 {
+            return Some(elem);
+        }
+        let evicted = if self.is_full() {
+            self.deque.pop_front()
+        } else {
+            None
+        };
+        self.deque.push_back(elem);
+        evicted
+    }
+
+    // removes the oldest element
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.deque.pop_front()
+    }
+
+    // removes the newest element
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.deque.pop_back()
+    }
+
+    pub fn oldest(&self) -> Option<&T> {
+        self.deque.front()
+    }
+
+    pub fn newest(&self) -> Option<&T> {
+        self.deque.back()
+    }
+
+    // `index` counts from the oldest element
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.deque.get(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.deque.get_mut(index)
+    }
+
+    // The newest `n` elements (or all of them, if there are fewer), oldest
+    // first.
+    pub fn latest(&self, n: usize) -> Iter<'_, T> {
+        let len = self.deque.len();
+        self.deque.range(len - cmp::min(n, len)..)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.deque.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        self.deque.iter_mut()
+    }
+
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        self.deque.as_slices()
+    }
+
+    pub fn make_contiguous(&mut self) -> &mut [T] {
+        self.deque.make_contiguous()
+    }
+
+    pub fn clear(&mut self) {
+        self.deque.clear()
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Clone for RingBuffer<T, A> {
+    fn clone(&self) -> Self {
+        let mut ring =
+            RingBuffer::new_in(self.capacity, self.allocator().clone());
+        ring.extend(self.iter().cloned());
+        ring
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator> fmt::Debug for RingBuffer<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.deque, f)
+    }
+}
+
+// Equal when they hold the same elements in the same order, whatever
+// their capacities.
+impl<T: PartialEq<U>, U, A: Allocator, B: Allocator> PartialEq<RingBuffer<U, B>>
+    for RingBuffer<T, A>
+{
+    fn eq(&self, other: &RingBuffer<U, B>) -> bool {
+        self.deque == other.deque
+    }
+}
+
+impl<T: PartialEq<U>, U, A: Allocator> PartialEq<[U]> for RingBuffer<T, A> {
+    fn eq(&self, other: &[U]) -> bool {
+        self.deque == *other
+    }
+}
+
+impl<T: PartialEq<U>, U, A: Allocator, const N: usize> PartialEq<[U; N]>
+    for RingBuffer<T, A>
+{
+    fn eq(&self, other: &[U; N]) -> bool {
+        self.deque == *other
+    }
+}
+
+impl<T: Eq, A: Allocator> Eq for RingBuffer<T, A> {}
+
+impl<T: Hash, A: Allocator> Hash for RingBuffer<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.deque.hash(state)
+    }
+}
+
+// Pushes every element in turn, so only the last `capacity` of them stay.
+impl<T, A: Allocator> Extend<T> for RingBuffer<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            drop(self.push(elem));
+        }
+    }
+}
+
+impl<T, A: Allocator> IntoIterator for RingBuffer<T, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+    fn into_iter(self) -> IntoIter<T, A> {
+        self.deque.into_iter()
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a RingBuffer<T, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_overwrites_oldest() {
+        let mut ring = RingBuffer::new(3);
+        assert_eq!(ring.push(1), None);
+        assert_eq!(ring.push(2), None);
+        assert_eq!(ring.push(3), None);
+        assert!(ring.is_full());
+        assert_eq!(ring.push(4), Some(1));
+        assert_eq!(ring, [2, 3, 4]);
+        assert_eq!(ring.oldest(), Some(&2));
+        assert_eq!(ring.newest(), Some(&4));
+
+        let capacity = ring.deque.capacity();
+        ring.extend(5..100);
+        assert_eq!(ring, [97, 98, 99]);
+        assert_eq!(ring.len(), 3);
+        // the buffer never reallocates
+        assert_eq!(ring.deque.capacity(), capacity);
+        assert_eq!(ring.clone(), ring);
+        assert_eq!(ring.into_iter().collect::<Vec<_>>(), [97, 98, 99]);
+
+        let mut empty = RingBuffer::new(0);
+        assert_eq!(empty.push('a'), Some('a'));
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn ring_latest() {
+        let mut ring = RingBuffer::new(5);
+        ring.extend(0..3);
+        assert!(ring.latest(2).eq(&[1, 2]));
+        assert!(ring.latest(10).eq(&[0, 1, 2]));
+
+        // once wrapped, `latest` spans both halves of the buffer
+        ring.extend(3..8);
+        assert!(!ring.as_slices().1.is_empty());
+        assert!(ring.latest(4).eq(&[4, 5, 6, 7]));
+        assert!(ring.latest(0).eq(&[] as &[i32]));
+        assert_eq!(ring.latest(3).len(), 3);
+        assert_eq!(ring.get(0), Some(&3));
+    }
+}
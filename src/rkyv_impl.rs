@@ -0,0 +1,93 @@
+// Archives exactly like std's Vec, so `ArchivedVec` is the archived form and
+// archived buffers can be accessed in place without deserializing.
+use crate::{Allocator, NomVec};
+use rkyv::rancor::{Fallible, Source};
+use rkyv::ser::{Allocator as SerAllocator, Writer};
+use rkyv::vec::{ArchivedVec, VecResolver};
+use rkyv::{Archive, Deserialize, DeserializeUnsized, Place, Serialize};
+
+impl<T: Archive, A: Allocator> Archive for NomVec<T, A> {
+    type Archived = ArchivedVec<T::Archived>;
+    type Resolver = VecResolver;
+
+    fn resolve(&self, resolver: Self::Resolver, out: Place<Self::Archived>) {
+        ArchivedVec::resolve_from_slice(self, resolver, out);
+    }
+}
+
+impl<T, A, S> Serialize<S> for NomVec<T, A>
+where
+    T: Serialize<S>,
+    A: Allocator,
+    S: Fallible + SerAllocator + Writer + ?Sized,
+{
+    fn serialize(
+        &self,
+        serializer: &mut S,
+    ) -> Result<Self::Resolver, S::Error> {
+        ArchivedVec::<T::Archived>::serialize_from_slice(self, serializer)
+    }
+}
+
+// rkyv already knows how to rebuild a std Vec, and that buffer can be
+// adopted without copying.
+impl<T, D> Deserialize<NomVec<T>, D> for ArchivedVec<T::Archived>
+where
+    T: Archive,
+    [T::Archived]: DeserializeUnsized<[T], D>,
+    D: Fallible + ?Sized,
+    D::Error: Source,
+{
+    fn deserialize(&self, deserializer: &mut D) -> Result<NomVec<T>, D::Error> {
+        let vec: Vec<T> =
+            Deserialize::<Vec<T>, D>::deserialize(self, deserializer)?;
+        Ok(NomVec::from(vec))
+    }
+}
+
+impl<T: PartialEq<U>, U, A: Allocator> PartialEq<NomVec<U, A>>
+    for ArchivedVec<T>
+{
+    fn eq(&self, other: &NomVec<U, A>) -> bool {
+        self.as_slice().eq(&other[..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rkyv::rancor::Error;
+
+    #[test]
+    fn rkyv_roundtrip() {
+        let cv = NomVec::from([1u32, 2, 3, 4]);
+        let bytes = rkyv::to_bytes::<Error>(&cv).unwrap();
+
+        let archived =
+            rkyv::access::<ArchivedVec<rkyv::Archived<u32>>, Error>(&bytes)
+                .unwrap();
+        assert_eq!(archived.len(), 4);
+        assert_eq!(archived[3], 4);
+        assert!(*archived == cv);
+
+        let back: NomVec<u32> =
+            rkyv::deserialize::<NomVec<u32>, Error>(archived).unwrap();
+        assert_eq!(back, cv);
+    }
+
+    #[test]
+    fn rkyv_roundtrip_nested() {
+        let cv =
+            NomVec::from([NomVec::from([String::from("a")]), NomVec::new()]);
+        let bytes = rkyv::to_bytes::<Error>(&cv).unwrap();
+        let archived = rkyv::access::<
+            rkyv::Archived<NomVec<NomVec<String>>>,
+            Error,
+        >(&bytes)
+        .unwrap();
+        assert_eq!(archived[0][0], "a");
+        let back: NomVec<NomVec<String>> =
+            rkyv::deserialize::<_, Error>(archived).unwrap();
+        assert_eq!(back, cv);
+    }
+}
@@ -0,0 +1,538 @@
+// A rope for large texts: the string is cut into chunks of at most
+// `MAX_CHUNK` bytes, held in the nodes of a treap ordered by position (each
+// node knows the byte length of its subtree). Splitting and merging the treap
+// by byte offset gives O(log n) insert, remove and slicing, however big the
+// text gets. The nodes live in an arena vector and each chunk in its own
+// `NomVec<u8, A>`, so all of it is allocated through `A`.
+//
+// Chunks always start and end on char boundaries, and every offset taken by
+// the public API must be a char boundary too, so each chunk is valid UTF-8
+// on its own.
+use crate::{slice_range, Allocator, Global, NomString, NomVec};
+use std::cmp;
+use std::fmt;
+use std::ops::{Range, RangeBounds};
+use std::str;
+
+const MAX_CHUNK: usize = 1024;
+const NIL: u32 = u32::MAX;
+
+struct Node<A: Allocator> {
+    chunk: NomVec<u8, A>,
+    left: u32,
+    right: u32,
+    priority: u32,
+    // bytes in this subtree, including `chunk`
+    len: usize,
+}
+
+impl<A: Allocator + Clone> Clone for Node<A> {
+    fn clone(&self) -> Self {
+        Node {
+            chunk: self.chunk.clone(),
+            left: self.left,
+            right: self.right,
+            priority: self.priority,
+            len: self.len,
+        }
+    }
+}
+
+pub struct Rope<A: Allocator + Clone = Global> {
+    nodes: NomVec<Node<A>, A>,
+    // indices of freed nodes, reused before the arena grows
+    free: NomVec<u32, A>,
+    root: u32,
+    // xorshift state for treap priorities
+    seed: u32,
+}
+
+impl Rope {
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<A: Allocator + Clone> Rope<A> {
+    pub fn new_in(alloc: A) -> Self {
+        Rope {
+            nodes: NomVec::new_in(alloc.clone()),
+            free: NomVec::new_in(alloc),
+            root: NIL,
+            seed: 0x9e37_79b9,
+        }
+    }
+
+    pub fn from_str_in(text: &str, alloc: A) -> Self {
+        let mut rope = Self::new_in(alloc);
+        rope.insert(0, text);
+        rope
+    }
+
+    // in bytes
+    pub fn len(&self) -> usize {
+        self.subtree_len(self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.nodes.allocator()
+    }
+
+    fn node(&self, t: u32) -> &Node<A> {
+        &self.nodes[t as usize]
+    }
+
+    fn node_mut(&mut self, t: u32) -> &mut Node<A> {
+        &mut self.nodes[t as usize]
+    }
+
+    fn subtree_len(&self, t: u32) -> usize {
+        if t == NIL {
+            0
+        } else {
+            self.node(t).len
+        }
+    }
+
+    fn update(&mut self, t: u32) {
+        let node = self.node(t);
+        let len = self.subtree_len(node.left)
+            + node.chunk.len()
+            + self.subtree_len(node.right);
+        self.node_mut(t).len = len;
+    }
+
+    fn next_priority(&mut self) -> u32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed
+    }
+
+    fn alloc_node(&mut self, chunk: NomVec<u8, A>, priority: u32) -> u32 {
+        let node = Node {
+            len: chunk.len(),
+            chunk,
+            left: NIL,
+            right: NIL,
+            priority,
+        };
+        match self.free.pop() {
+            Some(t) => {
+                self.nodes[t as usize] = node;
+                t
+            }
+            None => {
+                assert!(self.nodes.len() < NIL as usize, "rope too large");
+                self.nodes.push(node);
+                (self.nodes.len() - 1) as u32
+            }
+        }
+    }
+
+    fn free_tree(&mut self, t: u32) {
+        if t == NIL {
+            return;
+        }
+        let (left, right) = (self.node(t).left, self.node(t).right);
+        self.free_tree(left);
+        self.free_tree(right);
+        // give the chunk's memory back now rather than on reuse
+        self.node_mut(t).chunk = NomVec::new_in(self.allocator().clone());
+        self.free.push(t);
+    }
+
+    // Splits `t` into the trees holding its first `pos` bytes and the rest,
+    // cutting a chunk in two if `pos` falls inside one.
+    fn split(&mut self, t: u32, pos: usize) -> (u32, u32) {
+        if t == NIL {
+            return (NIL, NIL);
+        }
+        let left_len = self.subtree_len(self.node(t).left);
+        let chunk_len = self.node(t).chunk.len();
+        if pos <= left_len {
+            let (a, b) = self.split(self.node(t).left, pos);
+            self.node_mut(t).left = b;
+            self.update(t);
+            (a, t)
+        } else if pos >= left_len + chunk_len {
+            let right = self.node(t).right;
+            let (a, b) = self.split(right, pos - left_len - chunk_len);
+            self.node_mut(t).right = a;
+            self.update(t);
+            (t, b)
+        } else {
+            // the tail becomes a new node that takes over `t`'s right
+            // subtree; sharing `t`'s priority keeps the heap order
+            let alloc = self.allocator().clone();
+            let tail =
+                self.node_mut(t).chunk.split_off_in(pos - left_len, alloc);
+            let priority = self.node(t).priority;
+            let new = self.alloc_node(tail, priority);
+            let right = self.node(t).right;
+            self.node_mut(new).right = right;
+            self.node_mut(t).right = NIL;
+            self.update(new);
+            self.update(t);
+            (t, new)
+        }
+    }
+
+    // joins two trees, with everything in `a` coming before `b`
+    fn merge(&mut self, a: u32, b: u32) -> u32 {
+        if a == NIL {
+            return b;
+        }
+        if b == NIL {
+            return a;
+        }
+        if self.node(a).priority >= self.node(b).priority {
+            let merged = self.merge(self.node(a).right, b);
+            self.node_mut(a).right = merged;
+            self.update(a);
+            a
+        } else {
+            let merged = self.merge(a, self.node(b).left);
+            self.node_mut(b).left = merged;
+            self.update(b);
+            b
+        }
+    }
+
+    // the node holding byte `pos` and the offset of `pos` within its chunk
+    fn locate(&self, mut pos: usize) -> Option<(u32, usize)> {
+        let mut t = self.root;
+        while t != NIL {
+            let node = self.node(t);
+            let left_len = self.subtree_len(node.left);
+            if pos < left_len {
+                t = node.left;
+            } else if pos < left_len + node.chunk.len() {
+                return Some((t, pos - left_len));
+            } else {
+                pos -= left_len + node.chunk.len();
+                t = node.right;
+            }
+        }
+        None
+    }
+
+    pub fn is_char_boundary(&self, pos: usize) -> bool {
+        match self.locate(pos) {
+            // chunks start on boundaries, and continuation bytes are
+            // 0b10xx_xxxx
+            Some((t, offset)) => (self.node(t).chunk[offset] as i8) >= -0x40,
+            None => pos == self.len(),
+        }
+    }
+
+    // Adds `text` to the chunk containing `pos` if it fits there, fixing up
+    // the lengths on the way back up.
+    fn insert_in_place(&mut self, t: u32, pos: usize, text: &str) -> bool {
+        if t == NIL {
+            return false;
+        }
+        let left_len = self.subtree_len(self.node(t).left);
+        let chunk_len = self.node(t).chunk.len();
+        let done = if pos < left_len {
+            self.insert_in_place(self.node(t).left, pos, text)
+        } else if pos <= left_len + chunk_len {
+            // at the very end of this chunk it's as good a place as any
+            if chunk_len + text.len() > MAX_CHUNK {
+                false
+            } else {
+                let offset = pos - left_len;
+                self.node_mut(t).chunk.insert_slice(offset, text.as_bytes());
+                true
+            }
+        } else {
+            let right = self.node(t).right;
+            self.insert_in_place(right, pos - left_len - chunk_len, text)
+        };
+        if done {
+            self.node_mut(t).len += text.len();
+        }
+        done
+    }
+
+    // builds a tree of fresh chunks holding `text`
+    fn build(&mut self, mut text: &str) -> u32 {
+        let mut tree = NIL;
+        while !text.is_empty() {
+            let mut end = cmp::min(text.len(), MAX_CHUNK);
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            let mut chunk =
+                NomVec::with_capacity_in(end, self.allocator().clone());
+            chunk.extend_from_slice(&text.as_bytes()[..end]);
+            let priority = self.next_priority();
+            let node = self.alloc_node(chunk, priority);
+            tree = self.merge(tree, node);
+            text = &text[end..];
+        }
+        tree
+    }
+
+    // Panics if `pos` is past the end or not on a char boundary.
+    pub fn insert(&mut self, pos: usize, text: &str) {
+        assert!(pos <= self.len(), "position out of bounds");
+        assert!(
+            self.is_char_boundary(pos),
+            "position is not on a char boundary"
+        );
+        if text.is_empty() || self.insert_in_place(self.root, pos, text) {
+            return;
+        }
+        let (left, right) = self.split(self.root, pos);
+        let middle = self.build(text);
+        let left = self.merge(left, middle);
+        self.root = self.merge(left, right);
+    }
+
+    // Panics if the range is out of bounds or either end isn't on a char
+    // boundary.
+    pub fn remove<R: RangeBounds<usize>>(&mut self, range: R) {
+        let Range { start, end } = slice_range(range, self.len());
+        assert!(
+            self.is_char_boundary(start) && self.is_char_boundary(end),
+            "range is not on char boundaries"
+        );
+        let (left, rest) = self.split(self.root, start);
+        let (middle, right) = self.split(rest, end - start);
+        self.free_tree(middle);
+        self.root = self.merge(left, right);
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.free.clear();
+        self.root = NIL;
+    }
+
+    // The chunks covering `range`, trimmed to it, in order. Finding the
+    // first one is O(log n).
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> Chunks<'_, A> {
+        let Range { start, end } = slice_range(range, self.len());
+        assert!(
+            self.is_char_boundary(start) && self.is_char_boundary(end),
+            "range is not on char boundaries"
+        );
+        // walk down to `start`, keeping the ancestors still to visit
+        let mut stack = Vec::new();
+        let (mut t, mut pos) = (self.root, start);
+        let mut skip = 0;
+        while t != NIL {
+            let node = self.node(t);
+            let left_len = self.subtree_len(node.left);
+            if pos < left_len {
+                stack.push(t);
+                t = node.left;
+            } else if pos < left_len + node.chunk.len() {
+                stack.push(t);
+                skip = pos - left_len;
+                break;
+            } else {
+                pos -= left_len + node.chunk.len();
+                t = node.right;
+            }
+        }
+        Chunks {
+            rope: self,
+            stack,
+            skip,
+            remaining: end - start,
+        }
+    }
+
+    pub fn chunks(&self) -> Chunks<'_, A> {
+        self.slice(..)
+    }
+
+    // copies the text out into one string, in the rope's allocator
+    pub fn to_nomstring(&self) -> NomString<A> {
+        let mut s =
+            NomString::with_capacity_in(self.len(), self.allocator().clone());
+        for chunk in self.chunks() {
+            s.push_str(chunk);
+        }
+        s
+    }
+}
+
+impl Default for Rope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(text: &str) -> Self {
+        Rope::from_str_in(text, Global)
+    }
+}
+
+impl<A: Allocator + Clone> Clone for Rope<A> {
+    fn clone(&self) -> Self {
+        Rope {
+            nodes: self.nodes.clone(),
+            free: self.free.clone(),
+            root: self.root,
+            seed: self.seed,
+        }
+    }
+}
+
+impl<A: Allocator + Clone> fmt::Display for Rope<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl<A: Allocator + Clone> fmt::Debug for Rope<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.chunks()).finish()
+    }
+}
+
+impl<A: Allocator + Clone> PartialEq<str> for Rope<A> {
+    fn eq(&self, other: &str) -> bool {
+        let mut rest = other.as_bytes();
+        self.len() == other.len()
+            && self.chunks().all(|chunk| {
+                let (head, tail) = rest.split_at(chunk.len());
+                rest = tail;
+                head == chunk.as_bytes()
+            })
+    }
+}
+
+impl<A: Allocator + Clone> PartialEq<&str> for Rope<A> {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
+
+impl<A: Allocator + Clone, B: Allocator + Clone> PartialEq<Rope<B>>
+    for Rope<A>
+{
+    fn eq(&self, other: &Rope<B>) -> bool {
+        self.len() == other.len()
+            && self
+                .chunks()
+                .flat_map(str::bytes)
+                .eq(other.chunks().flat_map(str::bytes))
+    }
+}
+
+impl<A: Allocator + Clone> Eq for Rope<A> {}
+
+pub struct Chunks<'a, A: Allocator + Clone = Global> {
+    rope: &'a Rope<A>,
+    // nodes whose chunk (and then right subtree) are still to come
+    stack: Vec<u32>,
+    // bytes to drop from the front of the next chunk
+    skip: usize,
+    remaining: usize,
+}
+
+impl<'a, A: Allocator + Clone> Iterator for Chunks<'a, A> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.remaining > 0 {
+            let t = self.stack.pop()?;
+            let node = self.rope.node(t);
+            let mut right = node.right;
+            while right != NIL {
+                self.stack.push(right);
+                right = self.rope.node(right).left;
+            }
+            let chunk = &node.chunk[self.skip..];
+            self.skip = 0;
+            let chunk = &chunk[..cmp::min(chunk.len(), self.remaining)];
+            self.remaining -= chunk.len();
+            Some(unsafe { str::from_utf8_unchecked(chunk) })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rope_edits_match_string() {
+        let mut rope = Rope::new();
+        let mut model = String::new();
+        let mut seed = 7u32;
+        for step in 0..2000 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let pos = (seed >> 8) as usize % (model.len() + 1);
+            let pos = (0..=pos)
+                .rev()
+                .find(|&p| model.is_char_boundary(p))
+                .unwrap();
+            if step % 3 == 2 && !model.is_empty() {
+                let end = cmp::min(pos + (seed as usize % 40), model.len());
+                let end = (end..=model.len())
+                    .find(|&p| model.is_char_boundary(p))
+                    .unwrap();
+                rope.remove(pos..end);
+                model.replace_range(pos..end, "");
+            } else {
+                let text = if step % 5 == 0 {
+                    "héllo wörld "
+                } else {
+                    "ab"
+                };
+                rope.insert(pos, text);
+                model.insert_str(pos, text);
+            }
+            assert_eq!(rope.len(), model.len());
+        }
+        assert_eq!(rope, *model);
+        assert_eq!(rope.to_string(), model);
+        assert_eq!(rope.clone(), rope);
+    }
+
+    #[test]
+    fn rope_large_text_and_slices() {
+        let line = "the quick brown fox jumps over the lazy dög\n";
+        let text = line.repeat(5000);
+        let mut rope = Rope::from(&text[..]);
+        assert!(rope.chunks().count() >= text.len() / MAX_CHUNK);
+        assert_eq!(rope, *text);
+
+        let start = line.len() * 2500 + 4;
+        let slice: String = rope.slice(start..start + 15).collect();
+        assert_eq!(slice, &text[start..start + 15]);
+        assert!(rope.is_char_boundary(line.len() - 4));
+        assert!(!rope.is_char_boundary(line.len() - 3));
+
+        rope.insert(start, "[mark]");
+        let slice: String = rope.slice(start - 4..start + 10).collect();
+        assert_eq!(slice, "the [mark]quic");
+
+        rope.remove(..start);
+        assert!(rope.to_nomstring().starts_with("[mark]quick"));
+        rope.clear();
+        assert!(rope.is_empty());
+        assert_eq!(rope.chunks().next(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "char boundary")]
+    fn rope_rejects_mid_char_insert() {
+        Rope::from("ö").insert(1, "x");
+    }
+}
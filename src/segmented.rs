@@ -0,0 +1,425 @@
+// A vector stored in fixed-size segments of `N` elements. Each segment is a
+// NomVec allocated with room for exactly `N` and never pushed past that, so
+// it's never reallocated: once pushed, an element stays at the same address
+// until it's popped or the vector is dropped, and raw pointers to it stay
+// valid across later pushes. Growing only allocates a new segment; the
+// table of segments may move, but the elements don't.
+use crate::{Allocator, Global, NomVec};
+use std::fmt;
+use std::iter::{Flatten, FromIterator, FusedIterator};
+use std::ops::{Index, IndexMut};
+use std::slice;
+
+pub struct SegmentedNomVec<
+    T,
+    const N: usize = 32,
+    A: Allocator + Clone = Global,
+> {
+    // every segment before the one holding the last element is full; empty
+    // ones may follow it after pops and are reused by later pushes
+    segments: NomVec<NomVec<T, A>, A>,
+    len: usize,
+}
+
+impl<T, const N: usize> SegmentedNomVec<T, N> {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<T, const N: usize, A: Allocator + Clone> SegmentedNomVec<T, N, A> {
+    // Panics if `N` is 0.
+    pub const fn new_in(alloc: A) -> Self {
+        assert!(N > 0, "segments must hold at least one element");
+        SegmentedNomVec {
+            segments: NomVec::new_in(alloc),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // the number of elements the allocated segments hold
+    pub fn capacity(&self) -> usize {
+        self.segments.len() * N
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.segments.allocator()
+    }
+
+    // Appends `elem` and returns it. Its address doesn't change until it's
+    // popped, however many elements are pushed after it.
+    pub fn push(&mut self, elem: T) -> &mut T {
+        let segment = self.len / N;
+        if segment == self.segments.len() {
+            let alloc = self.allocator().clone();
+            self.segments.push(NomVec::with_capacity_in(N, alloc));
+        }
+        let segment = &mut self.segments[segment];
+        debug_assert!(segment.len() < segment.capacity());
+        segment.push(elem);
+        self.len += 1;
+        segment.last_mut().unwrap()
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        self.segments[self.len / N].pop()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index < self.len {
+            Some(&self.segments[index / N][index % N])
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index < self.len {
+            Some(&mut self.segments[index / N][index % N])
+        } else {
+            None
+        }
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.get(self.len.wrapping_sub(1))
+    }
+
+    // Drops the elements past `len` in place, last segment first; like
+    // everything else here, this never moves an element.
+    pub fn truncate(&mut self, len: usize) {
+        while self.len > len {
+            let start = (self.len - 1) / N * N;
+            let keep = len.max(start);
+            self.len = keep;
+            self.segments[start / N].truncate(keep - start);
+        }
+    }
+
+    // drops the elements but keeps the segments for reuse
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+
+    // the number of segments holding elements
+    fn used_segments(&self) -> usize {
+        self.len.div_ceil(N)
+    }
+
+    // frees the segments no element lives in
+    pub fn shrink_to_fit(&mut self) {
+        self.segments.truncate(self.used_segments());
+        self.segments.shrink_to_fit();
+    }
+
+    // the elements, one contiguous slice per non-empty segment
+    pub fn segments(&self) -> impl DoubleEndedIterator<Item = &[T]> + '_ {
+        self.segments[..self.used_segments()]
+            .iter()
+            .map(|segment| &segment[..])
+    }
+
+    pub fn segments_mut(
+        &mut self,
+    ) -> impl DoubleEndedIterator<Item = &mut [T]> + '_ {
+        let used = self.used_segments();
+        self.segments[..used]
+            .iter_mut()
+            .map(|segment| &mut segment[..])
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, A> {
+        Iter {
+            segments: self.segments.iter(),
+            front: [].iter(),
+            back: [].iter(),
+            remaining: self.len,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, A> {
+        IterMut {
+            segments: self.segments.iter_mut(),
+            front: [].iter_mut(),
+            back: [].iter_mut(),
+            remaining: self.len,
+        }
+    }
+}
+
+impl<T, const N: usize> Default for SegmentedNomVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, const N: usize, A: Allocator + Clone> Clone
+    for SegmentedNomVec<T, N, A>
+{
+    fn clone(&self) -> Self {
+        let mut clone = SegmentedNomVec::new_in(self.allocator().clone());
+        clone.extend(self.iter().cloned());
+        clone
+    }
+}
+
+impl<T: fmt::Debug, const N: usize, A: Allocator + Clone> fmt::Debug
+    for SegmentedNomVec<T, N, A>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq, const N: usize, A: Allocator + Clone> PartialEq
+    for SegmentedNomVec<T, N, A>
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, const N: usize, A: Allocator + Clone> Eq
+    for SegmentedNomVec<T, N, A>
+{
+}
+
+impl<T, const N: usize, A: Allocator + Clone> Index<usize>
+    for SegmentedNomVec<T, N, A>
+{
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        match self.get(index) {
+            Some(elem) => elem,
+            None => panic!("index out of bounds"),
+        }
+    }
+}
+
+impl<T, const N: usize, A: Allocator + Clone> IndexMut<usize>
+    for SegmentedNomVec<T, N, A>
+{
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        match self.get_mut(index) {
+            Some(elem) => elem,
+            None => panic!("index out of bounds"),
+        }
+    }
+}
+
+impl<T, const N: usize, A: Allocator + Clone> Extend<T>
+    for SegmentedNomVec<T, N, A>
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T, const N: usize> FromIterator<T> for SegmentedNomVec<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut sv = SegmentedNomVec::new();
+        sv.extend(iter);
+        sv
+    }
+}
+
+impl<T, const N: usize, A: Allocator + Clone> IntoIterator
+    for SegmentedNomVec<T, N, A>
+{
+    type Item = T;
+    type IntoIter = Flatten<crate::IntoIter<NomVec<T, A>, A>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.segments.into_iter().flatten()
+    }
+}
+
+impl<'a, T, const N: usize, A: Allocator + Clone> IntoIterator
+    for &'a SegmentedNomVec<T, N, A>
+{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, A>;
+    fn into_iter(self) -> Iter<'a, T, A> {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize, A: Allocator + Clone> IntoIterator
+    for &'a mut SegmentedNomVec<T, N, A>
+{
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T, A>;
+    fn into_iter(self) -> IterMut<'a, T, A> {
+        self.iter_mut()
+    }
+}
+
+pub struct Iter<'a, T, A: Allocator> {
+    segments: slice::Iter<'a, NomVec<T, A>>,
+    front: slice::Iter<'a, T>,
+    back: slice::Iter<'a, T>,
+    remaining: usize,
+}
+
+impl<'a, T, A: Allocator> Iterator for Iter<'a, T, A> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            if let Some(elem) = self.front.next() {
+                self.remaining -= 1;
+                return Some(elem);
+            }
+            match self.segments.next() {
+                Some(segment) => self.front = segment.iter(),
+                None => break,
+            }
+        }
+        let elem = self.back.next()?;
+        self.remaining -= 1;
+        Some(elem)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, A: Allocator> DoubleEndedIterator for Iter<'a, T, A> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        loop {
+            if let Some(elem) = self.back.next_back() {
+                self.remaining -= 1;
+                return Some(elem);
+            }
+            match self.segments.next_back() {
+                Some(segment) => self.back = segment.iter(),
+                None => break,
+            }
+        }
+        let elem = self.front.next_back()?;
+        self.remaining -= 1;
+        Some(elem)
+    }
+}
+
+impl<'a, T, A: Allocator> ExactSizeIterator for Iter<'a, T, A> {}
+impl<'a, T, A: Allocator> FusedIterator for Iter<'a, T, A> {}
+
+pub struct IterMut<'a, T, A: Allocator> {
+    segments: slice::IterMut<'a, NomVec<T, A>>,
+    front: slice::IterMut<'a, T>,
+    back: slice::IterMut<'a, T>,
+    remaining: usize,
+}
+
+impl<'a, T, A: Allocator> Iterator for IterMut<'a, T, A> {
+    type Item = &'a mut T;
+    fn next(&mut self) -> Option<&'a mut T> {
+        loop {
+            if let Some(elem) = self.front.next() {
+                self.remaining -= 1;
+                return Some(elem);
+            }
+            match self.segments.next() {
+                Some(segment) => self.front = segment.iter_mut(),
+                None => break,
+            }
+        }
+        let elem = self.back.next()?;
+        self.remaining -= 1;
+        Some(elem)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, A: Allocator> DoubleEndedIterator for IterMut<'a, T, A> {
+    fn next_back(&mut self) -> Option<&'a mut T> {
+        loop {
+            if let Some(elem) = self.back.next_back() {
+                self.remaining -= 1;
+                return Some(elem);
+            }
+            match self.segments.next_back() {
+                Some(segment) => self.back = segment.iter_mut(),
+                None => break,
+            }
+        }
+        let elem = self.front.next_back()?;
+        self.remaining -= 1;
+        Some(elem)
+    }
+}
+
+impl<'a, T, A: Allocator> ExactSizeIterator for IterMut<'a, T, A> {}
+impl<'a, T, A: Allocator> FusedIterator for IterMut<'a, T, A> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segmented_addresses_are_stable() {
+        let mut sv: SegmentedNomVec<String, 4> = SegmentedNomVec::new();
+        let first: *const String = sv.push("first".to_string());
+        let mut pointers = vec![first];
+        for i in 1..100 {
+            pointers.push(sv.push(i.to_string()));
+        }
+        assert_eq!(sv.len(), 100);
+        assert_eq!(sv.capacity(), 100);
+        for (i, &p) in pointers.iter().enumerate() {
+            assert!(std::ptr::eq(p, &sv[i]));
+        }
+        unsafe {
+            assert_eq!(*first, "first");
+        }
+        assert_eq!(sv.segments().count(), 25);
+        assert!(sv.segments().all(|segment| segment.len() == 4));
+    }
+
+    #[test]
+    fn segmented_iter_and_pop() {
+        let mut sv: SegmentedNomVec<u32, 3> = (0..10).collect();
+        assert!(sv.iter().copied().eq(0..10));
+        assert!(sv.iter().rev().copied().eq((0..10).rev()));
+        let mut iter = sv.iter();
+        assert_eq!(iter.next(), Some(&0));
+        assert_eq!(iter.next_back(), Some(&9));
+        assert_eq!(iter.len(), 8);
+        assert!(iter.eq(&[1, 2, 3, 4, 5, 6, 7, 8]));
+
+        for x in &mut sv {
+            *x *= 2;
+        }
+        assert_eq!(sv.pop(), Some(18));
+        sv.truncate(4);
+        assert_eq!(sv.last(), Some(&6));
+        assert_eq!(sv.segments().count(), 2);
+        // the emptied segments are kept until shrink_to_fit
+        assert_eq!(sv.capacity(), 12);
+        sv.push(100);
+        sv.shrink_to_fit();
+        assert_eq!(sv.capacity(), 6);
+        assert_eq!(sv.get(5), None);
+        assert!(sv.clone().into_iter().eq([0, 2, 4, 6, 100]));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{cautious_capacity, Allocator, NomVec};
+use serde::de::{
+    Deserialize, DeserializeSeed, Deserializer, SeqAccess, Visitor,
+};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+use std::fmt;
+use std::marker::PhantomData;
+
+impl<T: Serialize, A: Allocator> Serialize for NomVec<T, A> {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for elem in self.iter() {
+            seq.serialize_element(elem)?;
+        }
+        seq.end()
+    }
+}
+
+struct NomVecVisitor<T> {
+    _marker: PhantomData<T>,
+}
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for NomVecVisitor<T> {
+    type Value = NomVec<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a sequence")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(
+        self,
+        mut seq: S,
+    ) -> Result<Self::Value, S::Error> {
+        let mut vec =
+            NomVec::with_capacity(cautious_capacity::<T>(seq.size_hint()));
+        while let Some(elem) = seq.next_element()? {
+            vec.push(elem);
+        }
+        Ok(vec)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for NomVec<T> {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(NomVecVisitor {
+            _marker: PhantomData,
+        })
+    }
+}
+
+// Deserializes a sequence straight into a caller-supplied allocator, e.g. an
+// arena, without ever touching Global:
+//
+//     let vec: NomVec<u32, &Bump> = NomVecSeed::new(&bump).deserialize(de)?;
+pub struct NomVecSeed<'a, T, A: Allocator> {
+    alloc: &'a A,
+    _marker: PhantomData<T>,
+}
+
+impl<'a, T, A: Allocator> NomVecSeed<'a, T, A> {
+    pub fn new(alloc: &'a A) -> Self {
+        NomVecSeed {
+            alloc,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, 'de, T, A> DeserializeSeed<'de> for NomVecSeed<'a, T, A>
+where
+    T: Deserialize<'de>,
+    A: Allocator,
+{
+    type Value = NomVec<T, &'a A>;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'a, 'de, T, A> Visitor<'de> for NomVecSeed<'a, T, A>
+where
+    T: Deserialize<'de>,
+    A: Allocator,
+{
+    type Value = NomVec<T, &'a A>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a sequence")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(
+        self,
+        mut seq: S,
+    ) -> Result<Self::Value, S::Error> {
+        let capacity = cautious_capacity::<T>(seq.size_hint());
+        let mut vec = NomVec::with_capacity_in(capacity, self.alloc);
+        while let Some(elem) = seq.next_element()? {
+            vec.push(elem);
+        }
+        Ok(vec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serde_roundtrip() {
+        let cv = NomVec::from([1u32, 2, 3]);
+        let json = serde_json::to_string(&cv).unwrap();
+        assert_eq!(json, "[1,2,3]");
+        let back: NomVec<u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, cv);
+
+        let nested: NomVec<NomVec<String>> =
+            serde_json::from_str(r#"[["a"],[],["b","c"]]"#).unwrap();
+        assert_eq!(nested.len(), 3);
+        assert_eq!(nested[2], ["b", "c"]);
+    }
+
+    #[test]
+    fn serde_seed_uses_allocator() {
+        use crate::Global;
+
+        let alloc = Global;
+        let mut de = serde_json::Deserializer::from_str("[4, 5, 6]");
+        let vec: NomVec<u16, &Global> =
+            NomVecSeed::new(&alloc).deserialize(&mut de).unwrap();
+        assert_eq!(vec, [4, 5, 6]);
+    }
+
+    #[test]
+    fn serde_caps_preallocation() {
+        assert_eq!(cautious_capacity::<u8>(Some(usize::MAX)), 1024 * 1024);
+        assert_eq!(cautious_capacity::<u64>(Some(10)), 10);
+        assert_eq!(cautious_capacity::<()>(None), 0);
+    }
+}
@@ -0,0 +1,218 @@
+// A vector for write-heavy parallel work, split into shards that are each
+// a NomVec behind their own lock. Every thread is handed a shard the first
+// time it pushes and keeps it, so threads append side by side instead of
+// all fighting over one counter or one lock; with at least as many shards
+// as threads, no push ever waits. The shards are stitched back together,
+// in shard order, by `into_inner` or iteration.
+//
+// Each thread's values stay in the order it pushed them, but there's no
+// order between threads.
+use crate::{Allocator, Global, NomVec};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+// The shard every thread uses, modulo the shard count; numbered in the
+// order threads first push to any ShardedNomVec.
+fn thread_shard() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static SHARD: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    SHARD.with(|&shard| shard)
+}
+
+// aligned so that neighbouring shards' locks don't share a cache line
+#[repr(align(128))]
+struct Shard<T, A: Allocator>(Mutex<NomVec<T, A>>);
+
+impl<T, A: Allocator> Shard<T, A> {
+    // A panic while a shard was locked can't have left its NomVec broken,
+    // so poisoning is ignored.
+    fn lock(&self) -> MutexGuard<'_, NomVec<T, A>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn get_mut(&mut self) -> &mut NomVec<T, A> {
+        self.0.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn into_inner(self) -> NomVec<T, A> {
+        self.0.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+pub struct ShardedNomVec<T, A: Allocator + Clone = Global> {
+    shards: NomVec<Shard<T, A>, A>,
+}
+
+impl<T> ShardedNomVec<T> {
+    // one shard per thread the machine can run at once
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_shards(shards: usize) -> Self {
+        Self::with_shards_in(shards, Global)
+    }
+}
+
+impl<T, A: Allocator + Clone> ShardedNomVec<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        let shards = thread::available_parallelism().map_or(4, |n| n.get());
+        Self::with_shards_in(shards, alloc)
+    }
+
+    // Panics if `shards` is zero.
+    pub fn with_shards_in(shards: usize, alloc: A) -> Self {
+        assert!(shards > 0, "a ShardedNomVec needs at least one shard");
+        let mut list = NomVec::with_capacity_in(shards, alloc.clone());
+        for _ in 0..shards {
+            list.push(Shard(Mutex::new(NomVec::new_in(alloc.clone()))));
+        }
+        ShardedNomVec { shards: list }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.shards.allocator()
+    }
+
+    fn local(&self) -> &Shard<T, A> {
+        &self.shards[thread_shard() % self.shards.len()]
+    }
+
+    // Appends `value` to the calling thread's shard.
+    pub fn push(&self, value: T) {
+        self.local().lock().push(value)
+    }
+
+    // Appends every item of `iter` to the calling thread's shard, taking
+    // its lock once.
+    pub fn extend_local<I: IntoIterator<Item = T>>(&self, iter: I) {
+        self.local().lock().extend(iter)
+    }
+
+    // Runs `f` on the calling thread's shard, holding its lock.
+    pub fn with_local<R>(&self, f: impl FnOnce(&mut NomVec<T, A>) -> R) -> R {
+        f(&mut self.local().lock())
+    }
+
+    // Locks each shard in turn, so it's only a snapshot while other
+    // threads are pushing.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.lock().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        for shard in self.shards.iter_mut() {
+            shard.get_mut().clear();
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.shards.iter_mut().flat_map(|s| s.get_mut().iter_mut())
+    }
+
+    pub fn shards_mut(
+        &mut self,
+    ) -> impl Iterator<Item = &mut NomVec<T, A>> + '_ {
+        self.shards.iter_mut().map(Shard::get_mut)
+    }
+
+    // The shards themselves, in shard order.
+    pub fn into_shards(self) -> NomVec<NomVec<T, A>, A> {
+        let alloc = self.shards.allocator().clone();
+        NomVec::from_iter_in(
+            self.shards.into_iter().map(Shard::into_inner),
+            alloc,
+        )
+    }
+
+    // Every value, shard after shard, in one NomVec.
+    pub fn into_inner(self) -> NomVec<T, A> {
+        let shards = self.into_shards();
+        let len: usize = shards.iter().map(|s| s.len()).sum();
+        let mut shards = shards.into_iter();
+        // the first shard's buffer is reused for the rest
+        let mut vec = shards.next().unwrap();
+        vec.reserve(len - vec.len());
+        for shard in shards {
+            vec.extend(shard);
+        }
+        vec
+    }
+}
+
+impl<T> Default for ShardedNomVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator + Clone> fmt::Debug for ShardedNomVec<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        for shard in self.shards.iter() {
+            list.entries(shard.lock().iter());
+        }
+        list.finish()
+    }
+}
+
+impl<T, A: Allocator + Clone> Extend<T> for ShardedNomVec<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.extend_local(iter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sharded_pushes_from_many_threads() {
+        let results = ShardedNomVec::with_shards(3);
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                let results = &results;
+                s.spawn(move || {
+                    for i in 0..1000 {
+                        results.push((t, i));
+                    }
+                });
+            }
+        });
+        assert_eq!(results.len(), 8000);
+        let values = results.into_inner();
+        assert_eq!(values.len(), 8000);
+        // each thread's values keep their order
+        for t in 0..8 {
+            let mine = values.iter().filter(|v| v.0 == t).map(|v| v.1);
+            assert!(mine.eq(0..1000));
+        }
+    }
+
+    #[test]
+    fn sharded_local_access() {
+        let mut sv = ShardedNomVec::with_shards(2);
+        sv.extend_local(0..3);
+        sv.push(3);
+        assert_eq!(sv.with_local(|shard| shard.len()), 4);
+        for v in sv.iter_mut() {
+            *v *= 10;
+        }
+        assert_eq!(format!("{:?}", sv), "[0, 10, 20, 30]");
+        let sizes: Vec<_> = sv.shards_mut().map(|s| s.len()).collect();
+        assert_eq!(sizes.iter().sum::<usize>(), 4);
+        assert_eq!(sv.into_shards().len(), 2);
+    }
+}
@@ -0,0 +1,136 @@
+// An atomically reference-counted box allocated through `A`: std's `Arc`
+// only takes a custom allocator on nightly. There are no weak references,
+// so a count of one means the handle is unique.
+use crate::Allocator;
+use std::alloc::{self, Layout};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Deref;
+use std::process;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{self, AtomicUsize, Ordering};
+
+struct Inner<T> {
+    count: AtomicUsize,
+    value: T,
+}
+
+pub(crate) struct Shared<T, A: Allocator> {
+    ptr: NonNull<Inner<T>>,
+    alloc: A,
+    _marker: PhantomData<Inner<T>>,
+}
+
+unsafe impl<T: Send + Sync, A: Allocator + Send> Send for Shared<T, A> {}
+unsafe impl<T: Send + Sync, A: Allocator + Sync> Sync for Shared<T, A> {}
+
+impl<T, A: Allocator> Shared<T, A> {
+    pub(crate) fn new_in(value: T, alloc: A) -> Self {
+        let layout = Layout::new::<Inner<T>>();
+        let ptr = match alloc.allocate(layout) {
+            Ok(ptr) => ptr.cast::<Inner<T>>(),
+            Err(_) => alloc::handle_alloc_error(layout),
+        };
+        unsafe {
+            ptr.as_ptr().write(Inner {
+                count: AtomicUsize::new(1),
+                value,
+            });
+        }
+        Shared {
+            ptr,
+            alloc,
+            _marker: PhantomData,
+        }
+    }
+
+    fn inner(&self) -> &Inner<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    pub(crate) fn is_unique(&self) -> bool {
+        // pairs with the Release decrement in `drop`, so another handle's
+        // last writes are visible before we take over
+        self.inner().count.load(Ordering::Acquire) == 1
+    }
+
+    pub(crate) fn ptr_eq(&self, other: &Self) -> bool {
+        self.ptr == other.ptr
+    }
+
+    pub(crate) fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if this.is_unique() {
+            unsafe { Some(&mut (*this.ptr.as_ptr()).value) }
+        } else {
+            None
+        }
+    }
+
+    // Clones the value out first if anyone else can see it.
+    pub(crate) fn make_mut(this: &mut Self) -> &mut T
+    where
+        T: Clone,
+        A: Clone,
+    {
+        if !this.is_unique() {
+            *this = Shared::new_in((**this).clone(), this.alloc.clone());
+        }
+        unsafe { &mut (*this.ptr.as_ptr()).value }
+    }
+
+    // The value, if this is its only handle.
+    pub(crate) fn try_unwrap(this: Self) -> Result<T, Self> {
+        let inner = this.inner();
+        if inner
+            .count
+            .compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(this);
+        }
+        let this = mem::ManuallyDrop::new(this);
+        unsafe {
+            let value = ptr::read(&this.inner().value);
+            let alloc = ptr::read(&this.alloc);
+            alloc.deallocate(this.ptr.cast(), Layout::new::<Inner<T>>());
+            Ok(value)
+        }
+    }
+}
+
+impl<T, A: Allocator> Deref for Shared<T, A> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.inner().value
+    }
+}
+
+impl<T, A: Allocator + Clone> Clone for Shared<T, A> {
+    fn clone(&self) -> Self {
+        // nothing is published through the count itself, so Relaxed is
+        // enough, as in std's Arc
+        let old = self.inner().count.fetch_add(1, Ordering::Relaxed);
+        if old > isize::MAX as usize {
+            process::abort();
+        }
+        Shared {
+            ptr: self.ptr,
+            alloc: self.alloc.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, A: Allocator> Drop for Shared<T, A> {
+    fn drop(&mut self) {
+        if self.inner().count.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        atomic::fence(Ordering::Acquire);
+        unsafe {
+            ptr::drop_in_place(self.ptr.as_ptr());
+            self.alloc
+                .deallocate(self.ptr.cast(), Layout::new::<Inner<T>>());
+        }
+    }
+}
@@ -0,0 +1,424 @@
+// A slab of values named by generational keys. Freed slots go on a free
+// list threaded through the vacant slots themselves and are reused by later
+// inserts; every slot carries a generation that's bumped when its value is
+// removed, so a key held past its value's removal is detected as stale
+// rather than resolving to whatever lives in the slot now.
+use crate::{Allocator, Global, NomVec};
+use std::fmt;
+use std::iter::{Enumerate, FusedIterator};
+use std::mem;
+use std::ops::{Index, IndexMut};
+use std::slice;
+
+const NIL: u32 = u32::MAX;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct SlotKey {
+    index: u32,
+    generation: u32,
+}
+
+impl SlotKey {
+    pub const fn index(self) -> usize {
+        self.index as usize
+    }
+
+    pub const fn generation(self) -> u32 {
+        self.generation
+    }
+}
+
+enum Entry<T> {
+    Occupied(T),
+    Vacant { next_free: u32 },
+}
+
+struct Slot<T> {
+    generation: u32,
+    entry: Entry<T>,
+}
+
+impl<T: Clone> Clone for Slot<T> {
+    fn clone(&self) -> Self {
+        Slot {
+            generation: self.generation,
+            entry: match &self.entry {
+                Entry::Occupied(value) => Entry::Occupied(value.clone()),
+                Entry::Vacant { next_free } => Entry::Vacant {
+                    next_free: *next_free,
+                },
+            },
+        }
+    }
+}
+
+pub struct SlotMap<T, A: Allocator = Global> {
+    slots: NomVec<Slot<T>, A>,
+    free_head: u32,
+    len: usize,
+}
+
+impl<T> SlotMap<T> {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<T, A: Allocator> SlotMap<T, A> {
+    pub const fn new_in(alloc: A) -> Self {
+        SlotMap {
+            slots: NomVec::new_in(alloc),
+            free_head: NIL,
+            len: 0,
+        }
+    }
+
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        SlotMap {
+            slots: NomVec::with_capacity_in(capacity, alloc),
+            free_head: NIL,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // the number of slots allocated for, occupied or not
+    pub fn capacity(&self) -> usize {
+        self.slots.capacity()
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.slots.allocator()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let vacant = self.slots.len() - self.len;
+        self.slots.reserve(additional.saturating_sub(vacant))
+    }
+
+    pub fn insert(&mut self, value: T) -> SlotKey {
+        self.insert_with_key(|_| value)
+    }
+
+    // Inserts the value `f` builds from its own key. Panics once
+    // `u32::MAX` slots are in use.
+    pub fn insert_with_key<F: FnOnce(SlotKey) -> T>(
+        &mut self,
+        f: F,
+    ) -> SlotKey {
+        if self.free_head != NIL {
+            let index = self.free_head;
+            let slot = &mut self.slots[index as usize];
+            let key = SlotKey {
+                index,
+                generation: slot.generation,
+            };
+            let value = f(key);
+            match mem::replace(&mut slot.entry, Entry::Occupied(value)) {
+                Entry::Vacant { next_free } => self.free_head = next_free,
+                Entry::Occupied(_) => unreachable!(),
+            }
+            self.len += 1;
+            return key;
+        }
+        let index = match self.slots.len() {
+            len if len < NIL as usize => len as u32,
+            _ => panic!("SlotMap is full"),
+        };
+        let key = SlotKey {
+            index,
+            generation: 0,
+        };
+        self.slots.push(Slot {
+            generation: 0,
+            entry: Entry::Occupied(f(key)),
+        });
+        self.len += 1;
+        key
+    }
+
+    pub fn contains_key(&self, key: SlotKey) -> bool {
+        self.get(key).is_some()
+    }
+
+    // `None` if `key`'s value has been removed
+    pub fn get(&self, key: SlotKey) -> Option<&T> {
+        match self.slots.get(key.index()) {
+            Some(Slot {
+                generation,
+                entry: Entry::Occupied(value),
+            }) if *generation == key.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, key: SlotKey) -> Option<&mut T> {
+        match self.slots.get_mut(key.index()) {
+            Some(Slot {
+                generation,
+                entry: Entry::Occupied(value),
+            }) if *generation == key.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    // Vacates the slot at `index`. A slot whose generation has run out is
+    // retired rather than put back on the free list, so no key is ever
+    // handed out twice.
+    fn vacate(&mut self, index: u32) -> T {
+        let slot = &mut self.slots[index as usize];
+        let next_free = match slot.generation.checked_add(1) {
+            Some(generation) => {
+                slot.generation = generation;
+                mem::replace(&mut self.free_head, index)
+            }
+            None => NIL,
+        };
+        let old = mem::replace(&mut slot.entry, Entry::Vacant { next_free });
+        self.len -= 1;
+        match old {
+            Entry::Occupied(value) => value,
+            Entry::Vacant { .. } => unreachable!(),
+        }
+    }
+
+    pub fn remove(&mut self, key: SlotKey) -> Option<T> {
+        if !self.contains_key(key) {
+            return None;
+        }
+        Some(self.vacate(key.index))
+    }
+
+    pub fn retain<F: FnMut(SlotKey, &mut T) -> bool>(&mut self, mut f: F) {
+        for index in 0..self.slots.len() {
+            let slot = &mut self.slots[index];
+            let key = SlotKey {
+                index: index as u32,
+                generation: slot.generation,
+            };
+            if let Entry::Occupied(value) = &mut slot.entry {
+                if !f(key, value) {
+                    self.vacate(index as u32);
+                }
+            }
+        }
+    }
+
+    // removes every value; the slots stay allocated for reuse
+    pub fn clear(&mut self) {
+        self.retain(|_, _| false)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            slots: self.slots.iter().enumerate(),
+            remaining: self.len,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            slots: self.slots.iter_mut().enumerate(),
+            remaining: self.len,
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = SlotKey> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> + '_ {
+        self.iter().map(|(_, value)| value)
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.iter_mut().map(|(_, value)| value)
+    }
+}
+
+impl<T> Default for SlotMap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Clone for SlotMap<T, A> {
+    fn clone(&self) -> Self {
+        SlotMap {
+            slots: self.slots.clone(),
+            free_head: self.free_head,
+            len: self.len,
+        }
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator> fmt::Debug for SlotMap<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<T, A: Allocator> Index<SlotKey> for SlotMap<T, A> {
+    type Output = T;
+
+    // Panics if `key` is stale.
+    fn index(&self, key: SlotKey) -> &T {
+        self.get(key).expect("invalid SlotMap key")
+    }
+}
+
+impl<T, A: Allocator> IndexMut<SlotKey> for SlotMap<T, A> {
+    fn index_mut(&mut self, key: SlotKey) -> &mut T {
+        self.get_mut(key).expect("invalid SlotMap key")
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a SlotMap<T, A> {
+    type Item = (SlotKey, &'a T);
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a mut SlotMap<T, A> {
+    type Item = (SlotKey, &'a mut T);
+    type IntoIter = IterMut<'a, T>;
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+// the occupied slots with their keys, in slot order
+pub struct Iter<'a, T> {
+    slots: Enumerate<slice::Iter<'a, Slot<T>>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (SlotKey, &'a T);
+    fn next(&mut self) -> Option<(SlotKey, &'a T)> {
+        for (index, slot) in &mut self.slots {
+            if let Entry::Occupied(value) = &slot.entry {
+                self.remaining -= 1;
+                let key = SlotKey {
+                    index: index as u32,
+                    generation: slot.generation,
+                };
+                return Some((key, value));
+            }
+        }
+        None
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
+pub struct IterMut<'a, T> {
+    slots: Enumerate<slice::IterMut<'a, Slot<T>>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = (SlotKey, &'a mut T);
+    fn next(&mut self) -> Option<(SlotKey, &'a mut T)> {
+        for (index, slot) in &mut self.slots {
+            if let Entry::Occupied(value) = &mut slot.entry {
+                self.remaining -= 1;
+                let key = SlotKey {
+                    index: index as u32,
+                    generation: slot.generation,
+                };
+                return Some((key, value));
+            }
+        }
+        None
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+impl<'a, T> FusedIterator for IterMut<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slotmap_stale_keys() {
+        let mut map = SlotMap::new();
+        let a = map.insert("a");
+        let b = map.insert("b");
+        assert_eq!(map[a], "a");
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(map.remove(a), Some("a"));
+        assert_eq!(map.remove(a), None);
+        assert_eq!(map.get(a), None);
+        // the freed slot is reused, under a new generation
+        let c = map.insert("c");
+        assert_eq!(c.index(), a.index());
+        assert_ne!(c, a);
+        assert_eq!(map.get(a), None);
+        assert_eq!(map[c], "c");
+        map[b] = "B";
+        assert_eq!(map.get(b), Some(&"B"));
+
+        assert_eq!(map.len(), 2);
+
+        let mut nodes = SlotMap::new();
+        let key = nodes.insert_with_key(|key| (key, "self"));
+        assert_eq!(nodes[key].0, key);
+    }
+
+    #[test]
+    fn slotmap_iter_and_retain() {
+        let mut map: SlotMap<u32> = SlotMap::new();
+        let keys: Vec<_> = (0..10).map(|i| map.insert(i)).collect();
+        map.retain(|_, v| *v % 3 == 0);
+        assert!(map.values().copied().eq([0, 3, 6, 9]));
+        assert_eq!(map.iter().len(), 4);
+        for (_, v) in &mut map {
+            *v *= 10;
+        }
+        assert_eq!(map[keys[6]], 60);
+        assert!(!map.contains_key(keys[1]));
+
+        let capacity = map.capacity();
+        map.clear();
+        assert!(map.is_empty());
+        for i in 0..10 {
+            map.insert(i);
+        }
+        assert_eq!(map.capacity(), capacity);
+        assert!(keys.iter().all(|&key| map.get(key).is_none()));
+
+        // a slot whose generation runs out is never reused
+        let mut map = SlotMap::new();
+        let key = map.insert(());
+        map.slots[0].generation = u32::MAX;
+        let key = SlotKey {
+            generation: u32::MAX,
+            ..key
+        };
+        map.remove(key);
+        assert_eq!(map.insert(()).index(), 1);
+    }
+}
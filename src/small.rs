@@ -0,0 +1,633 @@
+// A vector that keeps up to `N` elements inline, inside the handle itself,
+// and only moves them to the allocator once it outgrows that. The spilled
+// state is an ordinary `RawVec`, so growth, shrinking and by-value iteration
+// there are exactly `NomVec`'s.
+use crate::{
+    handle_error, AllocationError, Allocator, Doubling, Global, NomVec, RawVec,
+};
+use std::alloc::Layout;
+use std::cmp;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, FusedIterator};
+use std::mem::{self, ManuallyDrop, MaybeUninit};
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+
+enum Data<T, A: Allocator, const N: usize> {
+    // the allocator waits here until the first spill needs it
+    Inline { buf: MaybeUninit<[T; N]>, alloc: A },
+    Heap(RawVec<T, A>),
+}
+
+pub struct SmallNomVec<T, const N: usize, A: Allocator = Global> {
+    data: Data<T, A, N>,
+    len: usize,
+}
+
+impl<T, const N: usize> SmallNomVec<T, N> {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<T, const N: usize, A: Allocator> SmallNomVec<T, N, A> {
+    pub const fn new_in(alloc: A) -> Self {
+        SmallNomVec {
+            data: Data::Inline {
+                buf: MaybeUninit::uninit(),
+                alloc,
+            },
+            len: 0,
+        }
+    }
+
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        let mut vec = Self::new_in(alloc);
+        vec.reserve_exact(capacity);
+        vec
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        match &self.data {
+            Data::Inline { .. } if mem::size_of::<T>() == 0 => usize::MAX,
+            Data::Inline { .. } => N,
+            Data::Heap(buf) => buf.cap,
+        }
+    }
+
+    // whether the elements have moved out to the allocator
+    pub fn spilled(&self) -> bool {
+        matches!(self.data, Data::Heap(_))
+    }
+
+    pub fn allocator(&self) -> &A {
+        match &self.data {
+            Data::Inline { alloc, .. } => alloc,
+            Data::Heap(buf) => &buf.alloc,
+        }
+    }
+
+    pub fn as_ptr(&self) -> *const T {
+        match &self.data {
+            Data::Inline { buf, .. } => buf.as_ptr() as *const T,
+            Data::Heap(buf) => buf.ptr.as_ptr(),
+        }
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        match &mut self.data {
+            Data::Inline { buf, .. } => buf.as_mut_ptr() as *mut T,
+            Data::Heap(buf) => buf.ptr.as_ptr(),
+        }
+    }
+
+    fn needs_to_grow(&self, additional: usize) -> bool {
+        additional > self.capacity() - self.len
+    }
+
+    // Moves the inline elements into a fresh allocation of `cap` elements.
+    // On failure nothing changes.
+    fn spill(&mut self, cap: usize) -> Result<(), AllocationError> {
+        let layout = Layout::array::<T>(cap)
+            .map_err(|_| AllocationError::CapacityOverflow)?;
+        let (buf, alloc) = match &self.data {
+            Data::Inline { buf, alloc } => (buf.as_ptr() as *const T, alloc),
+            Data::Heap(_) => unreachable!(),
+        };
+        let ptr = alloc
+            .allocate(layout)
+            .map_err(|_| AllocationError::AllocFailed { layout })?
+            .cast::<T>();
+        unsafe {
+            ptr::copy_nonoverlapping(buf, ptr.as_ptr(), self.len);
+            // the elements were moved and the inline buffer needs no drop,
+            // so only the allocator has to come along
+            let alloc = ptr::read(alloc);
+            let heap =
+                RawVec::from_raw_parts_in(ptr.as_ptr(), cap, alloc, Doubling);
+            ptr::write(&mut self.data, Data::Heap(heap));
+        }
+        Ok(())
+    }
+
+    fn required(&self, additional: usize) -> Result<usize, AllocationError> {
+        // since the capacity of a ZST vector is usize::MAX, getting here
+        // with one means it's overfull
+        if mem::size_of::<T>() == 0 {
+            return Err(AllocationError::CapacityOverflow);
+        }
+        self.len
+            .checked_add(additional)
+            .ok_or(AllocationError::CapacityOverflow)
+    }
+
+    pub fn try_reserve(
+        &mut self,
+        additional: usize,
+    ) -> Result<(), AllocationError> {
+        if !self.needs_to_grow(additional) {
+            return Ok(());
+        }
+        let len = self.len;
+        match &mut self.data {
+            Data::Heap(buf) => buf.try_reserve(len, additional),
+            Data::Inline { .. } => {
+                let required = self.required(additional)?;
+                self.spill(cmp::max(N.saturating_mul(2), required))
+            }
+        }
+    }
+
+    pub fn try_reserve_exact(
+        &mut self,
+        additional: usize,
+    ) -> Result<(), AllocationError> {
+        if !self.needs_to_grow(additional) {
+            return Ok(());
+        }
+        let len = self.len;
+        match &mut self.data {
+            Data::Heap(buf) => buf.try_reserve_exact(len, additional),
+            Data::Inline { .. } => {
+                let required = self.required(additional)?;
+                self.spill(required)
+            }
+        }
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        if let Err(err) = self.try_reserve(additional) {
+            handle_error(err);
+        }
+    }
+
+    pub fn reserve_exact(&mut self, additional: usize) {
+        if let Err(err) = self.try_reserve_exact(additional) {
+            handle_error(err);
+        }
+    }
+
+    // Moves the elements back inline if they fit again, and otherwise
+    // shrinks the allocation to fit them exactly.
+    pub fn shrink_to_fit(&mut self) {
+        let len = self.len;
+        let heap = match &mut self.data {
+            Data::Inline { .. } => return,
+            Data::Heap(buf) if len > N => return buf.shrink(len),
+            Data::Heap(buf) => buf,
+        };
+        let mut inline = MaybeUninit::<[T; N]>::uninit();
+        unsafe {
+            ptr::copy_nonoverlapping(
+                heap.ptr.as_ptr(),
+                inline.as_mut_ptr() as *mut T,
+                len,
+            );
+            // the elements are gone, so all the RawVec still has to do is
+            // free its buffer
+            heap.shrink(0);
+            let heap = ptr::read(heap);
+            let alloc = ptr::read(&heap.alloc);
+            mem::forget(heap);
+            ptr::write(&mut self.data, Data::Inline { buf: inline, alloc });
+        }
+    }
+
+    pub fn try_push(&mut self, elem: T) -> Result<(), AllocationError> {
+        if self.len == self.capacity() {
+            self.try_reserve(1)?;
+        }
+        unsafe {
+            ptr::write(self.as_mut_ptr().add(self.len), elem);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn push(&mut self, elem: T) {
+        if let Err(err) = self.try_push(elem) {
+            handle_error(err);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            None
+        } else {
+            self.len -= 1;
+            unsafe { Some(ptr::read(self.as_ptr().add(self.len))) }
+        }
+    }
+
+    pub fn insert(&mut self, index: usize, elem: T) {
+        assert!(index <= self.len, "index out of bounds");
+        if self.len == self.capacity() {
+            self.reserve(1);
+        }
+        unsafe {
+            let p = self.as_mut_ptr().add(index);
+            ptr::copy(p, p.add(1), self.len - index);
+            ptr::write(p, elem);
+        }
+        self.len += 1;
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "index out of bounds");
+        self.len -= 1;
+        unsafe {
+            let p = self.as_mut_ptr().add(index);
+            let result = ptr::read(p);
+            ptr::copy(p.add(1), p, self.len - index);
+            result
+        }
+    }
+
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "index out of bounds");
+        let last = self.len - 1;
+        self.swap(index, last);
+        self.pop().unwrap()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        let old_len = self.len;
+        // shorten first so a panicking destructor can't cause a double drop
+        self.len = len;
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                self.as_mut_ptr().add(len),
+                old_len - len,
+            ));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+
+    pub fn extend_from_slice(&mut self, other: &[T])
+    where
+        T: Clone,
+    {
+        self.reserve(other.len());
+        for elem in other {
+            unsafe {
+                ptr::write(self.as_mut_ptr().add(self.len), elem.clone());
+            }
+            self.len += 1;
+        }
+    }
+
+    // Reuses the allocation if the vector has spilled; otherwise the inline
+    // elements are moved into one sized to fit them.
+    pub fn into_nomvec(self) -> NomVec<T, A> {
+        let mut this = ManuallyDrop::new(self);
+        let len = this.len;
+        match unsafe { ptr::read(&this.data) } {
+            Data::Heap(buf) => NomVec { buf, len },
+            Data::Inline { alloc, .. } => {
+                let mut vec = NomVec::with_capacity_in(len, alloc);
+                unsafe {
+                    ptr::copy_nonoverlapping(
+                        this.as_mut_ptr(),
+                        vec.as_mut_ptr(),
+                        len,
+                    );
+                    vec.set_len(len);
+                }
+                vec
+            }
+        }
+    }
+}
+
+impl<T, const N: usize, A: Allocator> Drop for SmallNomVec<T, N, A> {
+    fn drop(&mut self) {
+        // the buffer, if any, is freed by the RawVec's own Drop
+        unsafe { ptr::drop_in_place(&mut self[..]) }
+    }
+}
+
+impl<T, const N: usize, A: Allocator> From<NomVec<T, A>>
+    for SmallNomVec<T, N, A>
+{
+    // keeps the vector's buffer, even if it would fit inline
+    fn from(vec: NomVec<T, A>) -> Self {
+        let vec = ManuallyDrop::new(vec);
+        SmallNomVec {
+            data: Data::Heap(unsafe { ptr::read(&vec.buf) }),
+            len: vec.len,
+        }
+    }
+}
+
+impl<T, const N: usize, A: Allocator> From<SmallNomVec<T, N, A>>
+    for NomVec<T, A>
+{
+    fn from(vec: SmallNomVec<T, N, A>) -> Self {
+        vec.into_nomvec()
+    }
+}
+
+impl<T, const N: usize> Default for SmallNomVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize, A: Allocator> Deref for SmallNomVec<T, N, A> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        unsafe { ::std::slice::from_raw_parts(self.as_ptr(), self.len) }
+    }
+}
+
+impl<T, const N: usize, A: Allocator> DerefMut for SmallNomVec<T, N, A> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { ::std::slice::from_raw_parts_mut(self.as_mut_ptr(), self.len) }
+    }
+}
+
+impl<T: Clone, const N: usize, A: Allocator + Clone> Clone
+    for SmallNomVec<T, N, A>
+{
+    fn clone(&self) -> Self {
+        let mut vec =
+            SmallNomVec::with_capacity_in(self.len, self.allocator().clone());
+        vec.extend_from_slice(self);
+        vec
+    }
+}
+
+impl<T: fmt::Debug, const N: usize, A: Allocator> fmt::Debug
+    for SmallNomVec<T, N, A>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T, U, A, B, const N: usize, const M: usize> PartialEq<SmallNomVec<U, M, B>>
+    for SmallNomVec<T, N, A>
+where
+    T: PartialEq<U>,
+    A: Allocator,
+    B: Allocator,
+{
+    fn eq(&self, other: &SmallNomVec<U, M, B>) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T: PartialEq<U>, U, const N: usize, A: Allocator> PartialEq<[U]>
+    for SmallNomVec<T, N, A>
+{
+    fn eq(&self, other: &[U]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T, U, const N: usize, A, const M: usize> PartialEq<[U; M]>
+    for SmallNomVec<T, N, A>
+where
+    T: PartialEq<U>,
+    A: Allocator,
+{
+    fn eq(&self, other: &[U; M]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T: Eq, const N: usize, A: Allocator> Eq for SmallNomVec<T, N, A> {}
+
+impl<T: Hash, const N: usize, A: Allocator> Hash for SmallNomVec<T, N, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Hash::hash(&**self, state)
+    }
+}
+
+impl<T, const N: usize, A: Allocator> Extend<T> for SmallNomVec<T, N, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T, const N: usize> FromIterator<T> for SmallNomVec<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vec = SmallNomVec::new();
+        vec.extend(iter);
+        vec
+    }
+}
+
+impl<T, const N: usize, A: Allocator> IntoIterator for SmallNomVec<T, N, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N, A>;
+
+    fn into_iter(self) -> IntoIter<T, N, A> {
+        if self.spilled() {
+            return IntoIter(IntoIterInner::Heap(
+                self.into_nomvec().into_iter(),
+            ));
+        }
+        let this = ManuallyDrop::new(self);
+        match unsafe { ptr::read(&this.data) } {
+            Data::Inline { buf, alloc } => IntoIter(IntoIterInner::Inline {
+                buf,
+                start: 0,
+                end: this.len,
+                _alloc: alloc,
+            }),
+            Data::Heap(_) => unreachable!(),
+        }
+    }
+}
+
+// The inline case can't use RawValIter: its pointers would dangle as soon as
+// the iterator moved, so it counts indices into the buffer instead.
+pub struct IntoIter<T, const N: usize, A: Allocator = Global>(
+    IntoIterInner<T, N, A>,
+);
+
+enum IntoIterInner<T, const N: usize, A: Allocator> {
+    Inline {
+        buf: MaybeUninit<[T; N]>,
+        start: usize,
+        end: usize,
+        _alloc: A,
+    },
+    Heap(crate::IntoIter<T, A>),
+}
+
+impl<T, const N: usize, A: Allocator> IntoIter<T, N, A> {
+    pub fn as_slice(&self) -> &[T] {
+        match &self.0 {
+            IntoIterInner::Inline {
+                buf, start, end, ..
+            } => unsafe {
+                ::std::slice::from_raw_parts(
+                    (buf.as_ptr() as *const T).add(*start),
+                    end - start,
+                )
+            },
+            IntoIterInner::Heap(iter) => iter.as_slice(),
+        }
+    }
+}
+
+impl<T, const N: usize, A: Allocator> Iterator for IntoIter<T, N, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        match &mut self.0 {
+            IntoIterInner::Inline {
+                buf, start, end, ..
+            } => {
+                if start == end {
+                    return None;
+                }
+                *start += 1;
+                unsafe {
+                    Some(ptr::read((buf.as_ptr() as *const T).add(*start - 1)))
+                }
+            }
+            IntoIterInner::Heap(iter) => iter.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.as_slice().len();
+        (len, Some(len))
+    }
+}
+
+impl<T, const N: usize, A: Allocator> DoubleEndedIterator
+    for IntoIter<T, N, A>
+{
+    fn next_back(&mut self) -> Option<T> {
+        match &mut self.0 {
+            IntoIterInner::Inline {
+                buf, start, end, ..
+            } => {
+                if start == end {
+                    return None;
+                }
+                *end -= 1;
+                unsafe { Some(ptr::read((buf.as_ptr() as *const T).add(*end))) }
+            }
+            IntoIterInner::Heap(iter) => iter.next_back(),
+        }
+    }
+}
+
+impl<T, const N: usize, A: Allocator> ExactSizeIterator for IntoIter<T, N, A> {}
+
+impl<T, const N: usize, A: Allocator> FusedIterator for IntoIter<T, N, A> {}
+
+impl<T, const N: usize, A: Allocator> Drop for IntoIter<T, N, A> {
+    fn drop(&mut self) {
+        if let IntoIterInner::Inline {
+            buf, start, end, ..
+        } = &mut self.0
+        {
+            unsafe {
+                ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                    (buf.as_mut_ptr() as *mut T).add(*start),
+                    *end - *start,
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DropCounter;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn small_stays_inline() {
+        let mut sv: SmallNomVec<u32, 4> = SmallNomVec::new();
+        assert_eq!(sv.capacity(), 4);
+        sv.extend(0..4);
+        assert!(!sv.spilled());
+        let addr = &sv as *const _ as usize;
+        let ptr = sv.as_ptr() as usize;
+        assert!(ptr >= addr && ptr < addr + mem::size_of_val(&sv));
+
+        sv.insert(0, 9);
+        assert!(sv.spilled());
+        assert_eq!(sv, [9, 0, 1, 2, 3]);
+        assert!(sv.capacity() >= 8);
+        assert_eq!(sv.remove(0), 9);
+        assert_eq!(sv.swap_remove(0), 0);
+        assert_eq!(sv, [3, 1, 2]);
+
+        sv.shrink_to_fit();
+        assert!(!sv.spilled());
+        assert_eq!(sv, [3, 1, 2]);
+        assert_eq!(sv.clone(), sv);
+    }
+
+    #[test]
+    fn small_nomvec_conversions() {
+        let inline: SmallNomVec<String, 2> =
+            ["a", "b"].iter().map(|s| s.to_string()).collect();
+        let vec = inline.into_nomvec();
+        assert_eq!(vec, ["a", "b"]);
+
+        let ptr = vec.as_ptr();
+        let back = SmallNomVec::<String, 8>::from(vec);
+        assert!(back.spilled());
+        assert_eq!(back.as_ptr(), ptr);
+        assert_eq!(NomVec::from(back).as_ptr(), ptr);
+
+        let mut zsts: SmallNomVec<(), 0> = SmallNomVec::new();
+        zsts.extend((0..100).map(|_| ()));
+        assert!(!zsts.spilled());
+        assert_eq!(zsts.len(), 100);
+    }
+
+    #[test]
+    fn small_into_iter_drops_rest() {
+        let drops = Rc::new(Cell::new(0));
+        for &count in &[3, 10] {
+            drops.set(0);
+            let sv: SmallNomVec<DropCounter, 4> =
+                (0..count).map(|_| DropCounter::new(&drops)).collect();
+            let mut iter = sv.into_iter();
+            drop(iter.next());
+            drop(iter.next_back());
+            assert_eq!(iter.len(), count - 2);
+            assert_eq!(drops.get(), 2);
+            drop(iter);
+            assert_eq!(drops.get(), count);
+        }
+
+        let sv: SmallNomVec<i32, 4> = (0..3).collect();
+        assert_eq!(sv.into_iter().rev().collect::<Vec<_>>(), [2, 1, 0]);
+    }
+}
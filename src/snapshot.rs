@@ -0,0 +1,228 @@
+// Read-copy-update for read-mostly data like config or routing tables.
+// `ArcNomVec` holds the current version of a NomVec behind a shared
+// handle: a reader's `load` clones that handle in O(1) and gets a
+// `NomVecSnapshot` that never changes under it, however long it's kept.
+// A writer builds the next version off to the side and `publish`es it,
+// which swaps the handle in one step; readers see either the old version
+// or the new one, never a half-built one, and an old version is freed once
+// the last snapshot of it is dropped.
+//
+// The swap happens under a lock, but it's held only for the length of a
+// refcount bump or a pointer swap, never while anything is built or
+// dropped. Writers take a second lock, so `update`s don't lose each
+// other's changes.
+use crate::shared::Shared;
+use crate::{Allocator, Global, NomVec};
+use std::fmt;
+use std::mem;
+use std::ops::Deref;
+use std::sync::{Mutex, PoisonError, RwLock};
+
+pub struct NomVecSnapshot<T, A: Allocator + Clone = Global> {
+    vec: Shared<NomVec<T, A>, A>,
+}
+
+impl<T, A: Allocator + Clone> NomVecSnapshot<T, A> {
+    pub fn as_slice(&self) -> &[T] {
+        &self.vec
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.vec.allocator()
+    }
+
+    // whether both are snapshots of the same published version
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.vec.ptr_eq(&other.vec)
+    }
+
+    // The version as a NomVec of its own, without copying if this was its
+    // last snapshot.
+    pub fn into_vec(self) -> NomVec<T, A>
+    where
+        T: Clone,
+    {
+        Shared::try_unwrap(self.vec).unwrap_or_else(|vec| (*vec).clone())
+    }
+}
+
+// O(1): the clone is of the same version
+impl<T, A: Allocator + Clone> Clone for NomVecSnapshot<T, A> {
+    fn clone(&self) -> Self {
+        NomVecSnapshot {
+            vec: self.vec.clone(),
+        }
+    }
+}
+
+impl<T, A: Allocator + Clone> Deref for NomVecSnapshot<T, A> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator + Clone> fmt::Debug for NomVecSnapshot<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_slice(), f)
+    }
+}
+
+impl<T: PartialEq, A: Allocator + Clone> PartialEq for NomVecSnapshot<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Eq, A: Allocator + Clone> Eq for NomVecSnapshot<T, A> {}
+
+pub struct ArcNomVec<T, A: Allocator + Clone = Global> {
+    current: RwLock<Shared<NomVec<T, A>, A>>,
+    // held by whoever is publishing a version
+    writer: Mutex<()>,
+}
+
+impl<T> ArcNomVec<T> {
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<T, A: Allocator + Clone> ArcNomVec<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        Self::from_vec(NomVec::new_in(alloc))
+    }
+
+    pub fn from_vec(vec: NomVec<T, A>) -> Self {
+        let alloc = vec.allocator().clone();
+        ArcNomVec {
+            current: RwLock::new(Shared::new_in(vec, alloc)),
+            writer: Mutex::new(()),
+        }
+    }
+
+    // The current version.
+    pub fn load(&self) -> NomVecSnapshot<T, A> {
+        // the locks guard nothing a panic could leave half-done, so
+        // poisoning is ignored
+        let current =
+            self.current.read().unwrap_or_else(PoisonError::into_inner);
+        NomVecSnapshot {
+            vec: current.clone(),
+        }
+    }
+
+    // Makes `vec` the current version, returning the one it replaces.
+    pub fn publish(&self, vec: NomVec<T, A>) -> NomVecSnapshot<T, A> {
+        let _writer =
+            self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        self.swap(vec)
+    }
+
+    fn swap(&self, vec: NomVec<T, A>) -> NomVecSnapshot<T, A> {
+        // allocated before the lock is taken
+        let alloc = vec.allocator().clone();
+        let next = Shared::new_in(vec, alloc);
+        let mut current =
+            self.current.write().unwrap_or_else(PoisonError::into_inner);
+        NomVecSnapshot {
+            vec: mem::replace(&mut *current, next),
+        }
+    }
+
+    // Publishes a copy of the current version with `f` applied to it.
+    // Readers go on seeing the old version until `f` has returned, and
+    // other writers wait for this one.
+    pub fn update<R>(&self, f: impl FnOnce(&mut NomVec<T, A>) -> R) -> R
+    where
+        T: Clone,
+    {
+        let _writer =
+            self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut next = (*self.load().vec).clone();
+        let result = f(&mut next);
+        self.swap(next);
+        result
+    }
+
+    pub fn into_snapshot(self) -> NomVecSnapshot<T, A> {
+        let current = self
+            .current
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        NomVecSnapshot { vec: current }
+    }
+}
+
+impl<T> Default for ArcNomVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator + Clone> fmt::Debug for ArcNomVec<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.load(), f)
+    }
+}
+
+impl<T, A: Allocator + Clone> From<NomVec<T, A>> for ArcNomVec<T, A> {
+    fn from(vec: NomVec<T, A>) -> Self {
+        Self::from_vec(vec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn snapshot_outlives_publish() {
+        let routes = ArcNomVec::from(NomVec::from(["a", "b"]));
+        let before = routes.load();
+        assert!(before.ptr_eq(&routes.load()));
+        let old = routes.publish(NomVec::from(["c"]));
+        assert!(old.ptr_eq(&before));
+        assert_eq!(*before, ["a", "b"]);
+        assert_eq!(*routes.load(), ["c"]);
+
+        let len = routes.update(|next| {
+            next.push("d");
+            next.len()
+        });
+        assert_eq!(len, 2);
+        assert_eq!(format!("{:?}", routes), r#"["c", "d"]"#);
+        drop(old);
+        assert_eq!(before.into_vec(), ["a", "b"]);
+        assert_eq!(routes.into_snapshot().into_vec(), ["c", "d"]);
+    }
+
+    #[test]
+    fn snapshot_readers_see_whole_versions() {
+        let table = ArcNomVec::from(NomVec::from([0; 64]));
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| loop {
+                    let snap = table.load();
+                    // every version is uniform, so a torn one would show
+                    assert!(snap.iter().all(|&v| v == snap[0]));
+                    assert_eq!(snap.len(), 64);
+                    if snap[0] == 100 {
+                        break;
+                    }
+                });
+            }
+            s.spawn(|| {
+                for _ in 0..100 {
+                    table.update(|next| {
+                        for v in next.iter_mut() {
+                            *v += 1;
+                        }
+                    });
+                }
+            });
+        });
+        assert_eq!(table.load()[63], 100);
+    }
+}
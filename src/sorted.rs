@@ -0,0 +1,518 @@
+// A NomVec that is always sorted. Nothing hands out mutable access to the
+// elements in place, so the order can't be broken from outside; lookups are
+// binary searches and equal elements are kept, in insertion order. Bulk
+// additions are appended and the whole vector re-sorted: the stable sort
+// spots the two sorted runs and merges them in linear time.
+use crate::vecmap::range_positions;
+use crate::{Allocator, Global, NomVec};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::ops::{Bound, Deref, RangeBounds};
+use std::slice;
+
+// Which elements a linear merge of two sorted slices keeps: those only in
+// the left one, those in both (once), and those only in the right one.
+// Equal elements pair up one to one, so with duplicates this is multiset
+// algebra: a union keeps the larger count, an intersection the smaller.
+#[derive(Copy, Clone)]
+pub(crate) struct SetOp {
+    left: bool,
+    both: bool,
+    right: bool,
+}
+
+impl SetOp {
+    pub(crate) const UNION: SetOp = SetOp {
+        left: true,
+        both: true,
+        right: true,
+    };
+    pub(crate) const INTERSECTION: SetOp = SetOp {
+        left: false,
+        both: true,
+        right: false,
+    };
+    pub(crate) const DIFFERENCE: SetOp = SetOp {
+        left: true,
+        both: false,
+        right: false,
+    };
+    pub(crate) const SYMMETRIC_DIFFERENCE: SetOp = SetOp {
+        left: true,
+        both: false,
+        right: true,
+    };
+}
+
+pub(crate) fn merge_sorted<T: Ord + Clone, A: Allocator>(
+    a: &[T],
+    b: &[T],
+    op: SetOp,
+    alloc: A,
+) -> NomVec<T, A> {
+    let mut out = NomVec::new_in(alloc);
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            Ordering::Less => {
+                if op.left {
+                    out.push(a[i].clone());
+                }
+                i += 1;
+            }
+            Ordering::Greater => {
+                if op.right {
+                    out.push(b[j].clone());
+                }
+                j += 1;
+            }
+            Ordering::Equal => {
+                if op.both {
+                    out.push(a[i].clone());
+                }
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    if op.left {
+        out.extend_from_slice(&a[i..]);
+    }
+    if op.right {
+        out.extend_from_slice(&b[j..]);
+    }
+    out
+}
+
+pub struct SortedNomVec<T, A: Allocator = Global> {
+    vec: NomVec<T, A>,
+}
+
+impl<T: Ord> SortedNomVec<T> {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<T: Ord, A: Allocator> SortedNomVec<T, A> {
+    pub const fn new_in(alloc: A) -> Self {
+        SortedNomVec {
+            vec: NomVec::new_in(alloc),
+        }
+    }
+
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        SortedNomVec {
+            vec: NomVec::with_capacity_in(capacity, alloc),
+        }
+    }
+
+    pub fn from_vec(mut vec: NomVec<T, A>) -> Self {
+        vec.sort();
+        SortedNomVec { vec }
+    }
+
+    // Wraps `vec` without sorting it; returns it back if it isn't sorted.
+    pub fn try_from_sorted_vec(
+        vec: NomVec<T, A>,
+    ) -> Result<Self, NomVec<T, A>> {
+        if vec.windows(2).all(|w| w[0] <= w[1]) {
+            Ok(SortedNomVec { vec })
+        } else {
+            Err(vec)
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.vec.capacity()
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.vec.allocator()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.vec.reserve(additional)
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.vec.shrink_to_fit()
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.vec
+    }
+
+    pub fn into_vec(self) -> NomVec<T, A> {
+        self.vec
+    }
+
+    // Inserts `elem` after any elements equal to it and returns its index.
+    pub fn insert(&mut self, elem: T) -> usize {
+        let index = self.vec.partition_point(|e| *e <= elem);
+        self.vec.insert(index, elem);
+        index
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        self.vec.remove(index)
+    }
+
+    // removes the first element equal to `value`
+    pub fn remove_item<Q>(&mut self, value: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let index = self.find(value)?;
+        Some(self.vec.remove(index))
+    }
+
+    // the smallest element
+    pub fn pop_first(&mut self) -> Option<T> {
+        if self.vec.is_empty() {
+            None
+        } else {
+            Some(self.vec.remove(0))
+        }
+    }
+
+    // the largest element
+    pub fn pop(&mut self) -> Option<T> {
+        self.vec.pop()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.vec.truncate(len)
+    }
+
+    pub fn clear(&mut self) {
+        self.vec.clear()
+    }
+
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, f: F) {
+        self.vec.retain(f)
+    }
+
+    pub fn dedup(&mut self) {
+        self.vec.dedup()
+    }
+
+    // the index of the first element equal to `value`
+    pub fn find<Q>(&self, value: &Q) -> Option<usize>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let index = self.vec.partition_point(|e| e.borrow() < value);
+        match self.vec.get(index) {
+            Some(e) if e.borrow() == value => Some(index),
+            _ => None,
+        }
+    }
+
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(value).is_some()
+    }
+
+    // the number of elements equal to `value`
+    pub fn count<Q>(&self, value: &Q) -> usize
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let bounds = (Bound::Included(value), Bound::Included(value));
+        self.range(bounds).len()
+    }
+
+    // the elements that fall in `range`, in order
+    pub fn range<Q, R>(&self, range: R) -> &[T]
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = range_positions(&self.vec, |e| e.borrow(), range);
+        &self.vec[start..end]
+    }
+
+    pub fn extend_from_slice(&mut self, other: &[T])
+    where
+        T: Clone,
+    {
+        self.vec.extend_from_slice(other);
+        self.vec.sort();
+    }
+
+    // Moves all of `other`'s elements in, merging the two sorted runs.
+    pub fn merge<B: Allocator>(&mut self, other: SortedNomVec<T, B>) {
+        self.vec.extend(other.vec);
+        self.vec.sort();
+    }
+}
+
+// Set algebra, in linear time. The `_in` forms build the result in
+// `alloc`; the others in a clone of `self`'s allocator.
+impl<T: Ord + Clone, A: Allocator> SortedNomVec<T, A> {
+    fn set_op_in<B: Allocator, C: Allocator>(
+        &self,
+        other: &SortedNomVec<T, B>,
+        op: SetOp,
+        alloc: C,
+    ) -> SortedNomVec<T, C> {
+        SortedNomVec {
+            vec: merge_sorted(&self.vec, &other.vec, op, alloc),
+        }
+    }
+
+    pub fn union_in<B: Allocator, C: Allocator>(
+        &self,
+        other: &SortedNomVec<T, B>,
+        alloc: C,
+    ) -> SortedNomVec<T, C> {
+        self.set_op_in(other, SetOp::UNION, alloc)
+    }
+
+    pub fn intersection_in<B: Allocator, C: Allocator>(
+        &self,
+        other: &SortedNomVec<T, B>,
+        alloc: C,
+    ) -> SortedNomVec<T, C> {
+        self.set_op_in(other, SetOp::INTERSECTION, alloc)
+    }
+
+    pub fn difference_in<B: Allocator, C: Allocator>(
+        &self,
+        other: &SortedNomVec<T, B>,
+        alloc: C,
+    ) -> SortedNomVec<T, C> {
+        self.set_op_in(other, SetOp::DIFFERENCE, alloc)
+    }
+
+    pub fn symmetric_difference_in<B: Allocator, C: Allocator>(
+        &self,
+        other: &SortedNomVec<T, B>,
+        alloc: C,
+    ) -> SortedNomVec<T, C> {
+        self.set_op_in(other, SetOp::SYMMETRIC_DIFFERENCE, alloc)
+    }
+}
+
+impl<T: Ord + Clone, A: Allocator + Clone> SortedNomVec<T, A> {
+    pub fn union<B: Allocator>(&self, other: &SortedNomVec<T, B>) -> Self {
+        self.union_in(other, self.allocator().clone())
+    }
+
+    pub fn intersection<B: Allocator>(
+        &self,
+        other: &SortedNomVec<T, B>,
+    ) -> Self {
+        self.intersection_in(other, self.allocator().clone())
+    }
+
+    pub fn difference<B: Allocator>(&self, other: &SortedNomVec<T, B>) -> Self {
+        self.difference_in(other, self.allocator().clone())
+    }
+
+    pub fn symmetric_difference<B: Allocator>(
+        &self,
+        other: &SortedNomVec<T, B>,
+    ) -> Self {
+        self.symmetric_difference_in(other, self.allocator().clone())
+    }
+}
+
+impl<T, A: Allocator> Deref for SortedNomVec<T, A> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        &self.vec
+    }
+}
+
+impl<T, A: Allocator> AsRef<[T]> for SortedNomVec<T, A> {
+    fn as_ref(&self) -> &[T] {
+        &self.vec
+    }
+}
+
+impl<T: Ord> Default for SortedNomVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Clone for SortedNomVec<T, A> {
+    fn clone(&self) -> Self {
+        SortedNomVec {
+            vec: self.vec.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator> fmt::Debug for SortedNomVec<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.vec, f)
+    }
+}
+
+impl<T: PartialEq, A: Allocator, B: Allocator> PartialEq<SortedNomVec<T, B>>
+    for SortedNomVec<T, A>
+{
+    fn eq(&self, other: &SortedNomVec<T, B>) -> bool {
+        self.vec[..] == other.vec[..]
+    }
+}
+
+impl<T: Eq, A: Allocator> Eq for SortedNomVec<T, A> {}
+
+impl<T: PartialOrd, A: Allocator> PartialOrd for SortedNomVec<T, A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.vec[..].partial_cmp(&other.vec[..])
+    }
+}
+
+impl<T: Ord, A: Allocator> Ord for SortedNomVec<T, A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.vec[..].cmp(&other.vec[..])
+    }
+}
+
+impl<T: Hash, A: Allocator> Hash for SortedNomVec<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.vec[..].hash(state)
+    }
+}
+
+impl<T: Ord, A: Allocator> From<NomVec<T, A>> for SortedNomVec<T, A> {
+    fn from(vec: NomVec<T, A>) -> Self {
+        SortedNomVec::from_vec(vec)
+    }
+}
+
+impl<T, A: Allocator> From<SortedNomVec<T, A>> for NomVec<T, A> {
+    fn from(sorted: SortedNomVec<T, A>) -> Self {
+        sorted.vec
+    }
+}
+
+impl<T: Ord, const N: usize> From<[T; N]> for SortedNomVec<T> {
+    fn from(arr: [T; N]) -> Self {
+        SortedNomVec::from_vec(NomVec::from(arr))
+    }
+}
+
+impl<T: Ord> FromIterator<T> for SortedNomVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        SortedNomVec::from_vec(iter.into_iter().collect())
+    }
+}
+
+impl<T: Ord, A: Allocator> Extend<T> for SortedNomVec<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.vec.extend(iter);
+        self.vec.sort();
+    }
+}
+
+impl<T, A: Allocator> IntoIterator for SortedNomVec<T, A> {
+    type Item = T;
+    type IntoIter = crate::IntoIter<T, A>;
+    fn into_iter(self) -> crate::IntoIter<T, A> {
+        self.vec.into_iter()
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a SortedNomVec<T, A> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.vec.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorted_insert_and_lookup() {
+        let mut sv = SortedNomVec::new();
+        for &x in &[5, 1, 4, 1, 9] {
+            sv.insert(x);
+        }
+        assert_eq!(sv.as_slice(), [1, 1, 4, 5, 9]);
+        assert_eq!(sv.insert(4), 3);
+        assert_eq!(sv.find(&4), Some(2));
+        assert_eq!(sv.find(&3), None);
+        assert_eq!(sv.count(&4), 2);
+        assert_eq!(sv.count(&7), 0);
+        assert!(sv.contains(&9));
+        assert_eq!(sv.range(2..6), [4, 4, 5]);
+        assert_eq!(sv.range(..=1), [1, 1]);
+        assert_eq!(sv.remove_item(&1), Some(1));
+        assert_eq!(sv.remove_item(&1), Some(1));
+        assert_eq!(sv.remove_item(&1), None);
+        assert_eq!(sv.pop(), Some(9));
+        assert_eq!(sv.pop_first(), Some(4));
+        // read-only slice methods come through Deref
+        assert_eq!(sv.first(), Some(&4));
+        assert_eq!(sv.len(), 2);
+
+        let words: SortedNomVec<String> =
+            vec!["pear".to_string(), "fig".to_string()]
+                .into_iter()
+                .collect();
+        assert!(words.contains("fig"));
+        assert_eq!(words.range("g".to_string()..), ["pear"]);
+    }
+
+    #[test]
+    fn sorted_bulk_additions() {
+        let mut sv = SortedNomVec::from([9, 3, 6]);
+        sv.extend(vec![8, 1, 3]);
+        assert_eq!(sv.as_slice(), [1, 3, 3, 6, 8, 9]);
+        sv.extend_from_slice(&[0, 10]);
+        sv.merge(SortedNomVec::from([2, 7]));
+        assert!(sv.iter().copied().eq([0, 1, 2, 3, 3, 6, 7, 8, 9, 10]));
+        sv.dedup();
+        assert_eq!(sv.len(), 9);
+
+        assert!(
+            SortedNomVec::try_from_sorted_vec(NomVec::from([1, 2, 2])).is_ok()
+        );
+        let unsorted = NomVec::from([2, 1]);
+        assert_eq!(
+            SortedNomVec::try_from_sorted_vec(unsorted).unwrap_err(),
+            [2, 1]
+        );
+    }
+
+    #[test]
+    fn sorted_set_algebra() {
+        let a = SortedNomVec::from([1, 2, 2, 3, 5, 8]);
+        let b = SortedNomVec::from([2, 3, 3, 4, 8]);
+        assert_eq!(a.union(&b).as_slice(), [1, 2, 2, 3, 3, 4, 5, 8]);
+        assert_eq!(a.intersection(&b).as_slice(), [2, 3, 8]);
+        assert_eq!(a.difference(&b).as_slice(), [1, 2, 5]);
+        assert_eq!(b.difference(&a).as_slice(), [3, 4]);
+        assert_eq!(a.symmetric_difference(&b).as_slice(), [1, 2, 3, 4, 5]);
+
+        let empty = SortedNomVec::new();
+        assert_eq!(a.union(&empty), a);
+        assert!(a.intersection(&empty).is_empty());
+        assert_eq!(empty.difference(&a).len(), 0);
+        let postings: SortedNomVec<u32, Global> = a.intersection_in(&b, Global);
+        assert_eq!(postings.len(), 3);
+    }
+}
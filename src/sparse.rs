@@ -0,0 +1,273 @@
+// A sparse set keyed by integers. The values are packed into a dense
+// NomVec, with their keys in a second one beside it, and a sparse NomVec
+// indexed by key points at each one's dense position. Insert, remove and
+// lookup are O(1) and iteration walks the dense values; the price is a
+// sparse array as long as the largest key ever inserted.
+use crate::{Allocator, Global, NomVec};
+use std::fmt;
+use std::iter::{Copied, FromIterator, Zip};
+use std::mem;
+use std::ops::{Index, IndexMut};
+use std::slice;
+
+const EMPTY: usize = usize::MAX;
+
+pub struct SparseVec<V, A: Allocator + Clone = Global> {
+    // dense position of each key's value, or EMPTY
+    sparse: NomVec<usize, A>,
+    keys: NomVec<usize, A>,
+    values: NomVec<V, A>,
+}
+
+impl<V> SparseVec<V> {
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<V, A: Allocator + Clone> SparseVec<V, A> {
+    pub fn new_in(alloc: A) -> Self {
+        SparseVec {
+            sparse: NomVec::new_in(alloc.clone()),
+            keys: NomVec::new_in(alloc.clone()),
+            values: NomVec::new_in(alloc),
+        }
+    }
+
+    // room for `capacity` values without reallocating the dense side
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        SparseVec {
+            sparse: NomVec::new_in(alloc.clone()),
+            keys: NomVec::with_capacity_in(capacity, alloc.clone()),
+            values: NomVec::with_capacity_in(capacity, alloc),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.values.allocator()
+    }
+
+    fn position(&self, key: usize) -> Option<usize> {
+        match self.sparse.get(key) {
+            Some(&pos) if pos != EMPTY => Some(pos),
+            _ => None,
+        }
+    }
+
+    pub fn contains_key(&self, key: usize) -> bool {
+        self.position(key).is_some()
+    }
+
+    pub fn get(&self, key: usize) -> Option<&V> {
+        let pos = self.position(key)?;
+        Some(&self.values[pos])
+    }
+
+    pub fn get_mut(&mut self, key: usize) -> Option<&mut V> {
+        let pos = self.position(key)?;
+        Some(&mut self.values[pos])
+    }
+
+    // Returns the old value if `key` was already present. Panics if `key` is
+    // `usize::MAX`.
+    pub fn insert(&mut self, key: usize, value: V) -> Option<V> {
+        if let Some(pos) = self.position(key) {
+            return Some(mem::replace(&mut self.values[pos], value));
+        }
+        assert!(key != EMPTY, "key out of range");
+        if key >= self.sparse.len() {
+            self.sparse.resize(key + 1, EMPTY);
+        }
+        self.sparse[key] = self.values.len();
+        self.keys.push(key);
+        self.values.push(value);
+        None
+    }
+
+    // Removes `key`'s value, moving the last dense value into its place.
+    pub fn remove(&mut self, key: usize) -> Option<V> {
+        let pos = self.position(key)?;
+        self.sparse[key] = EMPTY;
+        self.keys.swap_remove(pos);
+        if let Some(&moved) = self.keys.get(pos) {
+            self.sparse[moved] = pos;
+        }
+        Some(self.values.swap_remove(pos))
+    }
+
+    pub fn clear(&mut self) {
+        self.sparse.clear();
+        self.keys.clear();
+        self.values.clear();
+    }
+
+    pub fn retain<F: FnMut(usize, &mut V) -> bool>(&mut self, mut f: F) {
+        let mut pos = 0;
+        while pos < self.len() {
+            let key = self.keys[pos];
+            if f(key, &mut self.values[pos]) {
+                pos += 1;
+            } else {
+                // the last value moves into `pos`, so look at it next
+                self.remove(key);
+            }
+        }
+    }
+
+    // the keys, in dense order
+    pub fn keys(&self) -> &[usize] {
+        &self.keys
+    }
+
+    // the values, packed together in the same order as `keys`
+    pub fn values(&self) -> &[V] {
+        &self.values
+    }
+
+    pub fn values_mut(&mut self) -> &mut [V] {
+        &mut self.values
+    }
+
+    pub fn iter(
+        &self,
+    ) -> Zip<Copied<slice::Iter<'_, usize>>, slice::Iter<'_, V>> {
+        self.keys.iter().copied().zip(self.values.iter())
+    }
+
+    pub fn iter_mut(
+        &mut self,
+    ) -> Zip<Copied<slice::Iter<'_, usize>>, slice::IterMut<'_, V>> {
+        self.keys.iter().copied().zip(self.values.iter_mut())
+    }
+}
+
+impl<V> Default for SparseVec<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: Clone, A: Allocator + Clone> Clone for SparseVec<V, A> {
+    fn clone(&self) -> Self {
+        SparseVec {
+            sparse: self.sparse.clone(),
+            keys: self.keys.clone(),
+            values: self.values.clone(),
+        }
+    }
+}
+
+impl<V: fmt::Debug, A: Allocator + Clone> fmt::Debug for SparseVec<V, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<V, A: Allocator + Clone> Index<usize> for SparseVec<V, A> {
+    type Output = V;
+
+    // Panics if `key` isn't present.
+    fn index(&self, key: usize) -> &V {
+        self.get(key).expect("key not found")
+    }
+}
+
+impl<V, A: Allocator + Clone> IndexMut<usize> for SparseVec<V, A> {
+    fn index_mut(&mut self, key: usize) -> &mut V {
+        self.get_mut(key).expect("key not found")
+    }
+}
+
+impl<V, A: Allocator + Clone> Extend<(usize, V)> for SparseVec<V, A> {
+    fn extend<I: IntoIterator<Item = (usize, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<V> FromIterator<(usize, V)> for SparseVec<V> {
+    fn from_iter<I: IntoIterator<Item = (usize, V)>>(iter: I) -> Self {
+        let mut sv = SparseVec::new();
+        sv.extend(iter);
+        sv
+    }
+}
+
+impl<'a, V, A: Allocator + Clone> IntoIterator for &'a SparseVec<V, A> {
+    type Item = (usize, &'a V);
+    type IntoIter = Zip<Copied<slice::Iter<'a, usize>>, slice::Iter<'a, V>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, V, A: Allocator + Clone> IntoIterator for &'a mut SparseVec<V, A> {
+    type Item = (usize, &'a mut V);
+    type IntoIter = Zip<Copied<slice::Iter<'a, usize>>, slice::IterMut<'a, V>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparse_insert_remove() {
+        let mut sv = SparseVec::new();
+        assert_eq!(sv.insert(1000, "a"), None);
+        assert_eq!(sv.insert(3, "b"), None);
+        assert_eq!(sv.insert(42, "c"), None);
+        assert_eq!(sv.insert(3, "B"), Some("b"));
+        assert_eq!(sv.len(), 3);
+        assert_eq!(sv[3], "B");
+        assert_eq!(sv.get(4), None);
+        assert_eq!(sv.get(5000), None);
+        assert_eq!(sv.values(), ["a", "B", "c"]);
+
+        // the last value fills the gap
+        assert_eq!(sv.remove(1000), Some("a"));
+        assert_eq!(sv.remove(1000), None);
+        assert_eq!(sv.keys(), [42, 3]);
+        assert_eq!(sv[42], "c");
+        assert!(!sv.contains_key(1000));
+        assert_eq!(sv.remove(3), Some("B"));
+        assert_eq!(sv.remove(42), Some("c"));
+        assert!(sv.is_empty());
+    }
+
+    #[test]
+    fn sparse_dense_iteration() {
+        let mut sv: SparseVec<u32> =
+            (0..100).map(|k| (k * 7, k as u32)).collect();
+        sv.retain(|key, _| key % 2 == 0);
+        assert_eq!(sv.len(), 50);
+        for (key, value) in &mut sv {
+            assert_eq!(key, *value as usize * 7);
+            *value += 1;
+        }
+        for value in sv.values_mut() {
+            *value *= 2;
+        }
+        assert_eq!(sv[14], 6);
+        let mut keys = sv.keys().to_vec();
+        keys.sort_unstable();
+        assert!(keys.into_iter().eq((0..100).map(|k| k * 14).take(50)));
+        sv.clear();
+        assert_eq!(sv.get(14), None);
+    }
+}
@@ -0,0 +1,395 @@
+// A vector whose indices never change. `remove` leaves a hole where the
+// element was instead of shifting the ones after it, `push` always appends,
+// and iteration skips the holes. Holes only go away when `compact` is
+// called, which is the one operation that moves elements.
+use crate::{Allocator, Global, NomVec};
+use std::fmt;
+use std::iter::{Enumerate, FromIterator, FusedIterator};
+use std::ops::{Index, IndexMut};
+use std::slice;
+
+pub struct StableVec<T, A: Allocator = Global> {
+    slots: NomVec<Option<T>, A>,
+    // the number of slots that aren't holes
+    len: usize,
+}
+
+impl<T> StableVec<T> {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<T, A: Allocator> StableVec<T, A> {
+    pub const fn new_in(alloc: A) -> Self {
+        StableVec {
+            slots: NomVec::new_in(alloc),
+            len: 0,
+        }
+    }
+
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        StableVec {
+            slots: NomVec::with_capacity_in(capacity, alloc),
+            len: 0,
+        }
+    }
+
+    // the number of elements, not counting holes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // the index the next `push` will return
+    pub fn next_index(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn num_holes(&self) -> usize {
+        self.slots.len() - self.len
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.capacity()
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.slots.allocator()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.slots.reserve(additional)
+    }
+
+    // Appends `elem` and returns its index, which stays valid until it's
+    // removed or `compact` is called.
+    pub fn push(&mut self, elem: T) -> usize {
+        self.slots.push(Some(elem));
+        self.len += 1;
+        self.slots.len() - 1
+    }
+
+    // Removes the element at `index`, leaving a hole. `None` if there's no
+    // element there.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        let elem = self.slots.get_mut(index)?.take()?;
+        self.len -= 1;
+        Some(elem)
+    }
+
+    // Puts `elem` at `index`, which must be below `next_index`, returning
+    // the element it replaced, if it wasn't a hole.
+    pub fn replace(&mut self, index: usize, elem: T) -> Option<T> {
+        let old = self.slots[index].replace(elem);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    pub fn has_element_at(&self, index: usize) -> bool {
+        self.get(index).is_some()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.slots.get(index)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.slots.get_mut(index)?.as_mut()
+    }
+
+    // removes every element and hole; indices start from 0 again
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.len = 0;
+    }
+
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        for slot in self.slots.iter_mut() {
+            if matches!(slot, Some(elem) if !f(elem)) {
+                *slot = None;
+                self.len -= 1;
+            }
+        }
+    }
+
+    // Moves the elements down over the holes, keeping their order. This
+    // invalidates the indices of everything after the first hole.
+    pub fn compact(&mut self) {
+        if self.num_holes() > 0 {
+            self.slots.retain(Option::is_some);
+        }
+    }
+
+    // the elements with their indices, in index order
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            slots: self.slots.iter().enumerate(),
+            remaining: self.len,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            slots: self.slots.iter_mut().enumerate(),
+            remaining: self.len,
+        }
+    }
+
+    pub fn indices(&self) -> impl DoubleEndedIterator<Item = usize> + '_ {
+        self.iter().map(|(index, _)| index)
+    }
+
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &T> + '_ {
+        self.iter().map(|(_, elem)| elem)
+    }
+
+    pub fn values_mut(
+        &mut self,
+    ) -> impl DoubleEndedIterator<Item = &mut T> + '_ {
+        self.iter_mut().map(|(_, elem)| elem)
+    }
+}
+
+impl<T> Default for StableVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Clone for StableVec<T, A> {
+    fn clone(&self) -> Self {
+        StableVec {
+            slots: self.slots.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator> fmt::Debug for StableVec<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<T, A: Allocator> Index<usize> for StableVec<T, A> {
+    type Output = T;
+
+    // Panics if there's no element at `index`.
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("no element at index")
+    }
+}
+
+impl<T, A: Allocator> IndexMut<usize> for StableVec<T, A> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("no element at index")
+    }
+}
+
+impl<T, A: Allocator> Extend<T> for StableVec<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for StableVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut sv = StableVec::new();
+        sv.extend(iter);
+        sv
+    }
+}
+
+impl<T, A: Allocator> IntoIterator for StableVec<T, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+    fn into_iter(self) -> IntoIter<T, A> {
+        IntoIter {
+            slots: self.slots.into_iter(),
+            remaining: self.len,
+        }
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a StableVec<T, A> {
+    type Item = (usize, &'a T);
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a mut StableVec<T, A> {
+    type Item = (usize, &'a mut T);
+    type IntoIter = IterMut<'a, T>;
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+pub struct Iter<'a, T> {
+    slots: Enumerate<slice::Iter<'a, Option<T>>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (usize, &'a T);
+    fn next(&mut self) -> Option<(usize, &'a T)> {
+        for (index, slot) in &mut self.slots {
+            if let Some(elem) = slot {
+                self.remaining -= 1;
+                return Some((index, elem));
+            }
+        }
+        None
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<(usize, &'a T)> {
+        while let Some((index, slot)) = self.slots.next_back() {
+            if let Some(elem) = slot {
+                self.remaining -= 1;
+                return Some((index, elem));
+            }
+        }
+        None
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
+pub struct IterMut<'a, T> {
+    slots: Enumerate<slice::IterMut<'a, Option<T>>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = (usize, &'a mut T);
+    fn next(&mut self) -> Option<(usize, &'a mut T)> {
+        for (index, slot) in &mut self.slots {
+            if let Some(elem) = slot {
+                self.remaining -= 1;
+                return Some((index, elem));
+            }
+        }
+        None
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
+    fn next_back(&mut self) -> Option<(usize, &'a mut T)> {
+        while let Some((index, slot)) = self.slots.next_back() {
+            if let Some(elem) = slot {
+                self.remaining -= 1;
+                return Some((index, elem));
+            }
+        }
+        None
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+impl<'a, T> FusedIterator for IterMut<'a, T> {}
+
+pub struct IntoIter<T, A: Allocator> {
+    slots: crate::IntoIter<Option<T>, A>,
+    remaining: usize,
+}
+
+impl<T, A: Allocator> Iterator for IntoIter<T, A> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        let elem = self.slots.by_ref().flatten().next()?;
+        self.remaining -= 1;
+        Some(elem)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T, A: Allocator> DoubleEndedIterator for IntoIter<T, A> {
+    fn next_back(&mut self) -> Option<T> {
+        let elem = self.slots.by_ref().rev().flatten().next()?;
+        self.remaining -= 1;
+        Some(elem)
+    }
+}
+
+impl<T, A: Allocator> ExactSizeIterator for IntoIter<T, A> {}
+impl<T, A: Allocator> FusedIterator for IntoIter<T, A> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_indices_survive_removal() {
+        let mut sv = StableVec::new();
+        let a = sv.push("a");
+        let b = sv.push("b");
+        let c = sv.push("c");
+        assert_eq!(sv.remove(b), Some("b"));
+        assert_eq!(sv.remove(b), None);
+        assert_eq!(sv[a], "a");
+        assert_eq!(sv[c], "c");
+        assert!(!sv.has_element_at(b));
+        assert_eq!(sv.len(), 2);
+        assert_eq!(sv.num_holes(), 1);
+
+        // pushing appends rather than filling the hole
+        assert_eq!(sv.push("d"), 3);
+        assert!(sv.iter().eq([(0, &"a"), (2, &"c"), (3, &"d")]));
+        assert!(sv.values().rev().eq(&["d", "c", "a"]));
+        assert_eq!(sv.replace(b, "B"), None);
+        assert_eq!(sv.replace(b, "b"), Some("B"));
+        assert_eq!(sv.len(), 4);
+        assert_eq!(format!("{:?}", sv), r#"{0: "a", 1: "b", 2: "c", 3: "d"}"#);
+    }
+
+    #[test]
+    fn stable_compact() {
+        let mut sv: StableVec<String> = (0..6).map(|i| i.to_string()).collect();
+        sv.retain(|s| s != "1");
+        sv.remove(4);
+        for (_, s) in &mut sv {
+            s.push('!');
+        }
+        assert!(sv.indices().eq([0, 2, 3, 5]));
+        let mut other = sv.clone();
+
+        sv.compact();
+        assert_eq!(sv.num_holes(), 0);
+        assert_eq!(sv.next_index(), 4);
+        assert!(sv.values().eq(&["0!", "2!", "3!", "5!"]));
+
+        assert_eq!(other.iter_mut().len(), 4);
+        let mut owned = other.clone().into_iter();
+        assert_eq!(owned.next_back(), Some("5!".to_string()));
+        assert_eq!(owned.len(), 3);
+        other.clear();
+        assert_eq!(other.push("x".to_string()), 0);
+    }
+}
@@ -0,0 +1,263 @@
+// An allocator with `N` bytes of its own, inline, that it hands out first:
+// a NomVec allocating through a `StackAllocator` on the stack never touches
+// the heap while it fits, and moves over to the wrapped allocator once it
+// doesn't. That gives small-vector behaviour to any NomVec, or to several
+// sharing one buffer, without a separate SmallVec-style type.
+//
+// The inline bytes are handed out like a bump allocator's: freeing or
+// growing the newest block works in place, and anything else freed inline
+// is only reclaimed by `reset`.
+//
+// Only `&StackAllocator` implements `Allocator`, since blocks point into
+// the allocator itself; borrowing it keeps it from moving while they're
+// alive.
+use crate::{AllocError, Allocator, Global};
+use std::alloc::Layout;
+use std::cell::{Cell, UnsafeCell};
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::ptr::{self, NonNull};
+
+// aligned for anything short of SIMD types; bigger alignments are padded
+#[repr(C, align(16))]
+struct Buffer<const N: usize>([MaybeUninit<u8>; N]);
+
+pub struct StackAllocator<const N: usize, A: Allocator = Global> {
+    buf: UnsafeCell<Buffer<N>>,
+    // bytes of `buf` handed out
+    used: Cell<usize>,
+    fallback: A,
+}
+
+impl<const N: usize> StackAllocator<N> {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<const N: usize, A: Allocator> StackAllocator<N, A> {
+    pub const fn new_in(fallback: A) -> Self {
+        StackAllocator {
+            buf: UnsafeCell::new(Buffer([MaybeUninit::uninit(); N])),
+            used: Cell::new(0),
+            fallback,
+        }
+    }
+
+    pub fn fallback(&self) -> &A {
+        &self.fallback
+    }
+
+    // the inline bytes handed out so far
+    pub fn inline_used(&self) -> usize {
+        self.used.get()
+    }
+
+    // Makes all the inline bytes available again.
+    pub fn reset(&mut self) {
+        self.used.set(0)
+    }
+
+    fn base(&self) -> *mut u8 {
+        self.buf.get() as *mut u8
+    }
+
+    // where `ptr` is in the inline bytes, if it is
+    fn offset_of(&self, ptr: NonNull<u8>) -> Option<usize> {
+        let offset = (ptr.as_ptr() as usize).wrapping_sub(self.base() as usize);
+        if offset < N {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+
+    fn allocate_inline(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let used = self.used.get();
+        let pad = self.base().wrapping_add(used).align_offset(layout.align());
+        let end = used.checked_add(pad)?.checked_add(layout.size())?;
+        if end > N {
+            return None;
+        }
+        self.used.set(end);
+        unsafe { Some(NonNull::new_unchecked(self.base().add(used + pad))) }
+    }
+
+    // Moves a block to a fresh allocation, copying `len` bytes.
+    unsafe fn relocate(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        len: usize,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let new = self.allocate_block(new_layout)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new.as_ptr() as *mut u8, len);
+        self.deallocate_block(ptr, old_layout);
+        Ok(new)
+    }
+
+    fn allocate_block(
+        &self,
+        layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            // dangling, but aligned
+            let ptr =
+                unsafe { NonNull::new_unchecked(layout.align() as *mut u8) };
+            return Ok(NonNull::slice_from_raw_parts(ptr, 0));
+        }
+        match self.allocate_inline(layout) {
+            Some(ptr) => Ok(NonNull::slice_from_raw_parts(ptr, layout.size())),
+            None => self.fallback.allocate(layout),
+        }
+    }
+
+    unsafe fn deallocate_block(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
+        match self.offset_of(ptr) {
+            Some(offset) => {
+                // only the newest block can be given back
+                if offset + layout.size() == self.used.get() {
+                    self.used.set(offset);
+                }
+            }
+            None => self.fallback.deallocate(ptr, layout),
+        }
+    }
+}
+
+impl<const N: usize> Default for StackAllocator<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, A: Allocator> fmt::Debug for StackAllocator<N, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StackAllocator")
+            .field("capacity", &N)
+            .field("inline_used", &self.used.get())
+            .finish()
+    }
+}
+
+unsafe impl<const N: usize, A: Allocator> Allocator for &StackAllocator<N, A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.allocate_block(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.deallocate_block(ptr, layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if old_layout.size() == 0 {
+            return self.allocate_block(new_layout);
+        }
+        let offset = match self.offset_of(ptr) {
+            Some(offset) => offset,
+            None => return self.fallback.grow(ptr, old_layout, new_layout),
+        };
+        let in_place = offset + old_layout.size() == self.used.get()
+            && ptr.as_ptr().align_offset(new_layout.align()) == 0
+            && new_layout.size() <= N - offset;
+        if in_place {
+            self.used.set(offset + new_layout.size());
+            return Ok(NonNull::slice_from_raw_parts(ptr, new_layout.size()));
+        }
+        self.relocate(ptr, old_layout, new_layout, old_layout.size())
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if old_layout.size() == 0 {
+            return self.allocate_block(new_layout);
+        }
+        let offset = match self.offset_of(ptr) {
+            Some(offset) => offset,
+            None if new_layout.size() == 0 => {
+                self.fallback.deallocate(ptr, old_layout);
+                return self.allocate_block(new_layout);
+            }
+            None => return self.fallback.shrink(ptr, old_layout, new_layout),
+        };
+        if ptr.as_ptr().align_offset(new_layout.align()) != 0 {
+            return self.relocate(
+                ptr,
+                old_layout,
+                new_layout,
+                new_layout.size(),
+            );
+        }
+        if offset + old_layout.size() == self.used.get() {
+            self.used.set(offset + new_layout.size());
+        }
+        Ok(NonNull::slice_from_raw_parts(ptr, new_layout.size()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NomVec;
+
+    #[test]
+    fn stack_spills_to_the_heap() {
+        let stack = StackAllocator::<64>::new();
+        let mut cv = NomVec::with_capacity_in(4, &stack);
+        cv.extend(0u32..4);
+        assert!(stack
+            .offset_of(NonNull::new(cv.as_ptr() as *mut u8).unwrap())
+            .is_some());
+        // the newest block grows in place while there's room
+        let ptr = cv.as_ptr();
+        cv.extend(4..16);
+        assert_eq!(cv.as_ptr(), ptr);
+        assert_eq!(stack.inline_used(), 64);
+
+        cv.push(16);
+        assert!(stack
+            .offset_of(NonNull::new(cv.as_ptr() as *mut u8).unwrap())
+            .is_none());
+        assert!(cv.iter().copied().eq(0..17));
+        // moving out freed the inline bytes, as the newest block
+        assert_eq!(stack.inline_used(), 0);
+        cv.truncate(2);
+        cv.shrink_to_fit();
+        assert_eq!(cv, [0, 1]);
+    }
+
+    #[test]
+    fn stack_shares_and_resets() {
+        let mut stack = StackAllocator::<256>::new();
+        {
+            let a: NomVec<u8, _> = NomVec::from_iter_in(0..10, &stack);
+            let b: NomVec<u64, _> = NomVec::from_iter_in(0..3, &stack);
+            assert_eq!(b.as_ptr() as usize % 8, 0);
+            assert_eq!(stack.inline_used(), 40);
+            // `a` isn't the newest block, so its bytes stay used
+            drop(a);
+            assert_eq!(stack.inline_used(), 40);
+            drop(b);
+            assert_eq!(stack.inline_used(), 16);
+        }
+        stack.reset();
+        assert_eq!(stack.inline_used(), 0);
+        assert_eq!(
+            format!("{:?}", stack),
+            "StackAllocator { capacity: 256, inline_used: 0 }"
+        );
+    }
+}
@@ -0,0 +1,492 @@
+// A growable UTF-8 string on top of `NomVec<u8, A>`, for building text in
+// an arena or any other allocator. Every method keeps the bytes valid UTF-8;
+// the only ways around that are the `unsafe` ones.
+use crate::{AllocationError, Allocator, Global, NomVec};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::ops::{Add, AddAssign, Deref, DerefMut};
+use std::str::{self, Utf8Error};
+
+pub struct NomString<A: Allocator = Global> {
+    vec: NomVec<u8, A>,
+}
+
+// The bytes handed to `NomString::from_utf8` weren't UTF-8; holds on to them
+// so the caller gets them back.
+pub struct FromUtf8Error<A: Allocator = Global> {
+    bytes: NomVec<u8, A>,
+    error: Utf8Error,
+}
+
+impl<A: Allocator> FromUtf8Error<A> {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> NomVec<u8, A> {
+        self.bytes
+    }
+
+    pub fn utf8_error(&self) -> Utf8Error {
+        self.error
+    }
+}
+
+impl<A: Allocator> fmt::Debug for FromUtf8Error<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FromUtf8Error")
+            .field("bytes", &self.bytes)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl<A: Allocator> fmt::Display for FromUtf8Error<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl<A: Allocator> std::error::Error for FromUtf8Error<A> {}
+
+impl NomString {
+    pub const fn new() -> Self {
+        NomString { vec: NomVec::new() }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        NomString {
+            vec: NomVec::with_capacity(capacity),
+        }
+    }
+}
+
+impl<A: Allocator> NomString<A> {
+    pub const fn new_in(alloc: A) -> Self {
+        NomString {
+            vec: NomVec::new_in(alloc),
+        }
+    }
+
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        NomString {
+            vec: NomVec::with_capacity_in(capacity, alloc),
+        }
+    }
+
+    // Takes over `vec`'s buffer if it holds valid UTF-8.
+    pub fn from_utf8(vec: NomVec<u8, A>) -> Result<Self, FromUtf8Error<A>> {
+        match str::from_utf8(&vec) {
+            Ok(_) => Ok(NomString { vec }),
+            Err(error) => Err(FromUtf8Error { bytes: vec, error }),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `vec` must contain valid UTF-8.
+    pub unsafe fn from_utf8_unchecked(vec: NomVec<u8, A>) -> Self {
+        NomString { vec }
+    }
+
+    pub fn into_bytes(self) -> NomVec<u8, A> {
+        self.vec
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.vec
+    }
+
+    pub fn as_str(&self) -> &str {
+        unsafe { str::from_utf8_unchecked(&self.vec) }
+    }
+
+    pub fn as_mut_str(&mut self) -> &mut str {
+        unsafe { str::from_utf8_unchecked_mut(&mut self.vec) }
+    }
+
+    /// # Safety
+    ///
+    /// The bytes must still be valid UTF-8 once the borrow ends.
+    pub unsafe fn as_mut_vec(&mut self) -> &mut NomVec<u8, A> {
+        &mut self.vec
+    }
+
+    // in bytes, not chars
+    pub fn len(&self) -> usize {
+        self.vec.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vec.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.vec.capacity()
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.vec.allocator()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.vec.reserve(additional)
+    }
+
+    pub fn reserve_exact(&mut self, additional: usize) {
+        self.vec.reserve_exact(additional)
+    }
+
+    pub fn try_reserve(
+        &mut self,
+        additional: usize,
+    ) -> Result<(), AllocationError> {
+        self.vec.try_reserve(additional)
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.vec.shrink_to_fit()
+    }
+
+    pub fn push_str(&mut self, string: &str) {
+        self.vec.extend_from_slice(string.as_bytes())
+    }
+
+    pub fn try_push_str(
+        &mut self,
+        string: &str,
+    ) -> Result<(), AllocationError> {
+        self.vec.try_extend_from_slice(string.as_bytes())
+    }
+
+    pub fn push(&mut self, ch: char) {
+        match ch.len_utf8() {
+            1 => self.vec.push(ch as u8),
+            _ => self.push_str(ch.encode_utf8(&mut [0; 4])),
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<char> {
+        let ch = self.chars().next_back()?;
+        let new_len = self.len() - ch.len_utf8();
+        self.vec.truncate(new_len);
+        Some(ch)
+    }
+
+    // Panics if `new_len` isn't on a char boundary.
+    pub fn truncate(&mut self, new_len: usize) {
+        if new_len < self.len() {
+            assert!(
+                self.is_char_boundary(new_len),
+                "new_len is not on a char boundary"
+            );
+            self.vec.truncate(new_len)
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.vec.clear()
+    }
+
+    pub fn insert(&mut self, index: usize, ch: char) {
+        self.insert_str(index, ch.encode_utf8(&mut [0; 4]))
+    }
+
+    pub fn insert_str(&mut self, index: usize, string: &str) {
+        assert!(
+            self.is_char_boundary(index),
+            "index is not on a char boundary"
+        );
+        self.vec.insert_slice(index, string.as_bytes())
+    }
+
+    // Removes and returns the char starting at byte `index`.
+    pub fn remove(&mut self, index: usize) -> char {
+        let ch = match self[index..].chars().next() {
+            Some(ch) => ch,
+            None => panic!("cannot remove a char from the end of a string"),
+        };
+        self.vec.drain(index..index + ch.len_utf8());
+        ch
+    }
+}
+
+impl<A: Allocator> Deref for NomString<A> {
+    type Target = str;
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<A: Allocator> DerefMut for NomString<A> {
+    fn deref_mut(&mut self) -> &mut str {
+        self.as_mut_str()
+    }
+}
+
+impl<A: Allocator> AsRef<str> for NomString<A> {
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+impl<A: Allocator> AsRef<[u8]> for NomString<A> {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl<A: Allocator> Borrow<str> for NomString<A> {
+    fn borrow(&self) -> &str {
+        self
+    }
+}
+
+impl Default for NomString {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Allocator + Clone> Clone for NomString<A> {
+    fn clone(&self) -> Self {
+        NomString {
+            vec: self.vec.clone(),
+        }
+    }
+}
+
+impl<A: Allocator> fmt::Debug for NomString<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl From<&str> for NomString {
+    fn from(string: &str) -> Self {
+        let mut s = NomString::with_capacity(string.len());
+        s.push_str(string);
+        s
+    }
+}
+
+impl From<String> for NomString {
+    // reuses the String's buffer
+    fn from(string: String) -> Self {
+        NomString {
+            vec: NomVec::from(string.into_bytes()),
+        }
+    }
+}
+
+impl From<NomString> for String {
+    fn from(string: NomString) -> Self {
+        unsafe { String::from_utf8_unchecked(string.vec.into()) }
+    }
+}
+
+impl<A: Allocator, B: Allocator> PartialEq<NomString<B>> for NomString<A> {
+    fn eq(&self, other: &NomString<B>) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+macro_rules! impl_str_eq {
+    ($($rhs:ty),*) => {$(
+        impl<A: Allocator> PartialEq<$rhs> for NomString<A> {
+            fn eq(&self, other: &$rhs) -> bool {
+                self.as_str() == &other[..]
+            }
+        }
+
+        impl<A: Allocator> PartialEq<NomString<A>> for $rhs {
+            fn eq(&self, other: &NomString<A>) -> bool {
+                &self[..] == other.as_str()
+            }
+        }
+    )*};
+}
+
+impl_str_eq!(str, &str, String);
+
+impl<A: Allocator> Eq for NomString<A> {}
+
+impl<A: Allocator> PartialOrd for NomString<A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<A: Allocator> Ord for NomString<A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+// hashes like a `str`, to go with the `Borrow<str>` impl
+impl<A: Allocator> Hash for NomString<A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl<A: Allocator> fmt::Display for NomString<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+// lets `write!` append to a NomString; never fails
+impl<A: Allocator> fmt::Write for NomString<A> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        self.push(c);
+        Ok(())
+    }
+}
+
+impl<A: Allocator> Add<&str> for NomString<A> {
+    type Output = NomString<A>;
+
+    fn add(mut self, other: &str) -> NomString<A> {
+        self.push_str(other);
+        self
+    }
+}
+
+impl<A: Allocator> AddAssign<&str> for NomString<A> {
+    fn add_assign(&mut self, other: &str) {
+        self.push_str(other);
+    }
+}
+
+impl<A: Allocator> Extend<char> for NomString<A> {
+    fn extend<I: IntoIterator<Item = char>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        // every char is at least one byte
+        self.reserve(iter.size_hint().0);
+        for ch in iter {
+            self.push(ch);
+        }
+    }
+}
+
+impl<'a, A: Allocator> Extend<&'a char> for NomString<A> {
+    fn extend<I: IntoIterator<Item = &'a char>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+impl<'a, A: Allocator> Extend<&'a str> for NomString<A> {
+    fn extend<I: IntoIterator<Item = &'a str>>(&mut self, iter: I) {
+        for s in iter {
+            self.push_str(s);
+        }
+    }
+}
+
+impl FromIterator<char> for NomString {
+    fn from_iter<I: IntoIterator<Item = char>>(iter: I) -> Self {
+        let mut s = NomString::new();
+        s.extend(iter);
+        s
+    }
+}
+
+impl<'a> FromIterator<&'a char> for NomString {
+    fn from_iter<I: IntoIterator<Item = &'a char>>(iter: I) -> Self {
+        iter.into_iter().copied().collect()
+    }
+}
+
+impl<'a> FromIterator<&'a str> for NomString {
+    fn from_iter<I: IntoIterator<Item = &'a str>>(iter: I) -> Self {
+        let mut s = NomString::new();
+        s.extend(iter);
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::fmt::Write;
+
+    #[test]
+    fn string_push_and_edit() {
+        let mut s = NomString::new();
+        s.push_str("hello");
+        s.push(' ');
+        s.push('w');
+        s.push('ö');
+        s.push_str("rld");
+        assert_eq!(s, "hello wörld");
+        assert_eq!(s.len(), 12);
+        assert_eq!(s.pop(), Some('d'));
+        assert_eq!(s.remove(7), 'ö');
+        s.insert(7, 'o');
+        s.insert_str(0, "¡");
+        assert_eq!(s, "¡hello worl");
+        assert!(s.starts_with("¡h"));
+        s.truncate(2);
+        assert_eq!(s.as_str(), "¡");
+        assert_eq!(s.pop(), Some('¡'));
+        assert_eq!(s.pop(), None);
+    }
+
+    #[test]
+    fn string_formatting_and_building() {
+        let mut s = NomString::new();
+        let prefix = "id";
+        write!(s, "{}-{:03}", prefix, 7).unwrap();
+        s.write_char('!').unwrap();
+        assert_eq!(s, "id-007!");
+        assert_eq!(format!("[{}]", s), "[id-007!]");
+        assert_eq!(format!("{:>9}", s), "  id-007!");
+
+        let s = s + " ok";
+        assert_eq!(s, "id-007! ok");
+        let mut s: NomString = "abc".chars().rev().collect();
+        s += "d";
+        assert_eq!(s, "cbad");
+        let words: NomString = vec!["a", "b", "c"].into_iter().collect();
+        assert_eq!(words, "abc");
+        let upper: NomString =
+            "héllo".chars().flat_map(char::to_uppercase).collect();
+        assert_eq!(upper, "HÉLLO");
+    }
+
+    #[test]
+    #[should_panic(expected = "char boundary")]
+    fn string_truncate_mid_char_panics() {
+        NomString::from("ö").truncate(1);
+    }
+
+    #[test]
+    fn string_utf8_roundtrip() {
+        let bytes = NomVec::from(&b"caf\xc3\xa9"[..]);
+        let ptr = bytes.as_ptr();
+        let s = NomString::from_utf8(bytes).unwrap();
+        assert_eq!(s, "café");
+        let bytes = s.into_bytes();
+        assert_eq!(bytes.as_ptr(), ptr);
+
+        let err =
+            NomString::from_utf8(NomVec::from(&b"ab\xff"[..])).unwrap_err();
+        assert_eq!(err.utf8_error().valid_up_to(), 2);
+        assert_eq!(err.into_bytes(), [b'a', b'b', 0xff]);
+
+        let mut set = HashSet::new();
+        set.insert(NomString::from(String::from("key")));
+        assert!(set.contains("key"));
+        assert_eq!(String::from(NomString::from("x")), "x");
+    }
+}
@@ -0,0 +1,34 @@
+// Fixtures shared by the test modules.
+use std::cell::Cell;
+use std::rc::Rc;
+
+// bumps the shared counter when dropped, optionally panicking
+pub(crate) struct DropCounter {
+    drops: Rc<Cell<usize>>,
+    panic_on_drop: bool,
+}
+
+impl DropCounter {
+    pub(crate) fn new(drops: &Rc<Cell<usize>>) -> Self {
+        DropCounter {
+            drops: drops.clone(),
+            panic_on_drop: false,
+        }
+    }
+
+    pub(crate) fn panicking(drops: &Rc<Cell<usize>>) -> Self {
+        DropCounter {
+            drops: drops.clone(),
+            panic_on_drop: true,
+        }
+    }
+}
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.drops.set(self.drops.get() + 1);
+        if self.panic_on_drop {
+            panic!("drop panicked");
+        }
+    }
+}
@@ -0,0 +1,428 @@
+// A vector whose handle is a single pointer: `len` and `cap` live in a
+// header at the front of the heap allocation, with the elements right after
+// it. Empty vectors point at a shared static header instead of allocating,
+// so a struct full of usually-empty `ThinNomVec`s costs one word per field
+// and nothing on the heap.
+use crate::{handle_error, AllocationError, Allocator, Global};
+use std::alloc::Layout;
+use std::cmp;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::ptr::{self, NonNull};
+
+#[repr(C)]
+struct Header {
+    len: usize,
+    cap: usize,
+}
+
+// every empty ThinNomVec points here; it must never be written through
+static EMPTY_HEADER: Header = Header { len: 0, cap: 0 };
+
+// the layout of an allocation holding `cap` elements, plus the offset of the
+// first element (which doesn't depend on `cap`)
+fn layout<T>(cap: usize) -> Result<(Layout, usize), AllocationError> {
+    let array = Layout::array::<T>(cap)
+        .map_err(|_| AllocationError::CapacityOverflow)?;
+    let (layout, offset) = Layout::new::<Header>()
+        .extend(array)
+        .map_err(|_| AllocationError::CapacityOverflow)?;
+    Ok((layout.pad_to_align(), offset))
+}
+
+pub struct ThinNomVec<T> {
+    ptr: NonNull<Header>,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: Send> Send for ThinNomVec<T> {}
+unsafe impl<T: Sync> Sync for ThinNomVec<T> {}
+
+impl<T> ThinNomVec<T> {
+    pub const fn new() -> Self {
+        ThinNomVec {
+            ptr: unsafe {
+                NonNull::new_unchecked(
+                    ptr::addr_of!(EMPTY_HEADER) as *mut Header
+                )
+            },
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut vec = Self::new();
+        vec.reserve_exact(capacity);
+        vec
+    }
+
+    fn is_singleton(&self) -> bool {
+        ptr::eq(self.ptr.as_ptr(), &EMPTY_HEADER)
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    // Only valid once the vector has its own allocation.
+    unsafe fn header_mut(&mut self) -> &mut Header {
+        debug_assert!(!self.is_singleton());
+        self.ptr.as_mut()
+    }
+
+    fn data(&self) -> *mut T {
+        if self.is_singleton() {
+            // the static header isn't followed by anything (or aligned
+            // for T), so don't offset from it
+            return NonNull::dangling().as_ptr();
+        }
+        let offset = match layout::<T>(0) {
+            Ok((_, offset)) => offset,
+            Err(_) => unreachable!(),
+        };
+        unsafe { (self.ptr.as_ptr() as *mut u8).add(offset) as *mut T }
+    }
+
+    pub fn len(&self) -> usize {
+        self.header().len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        if mem::size_of::<T>() == 0 {
+            usize::MAX
+        } else {
+            self.header().cap
+        }
+    }
+
+    fn needs_to_grow(&self, additional: usize) -> bool {
+        // zero-sized types never run out of capacity, but still need a
+        // header of their own before the length can change
+        additional > self.capacity() - self.len()
+            || (additional > 0 && self.is_singleton())
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        if let Err(err) = self.try_reserve(additional) {
+            handle_error(err);
+        }
+    }
+
+    pub fn reserve_exact(&mut self, additional: usize) {
+        if self.needs_to_grow(additional) {
+            let required = match self.len().checked_add(additional) {
+                Some(required) => required,
+                None => handle_error(AllocationError::CapacityOverflow),
+            };
+            if let Err(err) = self.grow_to(required) {
+                handle_error(err);
+            }
+        }
+    }
+
+    pub fn try_reserve(
+        &mut self,
+        additional: usize,
+    ) -> Result<(), AllocationError> {
+        if !self.needs_to_grow(additional) {
+            return Ok(());
+        }
+        let required = self
+            .len()
+            .checked_add(additional)
+            .ok_or(AllocationError::CapacityOverflow)?;
+        // start at 4 so a few pushes don't each reallocate
+        let cap = cmp::max(cmp::max(self.header().cap * 2, required), 4);
+        self.grow_to(cap)
+    }
+
+    fn grow_to(&mut self, new_cap: usize) -> Result<(), AllocationError> {
+        let new_cap = if mem::size_of::<T>() == 0 { 0 } else { new_cap };
+        let (new_layout, _) = layout::<T>(new_cap)?;
+        let new_ptr = if self.is_singleton() {
+            Global.allocate(new_layout)
+        } else {
+            let (old_layout, _) = layout::<T>(self.header().cap)?;
+            unsafe { Global.grow(self.ptr.cast(), old_layout, new_layout) }
+        };
+        let new_ptr = new_ptr
+            .map_err(|_| AllocationError::AllocFailed { layout: new_layout })?
+            .cast::<Header>();
+        unsafe {
+            if self.is_singleton() {
+                ptr::write(
+                    new_ptr.as_ptr(),
+                    Header {
+                        len: 0,
+                        cap: new_cap,
+                    },
+                );
+            } else {
+                (*new_ptr.as_ptr()).cap = new_cap;
+            }
+        }
+        self.ptr = new_ptr;
+        Ok(())
+    }
+
+    pub fn push(&mut self, elem: T) {
+        let len = self.len();
+        if self.needs_to_grow(1) {
+            self.reserve(1);
+        }
+        unsafe {
+            ptr::write(self.data().add(len), elem);
+            self.header_mut().len = len + 1;
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let len = self.len();
+        if len == 0 {
+            None
+        } else {
+            unsafe {
+                self.header_mut().len = len - 1;
+                Some(ptr::read(self.data().add(len - 1)))
+            }
+        }
+    }
+
+    pub fn insert(&mut self, index: usize, elem: T) {
+        let len = self.len();
+        assert!(index <= len, "index out of bounds");
+        if self.needs_to_grow(1) {
+            self.reserve(1);
+        }
+        unsafe {
+            let p = self.data().add(index);
+            ptr::copy(p, p.add(1), len - index);
+            ptr::write(p, elem);
+            self.header_mut().len = len + 1;
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        assert!(index < len, "index out of bounds");
+        unsafe {
+            self.header_mut().len = len - 1;
+            let p = self.data().add(index);
+            let result = ptr::read(p);
+            ptr::copy(p.add(1), p, len - index - 1);
+            result
+        }
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        let old_len = self.len();
+        if len >= old_len {
+            return;
+        }
+        unsafe {
+            // shorten first so a panicking destructor can't cause a double drop
+            self.header_mut().len = len;
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                self.data().add(len),
+                old_len - len,
+            ));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+}
+
+impl<T> Drop for ThinNomVec<T> {
+    fn drop(&mut self) {
+        if self.is_singleton() {
+            return;
+        }
+        // frees the block even if dropping an element panics
+        struct DeallocGuard(NonNull<u8>, Layout);
+
+        impl Drop for DeallocGuard {
+            fn drop(&mut self) {
+                unsafe { Global.deallocate(self.0, self.1) }
+            }
+        }
+
+        let (layout, _) = layout::<T>(self.header().cap).unwrap();
+        let _guard = DeallocGuard(self.ptr.cast(), layout);
+        unsafe { ptr::drop_in_place(&mut self[..]) }
+    }
+}
+
+impl<T> Default for ThinNomVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Deref for ThinNomVec<T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        unsafe { ::std::slice::from_raw_parts(self.data(), self.len()) }
+    }
+}
+
+impl<T> DerefMut for ThinNomVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { ::std::slice::from_raw_parts_mut(self.data(), self.len()) }
+    }
+}
+
+impl<T: Clone> Clone for ThinNomVec<T> {
+    fn clone(&self) -> Self {
+        let mut vec = ThinNomVec::with_capacity(self.len());
+        vec.extend(self.iter().cloned());
+        vec
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ThinNomVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: PartialEq<U>, U> PartialEq<ThinNomVec<U>> for ThinNomVec<T> {
+    fn eq(&self, other: &ThinNomVec<U>) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T: PartialEq<U>, U> PartialEq<[U]> for ThinNomVec<T> {
+    fn eq(&self, other: &[U]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T: PartialEq<U>, U, const N: usize> PartialEq<[U; N]> for ThinNomVec<T> {
+    fn eq(&self, other: &[U; N]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T: Eq> Eq for ThinNomVec<T> {}
+
+impl<T: Hash> Hash for ThinNomVec<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Hash::hash(&**self, state)
+    }
+}
+
+impl<T> Extend<T> for ThinNomVec<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for ThinNomVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vec = ThinNomVec::new();
+        vec.extend(iter);
+        vec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DropCounter;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn thin_is_one_pointer() {
+        assert_eq!(mem::size_of::<ThinNomVec<u64>>(), mem::size_of::<usize>());
+        assert_eq!(
+            mem::size_of::<Option<ThinNomVec<u64>>>(),
+            mem::size_of::<usize>()
+        );
+    }
+
+    #[test]
+    fn thin_basic_ops() {
+        let mut tv = ThinNomVec::new();
+        assert!(tv.is_empty());
+        assert_eq!(tv.capacity(), 0);
+        tv.clear();
+        assert_eq!(tv.pop(), None);
+
+        tv.push(1);
+        tv.push(3);
+        tv.insert(1, 2);
+        assert_eq!(tv, [1, 2, 3]);
+        assert_eq!(tv.capacity(), 4);
+        assert_eq!(tv.remove(0), 1);
+        assert_eq!(tv.pop(), Some(3));
+        assert_eq!(tv, [2]);
+
+        tv.extend(0..100);
+        assert_eq!(tv.len(), 101);
+        let cloned = tv.clone();
+        assert_eq!(cloned, tv);
+        tv.truncate(1);
+        assert_eq!(tv, [2]);
+    }
+
+    #[test]
+    fn thin_overaligned_and_zst() {
+        #[derive(Clone, Copy, PartialEq, Debug)]
+        #[repr(align(64))]
+        struct Wide(u8);
+
+        let mut tv: ThinNomVec<Wide> = (0..5).map(Wide).collect();
+        assert_eq!(tv.as_ptr() as usize % 64, 0);
+        tv.reserve(100);
+        assert_eq!(tv.as_ptr() as usize % 64, 0);
+        assert_eq!(tv[4], Wide(4));
+
+        let mut zsts = ThinNomVec::new();
+        assert_eq!(zsts.capacity(), usize::MAX);
+        zsts.push(());
+        zsts.push(());
+        assert_eq!(zsts.len(), 2);
+        assert_eq!(zsts.pop(), Some(()));
+    }
+
+    #[test]
+    fn thin_drops_elements() {
+        let drops = Rc::new(Cell::new(0));
+        let mut tv = ThinNomVec::new();
+        for _ in 0..10 {
+            tv.push(DropCounter::new(&drops));
+        }
+        tv.truncate(7);
+        assert_eq!(drops.get(), 3);
+        drop(tv);
+        assert_eq!(drops.get(), 10);
+
+        // one panicking drop still drops the rest
+        let drops = Rc::new(Cell::new(0));
+        let mut tv = ThinNomVec::new();
+        tv.push(DropCounter::new(&drops));
+        tv.push(DropCounter::panicking(&drops));
+        tv.push(DropCounter::new(&drops));
+        let result =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+                drop(tv)
+            }));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 3);
+    }
+}
@@ -0,0 +1,921 @@
+// Sorted-vector maps and sets. Entries live in one NomVec kept in ascending
+// key order, so lookups and range queries are binary searches over
+// contiguous memory and iteration is a slice walk. Inserting and removing
+// shift the tail, which makes these a good fit for small to medium
+// collections that are read far more often than they are changed.
+use crate::sorted::{merge_sorted, SetOp};
+use crate::{Allocator, Global, NomVec};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, FusedIterator};
+use std::mem;
+use std::ops::{Bound, Index, RangeBounds};
+use std::slice;
+
+// the positions `[start, end)` of the sorted `entries` whose keys fall in
+// `range`
+pub(crate) fn range_positions<E, Q, R>(
+    entries: &[E],
+    key: impl Fn(&E) -> &Q,
+    range: R,
+) -> (usize, usize)
+where
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+{
+    let start = match range.start_bound() {
+        Bound::Included(b) => entries.partition_point(|e| key(e) < b),
+        Bound::Excluded(b) => entries.partition_point(|e| key(e) <= b),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(b) => entries.partition_point(|e| key(e) <= b),
+        Bound::Excluded(b) => entries.partition_point(|e| key(e) < b),
+        Bound::Unbounded => entries.len(),
+    };
+    (start, end.max(start))
+}
+
+// Sorts `vec` by `key`, keeping only the last of any equal keys.
+fn sort_dedup_last<E, K: Ord, A: Allocator>(
+    vec: &mut NomVec<E, A>,
+    key: impl Fn(&E) -> &K,
+) {
+    vec.sort_by(|a, b| key(a).cmp(key(b)));
+    vec.dedup_by(|later, kept| {
+        if key(later) == key(kept) {
+            mem::swap(later, kept);
+            true
+        } else {
+            false
+        }
+    });
+}
+
+// Panics unless `vec` is strictly ascending by `key`.
+fn assert_strictly_sorted<E, K: Ord>(vec: &[E], key: impl Fn(&E) -> &K) {
+    assert!(
+        vec.windows(2).all(|w| key(&w[0]) < key(&w[1])),
+        "keys are not strictly ascending"
+    );
+}
+
+pub struct VecMap<K, V, A: Allocator = Global> {
+    entries: NomVec<(K, V), A>,
+}
+
+impl<K, V> VecMap<K, V> {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<K, V, A: Allocator> VecMap<K, V, A> {
+    pub const fn new_in(alloc: A) -> Self {
+        VecMap {
+            entries: NomVec::new_in(alloc),
+        }
+    }
+
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        VecMap {
+            entries: NomVec::with_capacity_in(capacity, alloc),
+        }
+    }
+
+    // Builds a map from entries already in strictly ascending key order,
+    // without sorting. Panics if they aren't.
+    pub fn from_sorted_iter_in<I>(iter: I, alloc: A) -> Self
+    where
+        K: Ord,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut entries = NomVec::new_in(alloc);
+        entries.extend(iter);
+        assert_strictly_sorted(&entries, |e| &e.0);
+        VecMap { entries }
+    }
+
+    // Sorts `entries` by key; of equal keys, the last one wins.
+    pub fn from_vec(mut entries: NomVec<(K, V), A>) -> Self
+    where
+        K: Ord,
+    {
+        sort_dedup_last(&mut entries, |e| &e.0);
+        VecMap { entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries.capacity()
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.entries.allocator()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.entries.reserve(additional)
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.entries.shrink_to_fit()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear()
+    }
+
+    // the entries, in key order
+    pub fn as_slice(&self) -> &[(K, V)] {
+        &self.entries
+    }
+
+    pub fn into_vec(self) -> NomVec<(K, V), A> {
+        self.entries
+    }
+
+    fn search<Q>(&self, key: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entries.binary_search_by(|e| e.0.borrow().cmp(key))
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (k, v) = &self.entries[self.search(key).ok()?];
+        Some((k, v))
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let i = self.search(key).ok()?;
+        Some(&mut self.entries[i].1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.search(key).is_ok()
+    }
+
+    // Returns the old value if `key` was already present; the key itself is
+    // left as it was.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V>
+    where
+        K: Ord,
+    {
+        match self.search(&key) {
+            Ok(i) => Some(mem::replace(&mut self.entries[i].1, value)),
+            Err(i) => {
+                self.entries.insert(i, (key, value));
+                None
+            }
+        }
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, v)| v)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let i = self.search(key).ok()?;
+        Some(self.entries.remove(i))
+    }
+
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.entries.first().map(|(k, v)| (k, v))
+    }
+
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        self.entries.last().map(|(k, v)| (k, v))
+    }
+
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.entries.remove(0))
+        }
+    }
+
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        self.entries.pop()
+    }
+
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.entries.retain_mut(|(k, v)| f(k, v))
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.entries.iter(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            inner: self.entries.iter_mut(),
+        }
+    }
+
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> + '_ {
+        self.entries.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> + '_ {
+        self.entries.iter().map(|(_, v)| v)
+    }
+
+    pub fn values_mut(
+        &mut self,
+    ) -> impl DoubleEndedIterator<Item = &mut V> + '_ {
+        self.entries.iter_mut().map(|(_, v)| v)
+    }
+
+    // the entries whose keys fall in `range`, in key order
+    pub fn range<Q, R>(&self, range: R) -> Iter<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (start, end) =
+            range_positions(&self.entries, |e| e.0.borrow(), range);
+        Iter {
+            inner: self.entries[start..end].iter(),
+        }
+    }
+
+    pub fn range_mut<Q, R>(&mut self, range: R) -> IterMut<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (start, end) =
+            range_positions(&self.entries, |e| e.0.borrow(), range);
+        IterMut {
+            inner: self.entries[start..end].iter_mut(),
+        }
+    }
+}
+
+impl<K: Ord, V> VecMap<K, V> {
+    pub fn from_sorted_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        Self::from_sorted_iter_in(iter, Global)
+    }
+}
+
+impl<K, V> Default for VecMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone, V: Clone, A: Allocator + Clone> Clone for VecMap<K, V, A> {
+    fn clone(&self) -> Self {
+        VecMap {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, A: Allocator> fmt::Debug
+    for VecMap<K, V, A>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: PartialEq, V: PartialEq, A: Allocator, B: Allocator>
+    PartialEq<VecMap<K, V, B>> for VecMap<K, V, A>
+{
+    fn eq(&self, other: &VecMap<K, V, B>) -> bool {
+        self.entries[..] == other.entries[..]
+    }
+}
+
+impl<K: Eq, V: Eq, A: Allocator> Eq for VecMap<K, V, A> {}
+
+impl<K: Hash, V: Hash, A: Allocator> Hash for VecMap<K, V, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.entries[..].hash(state)
+    }
+}
+
+impl<K, V, A, Q> Index<&Q> for VecMap<K, V, A>
+where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    A: Allocator,
+{
+    type Output = V;
+
+    // Panics if `key` isn't present.
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("key not found")
+    }
+}
+
+impl<K: Ord, V, A: Allocator> From<NomVec<(K, V), A>> for VecMap<K, V, A> {
+    fn from(entries: NomVec<(K, V), A>) -> Self {
+        VecMap::from_vec(entries)
+    }
+}
+
+impl<K: Ord, V, const N: usize> From<[(K, V); N]> for VecMap<K, V> {
+    fn from(arr: [(K, V); N]) -> Self {
+        VecMap::from_vec(NomVec::from(arr))
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for VecMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        VecMap::from_vec(iter.into_iter().collect())
+    }
+}
+
+impl<K: Ord, V, A: Allocator> Extend<(K, V)> for VecMap<K, V, A> {
+    // appends and re-sorts once, rather than inserting one at a time
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.entries.extend(iter);
+        sort_dedup_last(&mut self.entries, |e| &e.0);
+    }
+}
+
+impl<K, V, A: Allocator> IntoIterator for VecMap<K, V, A> {
+    type Item = (K, V);
+    type IntoIter = crate::IntoIter<(K, V), A>;
+    fn into_iter(self) -> crate::IntoIter<(K, V), A> {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a, K, V, A: Allocator> IntoIterator for &'a VecMap<K, V, A> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, A: Allocator> IntoIterator for &'a mut VecMap<K, V, A> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+#[derive(Clone)]
+pub struct Iter<'a, K, V> {
+    inner: slice::Iter<'a, (K, V)>,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    pub(crate) fn new(entries: &'a [(K, V)]) -> Self {
+        Iter {
+            inner: entries.iter(),
+        }
+    }
+
+    // the entries not yet yielded
+    pub fn as_slice(&self) -> &'a [(K, V)] {
+        self.inner.as_slice()
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next().map(|(k, v)| (k, v))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next_back().map(|(k, v)| (k, v))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+impl<'a, K, V> FusedIterator for Iter<'a, K, V> {}
+
+pub struct IterMut<'a, K, V> {
+    inner: slice::IterMut<'a, (K, V)>,
+}
+
+impl<'a, K, V> IterMut<'a, K, V> {
+    pub(crate) fn new(entries: &'a mut [(K, V)]) -> Self {
+        IterMut {
+            inner: entries.iter_mut(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        self.inner.next().map(|(k, v)| (&*k, v))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for IterMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a mut V)> {
+        self.inner.next_back().map(|(k, v)| (&*k, v))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for IterMut<'a, K, V> {}
+impl<'a, K, V> FusedIterator for IterMut<'a, K, V> {}
+
+pub struct VecSet<T, A: Allocator = Global> {
+    elems: NomVec<T, A>,
+}
+
+impl<T> VecSet<T> {
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<T: Ord> VecSet<T> {
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_sorted_iter_in(iter, Global)
+    }
+}
+
+impl<T, A: Allocator> VecSet<T, A> {
+    pub const fn new_in(alloc: A) -> Self {
+        VecSet {
+            elems: NomVec::new_in(alloc),
+        }
+    }
+
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        VecSet {
+            elems: NomVec::with_capacity_in(capacity, alloc),
+        }
+    }
+
+    // Builds a set from elements already in strictly ascending order,
+    // without sorting. Panics if they aren't.
+    pub fn from_sorted_iter_in<I>(iter: I, alloc: A) -> Self
+    where
+        T: Ord,
+        I: IntoIterator<Item = T>,
+    {
+        let mut elems = NomVec::new_in(alloc);
+        elems.extend(iter);
+        assert_strictly_sorted(&elems, |e| e);
+        VecSet { elems }
+    }
+
+    // Sorts and deduplicates `elems`; of equal elements, the last one wins.
+    pub fn from_vec(mut elems: NomVec<T, A>) -> Self
+    where
+        T: Ord,
+    {
+        sort_dedup_last(&mut elems, |e| e);
+        VecSet { elems }
+    }
+
+    pub fn len(&self) -> usize {
+        self.elems.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elems.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.elems.capacity()
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.elems.allocator()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.elems.reserve(additional)
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.elems.shrink_to_fit()
+    }
+
+    pub fn clear(&mut self) {
+        self.elems.clear()
+    }
+
+    // the elements, in ascending order
+    pub fn as_slice(&self) -> &[T] {
+        &self.elems
+    }
+
+    pub fn into_vec(self) -> NomVec<T, A> {
+        self.elems
+    }
+
+    fn search<Q>(&self, value: &Q) -> Result<usize, usize>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.elems.binary_search_by(|e| e.borrow().cmp(value))
+    }
+
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.search(value).is_ok()
+    }
+
+    pub fn get<Q>(&self, value: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.search(value).ok().map(|i| &self.elems[i])
+    }
+
+    // Returns false, leaving the set unchanged, if `value` was already
+    // present.
+    pub fn insert(&mut self, value: T) -> bool
+    where
+        T: Ord,
+    {
+        match self.search(&value) {
+            Ok(_) => false,
+            Err(i) => {
+                self.elems.insert(i, value);
+                true
+            }
+        }
+    }
+
+    // Inserts `value`, returning the equal element it replaced, if any.
+    pub fn replace(&mut self, value: T) -> Option<T>
+    where
+        T: Ord,
+    {
+        match self.search(&value) {
+            Ok(i) => Some(mem::replace(&mut self.elems[i], value)),
+            Err(i) => {
+                self.elems.insert(i, value);
+                None
+            }
+        }
+    }
+
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.take(value).is_some()
+    }
+
+    pub fn take<Q>(&mut self, value: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let i = self.search(value).ok()?;
+        Some(self.elems.remove(i))
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.elems.first()
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.elems.last()
+    }
+
+    pub fn pop_first(&mut self) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.elems.remove(0))
+        }
+    }
+
+    pub fn pop_last(&mut self) -> Option<T> {
+        self.elems.pop()
+    }
+
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, f: F) {
+        self.elems.retain(f)
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.elems.iter()
+    }
+
+    // the elements that fall in `range`, in ascending order
+    pub fn range<Q, R>(&self, range: R) -> slice::Iter<'_, T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = range_positions(&self.elems, |e| e.borrow(), range);
+        self.elems[start..end].iter()
+    }
+}
+
+// Set algebra, in linear time, as on `SortedNomVec`.
+impl<T: Ord + Clone, A: Allocator> VecSet<T, A> {
+    pub fn union_in<B: Allocator, C: Allocator>(
+        &self,
+        other: &VecSet<T, B>,
+        alloc: C,
+    ) -> VecSet<T, C> {
+        let op = SetOp::UNION;
+        VecSet {
+            elems: merge_sorted(&self.elems, &other.elems, op, alloc),
+        }
+    }
+
+    pub fn intersection_in<B: Allocator, C: Allocator>(
+        &self,
+        other: &VecSet<T, B>,
+        alloc: C,
+    ) -> VecSet<T, C> {
+        let op = SetOp::INTERSECTION;
+        VecSet {
+            elems: merge_sorted(&self.elems, &other.elems, op, alloc),
+        }
+    }
+
+    pub fn difference_in<B: Allocator, C: Allocator>(
+        &self,
+        other: &VecSet<T, B>,
+        alloc: C,
+    ) -> VecSet<T, C> {
+        let op = SetOp::DIFFERENCE;
+        VecSet {
+            elems: merge_sorted(&self.elems, &other.elems, op, alloc),
+        }
+    }
+
+    pub fn symmetric_difference_in<B: Allocator, C: Allocator>(
+        &self,
+        other: &VecSet<T, B>,
+        alloc: C,
+    ) -> VecSet<T, C> {
+        let op = SetOp::SYMMETRIC_DIFFERENCE;
+        VecSet {
+            elems: merge_sorted(&self.elems, &other.elems, op, alloc),
+        }
+    }
+}
+
+impl<T: Ord + Clone, A: Allocator + Clone> VecSet<T, A> {
+    pub fn union<B: Allocator>(&self, other: &VecSet<T, B>) -> Self {
+        self.union_in(other, self.allocator().clone())
+    }
+
+    pub fn intersection<B: Allocator>(&self, other: &VecSet<T, B>) -> Self {
+        self.intersection_in(other, self.allocator().clone())
+    }
+
+    pub fn difference<B: Allocator>(&self, other: &VecSet<T, B>) -> Self {
+        self.difference_in(other, self.allocator().clone())
+    }
+
+    pub fn symmetric_difference<B: Allocator>(
+        &self,
+        other: &VecSet<T, B>,
+    ) -> Self {
+        self.symmetric_difference_in(other, self.allocator().clone())
+    }
+}
+
+impl<T> Default for VecSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Clone for VecSet<T, A> {
+    fn clone(&self) -> Self {
+        VecSet {
+            elems: self.elems.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator> fmt::Debug for VecSet<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq, A: Allocator, B: Allocator> PartialEq<VecSet<T, B>>
+    for VecSet<T, A>
+{
+    fn eq(&self, other: &VecSet<T, B>) -> bool {
+        self.elems[..] == other.elems[..]
+    }
+}
+
+impl<T: Eq, A: Allocator> Eq for VecSet<T, A> {}
+
+impl<T: PartialOrd, A: Allocator> PartialOrd for VecSet<T, A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.elems[..].partial_cmp(&other.elems[..])
+    }
+}
+
+impl<T: Ord, A: Allocator> Ord for VecSet<T, A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.elems[..].cmp(&other.elems[..])
+    }
+}
+
+impl<T: Hash, A: Allocator> Hash for VecSet<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.elems[..].hash(state)
+    }
+}
+
+impl<T: Ord, A: Allocator> From<NomVec<T, A>> for VecSet<T, A> {
+    fn from(elems: NomVec<T, A>) -> Self {
+        VecSet::from_vec(elems)
+    }
+}
+
+impl<T: Ord, const N: usize> From<[T; N]> for VecSet<T> {
+    fn from(arr: [T; N]) -> Self {
+        VecSet::from_vec(NomVec::from(arr))
+    }
+}
+
+impl<T: Ord> FromIterator<T> for VecSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        VecSet::from_vec(iter.into_iter().collect())
+    }
+}
+
+impl<T: Ord, A: Allocator> Extend<T> for VecSet<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.elems.extend(iter);
+        sort_dedup_last(&mut self.elems, |e| e);
+    }
+}
+
+impl<T, A: Allocator> IntoIterator for VecSet<T, A> {
+    type Item = T;
+    type IntoIter = crate::IntoIter<T, A>;
+    fn into_iter(self) -> crate::IntoIter<T, A> {
+        self.elems.into_iter()
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a VecSet<T, A> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vecmap_lookup_and_range() {
+        let mut map = VecMap::new();
+        assert_eq!(map.insert(3, "c"), None);
+        assert_eq!(map.insert(1, "a"), None);
+        assert_eq!(map.insert(2, "b"), None);
+        assert_eq!(map.insert(3, "C"), Some("c"));
+        assert_eq!(map.as_slice(), [(1, "a"), (2, "b"), (3, "C")]);
+        assert_eq!(map.get(&2), Some(&"b"));
+        assert_eq!(map[&3], "C");
+        assert!(!map.contains_key(&4));
+
+        map.extend(vec![(10, "x"), (5, "y"), (10, "z")]);
+        assert!(map.keys().eq(&[1, 2, 3, 5, 10]));
+        assert_eq!(map[&10], "z");
+        assert!(map.range(2..=5).map(|(k, _)| *k).eq([2, 3, 5]));
+        assert!(map.range(4..).map(|(k, _)| *k).eq([5, 10]));
+        let reversed = (Bound::Included(6), Bound::Excluded(4));
+        assert_eq!(map.range(reversed).len(), 0);
+        for (_, v) in map.range_mut(..3) {
+            *v = "low";
+        }
+        assert_eq!(map.remove(&1), Some("low"));
+        assert_eq!(map.first_key_value(), Some((&2, &"low")));
+        assert_eq!(map.pop_last(), Some((10, "z")));
+
+        let names: VecMap<String, u32> =
+            vec![("b".to_string(), 2), ("a".to_string(), 1)]
+                .into_iter()
+                .collect();
+        assert_eq!(names.get("a"), Some(&1));
+        assert_eq!(format!("{:?}", names), r#"{"a": 1, "b": 2}"#);
+    }
+
+    #[test]
+    fn vecmap_from_sorted_iter() {
+        let map = VecMap::from_sorted_iter((0..100).map(|i| (i * 2, i)));
+        assert_eq!(map.len(), 100);
+        assert_eq!(map.get(&50), Some(&25));
+        assert_eq!(map.get(&51), None);
+        let set = VecSet::from_sorted_iter(["a", "b", "c"]);
+        assert!(set.contains("b"));
+
+        let unsorted = std::panic::catch_unwind(|| {
+            VecSet::from_sorted_iter(vec![1, 3, 2]);
+        });
+        assert!(unsorted.is_err());
+        let duplicate = std::panic::catch_unwind(|| {
+            VecMap::from_sorted_iter(vec![(1, ()), (1, ())]);
+        });
+        assert!(duplicate.is_err());
+    }
+
+    #[test]
+    fn vecmap_set_basics() {
+        let mut set = VecSet::from([5, 1, 3, 1]);
+        assert_eq!(set.as_slice(), [1, 3, 5]);
+        assert!(set.insert(4));
+        assert!(!set.insert(4));
+        assert!(set.remove(&1));
+        assert!(!set.remove(&1));
+        assert!(set.range(4..).eq(&[4, 5]));
+        assert_eq!(set.first(), Some(&3));
+        assert_eq!(set.pop_first(), Some(3));
+        set.extend(vec![0, 9, 5]);
+        assert_eq!(set.as_slice(), [0, 4, 5, 9]);
+
+        let other = VecSet::from([4, 7, 9]);
+        assert_eq!(set.union(&other).as_slice(), [0, 4, 5, 7, 9]);
+        assert_eq!(set.intersection(&other).as_slice(), [4, 9]);
+        assert_eq!(set.difference(&other).as_slice(), [0, 5]);
+        assert_eq!(set.symmetric_difference(&other).into_vec(), [0, 5, 7]);
+    }
+}